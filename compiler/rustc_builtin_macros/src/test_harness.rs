@@ -16,7 +16,7 @@
 use rustc_session::Session;
 use rustc_session::lint::builtin::UNNAMEABLE_TEST_ITEMS;
 use rustc_span::hygiene::{AstPass, SyntaxContext, Transparency};
-use rustc_span::{DUMMY_SP, Ident, Span, Symbol, sym};
+use rustc_span::{DUMMY_SP, FileName, Ident, Span, Symbol, sym};
 use rustc_target::spec::PanicStrategy;
 use smallvec::smallvec;
 use thin_vec::{ThinVec, thin_vec};
@@ -38,6 +38,7 @@ struct TestCtxt<'a> {
     test_cases: Vec<Test>,
     reexport_test_harness_main: Option<Symbol>,
     test_runner: Option<ast::Path>,
+    instrument_main: bool,
 }
 
 /// Traverse the crate, collecting all the test functions, eliding any
@@ -245,7 +246,12 @@ fn generate_test_harness(
         &[sym::test, sym::rustc_attrs, sym::coverage_attribute],
         None,
     );
-    let def_site = DUMMY_SP.with_def_site_ctxt(expn_id.to_expn_id());
+    // Under `-Zcoverage-options=test-harness`, the generated `main` is
+    // instrumented, so its spans must point somewhere that won't be mistaken
+    // for user code in coverage reports.
+    let instrument_main = sess.instrument_coverage() && sess.coverage_test_harness();
+    let harness_span = if instrument_main { synthetic_harness_span(sess) } else { DUMMY_SP };
+    let def_site = harness_span.with_def_site_ctxt(expn_id.to_expn_id());
 
     // Remove the entry points
     let mut cleaner = EntryPointCleaner { sess, depth: 0, def_site };
@@ -258,11 +264,22 @@ fn generate_test_harness(
         test_cases: Vec::new(),
         reexport_test_harness_main,
         test_runner,
+        instrument_main,
     };
 
     TestHarnessGenerator { cx, tests: Vec::new() }.visit_crate(krate);
 }
 
+/// Returns a span covering a synthetic `<test harness>` source file, for the
+/// generated `main` to be attributed to in coverage reports.
+fn synthetic_harness_span(sess: &Session) -> Span {
+    let file = sess.source_map().new_source_file(
+        FileName::Custom("test harness".to_owned()),
+        "pub fn main() { test::test_main_static(TESTS) }\n".to_owned(),
+    );
+    Span::with_root_ctxt(file.start_pos, file.end_position())
+}
+
 /// Creates a function item for use as the main function of a test build.
 /// This function will call the `test_runner` as specified by the crate attribute
 ///
@@ -322,11 +339,13 @@ fn mk_main(cx: &mut TestCtxt<'_>) -> P<ast::Item> {
     );
 
     // #[rustc_main]
-    let main_attr = ecx.attr_word(sym::rustc_main, sp);
-    // #[coverage(off)]
-    let coverage_attr = ecx.attr_nested_word(sym::coverage, sym::off, sp);
+    let mut main_attrs = thin_vec![ecx.attr_word(sym::rustc_main, sp)];
+    // #[coverage(off)], unless the harness is being instrumented
+    if !cx.instrument_main {
+        main_attrs.push(ecx.attr_nested_word(sym::coverage, sym::off, sp));
+    }
     // #[doc(hidden)]
-    let doc_hidden_attr = ecx.attr_nested_word(sym::doc, sym::hidden, sp);
+    main_attrs.push(ecx.attr_nested_word(sym::doc, sym::hidden, sp));
 
     // pub fn main() { ... }
     let main_ret_ty = ecx.ty(sp, ast::TyKind::Tup(ThinVec::new()));
@@ -356,7 +375,7 @@ fn mk_main(cx: &mut TestCtxt<'_>) -> P<ast::Item> {
 
     let main = P(ast::Item {
         ident: main_id,
-        attrs: thin_vec![main_attr, coverage_attr, doc_hidden_attr],
+        attrs: main_attrs,
         id: ast::DUMMY_NODE_ID,
         kind: main,
         vis: ast::Visibility { span: sp, kind: ast::VisibilityKind::Public, tokens: None },
@@ -55,6 +55,10 @@ codegen_llvm_prepare_thin_lto_context_with_llvm_err = failed to prepare thin LTO
 codegen_llvm_prepare_thin_lto_module = failed to prepare thin LTO module
 codegen_llvm_prepare_thin_lto_module_with_llvm_err = failed to prepare thin LTO module: {$llvm_err}
 
+codegen_llvm_read_coverage_profile = failed to read coverage profile `{$path}`: {$err}
+
+codegen_llvm_read_doctest_line_table = failed to read doctest line table `{$path}`: {$err}
+
 codegen_llvm_run_passes = failed to run LLVM passes
 codegen_llvm_run_passes_with_llvm_err = failed to run LLVM passes: {$llvm_err}
 
@@ -88,6 +92,8 @@ codegen_llvm_unstable_ctarget_feature =
 
 codegen_llvm_write_bytecode = failed to write bytecode to {$path}: {$err}
 
+codegen_llvm_write_const_eval_profile = failed to write const-eval coverage profile to `{$path}`: {$err}
+
 codegen_llvm_write_ir = failed to write LLVM IR to {$path}
 codegen_llvm_write_ir_with_llvm_err = failed to write LLVM IR to {$path}: {$llvm_err}
 
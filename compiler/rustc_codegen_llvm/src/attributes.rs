@@ -176,6 +176,21 @@ fn function_return_attr<'ll>(cx: &CodegenCx<'ll, '_>) -> Option<&'ll Attribute>
             mcount_name,
         ));
     }
+    if cx.sess().opts.unstable_opts.instrument_function_entry_exit {
+        // Similar to `clang -finstrument-functions-after-inlining`. Also handled
+        // by the `post-inline-ee-instrument` LLVM pass, which passes the address
+        // of the function and of its call site to each hook.
+        attrs.push(llvm::CreateAttrStringValue(
+            cx.llcx,
+            "instrument-function-entry-inlined",
+            "__cyg_profile_func_enter",
+        ));
+        attrs.push(llvm::CreateAttrStringValue(
+            cx.llcx,
+            "instrument-function-exit-inlined",
+            "__cyg_profile_func_exit",
+        ));
+    }
     if let Some(options) = &cx.sess().opts.unstable_opts.instrument_xray {
         // XRay instrumentation is similar to __cyg_profile_func_{enter,exit}.
         // Function prologue and epilogue are instrumented with NOP sleds,
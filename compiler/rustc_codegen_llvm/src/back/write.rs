@@ -527,7 +527,7 @@ fn get_pgo_sample_use_path(config: &ModuleConfig) -> Option<CString> {
 }
 
 fn get_instr_profile_output_path(config: &ModuleConfig) -> Option<CString> {
-    config.instrument_coverage.then(|| c"default_%m_%p.profraw".to_owned())
+    config.instr_profile_output.as_deref().map(|output| CString::new(output).unwrap())
 }
 
 pub(crate) unsafe fn llvm_optimize(
@@ -599,6 +599,9 @@ pub(crate) unsafe fn llvm_optimize(
             sanitize_kernel_address_recover: config
                 .sanitizer_recover
                 .contains(SanitizerSet::KERNELADDRESS),
+            sanitize_coverage_inline_8bit_counters: config.sanitizer_coverage.inline_8bit_counters,
+            sanitize_coverage_stack_depth: config.sanitizer_coverage.stack_depth,
+            sanitize_coverage_control_flow: config.sanitizer_coverage.control_flow,
         })
     } else {
         None
@@ -638,6 +641,7 @@ pub(crate) unsafe fn llvm_optimize(
             pgo_use_path.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
             config.instrument_coverage,
             instr_profile_output_path.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            config.atomic_coverage_counters,
             pgo_sample_use_path.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
             config.debug_info_for_profiling,
             llvm_selfprofiler,
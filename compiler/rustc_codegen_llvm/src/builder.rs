@@ -211,6 +211,18 @@ fn cond_br(
         }
     }
 
+    fn cond_br_with_weights(
+        &mut self,
+        cond: &'ll Value,
+        then_llbb: &'ll BasicBlock,
+        else_llbb: &'ll BasicBlock,
+        then_weight: u64,
+        else_weight: u64,
+    ) {
+        let br = unsafe { llvm::LLVMBuildCondBr(self.llbuilder, cond, then_llbb, else_llbb) };
+        self.set_branch_weights(br, &[then_weight, else_weight]);
+    }
+
     fn switch(
         &mut self,
         v: &'ll Value,
@@ -225,6 +237,26 @@ fn switch(
         }
     }
 
+    fn switch_with_weights(
+        &mut self,
+        v: &'ll Value,
+        else_llbb: &'ll BasicBlock,
+        else_weight: u64,
+        cases: impl ExactSizeIterator<Item = (u128, &'ll BasicBlock, u64)>,
+    ) {
+        let switch =
+            unsafe { llvm::LLVMBuildSwitch(self.llbuilder, v, else_llbb, cases.len() as c_uint) };
+        // The default destination's weight comes first, followed by each case.
+        let mut weights = Vec::with_capacity(cases.len() + 1);
+        weights.push(else_weight);
+        for (on_val, dest, weight) in cases {
+            let on_val = self.const_uint_big(self.val_ty(v), on_val);
+            unsafe { llvm::LLVMAddCase(switch, on_val, dest) }
+            weights.push(weight);
+        }
+        self.set_branch_weights(switch, &weights);
+    }
+
     fn invoke(
         &mut self,
         llty: &'ll Type,
@@ -1260,6 +1292,23 @@ pub(crate) fn set_unpredictable(&mut self, inst: &'ll Value) {
         }
     }
 
+    /// Attaches `!prof` branch weights to a terminator, given one execution
+    /// count per successor.
+    fn set_branch_weights(&mut self, inst: &'ll Value, counts: &[u64]) {
+        // Branch weights are 32-bit, so scale large counts down the same way
+        // as Clang does, including adding one so that no weight is zero.
+        let scale = counts.iter().max().map_or(1, |&max| max / u64::from(u32::MAX) + 1);
+        let mut md = Vec::with_capacity(counts.len() + 1);
+        md.push(self.cx.create_metadata("branch_weights".to_owned()).unwrap());
+        md.extend(counts.iter().map(|&count| unsafe {
+            llvm::LLVMValueAsMetadata(self.cx.const_u32((count / scale + 1) as u32))
+        }));
+        unsafe {
+            let md = llvm::LLVMMDNodeInContext2(self.cx.llcx, md.as_ptr(), md.len());
+            self.set_metadata(inst, llvm::MD_prof, md);
+        }
+    }
+
     pub(crate) fn minnum(&mut self, lhs: &'ll Value, rhs: &'ll Value) -> &'ll Value {
         unsafe { llvm::LLVMRustBuildMinNum(self.llbuilder, lhs, rhs) }
     }
@@ -1642,6 +1691,51 @@ pub(crate) fn instrprof_increment(
         self.call_intrinsic("llvm.instrprof.increment", &[fn_name, hash, num_counters, index]);
     }
 
+    /// Emits a call to `llvm.instrprof.increment.step`. Used by coverage
+    /// instrumentation.
+    #[instrument(level = "debug", skip(self))]
+    pub(crate) fn instrprof_increment_step(
+        &mut self,
+        fn_name: &'ll Value,
+        hash: &'ll Value,
+        num_counters: &'ll Value,
+        index: &'ll Value,
+        step: &'ll Value,
+    ) {
+        let args = &[fn_name, hash, num_counters, index, step];
+        self.call_intrinsic("llvm.instrprof.increment.step", args);
+    }
+
+    /// Emits a call to `llvm.instrprof.cover`, which marks a single-byte counter
+    /// as reached. Used by coverage instrumentation under
+    /// `-Zcoverage-options=boolean-counters`.
+    #[instrument(level = "debug", skip(self))]
+    pub(crate) fn instrprof_cover(
+        &mut self,
+        fn_name: &'ll Value,
+        hash: &'ll Value,
+        num_counters: &'ll Value,
+        index: &'ll Value,
+    ) {
+        self.call_intrinsic("llvm.instrprof.cover", &[fn_name, hash, num_counters, index]);
+    }
+
+    /// Emits a call to `llvm.instrprof.value.profile`, which records `value`
+    /// at the given value-profiling site of the function. Used by coverage
+    /// instrumentation to profile the targets of indirect calls.
+    #[instrument(level = "debug", skip(self))]
+    pub(crate) fn instrprof_value_profile(
+        &mut self,
+        fn_name: &'ll Value,
+        hash: &'ll Value,
+        value: &'ll Value,
+        value_kind: &'ll Value,
+        index: &'ll Value,
+    ) {
+        let args = &[fn_name, hash, value, value_kind, index];
+        self.call_intrinsic("llvm.instrprof.value.profile", args);
+    }
+
     /// Emits a call to `llvm.instrprof.mcdc.parameters`.
     ///
     /// This doesn't produce any code directly, but is used as input by
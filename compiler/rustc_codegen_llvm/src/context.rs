@@ -84,6 +84,8 @@ pub(crate) struct CodegenCx<'ll, 'tcx> {
 
     /// Extra per-CGU codegen state needed when coverage instrumentation is enabled.
     pub coverage_cx: Option<coverageinfo::CguCoverageContext<'ll, 'tcx>>,
+    /// The profile to derive branch weights from, under `-Zprofile-use-coverage`.
+    pub(crate) coverage_profile: Option<coverageinfo::CoverageProfile>,
     pub dbg_cx: Option<debuginfo::CodegenUnitDebugContext<'ll, 'tcx>>,
 
     eh_personality: Cell<Option<&'ll Value>>,
@@ -536,6 +538,16 @@ pub(crate) fn new(
 
         let coverage_cx =
             tcx.sess.instrument_coverage().then(coverageinfo::CguCoverageContext::new);
+        let coverage_profile = tcx
+            .sess
+            .profile_use_coverage()
+            .then(|| tcx.sess.opts.cg.profile_use.as_deref())
+            .flatten()
+            .map(|path| {
+                coverageinfo::CoverageProfile::open(path).unwrap_or_else(|err| {
+                    tcx.dcx().emit_fatal(crate::errors::ReadCoverageProfile { path, err })
+                })
+            });
 
         let dbg_cx = if tcx.sess.opts.debuginfo != DebugInfo::None {
             let dctx = debuginfo::CodegenUnitDebugContext::new(llmod);
@@ -569,6 +581,7 @@ pub(crate) fn new(
             scalar_lltypes: Default::default(),
             isize_ty,
             coverage_cx,
+            coverage_profile,
             dbg_cx,
             eh_personality: Cell::new(None),
             eh_catch_typeinfo: Cell::new(None),
@@ -1115,6 +1128,9 @@ macro_rules! mk_struct {
 
         if self.sess().instrument_coverage() {
             ifn!("llvm.instrprof.increment", fn(ptr, t_i64, t_i32, t_i32) -> void);
+            ifn!("llvm.instrprof.increment.step", fn(ptr, t_i64, t_i32, t_i32, t_i64) -> void);
+            ifn!("llvm.instrprof.cover", fn(ptr, t_i64, t_i32, t_i32) -> void);
+            ifn!("llvm.instrprof.value.profile", fn(ptr, t_i64, t_i64, t_i32, t_i32) -> void);
             if crate::llvm_util::get_version() >= (19, 0, 0) {
                 ifn!("llvm.instrprof.mcdc.parameters", fn(ptr, t_i64, t_i32) -> void);
                 ifn!("llvm.instrprof.mcdc.tvbitmap.update", fn(ptr, t_i64, t_i32, ptr) -> void);
@@ -0,0 +1,61 @@
+//! The profiler runtime writes the profile from an exit-time handler, which
+//! never runs if the process ends with `panic=abort` or `std::process::abort`.
+//! To keep the counters of such processes, crates that are linked into an
+//! executable or library define a hook that dumps the profile, which the
+//! standard library looks up (through a weak reference) and calls just before
+//! it aborts.
+//!
+//! Passing `-Zcoverage-options=no-abort-flush` leaves the hook out.
+
+use rustc_codegen_ssa::traits::{BaseTypeCodegenMethods, BuilderMethods};
+use rustc_session::config::CrateType;
+use rustc_target::spec::HasTargetSpec;
+
+use crate::builder::Builder;
+use crate::common::CodegenCx;
+use crate::llvm;
+
+pub(crate) fn generate_abort_flush_hook(cx: &CodegenCx<'_, '_>) {
+    let tcx = cx.tcx;
+    let sess = tcx.sess;
+
+    // Only one CGU per crate needs to define the hook.
+    if !cx.codegen_unit.is_code_coverage_dead_code_cgu() {
+        return;
+    }
+    if sess.coverage_no_abort_flush() || tcx.crate_types().iter().all(|ct| *ct == CrateType::Rlib) {
+        return;
+    }
+    // Without the profiler runtime, there's no `__llvm_profile_dump` to call.
+    if sess.opts.unstable_opts.no_profiler_runtime {
+        return;
+    }
+
+    // `int __llvm_profile_dump(void)` writes the profile and marks it as
+    // dumped, so the runtime won't write it a second time at process exit,
+    // and calling the hook again is harmless.
+    let dump_ty = cx.type_func(&[], cx.type_i32());
+    let dump_fn = cx.declare_cfn("__llvm_profile_dump", llvm::UnnamedAddr::No, dump_ty);
+
+    // `void __rustc_coverage_flush_on_abort(void)`
+    //
+    // Several crates linked into the same image may each define the hook, so
+    // it is deduplicated like an inline function. It stays hidden, because
+    // the standard library only looks for it in its own image.
+    let hook_ty = cx.type_func(&[], cx.type_void());
+    let hook =
+        cx.declare_cfn("__rustc_coverage_flush_on_abort", llvm::UnnamedAddr::Global, hook_ty);
+    llvm::set_linkage(hook, llvm::Linkage::LinkOnceODRLinkage);
+    llvm::set_visibility(hook, llvm::Visibility::Hidden);
+    if cx.target_spec().supports_comdat() {
+        llvm::set_comdat(cx.llmod, hook, c"__rustc_coverage_flush_on_abort");
+    }
+
+    let start = Builder::append_block(cx, hook, "start");
+    let mut bx = Builder::build(cx, start);
+    bx.call(dump_ty, None, None, dump_fn, &[], None, None);
+    bx.ret_void();
+
+    // Nothing in this crate calls the hook, so keep LLVM from discarding it.
+    cx.add_used_global(hook);
+}
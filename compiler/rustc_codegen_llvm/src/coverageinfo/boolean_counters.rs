@@ -0,0 +1,42 @@
+//! Under `-Zcoverage-options=boolean-counters`, each physical counter is a
+//! single byte that `llvm.instrprof.cover` clears when it is reached, instead
+//! of a 64-bit count. LLVM lowers the intrinsic by itself, but the profiler
+//! runtime and `llvm-profdata` also need to know that the counters are bytes,
+//! which they learn from a flag in the profile version variable.
+
+use rustc_codegen_ssa::traits::{
+    BaseTypeCodegenMethods, ConstCodegenMethods, StaticCodegenMethods,
+};
+use rustc_target::spec::HasTargetSpec;
+
+use crate::common::CodegenCx;
+use crate::coverageinfo::llvm_cov;
+use crate::llvm;
+
+pub(crate) fn generate_profile_version_var(cx: &CodegenCx<'_, '_>) {
+    if !cx.tcx.sess.coverage_boolean_counters() {
+        return;
+    }
+    // Only one CGU per crate needs to define the variable.
+    if !cx.codegen_unit.is_code_coverage_dead_code_cgu() {
+        return;
+    }
+
+    // `uint64_t __llvm_profile_raw_version`
+    //
+    // The profiler runtime has a weak definition without the flag, which this
+    // one takes precedence over. Every crate that uses boolean counters
+    // defines it, so it is weak here too, like the one that Clang defines
+    // for `-enable-single-byte-coverage`.
+    let version = llvm_cov::raw_profile_version() | llvm_cov::byte_coverage_variant_mask();
+    let version = cx.const_u64(version);
+    let global = llvm::add_global(cx.llmod, cx.type_i64(), c"__llvm_profile_raw_version");
+    llvm::set_initializer(global, version);
+    llvm::set_global_constant(global, true);
+    llvm::set_linkage(global, llvm::Linkage::WeakAnyLinkage);
+    llvm::set_visibility(global, llvm::Visibility::Hidden);
+    if cx.target_spec().supports_comdat() {
+        llvm::set_comdat(cx.llmod, global, c"__llvm_profile_raw_version");
+    }
+    cx.add_used_global(global);
+}
@@ -0,0 +1,84 @@
+//! When an instrumented DLL is unloaded before its host process exits, the
+//! profiler runtime's exit-time handler never gets a chance to write that
+//! DLL's counters. To avoid losing them, Windows DLL crates register a TLS
+//! callback that dumps the profile on `DLL_PROCESS_DETACH`.
+
+use rustc_codegen_ssa::common::IntPredicate;
+use rustc_codegen_ssa::traits::{
+    BaseTypeCodegenMethods, BuilderMethods, ConstCodegenMethods, StaticCodegenMethods,
+};
+use rustc_session::config::CrateType;
+
+use crate::builder::Builder;
+use crate::common::CodegenCx;
+use crate::llvm;
+
+/// The `fdwReason` value passed to TLS callbacks when the image is unloaded.
+const DLL_PROCESS_DETACH: i32 = 0;
+
+pub(crate) fn generate_dll_unload_hook(cx: &CodegenCx<'_, '_>) {
+    let tcx = cx.tcx;
+    let sess = tcx.sess;
+
+    // Only one CGU per crate needs to register the hook.
+    if !cx.codegen_unit.is_code_coverage_dead_code_cgu() {
+        return;
+    }
+    if !sess.target.is_like_windows
+        || !tcx.crate_types().iter().any(|ct| matches!(ct, CrateType::Dylib | CrateType::Cdylib))
+    {
+        return;
+    }
+    // Without the profiler runtime, there's no `__llvm_profile_dump` to call.
+    if sess.opts.unstable_opts.no_profiler_runtime {
+        return;
+    }
+
+    // `int __llvm_profile_dump(void)` writes the profile and marks it as
+    // dumped, so the runtime won't write it a second time at process exit.
+    let dump_ty = cx.type_func(&[], cx.type_i32());
+    let dump_fn = cx.declare_cfn("__llvm_profile_dump", llvm::UnnamedAddr::No, dump_ty);
+
+    // `void NTAPI callback(PVOID DllHandle, DWORD Reason, PVOID Reserved)`
+    let callback_ty = cx.type_func(&[cx.type_ptr(), cx.type_i32(), cx.type_ptr()], cx.type_void());
+    let callback =
+        cx.declare_cfn("__rustc_coverage_dll_unload", llvm::UnnamedAddr::Global, callback_ty);
+    llvm::set_linkage(callback, llvm::Linkage::InternalLinkage);
+    if sess.target.arch == "x86" {
+        llvm::SetFunctionCallConv(callback, llvm::CallConv::X86StdcallCallConv);
+    }
+
+    let start = Builder::append_block(cx, callback, "start");
+    let dump = Builder::append_block(cx, callback, "dump");
+    let done = Builder::append_block(cx, callback, "done");
+    let mut bx = Builder::build(cx, start);
+    let reason = llvm::get_param(callback, 1);
+    let is_detach = bx.icmp(IntPredicate::IntEQ, reason, cx.const_i32(DLL_PROCESS_DETACH));
+    bx.cond_br(is_detach, dump, done);
+    bx.switch_to_block(dump);
+    bx.call(dump_ty, None, None, dump_fn, &[], None, None);
+    bx.br(done);
+    bx.switch_to_block(done);
+    bx.ret_void();
+
+    // The loader calls every function pointer in the `.CRT$XL*` sections, in
+    // order. Using `.CRT$XLD` places this hook after the standard library's
+    // own callback (in `.CRT$XLB`), so that code run by thread-local
+    // destructors is still counted.
+    let callback_ptr = llvm::add_global(cx.llmod, cx.type_ptr(), c"__rustc_coverage_dll_unload_cb");
+    llvm::set_initializer(callback_ptr, callback);
+    llvm::set_global_constant(callback_ptr, true);
+    llvm::set_linkage(callback_ptr, llvm::Linkage::InternalLinkage);
+    llvm::set_section(callback_ptr, c".CRT$XLD");
+    cx.add_used_global(callback_ptr);
+
+    // TLS callbacks are only called if the image has a TLS directory, which
+    // the linker only creates if something refers to `_tls_used`. (See the
+    // standard library's `sys::thread_local::guard::windows` for details.)
+    let tls_used = cx.declare_global("_tls_used", cx.type_i8());
+    let tls_used_ref = llvm::add_global(cx.llmod, cx.type_ptr(), c"__rustc_coverage_tls_used_ref");
+    llvm::set_initializer(tls_used_ref, tls_used);
+    llvm::set_global_constant(tls_used_ref, true);
+    llvm::set_linkage(tls_used_ref, llvm::Linkage::InternalLinkage);
+    cx.add_used_global(tls_used_ref);
+}
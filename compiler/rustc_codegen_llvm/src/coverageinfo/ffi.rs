@@ -50,6 +50,12 @@ pub(crate) fn from_term(term: CovTerm) -> Self {
             CovTerm::Expression(id) => Self::expression(id),
         }
     }
+
+    /// Returns the index of the physical counter that this refers to, if it
+    /// is of kind `CounterValueReference`.
+    pub(crate) fn physical_counter_id(&self) -> Option<u32> {
+        matches!(self.kind, CounterKind::CounterValueReference).then_some(self.id)
+    }
 }
 
 /// Corresponds to enum `llvm::coverage::CounterExpression::ExprKind`.
@@ -149,18 +155,31 @@ pub(crate) struct Regions {
     pub(crate) branch_regions: Vec<BranchRegion>,
     pub(crate) mcdc_branch_regions: Vec<MCDCBranchRegion>,
     pub(crate) mcdc_decision_regions: Vec<MCDCDecisionRegion>,
+    pub(crate) expansion_regions: Vec<ExpansionRegion>,
+    pub(crate) gap_regions: Vec<GapRegion>,
+    pub(crate) skipped_regions: Vec<SkippedRegion>,
 }
 
 impl Regions {
     /// Returns true if none of this structure's tables contain any regions.
     pub(crate) fn has_no_regions(&self) -> bool {
-        let Self { code_regions, branch_regions, mcdc_branch_regions, mcdc_decision_regions } =
-            self;
+        let Self {
+            code_regions,
+            branch_regions,
+            mcdc_branch_regions,
+            mcdc_decision_regions,
+            expansion_regions,
+            gap_regions,
+            skipped_regions,
+        } = self;
 
         code_regions.is_empty()
             && branch_regions.is_empty()
             && mcdc_branch_regions.is_empty()
             && mcdc_decision_regions.is_empty()
+            && expansion_regions.is_empty()
+            && gap_regions.is_empty()
+            && skipped_regions.is_empty()
     }
 }
 
@@ -198,3 +217,28 @@ pub(crate) struct MCDCDecisionRegion {
     pub(crate) cov_span: CoverageSpan,
     pub(crate) mcdc_decision_params: mcdc::DecisionParameters,
 }
+
+/// Must match the layout of `LLVMRustCoverageExpansionRegion`.
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub(crate) struct ExpansionRegion {
+    /// The span of the macro invocation, in the file that invokes it.
+    pub(crate) cov_span: CoverageSpan,
+    /// Local ID of the virtual file holding the code regions of the expansion.
+    pub(crate) expanded_file_id: u32,
+}
+
+/// Must match the layout of `LLVMRustCoverageGapRegion`.
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub(crate) struct GapRegion {
+    pub(crate) cov_span: CoverageSpan,
+    pub(crate) counter: Counter,
+}
+
+/// Must match the layout of `LLVMRustCoverageSkippedRegion`.
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub(crate) struct SkippedRegion {
+    pub(crate) cov_span: CoverageSpan,
+}
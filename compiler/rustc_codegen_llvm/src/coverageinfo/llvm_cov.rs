@@ -1,6 +1,9 @@
 //! Safe wrappers for coverage-specific FFI functions.
 
 use std::ffi::CString;
+use std::path::Path;
+
+use rustc_fs_util::path_to_c_string;
 
 use crate::common::AsCCharPtr;
 use crate::coverageinfo::ffi;
@@ -63,8 +66,15 @@ pub(crate) fn write_function_mappings_to_buffer(
     expressions: &[ffi::CounterExpression],
     regions: &ffi::Regions,
 ) -> Vec<u8> {
-    let ffi::Regions { code_regions, branch_regions, mcdc_branch_regions, mcdc_decision_regions } =
-        regions;
+    let ffi::Regions {
+        code_regions,
+        branch_regions,
+        mcdc_branch_regions,
+        mcdc_decision_regions,
+        expansion_regions,
+        gap_regions,
+        skipped_regions,
+    } = regions;
     llvm::build_byte_buffer(|buffer| unsafe {
         llvm::LLVMRustCoverageWriteFunctionMappingsToBuffer(
             virtual_file_mapping.as_ptr(),
@@ -79,6 +89,12 @@ pub(crate) fn write_function_mappings_to_buffer(
             mcdc_branch_regions.len(),
             mcdc_decision_regions.as_ptr(),
             mcdc_decision_regions.len(),
+            expansion_regions.as_ptr(),
+            expansion_regions.len(),
+            gap_regions.as_ptr(),
+            gap_regions.len(),
+            skipped_regions.as_ptr(),
+            skipped_regions.len(),
             buffer,
         )
     })
@@ -96,3 +112,68 @@ pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
 pub(crate) fn mapping_version() -> u32 {
     unsafe { llvm::LLVMRustCoverageMappingVersion() }
 }
+
+/// Returns LLVM's `INSTR_PROF_RAW_VERSION` (InstrProfData.inc), the version of
+/// the `.profraw` format written by the profiler runtime.
+pub(crate) fn raw_profile_version() -> u64 {
+    unsafe { llvm::LLVMRustInstrProfRawVersion() }
+}
+
+/// Returns LLVM's `VARIANT_MASK_BYTE_COVERAGE` (InstrProfData.inc), the flag in
+/// the raw profile version that marks a profile's counters as single bytes.
+pub(crate) fn byte_coverage_variant_mask() -> u64 {
+    unsafe { llvm::LLVMRustInstrProfByteCoverageVariantMask() }
+}
+
+/// Returns LLVM's `IndexedInstrProf::ProfVersion::CurrentVersion` (InstrProf.h),
+/// the version of the `.profdata` format written by `llvm-profdata`.
+pub(crate) fn indexed_profile_version() -> u64 {
+    unsafe { llvm::LLVMRustInstrProfIndexedVersion() }
+}
+
+/// An indexed profile produced by a `-Cinstrument-coverage` build, read under
+/// `-Zprofile-use-coverage`.
+pub(crate) struct CoverageProfile {
+    raw: &'static mut llvm::InstrProfReader,
+}
+
+impl CoverageProfile {
+    pub(crate) fn open(path: &Path) -> Result<Self, String> {
+        let path = path_to_c_string(path);
+        let raw = unsafe { llvm::LLVMRustCoverageOpenProfile(path.as_ptr()) }.ok_or_else(|| {
+            llvm::last_error().unwrap_or_else(|| "failed to read profile".to_owned())
+        })?;
+        Ok(Self { raw })
+    }
+
+    /// Returns the counter values recorded for the function with the given
+    /// symbol name and coverage hash, if the profile has a record for it.
+    pub(crate) fn function_counters(
+        &self,
+        mangled_fn_name: &str,
+        function_source_hash: u64,
+    ) -> Option<Vec<u64>> {
+        let mut found = false;
+        let bytes = llvm::build_byte_buffer(|buffer| unsafe {
+            found = llvm::LLVMRustCoverageReadFunctionCounts(
+                self.raw,
+                mangled_fn_name.as_c_char_ptr(),
+                mangled_fn_name.len(),
+                function_source_hash,
+                buffer,
+            );
+        });
+        found.then(|| {
+            bytes
+                .chunks_exact(size_of::<u64>())
+                .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()))
+                .collect()
+        })
+    }
+}
+
+impl Drop for CoverageProfile {
+    fn drop(&mut self) {
+        unsafe { llvm::LLVMRustCoverageCloseProfile(&mut *(self.raw as *mut _)) }
+    }
+}
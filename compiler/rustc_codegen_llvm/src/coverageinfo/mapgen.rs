@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use itertools::Itertools;
@@ -10,18 +13,25 @@
 use rustc_index::IndexVec;
 use rustc_middle::mir;
 use rustc_middle::ty::{self, TyCtxt};
-use rustc_session::RemapFileNameExt;
 use rustc_session::config::RemapPathScopeComponents;
-use rustc_span::def_id::DefIdSet;
-use rustc_span::{SourceFile, StableSourceFileId};
+use rustc_session::{RemapFileNameExt, Session};
+use rustc_span::{FileName, RealFileName, SourceFile, Span, StableSourceFileId};
+use rustc_target::spec::HasTargetSpec;
 use tracing::debug;
 
 use crate::common::CodegenCx;
 use crate::coverageinfo::llvm_cov;
-use crate::coverageinfo::mapgen::covfun::prepare_covfun_record;
+use crate::coverageinfo::mapgen::covfun::{FunctionUsage, prepare_covfun_record};
+use crate::coverageinfo::mapgen::spans::{DoctestLineTable, DoctestLines};
 use crate::llvm;
 
+mod covarm;
+mod covconst;
 mod covfun;
+mod covmeta;
+mod covpath;
+mod covregion;
+mod covtls;
 mod spans;
 
 /// Generates and exports the coverage map, which is embedded in special
@@ -55,42 +65,72 @@ pub(crate) fn finalize(cx: &CodegenCx<'_, '_>) {
         None => return,
     };
 
+    // Under `-Zcoverage-options=thread-local-counters`, describe the layout of
+    // each function's per-thread counters, so that std can merge them.
+    covtls::generate_covtls_records(cx);
+
     // The order of entries in this global file table needs to be deterministic,
     // and ideally should also be independent of the details of stable-hashing,
     // because coverage tests snapshots (`.cov-map`) can observe the order and
     // would need to be re-blessed if it changes. As long as those requirements
     // are satisfied, the order can be arbitrary.
-    let mut global_file_table = GlobalFileTable::new();
-
-    let mut covfun_records = instances_used
+    //
+    // Under `-Zcoverage-options=shared-file-table`, the table starts out with
+    // every file of the crate, so that all CGUs encode the same filenames
+    // buffer, instead of each repeating its own subset of the paths.
+    let shared_file_table = tcx.sess.coverage_shared_file_table();
+    let mut global_file_table =
+        if shared_file_table { GlobalFileTable::for_crate(tcx) } else { GlobalFileTable::new(tcx) };
+
+    let mut functions = instances_used
         .iter()
         .copied()
         // Sort by symbol name, so that the global file table is built in an
         // order that doesn't depend on the stable-hash-based order in which
         // instances were visited during codegen.
         .sorted_by_cached_key(|&instance| tcx.symbol_name(instance).name)
-        .filter_map(|instance| prepare_covfun_record(tcx, &mut global_file_table, instance, true))
+        .map(|instance| (instance, FunctionUsage::Used))
         .collect::<Vec<_>>();
 
-    // In a single designated CGU, also prepare covfun records for functions
-    // in this crate that were instrumented for coverage, but are unused.
-    if cx.codegen_unit.is_code_coverage_dead_code_cgu() {
-        let mut unused_instances = gather_unused_function_instances(cx);
-        // Sort the unused instances by symbol name, for the same reason as the used ones.
-        unused_instances.sort_by_cached_key(|&instance| tcx.symbol_name(instance).name);
-        covfun_records.extend(unused_instances.into_iter().filter_map(|instance| {
-            prepare_covfun_record(tcx, &mut global_file_table, instance, false)
-        }));
+    // Under `-Zcoverage-options=const-eval`, functions that only ran during
+    // const evaluation still get real mappings, so that their counts from
+    // the const-eval profile can be reported. The profile itself is written
+    // by a single designated CGU.
+    let const_eval_counts = tcx.sess.coverage_const_eval().then(|| tcx.const_eval_coverage());
+    if let Some(counts) = const_eval_counts
+        && cx.codegen_unit.is_code_coverage_dead_code_cgu()
+    {
+        covconst::write_const_eval_profile(cx, counts);
     }
 
-    // If there are no covfun records for this CGU, don't generate a covmap record.
-    // Emitting a covmap record without any covfun records causes `llvm-cov` to
-    // fail when generating coverage reports, and if there are no covfun records
-    // then the covmap record isn't useful anyway.
-    // This should prevent a repeat of <https://github.com/rust-lang/rust/issues/133606>.
-    if covfun_records.is_empty() {
-        return;
-    }
+    // Also prepare covfun records for this CGU's share of the functions in
+    // this crate that were instrumented for coverage, but are unused.
+    let mut unused_instances = gather_unused_function_instances(cx);
+    // Sort the unused instances by symbol name, for the same reason as the used ones.
+    unused_instances.sort_by_cached_key(|&instance| tcx.symbol_name(instance).name);
+    functions.extend(unused_instances.into_iter().map(|instance| {
+        let usage = match instance.def_id().as_local() {
+            Some(def_id)
+                if const_eval_counts.is_some_and(|counts| counts.contains_key(&def_id)) =>
+            {
+                FunctionUsage::ConstEvalOnly
+            }
+            _ => FunctionUsage::Unused,
+        };
+        (instance, usage)
+    }));
+
+    // Every covfun record refers to the hash of the complete filenames table,
+    // so intern each function's file up front. That way each function's
+    // mapping data can be prepared, encoded and dropped in turn, instead of
+    // keeping every function's mapping tables alive until the table is done.
+    let functions = functions
+        .into_iter()
+        .filter_map(|(instance, usage)| {
+            let global_file_id = covfun::intern_source_file(tcx, &mut global_file_table, instance)?;
+            Some((instance, usage, global_file_id))
+        })
+        .collect::<Vec<_>>();
 
     // Encode all filenames referenced by coverage mappings in this CGU.
     let filenames_buffer = global_file_table.make_filenames_buffer(tcx);
@@ -99,12 +139,70 @@ pub(crate) fn finalize(cx: &CodegenCx<'_, '_>) {
     // contain multiple covmap records from different compilation units.
     let filenames_hash = llvm_cov::hash_bytes(&filenames_buffer);
 
+    let mut num_covfun_records = 0;
     let mut unused_function_names = vec![];
 
-    for covfun in &covfun_records {
+    for (instance, usage, global_file_id) in functions {
+        let Some(covfun) =
+            prepare_covfun_record(tcx, &global_file_table, global_file_id, instance, usage)
+        else {
+            continue;
+        };
+        num_covfun_records += 1;
         unused_function_names.extend(covfun.mangled_function_name_if_unused());
 
-        covfun::generate_covfun_record(cx, filenames_hash, covfun)
+        // Under `-Zcoverage-options=counter-regions`, link each physical
+        // counter to the source regions that it counts.
+        if tcx.sess.coverage_counter_regions() {
+            covregion::generate_covregion_record(cx, filenames_hash, &covfun);
+        }
+
+        // Under `-Zcoverage-options=paths`, describe the spans along each
+        // numbered path of functions that were path-profiled.
+        if tcx.sess.coverage_paths() && usage == FunctionUsage::Used {
+            covpath::generate_covpath_record(
+                cx,
+                filenames_hash,
+                &global_file_table,
+                global_file_id,
+                instance,
+                &covfun,
+            );
+        }
+
+        // Under `-Zcoverage-options=switch-arms`, describe the spans of the
+        // arm that each switch-arm slot counts.
+        if tcx.sess.coverage_switch_arms() && usage == FunctionUsage::Used {
+            covarm::generate_covarm_record(
+                cx,
+                filenames_hash,
+                &global_file_table,
+                global_file_id,
+                instance,
+                &covfun,
+            );
+        }
+
+        let mapping_bytes = covfun::generate_covfun_record(cx, filenames_hash, &covfun);
+
+        if tcx.sess.opts.unstable_opts.coverage_stats {
+            covfun::record_coverage_stats(tcx, instance, usage, &covfun, mapping_bytes);
+        }
+    }
+
+    // If there are no covfun records for this CGU, don't generate a covmap record.
+    // Emitting a covmap record without any covfun records causes `llvm-cov` to
+    // fail when generating coverage reports, and if there are no covfun records
+    // then the covmap record isn't useful anyway.
+    // This should prevent a repeat of <https://github.com/rust-lang/rust/issues/133606>.
+    if num_covfun_records == 0 {
+        return;
+    }
+
+    // Under `-Zcoverage-options=counter-metadata`, describe the site of each
+    // physical counter in every used function.
+    for &instance in instances_used.iter() {
+        covmeta::generate_covmeta_record(cx, instance);
     }
 
     // For unused functions, we need to take their mangled names and store them
@@ -112,8 +210,6 @@ pub(crate) fn finalize(cx: &CodegenCx<'_, '_>) {
     // detect this global and include those names in its `__llvm_prf_names`
     // section. (See `llvm/lib/Transforms/Instrumentation/InstrProfiling.cpp`.)
     if !unused_function_names.is_empty() {
-        assert!(cx.codegen_unit.is_code_coverage_dead_code_cgu());
-
         let name_globals = unused_function_names
             .into_iter()
             .map(|mangled_function_name| cx.const_str(mangled_function_name).0)
@@ -129,24 +225,67 @@ pub(crate) fn finalize(cx: &CodegenCx<'_, '_>) {
     // Generate the coverage map header, which contains the filenames used by
     // this CGU's coverage mappings, and store it in a well-known global.
     // (This is skipped if we returned early due to having no covfun records.)
-    generate_covmap_record(cx, covmap_version, &filenames_buffer);
+    generate_covmap_record(
+        cx,
+        covmap_version,
+        &filenames_buffer,
+        filenames_hash,
+        shared_file_table,
+    );
 }
 
 /// Maps "global" (per-CGU) file ID numbers to their underlying source files.
 struct GlobalFileTable {
     /// This "raw" table doesn't include the working dir, so a file's
     /// global ID is its index in this set **plus one**.
-    raw_file_table: FxIndexMap<StableSourceFileId, Arc<SourceFile>>,
+    raw_file_table: FxIndexMap<GlobalFileKey, Arc<SourceFile>>,
+    /// Under `-Zdoctest-line-table`, the crate's source file of merged
+    /// doctests, and the table describing where each doctest came from.
+    doctest_lines: Option<(StableSourceFileId, DoctestLineTable)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum GlobalFileKey {
+    /// An ordinary source file.
+    File(StableSourceFileId),
+    /// The lines of one doctest in a file of merged doctests, identified by
+    /// its index in the [`DoctestLineTable`]. Its regions are reported against
+    /// the file that the doctest was written in.
+    Doctest(StableSourceFileId, usize),
 }
 
 impl GlobalFileTable {
-    fn new() -> Self {
-        Self { raw_file_table: FxIndexMap::default() }
+    fn new(tcx: TyCtxt<'_>) -> Self {
+        Self { raw_file_table: FxIndexMap::default(), doctest_lines: read_doctest_lines(tcx) }
     }
 
-    fn global_file_id_for_file(&mut self, file: &Arc<SourceFile>) -> GlobalFileId {
+    /// Creates a table that already contains every file referred to by the
+    /// coverage mappings of this crate, in the order given by the
+    /// `coverage_crate_file_table` query, so that every CGU assigns the same
+    /// global IDs and encodes the same filenames buffer.
+    fn for_crate(tcx: TyCtxt<'_>) -> Self {
+        let mut table = Self::new(tcx);
+        let source_map = tcx.sess.source_map();
+        for &(stable_id, doctest_index) in tcx.coverage_crate_file_table(()) {
+            let file = source_map
+                .source_file_by_stable_id(stable_id)
+                .expect("file in the crate-wide coverage file table should be in the source map");
+            let key = match doctest_index {
+                Some(index) => GlobalFileKey::Doctest(stable_id, index),
+                None => GlobalFileKey::File(stable_id),
+            };
+            table.global_file_id_for_key(key, &file);
+        }
+        table
+    }
+
+    fn global_file_id_for_key(
+        &mut self,
+        key: GlobalFileKey,
+        file: &Arc<SourceFile>,
+    ) -> GlobalFileId {
         // Ensure the given file has a table entry, and get its index.
-        let entry = self.raw_file_table.entry(file.stable_id);
+        let entry = self.raw_file_table.entry(key);
         let raw_id = entry.index();
         entry.or_insert_with(|| Arc::clone(file));
 
@@ -155,6 +294,50 @@ fn global_file_id_for_file(&mut self, file: &Arc<SourceFile>) -> GlobalFileId {
         GlobalFileId::from_usize(raw_id + 1)
     }
 
+    fn global_file_id_for_file(&mut self, file: &Arc<SourceFile>) -> GlobalFileId {
+        self.global_file_id_for_key(GlobalFileKey::File(file.stable_id), file)
+    }
+
+    /// Like [`Self::global_file_id_for_file`], but if the function's body is
+    /// one of several doctests merged into the file, returns an ID that
+    /// stands for that doctest's original source instead.
+    fn global_file_id_for_body(&mut self, file: &Arc<SourceFile>, body_span: Span) -> GlobalFileId {
+        if let Some((stable_id, table)) = &self.doctest_lines
+            && *stable_id == file.stable_id
+            && let Some(line_index) = file.lookup_line(file.relative_position(body_span.lo()))
+            && let Some(index) = table.entry_index_for_line(line_index + 1)
+        {
+            let key = GlobalFileKey::Doctest(file.stable_id, index);
+            return self.global_file_id_for_key(key, file);
+        }
+        self.global_file_id_for_file(file)
+    }
+
+    /// Returns the ID of a file that was already added to the table, or `None`
+    /// if it wasn't.
+    fn interned_global_file_id(&self, file: &SourceFile) -> Option<GlobalFileId> {
+        let raw_id = self.raw_file_table.get_index_of(&GlobalFileKey::File(file.stable_id))?;
+        Some(GlobalFileId::from_usize(raw_id + 1))
+    }
+
+    fn source_file(&self, global_file_id: GlobalFileId) -> &Arc<SourceFile> {
+        // The working dir has ID 0, and isn't in the raw file table.
+        &self.raw_file_table[global_file_id.as_usize() - 1]
+    }
+
+    /// Returns the offset to add to line numbers in the given file, which is
+    /// only nonzero for a doctest from a file of merged doctests.
+    fn line_offset(&self, global_file_id: GlobalFileId) -> isize {
+        let (key, _) = self.raw_file_table.get_index(global_file_id.as_usize() - 1).unwrap();
+        self.doctest_entry(key).map_or(0, |entry| entry.line_offset)
+    }
+
+    fn doctest_entry(&self, key: &GlobalFileKey) -> Option<&DoctestLines> {
+        let &GlobalFileKey::Doctest(_, index) = key else { return None };
+        let (_, table) = self.doctest_lines.as_ref()?;
+        Some(table.entry(index))
+    }
+
     fn make_filenames_buffer(&self, tcx: TyCtxt<'_>) -> Vec<u8> {
         let mut table = Vec::with_capacity(self.raw_file_table.len() + 1);
 
@@ -172,7 +355,13 @@ fn make_filenames_buffer(&self, tcx: TyCtxt<'_>) -> Vec<u8> {
         );
 
         // Add the regular entries after the base directory.
-        table.extend(self.raw_file_table.values().map(|file| {
+        table.extend(self.raw_file_table.iter().map(|(key, file)| {
+            if let Some(entry) = self.doctest_entry(key) {
+                return entry.path.to_string_lossy();
+            }
+            if let Some(local_path) = sysroot_local_path(tcx.sess, &file.name) {
+                return Cow::Owned(local_path.to_string_lossy().into_owned());
+            }
             file.name.for_scope(tcx.sess, RemapPathScopeComponents::MACRO).to_string_lossy()
         }));
 
@@ -180,6 +369,66 @@ fn make_filenames_buffer(&self, tcx: TyCtxt<'_>) -> Vec<u8> {
     }
 }
 
+/// Reads the table given by `-Zdoctest-line-table`, if any, and finds the
+/// crate's own source file, which is where rustdoc merged the doctests.
+fn read_doctest_lines(tcx: TyCtxt<'_>) -> Option<(StableSourceFileId, DoctestLineTable)> {
+    let path = tcx.sess.opts.unstable_opts.doctest_line_table.as_deref()?;
+    let table = DoctestLineTable::read(path).unwrap_or_else(|err| {
+        tcx.dcx().emit_fatal(crate::errors::ReadDoctestLineTable { path, err })
+    });
+
+    let crate_path = tcx.sess.io.input.opt_path();
+    let stable_id = tcx.sess.source_map().files().iter().find_map(|file| {
+        matches!(&file.name, FileName::Real(name) if name.local_path() == crate_path)
+            .then_some(file.stable_id)
+    })?;
+    Some((stable_id, table))
+}
+
+/// If `-Zcoverage-options=sysroot-local-paths` is enabled, and the given file
+/// is a standard library source file whose path was remapped to the virtual
+/// `/rustc/$hash` directory, returns the path of that file in the local
+/// `rust-src` component instead.
+///
+/// User-requested remappings (via `--remap-path-prefix`) still take priority,
+/// unless they merely remap the sources back to the virtual directory, as
+/// happens when building the standard library with `-Zbuild-std`.
+fn sysroot_local_path(sess: &Session, name: &FileName) -> Option<PathBuf> {
+    if !sess.coverage_sysroot_local_paths() {
+        return None;
+    }
+    let real_dir = sess.opts.real_rust_source_base_dir.as_deref()?;
+    let FileName::Real(RealFileName::Remapped { local_path, virtual_name }) = name else {
+        return None;
+    };
+
+    let virtual_dirs = [
+        option_env!("CFG_VIRTUAL_RUST_SOURCE_BASE_DIR").map(Path::new),
+        sess.opts.unstable_opts.simulate_remapped_rust_src_base.as_deref(),
+    ];
+    let is_virtual = |path: &Path| virtual_dirs.iter().flatten().any(|dir| path.starts_with(dir));
+    if !is_virtual(virtual_name) {
+        return None;
+    }
+
+    let local_path = match local_path {
+        // The file was compiled locally (e.g. by `-Zbuild-std`) from the
+        // `rust-src` component, so we already know its real path.
+        Some(local_path) if local_path.starts_with(real_dir) => local_path.clone(),
+        // Otherwise, translate the virtual path back into the `rust-src` component.
+        _ => virtual_dirs
+            .iter()
+            .flatten()
+            .find_map(|dir| virtual_name.strip_prefix(dir).ok())
+            .map(|rest| real_dir.join(rest))?,
+    };
+
+    // Respect any user remapping that doesn't just lead back to `/rustc/$hash`.
+    let (remapped, applied) = sess.source_map().path_mapping().map_prefix(local_path.as_path());
+    let remapped = (applied && !is_virtual(&remapped)).then(|| remapped.into_owned());
+    Some(remapped.unwrap_or(local_path))
+}
+
 rustc_index::newtype_index! {
     /// An index into the CGU's overall list of file paths. The underlying paths
     /// will be embedded in the `__llvm_covmap` linker section.
@@ -208,6 +457,14 @@ fn local_id_for_global(&mut self, global_file_id: GlobalFileId) -> LocalFileId {
             .or_insert_with(|| self.local_to_global.push(global_file_id))
     }
 
+    /// Adds a new local file ID for the given global file, even if the global
+    /// file already has one. Each macro expansion needs a virtual file of its
+    /// own, so that its regions aren't mixed up with other regions in the
+    /// same source file.
+    fn push_expansion_file(&mut self, global_file_id: GlobalFileId) -> LocalFileId {
+        self.local_to_global.push(global_file_id)
+    }
+
     fn to_vec(&self) -> Vec<u32> {
         // This clone could be avoided by transmuting `&[GlobalFileId]` to `&[u32]`,
         // but it isn't hot or expensive enough to justify the extra unsafety.
@@ -218,7 +475,17 @@ fn to_vec(&self) -> Vec<u32> {
 /// Generates the contents of the covmap record for this CGU, which mostly
 /// consists of a header and a list of filenames. The record is then stored
 /// as a global variable in the `__llvm_covmap` section.
-fn generate_covmap_record<'ll>(cx: &CodegenCx<'ll, '_>, version: u32, filenames_buffer: &[u8]) {
+///
+/// If every CGU was given the same crate-wide file table, their covmap records
+/// are identical, so the record is emitted as a mergeable global named after
+/// the filenames hash, and the linker only keeps one copy of it.
+fn generate_covmap_record<'ll>(
+    cx: &CodegenCx<'ll, '_>,
+    version: u32,
+    filenames_buffer: &[u8],
+    filenames_hash: u64,
+    shared_file_table: bool,
+) {
     // A covmap record consists of four target-endian u32 values, followed by
     // the encoded filenames table. Two of the header fields are unused in
     // modern versions of the LLVM coverage mapping format, and are always 0.
@@ -236,11 +503,23 @@ fn generate_covmap_record<'ll>(cx: &CodegenCx<'ll, '_>, version: u32, filenames_
     let covmap_record = cx
         .const_struct(&[covmap_header, cx.const_bytes(filenames_buffer)], /* packed */ false);
 
-    let covmap_global =
-        llvm::add_global(cx.llmod, cx.val_ty(covmap_record), &llvm_cov::covmap_var_name());
+    let covmap_var_name = if shared_file_table {
+        CString::new(format!("__covmap_{filenames_hash:X}")).unwrap()
+    } else {
+        llvm_cov::covmap_var_name()
+    };
+    let covmap_global = llvm::add_global(cx.llmod, cx.val_ty(covmap_record), &covmap_var_name);
     llvm::set_initializer(covmap_global, covmap_record);
     llvm::set_global_constant(covmap_global, true);
-    llvm::set_linkage(covmap_global, llvm::Linkage::PrivateLinkage);
+    if shared_file_table {
+        llvm::set_linkage(covmap_global, llvm::Linkage::LinkOnceODRLinkage);
+        llvm::set_visibility(covmap_global, llvm::Visibility::Hidden);
+        if cx.target_spec().supports_comdat() {
+            llvm::set_comdat(cx.llmod, covmap_global, &covmap_var_name);
+        }
+    } else {
+        llvm::set_linkage(covmap_global, llvm::Linkage::PrivateLinkage);
+    }
     llvm::set_section(covmap_global, &llvm_cov::covmap_section_name(cx.llmod));
     // LLVM's coverage mapping format specifies 8-byte alignment for items in this section.
     // <https://llvm.org/docs/CoverageMappingFormat.html>
@@ -249,18 +528,114 @@ fn generate_covmap_record<'ll>(cx: &CodegenCx<'ll, '_>, version: u32, filenames_
     cx.add_used_global(covmap_global);
 }
 
+/// Emits one of rustc's own per-function side records, such as a covmeta or
+/// covregion record, as a constant struct of `fields` in `section`, named
+/// `{prefix}_{func_name_hash:X}`.
+///
+/// If `shared` is true, instances used by several CGUs share one record, as
+/// with covfun records. Otherwise each CGU keeps its own private record.
+fn emit_side_record<'ll>(
+    cx: &CodegenCx<'ll, '_>,
+    prefix: &str,
+    func_name_hash: u64,
+    section: &CStr,
+    fields: &[&'ll llvm::Value],
+    shared: bool,
+) {
+    let record = cx.const_struct(fields, /* packed */ false);
+
+    let var_name = CString::new(format!("{prefix}_{func_name_hash:X}")).unwrap();
+    let global = llvm::add_global(cx.llmod, cx.val_ty(record), &var_name);
+    llvm::set_initializer(global, record);
+    llvm::set_global_constant(global, true);
+    if shared {
+        llvm::set_linkage(global, llvm::Linkage::LinkOnceODRLinkage);
+        llvm::set_visibility(global, llvm::Visibility::Hidden);
+        if cx.target_spec().supports_comdat() {
+            llvm::set_comdat(cx.llmod, global, &var_name);
+        }
+    } else {
+        llvm::set_linkage(global, llvm::Linkage::PrivateLinkage);
+    }
+    llvm::set_section(global, section);
+    llvm::set_alignment(global, Align::EIGHT);
+
+    cx.add_used_global(global);
+}
+
 /// Each CGU will normally only emit coverage metadata for the functions that it actually generates.
 /// But since we don't want unused functions to disappear from coverage reports, we also scan for
 /// functions that were instrumented but are not participating in codegen.
 ///
 /// These unused functions don't need to be codegenned, but we do need to add them to the function
-/// coverage map (in a single designated CGU) so that we still emit coverage mappings for them.
-/// We also end up adding their symbol names to a special global array that LLVM will include in
-/// its embedded coverage data.
+/// coverage map so that we still emit coverage mappings for them. We also end up adding their
+/// symbol names to a special global array that LLVM will include in its embedded coverage data.
+///
+/// Deciding which functions are unused requires looking at every CGU, so that is done once by
+/// the `coverage_unused_functions` query. Building their records is then shared out between all
+/// CGUs, so that it happens on the codegen threads in parallel, instead of in one CGU.
 fn gather_unused_function_instances<'tcx>(cx: &CodegenCx<'_, 'tcx>) -> Vec<ty::Instance<'tcx>> {
-    assert!(cx.codegen_unit.is_code_coverage_dead_code_cgu());
-
     let tcx = cx.tcx;
+    let (_, cgus) = tcx.collect_and_partition_mono_items(());
+    let num_cgus = cgus.len() as u64;
+    let cgu_index = cgus
+        .iter()
+        .position(|cgu| cgu.name() == cx.codegen_unit.name())
+        .expect("codegen unit should have been partitioned") as u64;
+
+    // Assign each function to a CGU by the stable hash of its def-path, so
+    // that under incremental compilation, adding or removing one unused
+    // function doesn't move the records of all the others.
+    let is_in_this_cgu = |def_id: LocalDefId| {
+        tcx.def_path_hash(def_id.to_def_id()).local_hash().as_u64() % num_cgus == cgu_index
+    };
+
+    tcx.coverage_unused_functions(())
+        .iter()
+        .copied()
+        .filter(|&def_id| is_in_this_cgu(def_id))
+        .map(|def_id| make_dummy_instance(tcx, def_id))
+        .collect::<Vec<_>>()
+}
+
+/// Query implementation for `coverage_crate_file_table`.
+pub(crate) fn coverage_crate_file_table<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    (): (),
+) -> &'tcx [(StableSourceFileId, Option<usize>)] {
+    let (_, cgus) = tcx.collect_and_partition_mono_items(());
+
+    // Intern the files of every function that any CGU could give a covfun
+    // record, in symbol-name order like the per-CGU tables, so that the order
+    // of the table stays deterministic.
+    let used_instances = cgus
+        .iter()
+        .flat_map(|cgu| cgu.items().keys())
+        .filter_map(|item| match *item {
+            mir::mono::MonoItem::Fn(instance) => Some(instance),
+            mir::mono::MonoItem::Static(_) | mir::mono::MonoItem::GlobalAsm(_) => None,
+        })
+        .unique()
+        .sorted_by_cached_key(|&instance| tcx.symbol_name(instance).name);
+    let unused_instances = tcx
+        .coverage_unused_functions(())
+        .iter()
+        .map(|&def_id| make_dummy_instance(tcx, def_id))
+        .sorted_by_cached_key(|&instance| tcx.symbol_name(instance).name);
+
+    let mut global_file_table = GlobalFileTable::new(tcx);
+    for instance in used_instances.chain(unused_instances) {
+        covfun::intern_source_file(tcx, &mut global_file_table, instance);
+    }
+
+    tcx.arena.alloc_from_iter(global_file_table.raw_file_table.keys().map(|&key| match key {
+        GlobalFileKey::File(stable_id) => (stable_id, None),
+        GlobalFileKey::Doctest(stable_id, index) => (stable_id, Some(index)),
+    }))
+}
+
+/// Query implementation for `coverage_unused_functions`.
+pub(crate) fn coverage_unused_functions<'tcx>(tcx: TyCtxt<'tcx>, (): ()) -> &'tcx [LocalDefId] {
     let usage = prepare_usage_sets(tcx);
 
     let is_unused_fn = |def_id: LocalDefId| -> bool {
@@ -268,10 +643,11 @@ fn gather_unused_function_instances<'tcx>(cx: &CodegenCx<'_, 'tcx>) -> Vec<ty::I
         let d: DefId = LocalDefId::to_def_id(def_id);
         // To be potentially eligible for "unused function" mappings, a definition must:
         // - Be eligible for coverage instrumentation
-        // - Not participate directly in codegen (or have lost all its coverage statements)
+        // - Not be codegenned as itself (or have lost all its coverage statements);
+        //   only being the target of a shim (e.g. a vtable entry) doesn't count
         // - Not have any coverage statements inlined into codegenned functions
         tcx.is_eligible_for_coverage(def_id)
-            && (!usage.all_mono_items.contains(&d) || usage.missing_own_coverage.contains(&d))
+            && (!usage.codegenned_items.contains(&d) || usage.missing_own_coverage.contains(&d))
             && !usage.used_via_inlining.contains(&d)
     };
 
@@ -280,50 +656,50 @@ fn gather_unused_function_instances<'tcx>(cx: &CodegenCx<'_, 'tcx>) -> Vec<ty::I
     // "unused instantiation" in coverage reports even when they are actually
     // used by some downstream crate in the same binary.
 
-    tcx.mir_keys(())
-        .iter()
-        .copied()
-        .filter(|&def_id| is_unused_fn(def_id))
-        .map(|def_id| make_dummy_instance(tcx, def_id))
-        .collect::<Vec<_>>()
+    tcx.arena
+        .alloc_from_iter(tcx.mir_keys(()).iter().copied().filter(|&def_id| is_unused_fn(def_id)))
 }
 
-struct UsageSets<'tcx> {
-    all_mono_items: &'tcx DefIdSet,
+struct UsageSets {
+    /// Functions that are codegenned as themselves, rather than only appearing
+    /// as the target of a shim (e.g. a vtable shim or reify shim).
+    codegenned_items: FxHashSet<DefId>,
     used_via_inlining: FxHashSet<DefId>,
     missing_own_coverage: FxHashSet<DefId>,
 }
 
 /// Prepare sets of definitions that are relevant to deciding whether something
 /// is an "unused function" for coverage purposes.
-fn prepare_usage_sets<'tcx>(tcx: TyCtxt<'tcx>) -> UsageSets<'tcx> {
-    let (all_mono_items, cgus) = tcx.collect_and_partition_mono_items(());
+fn prepare_usage_sets<'tcx>(tcx: TyCtxt<'tcx>) -> UsageSets {
+    let (_, cgus) = tcx.collect_and_partition_mono_items(());
 
-    // Obtain a MIR body for each function participating in codegen, via an
-    // arbitrary instance.
-    let mut def_ids_seen = FxHashSet::default();
-    let def_and_mir_for_all_mono_fns = cgus
-        .iter()
-        .flat_map(|cgu| cgu.items().keys())
-        .filter_map(|item| match item {
+    let all_mono_fns =
+        cgus.iter().flat_map(|cgu| cgu.items().keys()).filter_map(|item| match item {
             mir::mono::MonoItem::Fn(instance) => Some(instance),
             mir::mono::MonoItem::Static(_) | mir::mono::MonoItem::GlobalAsm(_) => None,
-        })
-        // We only need one arbitrary instance per definition.
-        .filter(move |instance| def_ids_seen.insert(instance.def_id()))
-        .map(|instance| {
-            // We don't care about the instance, just its underlying MIR.
-            let body = tcx.instance_mir(instance.def);
-            (instance.def_id(), body)
         });
 
+    // Functions that are codegenned as themselves.
+    let mut codegenned_items = FxHashSet::default();
     // Functions whose coverage statements were found inlined into other functions.
     let mut used_via_inlining = FxHashSet::default();
     // Functions that were instrumented, but had all of their coverage statements
     // removed by later MIR transforms (e.g. UnreachablePropagation).
     let mut missing_own_coverage = FxHashSet::default();
 
-    for (def_id, body) in def_and_mir_for_all_mono_fns {
+    // We only need the MIR body of one arbitrary instance per definition and
+    // kind of instance. Shims (e.g. for methods that are only reachable through
+    // a vtable) have a different body from the item they were made for, so
+    // they mustn't stand in for that item when checking for own coverage.
+    let mut instances_seen = FxHashSet::default();
+    for instance in all_mono_fns {
+        let def_id = instance.def_id();
+        let is_item = matches!(instance.def, ty::InstanceKind::Item(_));
+        if !instances_seen.insert((def_id, is_item)) {
+            continue;
+        }
+        let body = tcx.instance_mir(instance.def);
+
         let mut saw_own_coverage = false;
 
         // Inspect every coverage statement in the function's MIR.
@@ -342,12 +718,15 @@ fn prepare_usage_sets<'tcx>(tcx: TyCtxt<'tcx>) -> UsageSets<'tcx> {
             }
         }
 
-        if !saw_own_coverage && body.function_coverage_info.is_some() {
-            missing_own_coverage.insert(def_id);
+        if is_item {
+            codegenned_items.insert(def_id);
+            if !saw_own_coverage && body.function_coverage_info.is_some() {
+                missing_own_coverage.insert(def_id);
+            }
         }
     }
 
-    UsageSets { all_mono_items, used_via_inlining, missing_own_coverage }
+    UsageSets { codegenned_items, used_via_inlining, missing_own_coverage }
 }
 
 fn make_dummy_instance<'tcx>(tcx: TyCtxt<'tcx>, local_def_id: LocalDefId) -> ty::Instance<'tcx> {
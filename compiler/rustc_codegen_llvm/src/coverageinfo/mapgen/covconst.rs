@@ -0,0 +1,90 @@
+//! Under `-Zcoverage-options=const-eval`, the counters that const evaluation
+//! incremented in this crate's functions are written to a text profile next to
+//! the crate's other outputs, in the format accepted by `llvm-profdata merge`.
+//! Merging it with the runtime profiles attributes compile-time execution to
+//! the same coverage regions as runtime execution.
+
+use std::fmt::Write as _;
+
+use itertools::Itertools;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def_id::LocalDefId;
+use rustc_index::IndexVec;
+use rustc_middle::mir::coverage::CounterId;
+use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::ty::{self, Instance};
+use tracing::debug;
+
+use crate::common::CodegenCx;
+use crate::coverageinfo::coverage_function_name;
+use crate::coverageinfo::mapgen::make_dummy_instance;
+
+pub(crate) fn write_const_eval_profile<'tcx>(
+    cx: &CodegenCx<'_, 'tcx>,
+    counts: &FxHashMap<LocalDefId, IndexVec<CounterId, u64>>,
+) {
+    let tcx = cx.tcx;
+
+    // A function's counters are named after one of its codegenned instances,
+    // so that they merge with that instance's runtime counters. Functions that
+    // weren't codegenned are named after the dummy instance that their
+    // "unused" covfun record was made for.
+    let (_, cgus) = tcx.collect_and_partition_mono_items(());
+    let mut codegenned_instances = FxHashMap::<LocalDefId, Instance<'tcx>>::default();
+    for item in cgus.iter().flat_map(|cgu| cgu.items().keys()) {
+        let MonoItem::Fn(instance) = *item else { continue };
+        let ty::InstanceKind::Item(def_id) = instance.def else { continue };
+        let Some(def_id) = def_id.as_local() else { continue };
+        if !counts.contains_key(&def_id) {
+            continue;
+        }
+        let name = tcx.symbol_name(instance).name;
+        codegenned_instances
+            .entry(def_id)
+            .and_modify(|prev| {
+                if name < tcx.symbol_name(*prev).name {
+                    *prev = instance;
+                }
+            })
+            .or_insert(instance);
+    }
+
+    let mut profile = String::new();
+    let records = counts.iter().map(|(&def_id, counters)| {
+        let (instance, num_counters) = match codegenned_instances.get(&def_id) {
+            // The runtime profile only has the counters that survived MIR
+            // optimizations, and `llvm-profdata` rejects records whose number
+            // of counters disagree.
+            Some(&instance) => {
+                let num_counters = tcx.coverage_ids_info(instance).num_counters_after_mir_opts();
+                (instance, num_counters as usize)
+            }
+            None => (make_dummy_instance(tcx, def_id), counters.len()),
+        };
+        (
+            coverage_function_name(tcx, instance),
+            instance,
+            &counters.raw[..num_counters.min(counters.len())],
+        )
+    });
+    for (name, instance, counters) in records.sorted_by_key(|&(name, ..)| name) {
+        let Some(fn_cov_info) = tcx.instance_mir(instance.def).function_coverage_info.as_deref()
+        else {
+            continue;
+        };
+        debug!(?instance, ?counters, "const-eval coverage");
+        writeln!(profile, "{name}").unwrap();
+        writeln!(profile, "# Func Hash:\n{}", fn_cov_info.function_source_hash).unwrap();
+        writeln!(profile, "# Num Counters:\n{}", counters.len()).unwrap();
+        writeln!(profile, "# Counter Values:").unwrap();
+        for count in counters {
+            writeln!(profile, "{count}").unwrap();
+        }
+        writeln!(profile).unwrap();
+    }
+
+    let path = tcx.output_filenames(()).with_extension("const-eval.proftext");
+    if let Err(err) = std::fs::write(&path, profile) {
+        tcx.dcx().emit_err(crate::errors::WriteConstEvalProfile { path: &path, err });
+    }
+}
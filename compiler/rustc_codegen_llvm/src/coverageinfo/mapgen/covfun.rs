@@ -5,22 +5,30 @@
 //! [^win]: On Windows the section name is `.lcovfun`.
 
 use std::ffi::CString;
+use std::sync::Arc;
 
 use rustc_abi::Align;
 use rustc_codegen_ssa::traits::{
     BaseTypeCodegenMethods, ConstCodegenMethods, StaticCodegenMethods,
 };
+use rustc_data_structures::fx::FxHashMap;
+use rustc_index::IndexVec;
 use rustc_middle::mir::coverage::{
-    CovTerm, CoverageIdsInfo, Expression, FunctionCoverageInfo, Mapping, MappingKind, Op,
+    CovTerm, CoverageIdsInfo, ExpansionMapping, Expression, ExpressionId, FunctionCoverageInfo,
+    Mapping, MappingKind, Op,
 };
 use rustc_middle::ty::{Instance, TyCtxt};
-use rustc_span::Span;
+use rustc_session::code_stats::CoverageFnStats;
+use rustc_span::source_map::SourceMap;
+use rustc_span::{SourceFile, Span};
 use rustc_target::spec::HasTargetSpec;
 use tracing::debug;
 
 use crate::common::CodegenCx;
-use crate::coverageinfo::mapgen::{GlobalFileTable, VirtualFileMapping, spans};
-use crate::coverageinfo::{ffi, llvm_cov};
+use crate::coverageinfo::mapgen::{
+    GlobalFileId, GlobalFileTable, LocalFileId, VirtualFileMapping, spans,
+};
+use crate::coverageinfo::{coverage_function_name, ffi, llvm_cov};
 use crate::llvm;
 
 /// Intermediate coverage metadata for a single function, used to help build
@@ -30,6 +38,7 @@ pub(crate) struct CovfunRecord<'tcx> {
     mangled_function_name: &'tcx str,
     source_hash: u64,
     is_used: bool,
+    has_runtime_counters: bool,
 
     virtual_file_mapping: VirtualFileMapping,
     expressions: Vec<ffi::CounterExpression>,
@@ -40,31 +49,84 @@ impl<'tcx> CovfunRecord<'tcx> {
     /// FIXME(Zalathar): Make this the responsibility of the code that determines
     /// which functions are unused.
     pub(crate) fn mangled_function_name_if_unused(&self) -> Option<&'tcx str> {
-        (!self.is_used).then_some(self.mangled_function_name)
+        (!self.has_runtime_counters).then_some(self.mangled_function_name)
+    }
+
+    pub(crate) fn mangled_function_name(&self) -> &'tcx str {
+        self.mangled_function_name
     }
+
+    pub(crate) fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
+    /// Yields `(counter ID, global file ID, span)` for each code region that
+    /// is counted directly by one of this function's physical counters.
+    /// Records for functions that have no runtime counters yield nothing.
+    pub(crate) fn physical_counter_regions(
+        &self,
+    ) -> impl Iterator<Item = (u32, u32, &ffi::CoverageSpan)> + '_ {
+        let code_regions =
+            if self.has_runtime_counters { &self.regions.code_regions[..] } else { &[] };
+        code_regions.iter().filter_map(|region| {
+            let counter_id = region.counter.physical_counter_id()?;
+            let local_file_id = LocalFileId::from_u32(region.cov_span.file_id);
+            let global_file_id = self.virtual_file_mapping.local_to_global[local_file_id];
+            Some((counter_id, global_file_id.as_u32(), &region.cov_span))
+        })
+    }
+}
+
+/// How a function that has a covfun record was used, which determines
+/// whether its record refers to real counters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FunctionUsage {
+    /// The function was codegenned, so its counters are in the runtime profile.
+    Used,
+    /// The function was never codegenned, so all of its counts are zero.
+    Unused,
+    /// The function was never codegenned, but ran during const evaluation
+    /// under `-Zcoverage-options=const-eval`, so its counters only appear in
+    /// the const-eval profile.
+    ConstEvalOnly,
 }
 
 pub(crate) fn prepare_covfun_record<'tcx>(
     tcx: TyCtxt<'tcx>,
-    global_file_table: &mut GlobalFileTable,
+    global_file_table: &GlobalFileTable,
+    global_file_id: GlobalFileId,
     instance: Instance<'tcx>,
-    is_used: bool,
+    usage: FunctionUsage,
 ) -> Option<CovfunRecord<'tcx>> {
+    let is_used = usage != FunctionUsage::Unused;
     let fn_cov_info = tcx.instance_mir(instance.def).function_coverage_info.as_deref()?;
-    let ids_info = tcx.coverage_ids_info(instance.def);
+    let ids_info = tcx.coverage_ids_info(instance);
 
-    let expressions = prepare_expressions(fn_cov_info, ids_info, is_used);
+    let (expressions, expression_terms) = prepare_expressions(fn_cov_info, ids_info, is_used);
 
     let mut covfun = CovfunRecord {
-        mangled_function_name: tcx.symbol_name(instance).name,
+        mangled_function_name: coverage_function_name(tcx, instance),
         source_hash: if is_used { fn_cov_info.function_source_hash } else { 0 },
         is_used,
+        has_runtime_counters: usage == FunctionUsage::Used,
         virtual_file_mapping: VirtualFileMapping::default(),
         expressions,
         regions: ffi::Regions::default(),
     };
 
-    fill_region_tables(tcx, global_file_table, fn_cov_info, ids_info, &mut covfun);
+    fill_region_tables(
+        tcx,
+        global_file_table,
+        global_file_id,
+        fn_cov_info,
+        ids_info,
+        &expression_terms,
+        &mut covfun,
+    );
+
+    if tcx.sess.coverage_line_only() {
+        spans::collapse_regions_to_lines(&mut covfun.regions);
+    }
 
     if covfun.regions.has_no_regions() {
         debug!(?covfun, "function has no mappings to embed; skipping");
@@ -74,73 +136,221 @@ pub(crate) fn prepare_covfun_record<'tcx>(
     Some(covfun)
 }
 
+/// Interns the file containing the function's coverage mappings in the global
+/// file table, without preparing the mappings themselves. Returns `None` if
+/// the function has no coverage info.
+pub(crate) fn intern_source_file<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    global_file_table: &mut GlobalFileTable,
+    instance: Instance<'tcx>,
+) -> Option<GlobalFileId> {
+    let fn_cov_info = tcx.instance_mir(instance.def).function_coverage_info.as_deref()?;
+    // Apart from the code in macro expansions and in files pulled in by
+    // `include!`, a function's mappings must all be in the same file as its
+    // body span.
+    let source_map = tcx.sess.source_map();
+    let source_file = source_map.lookup_source_file(fn_cov_info.body_span.lo());
+    let global_file_id =
+        global_file_table.global_file_id_for_body(&source_file, fn_cov_info.body_span);
+
+    for &Mapping { ref kind, span } in &fn_cov_info.mappings {
+        if matches!(kind, MappingKind::Code(_)) && !source_file.contains(span.lo()) {
+            let included_file = source_map.lookup_source_file(span.lo());
+            global_file_table.global_file_id_for_file(&included_file);
+        }
+    }
+
+    for expansion in
+        fn_cov_info.expansion_mappings.iter().chain(&fn_cov_info.macro_definition_mappings)
+    {
+        if let Some(expansion_file) = expansion_source_file(source_map, expansion) {
+            global_file_table.global_file_id_for_file(&expansion_file);
+        }
+    }
+
+    Some(global_file_id)
+}
+
+/// Returns the file containing the macro definition that an expansion's code
+/// mappings point into. Those mappings are all expected to be in one file, so
+/// the file of the first mapping is used.
+fn expansion_source_file(
+    source_map: &SourceMap,
+    expansion: &ExpansionMapping,
+) -> Option<Arc<SourceFile>> {
+    let first_span = expansion.mappings.first()?.span;
+    Some(source_map.lookup_source_file(first_span.lo()))
+}
+
+/// Converts a code span in a file that `include!` pulled into the function's
+/// body, using the included file's local file ID. The file is only added to the
+/// mapping once its span has been converted, to avoid empty virtual files.
+fn make_included_cov_span(
+    source_map: &SourceMap,
+    global_file_table: &GlobalFileTable,
+    virtual_file_mapping: &mut VirtualFileMapping,
+    fn_cov_info: &FunctionCoverageInfo,
+    char_columns: bool,
+    span: Span,
+) -> Option<ffi::CoverageSpan> {
+    // Empty spans can't be widened here, because that is only done within the
+    // bounds of the body span.
+    if span.is_empty() {
+        return None;
+    }
+    let included_file = source_map.lookup_source_file(span.lo());
+    if !included_file.contains(span.hi()) {
+        return None;
+    }
+    let global_file_id = global_file_table.interned_global_file_id(&included_file)?;
+
+    let mut cov_span = spans::make_coverage_span(
+        LocalFileId::ZERO,
+        source_map,
+        fn_cov_info,
+        &included_file,
+        0,
+        char_columns,
+        span,
+    )?;
+    cov_span.file_id = virtual_file_mapping.local_id_for_global(global_file_id).as_u32();
+    Some(cov_span)
+}
+
 /// Convert the function's coverage-counter expressions into a form suitable for FFI.
+///
+/// Also returns the term that should replace each of the function's original
+/// expressions wherever it is referred to, since identical expressions are
+/// only converted once.
 fn prepare_expressions(
     fn_cov_info: &FunctionCoverageInfo,
     ids_info: &CoverageIdsInfo,
     is_used: bool,
-) -> Vec<ffi::CounterExpression> {
-    // If any counters or expressions were removed by MIR opts, replace their
-    // terms with zero.
-    let counter_for_term = |term| {
-        if !is_used || ids_info.is_zero_term(term) {
-            ffi::Counter::ZERO
-        } else {
-            ffi::Counter::from_term(term)
-        }
-    };
+) -> (Vec<ffi::CounterExpression>, IndexVec<ExpressionId, CovTerm>) {
+    let mut expressions = vec![];
+    let mut expression_terms = IndexVec::with_capacity(fn_cov_info.expressions.len());
 
+    // If any counters or expressions were removed by MIR opts, their terms are
+    // replaced with zero, which can leave several expressions identical, so
+    // only the first of each is kept. The mapping format has no table of
+    // expressions shared between functions, so this can only be done within
+    // each function. Expressions only refer to earlier expressions, so their
+    // operands have already been interned.
+    //
     // We know that LLVM will optimize out any unused expressions before
     // producing the final coverage map, so there's no need to do the same
     // thing on the Rust side unless we're confident we can do much better.
     // (See `CounterExpressionsMinimizer` in `CoverageMappingWriter.cpp`.)
-    fn_cov_info
-        .expressions
-        .iter()
-        .map(move |&Expression { lhs, op, rhs }| ffi::CounterExpression {
-            lhs: counter_for_term(lhs),
-            kind: match op {
-                Op::Add => ffi::ExprKind::Add,
-                Op::Subtract => ffi::ExprKind::Subtract,
-            },
-            rhs: counter_for_term(rhs),
-        })
-        .collect::<Vec<_>>()
+    let mut interned = FxHashMap::<(CovTerm, Op, CovTerm), ExpressionId>::default();
+    for &Expression { lhs, op, rhs } in &fn_cov_info.expressions {
+        let lhs = new_term(ids_info, is_used, &expression_terms, lhs);
+        let rhs = new_term(ids_info, is_used, &expression_terms, rhs);
+        let key = (lhs, op, rhs);
+        let id = *interned.entry(key).or_insert_with(|| {
+            let id = ExpressionId::from_usize(expressions.len());
+            expressions.push(ffi::CounterExpression {
+                lhs: ffi::Counter::from_term(lhs),
+                kind: match op {
+                    Op::Add => ffi::ExprKind::Add,
+                    Op::Subtract => ffi::ExprKind::Subtract,
+                },
+                rhs: ffi::Counter::from_term(rhs),
+            });
+            id
+        });
+        expression_terms.push(CovTerm::Expression(id));
+    }
+
+    (expressions, expression_terms)
+}
+
+/// Returns the term to embed in place of one of the function's original terms.
+/// Counters and expressions that were removed by MIR opts become zero, and
+/// other expressions are renumbered as interned by [`prepare_expressions`].
+fn new_term(
+    ids_info: &CoverageIdsInfo,
+    is_used: bool,
+    expression_terms: &IndexVec<ExpressionId, CovTerm>,
+    term: CovTerm,
+) -> CovTerm {
+    match term {
+        _ if !is_used || ids_info.is_zero_term(term) => CovTerm::Zero,
+        CovTerm::Expression(id) => expression_terms[id],
+        CovTerm::Zero | CovTerm::Counter(_) => term,
+    }
 }
 
 /// Populates the mapping region tables in the current function's covfun record.
 fn fill_region_tables<'tcx>(
     tcx: TyCtxt<'tcx>,
-    global_file_table: &mut GlobalFileTable,
+    global_file_table: &GlobalFileTable,
+    global_file_id: GlobalFileId,
     fn_cov_info: &'tcx FunctionCoverageInfo,
     ids_info: &'tcx CoverageIdsInfo,
+    expression_terms: &IndexVec<ExpressionId, CovTerm>,
     covfun: &mut CovfunRecord<'tcx>,
 ) {
-    // Currently a function's mappings must all be in the same file as its body span.
+    // The function's file was already interned, so reuse it instead of
+    // looking it up in the source map again.
     let source_map = tcx.sess.source_map();
-    let source_file = source_map.lookup_source_file(fn_cov_info.body_span.lo());
-
-    // Look up the global file ID for that file.
-    let global_file_id = global_file_table.global_file_id_for_file(&source_file);
+    let source_file = global_file_table.source_file(global_file_id);
+    let line_offset = global_file_table.line_offset(global_file_id);
+    let char_columns = tcx.sess.coverage_char_columns();
 
     // Associate that global file ID with a local file ID for this function.
     let local_file_id = covfun.virtual_file_mapping.local_id_for_global(global_file_id);
 
-    let ffi::Regions { code_regions, branch_regions, mcdc_branch_regions, mcdc_decision_regions } =
-        &mut covfun.regions;
+    let ffi::Regions {
+        code_regions,
+        branch_regions,
+        mcdc_branch_regions,
+        mcdc_decision_regions,
+        expansion_regions,
+        gap_regions,
+        skipped_regions,
+    } = &mut covfun.regions;
 
     let make_cov_span = |span: Span| {
-        spans::make_coverage_span(local_file_id, source_map, fn_cov_info, &source_file, span)
+        spans::make_coverage_span(
+            local_file_id,
+            source_map,
+            fn_cov_info,
+            source_file,
+            line_offset,
+            char_columns,
+            span,
+        )
     };
     let discard_all = tcx.sess.coverage_discard_all_spans_in_codegen();
 
     // For each counter/region pair in this function+file, convert it to a
     // form suitable for FFI.
-    let is_zero_term = |term| !covfun.is_used || ids_info.is_zero_term(term);
+    let is_used = covfun.is_used;
     for &Mapping { ref kind, span } in &fn_cov_info.mappings {
         // If the mapping refers to counters/expressions that were removed by
-        // MIR opts, replace those occurrences with zero.
-        let kind = kind.map_terms(|term| if is_zero_term(term) { CovTerm::Zero } else { term });
+        // MIR opts, replace those occurrences with zero, and refer to the
+        // interned copies of the remaining expressions.
+        let kind = kind.map_terms(|term| new_term(ids_info, is_used, expression_terms, term));
+
+        // Code that `include!` pulled into the body is mapped against the
+        // included file, under a local file ID of its own.
+        if !source_file.contains(span.lo()) {
+            if let MappingKind::Code(term) = kind
+                && !discard_all
+                && let Some(cov_span) = make_included_cov_span(
+                    source_map,
+                    global_file_table,
+                    &mut covfun.virtual_file_mapping,
+                    fn_cov_info,
+                    char_columns,
+                    span,
+                )
+            {
+                code_regions
+                    .push(ffi::CodeRegion { cov_span, counter: ffi::Counter::from_term(term) });
+            }
+            continue;
+        }
 
         // Convert the `Span` into coordinates that we can pass to LLVM, or
         // discard the span if conversion fails. In rare, cases _all_ of a
@@ -174,12 +384,139 @@ fn fill_region_tables<'tcx>(
                     mcdc_branch_params: ffi::mcdc::BranchParameters::from(mcdc_params),
                 });
             }
+            // LLVM has no region kind for assertions, so they are embedded as
+            // branch regions, with the guard's failures as the false branch.
+            MappingKind::Assertion { passed_term, failed_term } => {
+                branch_regions.push(ffi::BranchRegion {
+                    cov_span,
+                    true_counter: ffi::Counter::from_term(passed_term),
+                    false_counter: ffi::Counter::from_term(failed_term),
+                });
+            }
             MappingKind::MCDCDecision(mcdc_decision_params) => {
                 mcdc_decision_regions.push(ffi::MCDCDecisionRegion {
                     cov_span,
                     mcdc_decision_params: ffi::mcdc::DecisionParameters::from(mcdc_decision_params),
                 });
             }
+            MappingKind::Gap(term) => {
+                gap_regions
+                    .push(ffi::GapRegion { cov_span, counter: ffi::Counter::from_term(term) });
+            }
+            MappingKind::Skipped => {
+                skipped_regions.push(ffi::SkippedRegion { cov_span });
+            }
+        }
+    }
+
+    // Under `-Zcoverage-options=expansions`, give each macro invocation a
+    // virtual file of its own, holding the code regions of its expansion, and
+    // link that file to the invocation with an expansion region.
+    for expansion in &fn_cov_info.expansion_mappings {
+        if discard_all {
+            break;
+        }
+        let Some(expansion_file) = expansion_source_file(source_map, expansion) else { continue };
+        let Some(expansion_global_file_id) =
+            global_file_table.interned_global_file_id(&expansion_file)
+        else {
+            continue;
+        };
+        let Some(call_site_cov_span) = make_cov_span(expansion.call_site) else { continue };
+
+        // The expansion's file is only added to the mapping once it's known
+        // to have regions, since an expansion region that points to an empty
+        // file would confuse `llvm-cov`.
+        let expanded_file_id =
+            LocalFileId::from_usize(covfun.virtual_file_mapping.local_to_global.len());
+        let mut expansion_code_regions = vec![];
+        for &Mapping { ref kind, span } in &expansion.mappings {
+            // Only ordinary code regions are extracted from expansions, and
+            // they must all be in the file containing the macro definition.
+            let &MappingKind::Code(term) = kind else { continue };
+            if span.is_empty()
+                || !expansion_file.contains(span.lo())
+                || !expansion_file.contains(span.hi())
+            {
+                continue;
+            }
+            let Some(cov_span) = spans::make_coverage_span(
+                expanded_file_id,
+                source_map,
+                fn_cov_info,
+                &expansion_file,
+                0,
+                char_columns,
+                span,
+            ) else {
+                continue;
+            };
+            let term = new_term(ids_info, is_used, expression_terms, term);
+            expansion_code_regions
+                .push(ffi::CodeRegion { cov_span, counter: ffi::Counter::from_term(term) });
+        }
+        if expansion_code_regions.is_empty() {
+            continue;
+        }
+
+        let pushed_file_id =
+            covfun.virtual_file_mapping.push_expansion_file(expansion_global_file_id);
+        debug_assert_eq!(pushed_file_id, expanded_file_id);
+        code_regions.extend(expansion_code_regions);
+        expansion_regions.push(ffi::ExpansionRegion {
+            cov_span: call_site_cov_span,
+            expanded_file_id: expanded_file_id.as_u32(),
+        });
+    }
+
+    // Under `-Zcoverage-options=macro-definitions`, also attribute the code in
+    // each expansion directly to the lines of the macro's definition. Regions
+    // with the same span from several invocations are added up by `llvm-cov`.
+    for expansion in &fn_cov_info.macro_definition_mappings {
+        if discard_all {
+            break;
+        }
+        let Some(definition_file) = expansion_source_file(source_map, expansion) else { continue };
+        let Some(definition_global_file_id) =
+            global_file_table.interned_global_file_id(&definition_file)
+        else {
+            continue;
+        };
+        // The definition's file is only added to the mapping once it's known
+        // to have regions, so its local ID is filled in afterwards.
+        let mut definition_code_regions = vec![];
+        for &Mapping { ref kind, span } in &expansion.mappings {
+            let &MappingKind::Code(term) = kind else { continue };
+            if span.is_empty()
+                || !definition_file.contains(span.lo())
+                || !definition_file.contains(span.hi())
+            {
+                continue;
+            }
+            let Some(cov_span) = spans::make_coverage_span(
+                LocalFileId::ZERO,
+                source_map,
+                fn_cov_info,
+                &definition_file,
+                0,
+                char_columns,
+                span,
+            ) else {
+                continue;
+            };
+            let term = new_term(ids_info, is_used, expression_terms, term);
+            definition_code_regions
+                .push(ffi::CodeRegion { cov_span, counter: ffi::Counter::from_term(term) });
+        }
+        if definition_code_regions.is_empty() {
+            continue;
+        }
+
+        let definition_file_id =
+            covfun.virtual_file_mapping.local_id_for_global(definition_global_file_id);
+        for mut region in definition_code_regions {
+            region.cov_span.file_id = definition_file_id.as_u32();
+            code_regions.push(region);
         }
     }
 }
@@ -187,15 +524,18 @@ fn fill_region_tables<'tcx>(
 /// Generates the contents of the covfun record for this function, which
 /// contains the function's coverage mapping data. The record is then stored
 /// as a global variable in the `__llvm_covfun` section.
+///
+/// Returns the size of the encoded mapping data, for `-Zcoverage-stats`.
 pub(crate) fn generate_covfun_record<'tcx>(
     cx: &CodegenCx<'_, 'tcx>,
     filenames_hash: u64,
     covfun: &CovfunRecord<'tcx>,
-) {
+) -> usize {
     let &CovfunRecord {
         mangled_function_name,
         source_hash,
         is_used,
+        has_runtime_counters: _,
         ref virtual_file_mapping,
         ref expressions,
         ref regions,
@@ -249,4 +589,40 @@ pub(crate) fn generate_covfun_record<'tcx>(
     }
 
     cx.add_used_global(covfun_global);
+
+    coverage_mapping_buffer.len()
+}
+
+/// Under `-Zcoverage-stats`, records the size of the function's coverage
+/// instrumentation and mappings, to be summarized once the whole crate has
+/// been codegenned.
+pub(crate) fn record_coverage_stats<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    instance: Instance<'tcx>,
+    usage: FunctionUsage,
+    covfun: &CovfunRecord<'tcx>,
+    mapping_bytes: usize,
+) {
+    let Some(fn_cov_info) = tcx.instance_mir(instance.def).function_coverage_info.as_deref() else {
+        return;
+    };
+    let counters = match usage {
+        FunctionUsage::Used => tcx.coverage_ids_info(instance).num_counters_after_mir_opts(),
+        FunctionUsage::Unused | FunctionUsage::ConstEvalOnly => 0,
+    };
+    let regions = &covfun.regions;
+
+    let stats = CoverageFnStats {
+        function_name: instance.to_string(),
+        bcbs: fn_cov_info.num_bcbs,
+        counters: counters as usize,
+        expressions: covfun.expressions.len(),
+        branch_regions: regions.branch_regions.len() + regions.mcdc_branch_regions.len(),
+        mcdc_decisions: regions.mcdc_decision_regions.len(),
+        mapping_bytes,
+    };
+    // Used and unused records are named differently, like their globals.
+    let u = if covfun.is_used { "u" } else { "" };
+    let record_name = format!("{}{u}", covfun.mangled_function_name);
+    tcx.sess.code_stats.record_coverage_fn(&record_name, stats);
 }
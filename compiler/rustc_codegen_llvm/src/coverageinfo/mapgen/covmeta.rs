@@ -0,0 +1,65 @@
+//! Under `-Zcoverage-options=counter-metadata`, each instrumented function
+//! gets a record in the `__rustc_covmeta` linker section, describing static
+//! properties of the coverage graph node or edge behind each of its physical
+//! counters.
+//!
+//! The record layout is documented in the unstable book's page on
+//! `-Zcoverage-options`.
+
+use rustc_codegen_ssa::traits::{BaseTypeCodegenMethods, ConstCodegenMethods};
+use rustc_middle::mir::coverage::CounterMetadata;
+use rustc_middle::ty::Instance;
+use tracing::debug;
+
+use crate::common::CodegenCx;
+use crate::coverageinfo::mapgen::emit_side_record;
+use crate::coverageinfo::{coverage_function_name, llvm_cov, rustc_cov_section_name};
+
+pub(crate) fn generate_covmeta_record<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    instance: Instance<'tcx>,
+) {
+    let tcx = cx.tcx;
+    let Some(fn_cov_info) = tcx.instance_mir(instance.def).function_coverage_info.as_deref() else {
+        return;
+    };
+    if fn_cov_info.counter_metadata.is_empty() {
+        return;
+    }
+
+    // Counters that were removed by MIR optimizations don't exist at runtime,
+    // so only describe the ones that remain.
+    let num_counters = tcx.coverage_ids_info(instance).num_counters_after_mir_opts();
+    let counter_metadata = &fn_cov_info.counter_metadata.raw[..num_counters as usize];
+
+    // Use the same name as the function's other records, so that the hash
+    // matches its `__llvm_prf_data` record under `per-instance` too.
+    let mangled_function_name = coverage_function_name(tcx, instance);
+    let func_name_hash = llvm_cov::hash_bytes(mangled_function_name.as_bytes());
+    debug!("generating covmeta record for {mangled_function_name} ({num_counters} counters)");
+
+    let i16_ty = cx.type_i16();
+    let entries = counter_metadata
+        .iter()
+        .map(|&CounterMetadata { loop_depth, fan_out }| {
+            let loop_depth = cx.const_uint(i16_ty, u64::from(loop_depth));
+            let fan_out = cx.const_uint(i16_ty, u64::from(fan_out));
+            cx.const_struct(&[loop_depth, fan_out], false)
+        })
+        .collect::<Vec<_>>();
+    let entry_ty = cx.type_struct(&[i16_ty, i16_ty], false);
+
+    emit_side_record(
+        cx,
+        "__covmeta",
+        func_name_hash,
+        rustc_cov_section_name(cx, c"__DATA,__rustc_covmeta", c".rcovmd$M", c"__rustc_covmeta"),
+        &[
+            cx.const_u64(func_name_hash),
+            cx.const_u64(fn_cov_info.function_source_hash),
+            cx.const_u64(u64::from(num_counters)),
+            cx.const_array(entry_ty, &entries),
+        ],
+        /* shared */ true,
+    );
+}
@@ -0,0 +1,90 @@
+//! Under `-Zcoverage-options=paths`, each used function that was path-profiled
+//! gets a record in the `__rustc_covpath` linker section, listing the source
+//! regions along each of its numbered acyclic paths. The runtime counts for
+//! those paths are in the `__rustc_covpathcnt` section.
+//!
+//! The record layouts are documented in the unstable book's page on
+//! `-Zcoverage-options`.
+
+use rustc_codegen_ssa::traits::{BaseTypeCodegenMethods, ConstCodegenMethods};
+use rustc_middle::mir::coverage::PathMapping;
+use rustc_middle::ty::Instance;
+use tracing::debug;
+
+use crate::common::CodegenCx;
+use crate::coverageinfo::mapgen::covfun::CovfunRecord;
+use crate::coverageinfo::mapgen::emit_side_record;
+use crate::coverageinfo::mapgen::{GlobalFileId, GlobalFileTable, LocalFileId, spans};
+use crate::coverageinfo::{llvm_cov, rustc_cov_section_name};
+
+pub(crate) fn generate_covpath_record<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    filenames_hash: u64,
+    global_file_table: &GlobalFileTable,
+    global_file_id: GlobalFileId,
+    instance: Instance<'tcx>,
+    covfun: &CovfunRecord<'tcx>,
+) {
+    let tcx = cx.tcx;
+    let Some(fn_cov_info) = tcx.instance_mir(instance.def).function_coverage_info.as_deref() else {
+        return;
+    };
+    if fn_cov_info.num_paths == 0 {
+        return;
+    }
+
+    let source_map = tcx.sess.source_map();
+    let source_file = global_file_table.source_file(global_file_id);
+    let line_offset = global_file_table.line_offset(global_file_id);
+    let i32_ty = cx.type_i32();
+    let entries = fn_cov_info
+        .path_mappings
+        .iter()
+        .filter_map(|&PathMapping { path, span }| {
+            let cov_span = spans::make_coverage_span(
+                LocalFileId::ZERO,
+                source_map,
+                fn_cov_info,
+                source_file,
+                line_offset,
+                tcx.sess.coverage_char_columns(),
+                span,
+            )?;
+            let fields = [
+                path,
+                global_file_id.as_u32(),
+                cov_span.start_line,
+                cov_span.start_col,
+                cov_span.end_line,
+                cov_span.end_col,
+            ];
+            let fields = fields.map(|field| cx.const_u32(field));
+            Some(cx.const_struct(&fields, false))
+        })
+        .collect::<Vec<_>>();
+    let entry_ty = cx.type_struct(&[i32_ty; 6], false);
+
+    let mangled_function_name = covfun.mangled_function_name();
+    let func_name_hash = llvm_cov::hash_bytes(mangled_function_name.as_bytes());
+    debug!(
+        "generating covpath record for {mangled_function_name} ({} paths, {} regions)",
+        fn_cov_info.num_paths,
+        entries.len(),
+    );
+
+    emit_side_record(
+        cx,
+        "__covpath",
+        func_name_hash,
+        rustc_cov_section_name(cx, c"__DATA,__rustc_covpath", c".rcovpt$M", c"__rustc_covpath"),
+        &[
+            cx.const_u64(func_name_hash),
+            cx.const_u64(covfun.source_hash()),
+            cx.const_u64(filenames_hash),
+            cx.const_u64(fn_cov_info.num_paths as u64),
+            cx.const_u64(entries.len() as u64),
+            cx.const_array(entry_ty, &entries),
+        ],
+        /* shared */ true,
+    );
+}
@@ -0,0 +1,62 @@
+//! Under `-Zcoverage-options=counter-regions`, each used function gets a
+//! record in the `__rustc_covregion` linker section, linking each of its
+//! physical counters to the source regions that the counter counts directly.
+//! Fuzzers can use this to weight inputs by the source code they reach.
+//!
+//! The record layout is documented in the unstable book's page on
+//! `-Zcoverage-options`.
+
+use rustc_codegen_ssa::traits::{BaseTypeCodegenMethods, ConstCodegenMethods};
+use tracing::debug;
+
+use crate::common::CodegenCx;
+use crate::coverageinfo::mapgen::covfun::CovfunRecord;
+use crate::coverageinfo::mapgen::emit_side_record;
+use crate::coverageinfo::{llvm_cov, rustc_cov_section_name};
+
+pub(crate) fn generate_covregion_record<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    filenames_hash: u64,
+    covfun: &CovfunRecord<'tcx>,
+) {
+    let i32_ty = cx.type_i32();
+    let entries = covfun
+        .physical_counter_regions()
+        .map(|(counter_id, file_id, cov_span)| {
+            let fields = [
+                counter_id,
+                file_id,
+                cov_span.start_line,
+                cov_span.start_col,
+                cov_span.end_line,
+                cov_span.end_col,
+            ];
+            let fields = fields.map(|field| cx.const_u32(field));
+            cx.const_struct(&fields, false)
+        })
+        .collect::<Vec<_>>();
+    // Functions without runtime counters have nothing to link regions to.
+    if entries.is_empty() {
+        return;
+    }
+    let entry_ty = cx.type_struct(&[i32_ty; 6], false);
+
+    let mangled_function_name = covfun.mangled_function_name();
+    let func_name_hash = llvm_cov::hash_bytes(mangled_function_name.as_bytes());
+    debug!("generating covregion record for {mangled_function_name} ({} regions)", entries.len());
+
+    emit_side_record(
+        cx,
+        "__covregion",
+        func_name_hash,
+        rustc_cov_section_name(cx, c"__DATA,__rustc_covregion", c".rcovrg$M", c"__rustc_covregion"),
+        &[
+            cx.const_u64(func_name_hash),
+            cx.const_u64(covfun.source_hash()),
+            cx.const_u64(filenames_hash),
+            cx.const_u64(entries.len() as u64),
+            cx.const_array(entry_ty, &entries),
+        ],
+        /* shared */ true,
+    );
+}
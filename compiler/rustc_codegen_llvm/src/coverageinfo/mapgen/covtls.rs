@@ -0,0 +1,78 @@
+//! Under `-Zcoverage-options=thread-local-counters`, counter increments go to
+//! per-thread shadow copies of each function's counters. For each function
+//! with shadow counters in this CGU, we embed a record in the `__rustc_covtls`
+//! linker section, describing where those counters live, so that the standard
+//! library can merge them into the shared `__llvm_prf_cnts` counters (see
+//! `library/std/src/sys/coverage/llvm.rs`).
+//!
+//! The record layout is documented in the unstable book's page on
+//! `-Zcoverage-options`.
+
+use rustc_codegen_ssa::traits::{BaseTypeCodegenMethods, BuilderMethods, ConstCodegenMethods};
+use rustc_middle::mir::coverage::FunctionCoverageInfo;
+use rustc_middle::ty::Instance;
+use tracing::debug;
+
+use crate::builder::Builder;
+use crate::common::CodegenCx;
+use crate::coverageinfo::mapgen::emit_side_record;
+use crate::coverageinfo::{coverage_function_name, llvm_cov, rustc_cov_section_name};
+use crate::llvm;
+
+pub(crate) fn generate_covtls_records<'ll, 'tcx>(cx: &CodegenCx<'ll, 'tcx>) {
+    let Some(coverage_cx) = &cx.coverage_cx else { return };
+    let thread_local_counters = coverage_cx.thread_local_counters.borrow();
+
+    for (&instance, &(counters, num_counters)) in thread_local_counters.iter() {
+        generate_covtls_record(cx, instance, counters, num_counters);
+    }
+}
+
+fn generate_covtls_record<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    instance: Instance<'tcx>,
+    counters: &'ll llvm::Value,
+    num_counters: u32,
+) {
+    let tcx = cx.tcx;
+    let fn_cov_info: &FunctionCoverageInfo = tcx
+        .instance_mir(instance.def)
+        .function_coverage_info
+        .as_deref()
+        .expect("instance with thread-local counters should have coverage info");
+
+    let mangled_function_name = coverage_function_name(tcx, instance);
+    let func_name_hash = llvm_cov::hash_bytes(mangled_function_name.as_bytes());
+    debug!(
+        "generating covtls record for {mangled_function_name} \
+        (hash {func_name_hash:X}, {num_counters} counters)"
+    );
+
+    // The address of a thread-local variable can't be a link-time constant,
+    // so the record points to a small function that returns the address of
+    // the calling thread's copy.
+    let get_counters = {
+        let fn_ty = cx.type_func(&[], cx.type_ptr());
+        let name = format!("__covtls_get_{func_name_hash:X}");
+        let llfn = cx.declare_cfn(&name, llvm::UnnamedAddr::Global, fn_ty);
+        llvm::set_linkage(llfn, llvm::Linkage::InternalLinkage);
+        let llbb = Builder::append_block(cx, llfn, "start");
+        let mut bx = Builder::build(cx, llbb);
+        bx.ret(counters);
+        llfn
+    };
+
+    emit_side_record(
+        cx,
+        "__covtls",
+        func_name_hash,
+        rustc_cov_section_name(cx, c"__DATA,__rustc_covtls", c".rcovtls$M", c"__rustc_covtls"),
+        &[
+            cx.const_u64(func_name_hash),
+            cx.const_u64(fn_cov_info.function_source_hash),
+            get_counters,
+            cx.const_u32(num_counters),
+        ],
+        /* shared */ false,
+    );
+}
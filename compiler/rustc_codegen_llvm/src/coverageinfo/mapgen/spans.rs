@@ -1,3 +1,7 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use rustc_data_structures::fx::FxHashSet;
 use rustc_middle::mir::coverage::FunctionCoverageInfo;
 use rustc_span::source_map::SourceMap;
 use rustc_span::{BytePos, Pos, SourceFile, Span};
@@ -10,17 +14,24 @@
 ///
 /// Line numbers and column numbers are 1-based. Unlike most column numbers emitted by
 /// the compiler, these column numbers are denoted in **bytes**, because that's what
-/// LLVM's `llvm-cov` tool expects to see in coverage maps.
+/// LLVM's `llvm-cov` tool expects to see in coverage maps. Under
+/// `-Zcoverage-options=char-columns` (`char_columns`), they are denoted in
+/// Unicode scalar values instead, for tools that expect character columns.
 ///
 /// Returns `None` if the conversion failed for some reason. This shouldn't happen,
 /// but it's hard to rule out entirely (especially in the presence of complex macros
 /// or other expansions), and if it does happen then skipping a span or function is
 /// better than an ICE or `llvm-cov` failure that the user might have no way to avoid.
+///
+/// The `line_offset` is added to both line numbers. It is only nonzero for code
+/// in a file of merged doctests (see [`DoctestLineTable`]).
 pub(crate) fn make_coverage_span(
     file_id: LocalFileId,
     source_map: &SourceMap,
     fn_cov_info: &FunctionCoverageInfo,
     file: &SourceFile,
+    line_offset: isize,
+    char_columns: bool,
     span: Span,
 ) -> Option<ffi::CoverageSpan> {
     let span = ensure_non_empty_span(source_map, fn_cov_info, span)?;
@@ -30,21 +41,33 @@ pub(crate) fn make_coverage_span(
 
     // Column numbers need to be in bytes, so we can't use the more convenient
     // `SourceMap` methods for looking up file coordinates.
-    let line_and_byte_column = |pos: BytePos| -> Option<(usize, usize)> {
+    let line_and_column = |pos: BytePos| -> Option<(usize, usize)> {
         let rpos = file.relative_position(pos);
         let line_index = file.lookup_line(rpos)?;
         let line_start = file.lines()[line_index];
+        let byte_column = (rpos - line_start).to_usize();
+        let column = if char_columns {
+            // If the line's text isn't available, fall back to bytes, which
+            // are still correct for ASCII-only lines.
+            file.get_line(line_index)
+                .and_then(|line| Some(line.get(..byte_column)?.chars().count()))
+                .unwrap_or(byte_column)
+        } else {
+            byte_column
+        };
         // Line numbers and column numbers are 1-based, so add 1 to each.
-        Some((line_index + 1, (rpos - line_start).to_usize() + 1))
+        Some((line_index + 1, column + 1))
     };
 
-    let (mut start_line, start_col) = line_and_byte_column(lo)?;
-    let (mut end_line, end_col) = line_and_byte_column(hi)?;
+    let (mut start_line, start_col) = line_and_column(lo)?;
+    let (mut end_line, end_col) = line_and_column(hi)?;
 
     // Apply an offset so that code in doctests has correct line numbers.
     // FIXME(#79417): Currently we have no way to offset doctest _columns_.
     start_line = source_map.doctest_offset_line(&file.name, start_line);
     end_line = source_map.doctest_offset_line(&file.name, end_line);
+    start_line = start_line.checked_add_signed(line_offset)?;
+    end_line = end_line.checked_add_signed(line_offset)?;
 
     check_coverage_span(ffi::CoverageSpan {
         file_id: file_id.as_u32(),
@@ -55,6 +78,99 @@ pub(crate) fn make_coverage_span(
     })
 }
 
+/// The largest end column that a region can have, since the high bit of the
+/// end column is reserved for marking gap regions.
+const MAX_END_COL: u32 = (1 << 31) - 1;
+
+/// Under `-Zcoverage-options=line-only`, widens every region to cover whole
+/// lines. Code regions that end up on the same lines of the same file are then
+/// deduplicated, keeping the first, because `llvm-cov` would otherwise add up
+/// the counts of identical regions.
+pub(crate) fn collapse_regions_to_lines(regions: &mut ffi::Regions) {
+    let ffi::Regions {
+        code_regions,
+        branch_regions,
+        mcdc_branch_regions,
+        mcdc_decision_regions,
+        expansion_regions,
+        gap_regions,
+        skipped_regions,
+    } = regions;
+
+    let cov_spans = code_regions
+        .iter_mut()
+        .map(|region| &mut region.cov_span)
+        .chain(branch_regions.iter_mut().map(|region| &mut region.cov_span))
+        .chain(mcdc_branch_regions.iter_mut().map(|region| &mut region.cov_span))
+        .chain(mcdc_decision_regions.iter_mut().map(|region| &mut region.cov_span))
+        .chain(expansion_regions.iter_mut().map(|region| &mut region.cov_span))
+        .chain(gap_regions.iter_mut().map(|region| &mut region.cov_span))
+        .chain(skipped_regions.iter_mut().map(|region| &mut region.cov_span));
+    for cov_span in cov_spans {
+        cov_span.start_col = 1;
+        cov_span.end_col = MAX_END_COL;
+    }
+
+    let mut seen_lines = FxHashSet::default();
+    code_regions.retain(|region| {
+        let ffi::CoverageSpan { file_id, start_line, end_line, .. } = region.cov_span;
+        seen_lines.insert((file_id, start_line, end_line))
+    });
+}
+
+/// The table given by `-Zdoctest-line-table`, which rustdoc writes when it
+/// merges several doctests into one crate. Each entry covers the lines of the
+/// crate's source file that hold one doctest, and says where that doctest came
+/// from, so that its regions can be reported against the original doc comment.
+///
+/// Doctests compiled on their own don't need this, because rustdoc already
+/// gives them a file name that carries their line offset.
+#[derive(Debug)]
+pub(crate) struct DoctestLineTable {
+    entries: Vec<DoctestLines>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DoctestLines {
+    /// The 1-based lines of the merged file that hold the doctest.
+    lines: Range<usize>,
+    /// Added to a line number in `lines` to get its line in `path`.
+    pub(crate) line_offset: isize,
+    pub(crate) path: PathBuf,
+}
+
+impl DoctestLineTable {
+    /// Reads the table, which has one line per doctest of the form
+    /// `<first line> <number of lines> <line offset> <path>`.
+    pub(crate) fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let parse_entry = |row: &str| -> Option<DoctestLines> {
+            let mut fields = row.splitn(4, ' ');
+            let first_line = fields.next()?.parse::<usize>().ok()?;
+            let num_lines = fields.next()?.parse::<usize>().ok()?;
+            let line_offset = fields.next()?.parse::<isize>().ok()?;
+            let path = PathBuf::from(fields.next()?);
+            Some(DoctestLines { lines: first_line..first_line + num_lines, line_offset, path })
+        };
+        let entries = text
+            .lines()
+            .enumerate()
+            .map(|(i, row)| parse_entry(row).ok_or_else(|| format!("malformed line {}", i + 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Returns the index of the entry for the doctest that contains the given
+    /// 1-based line of the merged file, if any.
+    pub(crate) fn entry_index_for_line(&self, line: usize) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.lines.contains(&line))
+    }
+
+    pub(crate) fn entry(&self, index: usize) -> &DoctestLines {
+        &self.entries[index]
+    }
+}
+
 fn ensure_non_empty_span(
     source_map: &SourceMap,
     fn_cov_info: &FunctionCoverageInfo,
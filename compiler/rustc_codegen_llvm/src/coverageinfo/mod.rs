@@ -1,30 +1,149 @@
 use std::cell::{OnceCell, RefCell};
 use std::ffi::{CStr, CString};
 
-use rustc_abi::Size;
+use rustc_abi::{Align, Size};
+use rustc_codegen_ssa::common::{AtomicOrdering, AtomicRmwBinOp, IntPredicate};
 use rustc_codegen_ssa::traits::{
-    BuilderMethods, ConstCodegenMethods, CoverageInfoBuilderMethods, MiscCodegenMethods,
+    BaseTypeCodegenMethods, BuilderMethods, ConstCodegenMethods, CoverageInfoBuilderMethods,
+    MiscCodegenMethods, StaticCodegenMethods,
 };
-use rustc_data_structures::fx::{FxHashMap, FxIndexSet};
-use rustc_middle::mir::coverage::CoverageKind;
-use rustc_middle::ty::Instance;
+use rustc_data_structures::fx::{FxHashMap, FxIndexMap, FxIndexSet};
+use rustc_middle::bug;
+use rustc_middle::mir::coverage::{CounterId, CoverageKind, FunctionCoverageInfo};
+use rustc_middle::query::TyCtxtAt;
 use rustc_middle::ty::layout::HasTyCtxt;
+use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_middle::ty::{self, Instance, TyCtxt};
+use rustc_middle::util::Providers;
+use rustc_span::def_id::LocalDefId;
+use rustc_target::spec::HasTargetSpec;
 use tracing::{debug, instrument};
 
 use crate::builder::Builder;
 use crate::common::CodegenCx;
 use crate::llvm;
 
+mod abort_flush;
+mod boolean_counters;
+mod dll_unload;
 pub(crate) mod ffi;
 mod llvm_cov;
 mod mapgen;
 
+pub(crate) fn provide(providers: &mut Providers) {
+    providers.queries.coverage_unused_functions = mapgen::coverage_unused_functions;
+    providers.queries.coverage_crate_file_table = mapgen::coverage_crate_file_table;
+    providers.hooks.coverage_profile_counters = coverage_profile_counters;
+}
+
+/// Hook implementation for [`TyCtxt::coverage_profile_counters`].
+///
+/// The profile is reopened for each lookup, since the reader can't be shared
+/// between threads. Opening an indexed profile only maps it and reads its
+/// header, so this is cheap compared to instrumenting the function.
+///
+/// [`TyCtxt::coverage_profile_counters`]: rustc_middle::ty::TyCtxt::coverage_profile_counters
+fn coverage_profile_counters(
+    TyCtxtAt { tcx, .. }: TyCtxtAt<'_>,
+    def_id: LocalDefId,
+    function_source_hash: u64,
+) -> Option<Vec<u64>> {
+    let path = tcx.sess.opts.cg.profile_use.as_deref()?;
+    let profile = CoverageProfile::open(path).unwrap_or_else(|err| {
+        tcx.dcx().emit_fatal(crate::errors::ReadCoverageProfile { path, err })
+    });
+
+    let mangled_fn_name: &str = tcx.symbol_name(Instance::mono(tcx, def_id.to_def_id())).name;
+    profile.function_counters(mangled_fn_name, function_source_hash)
+}
+
+pub(crate) use llvm_cov::CoverageProfile;
+
+/// Returns the name of the instance's coverage record, which is also the name
+/// that its counters are recorded under in the profile. This is normally the
+/// instance's symbol name.
+///
+/// Under `-Zcoverage-options=per-instance`, the name of each instance of a
+/// trait's default method also says which impl it was instantiated for. The
+/// demangled symbols of those instances can otherwise be identical (e.g. with
+/// legacy mangling), so `llvm-cov` couldn't tell which impl left the default
+/// body unexercised.
+pub(crate) fn coverage_function_name<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    instance: Instance<'tcx>,
+) -> &'tcx str {
+    let symbol_name = tcx.symbol_name(instance).name;
+    if tcx.sess.coverage_per_instance()
+        && let ty::InstanceKind::Item(def_id) = instance.def
+        && let Some(trait_def_id) = tcx.trait_of_item(def_id)
+    {
+        let self_ty = instance.args.type_at(0);
+        let name = with_no_trimmed_paths!(format!(
+            "{symbol_name} <{self_ty} as {}>",
+            tcx.def_path_str(trait_def_id)
+        ));
+        return ty::SymbolName::new(tcx, &name).name;
+    }
+    symbol_name
+}
+
+/// Prints the versions of the coverage formats that this compiler's LLVM
+/// reads and writes, for `--print=coverage-format`, as `key=value` lines.
+///
+/// `llvm-profdata` only reads `.profraw` files whose version matches its own,
+/// so the tools are required to come from the same LLVM major version as
+/// rustc. Newer tools can still read older coverage mappings and `.profdata`
+/// files, but rustc doesn't promise which versions are compatible.
+pub(crate) fn print_coverage_format(out: &mut String) {
+    use std::fmt::Write;
+
+    let (major, minor, patch) = crate::llvm_util::get_version();
+    writeln!(out, "llvm-version={major}.{minor}.{patch}").unwrap();
+    writeln!(out, "profraw-version={}", llvm_cov::raw_profile_version()).unwrap();
+    writeln!(out, "profdata-version={}", llvm_cov::indexed_profile_version()).unwrap();
+    // Report the version as it is named, rather than its encoded value.
+    writeln!(out, "coverage-mapping-version={}", llvm_cov::mapping_version() + 1).unwrap();
+    writeln!(out, "llvm-profdata-version={major}").unwrap();
+    writeln!(out, "llvm-cov-version={major}").unwrap();
+}
+
 /// Extra per-CGU context/state needed for coverage instrumentation.
 pub(crate) struct CguCoverageContext<'ll, 'tcx> {
     /// Coverage data for each instrumented function identified by DefId.
     pub(crate) instances_used: RefCell<FxIndexSet<Instance<'tcx>>>,
     pub(crate) pgo_func_name_var_map: RefCell<FxHashMap<Instance<'tcx>, &'ll llvm::Value>>,
-    pub(crate) mcdc_condition_bitmap_map: RefCell<FxHashMap<Instance<'tcx>, Vec<&'ll llvm::Value>>>,
+    /// Stack-allocated MC/DC condition bitmaps, keyed by the LLVM function
+    /// that holds them and the instance whose decisions they track, which is
+    /// a different instance for decisions that were MIR-inlined.
+    pub(crate) mcdc_condition_bitmap_map:
+        RefCell<FxHashMap<(&'ll llvm::Value, Instance<'tcx>), Vec<&'ll llvm::Value>>>,
+    /// Per-thread shadow counter arrays, used instead of the shared counters
+    /// under `-Zcoverage-options=thread-local-counters`. Each entry holds the
+    /// thread-local global and its number of counters.
+    pub(crate) thread_local_counters: RefCell<FxIndexMap<Instance<'tcx>, (&'ll llvm::Value, u32)>>,
+    /// First-hit timestamp records, used under
+    /// `-Zcoverage-options=first-hit-timestamps`.
+    first_hit_timestamps: RefCell<FxHashMap<Instance<'tcx>, &'ll llvm::Value>>,
+    /// Logical clock shared by all first-hit timestamp records in the same
+    /// linked image.
+    first_hit_clock: OnceCell<&'ll llvm::Value>,
+    /// Stack-allocated path registers under `-Zcoverage-options=paths`, keyed
+    /// by the LLVM function that holds the register and the instance whose
+    /// paths it numbers.
+    path_registers: RefCell<FxHashMap<(&'ll llvm::Value, Instance<'tcx>), &'ll llvm::Value>>,
+    /// Path count records, used under `-Zcoverage-options=paths`.
+    path_counts: RefCell<FxHashMap<Instance<'tcx>, &'ll llvm::Value>>,
+    /// Switch-arm count records, used under `-Zcoverage-options=switch-arms`.
+    switch_arm_counts: RefCell<FxHashMap<Instance<'tcx>, &'ll llvm::Value>>,
+    /// Call edge records for statically known callees, keyed by caller and
+    /// callee, used under `-Zcoverage-options=call-graph`.
+    call_edges: RefCell<FxHashMap<(Instance<'tcx>, Instance<'tcx>), &'ll llvm::Value>>,
+    /// Number of indirect call sites that have been value-profiled so far in
+    /// each caller, under `-Zcoverage-options=call-graph`.
+    indirect_call_sites: RefCell<FxHashMap<Instance<'tcx>, u32>>,
+    /// Overflow records holding a per-function overflow flag, used under
+    /// `-Zcoverage-options=overflow-checks`.
+    overflow_records: RefCell<FxHashMap<Instance<'tcx>, &'ll llvm::Value>>,
 
     covfun_section_name: OnceCell<CString>,
 }
@@ -35,6 +154,15 @@ pub(crate) fn new() -> Self {
             instances_used: RefCell::<FxIndexSet<_>>::default(),
             pgo_func_name_var_map: Default::default(),
             mcdc_condition_bitmap_map: Default::default(),
+            thread_local_counters: Default::default(),
+            first_hit_timestamps: Default::default(),
+            first_hit_clock: Default::default(),
+            path_registers: Default::default(),
+            path_counts: Default::default(),
+            switch_arm_counts: Default::default(),
+            call_edges: Default::default(),
+            indirect_call_sites: Default::default(),
+            overflow_records: Default::default(),
             covfun_section_name: Default::default(),
         }
     }
@@ -45,12 +173,13 @@ pub(crate) fn new() -> Self {
     /// integers. They respectively hold the condition bitmaps for decisions with a depth of `i`.
     fn try_get_mcdc_condition_bitmap(
         &self,
-        instance: &Instance<'tcx>,
+        llfn: &'ll llvm::Value,
+        instance: Instance<'tcx>,
         decision_depth: u16,
     ) -> Option<&'ll llvm::Value> {
         self.mcdc_condition_bitmap_map
             .borrow()
-            .get(instance)
+            .get(&(llfn, instance))
             .and_then(|bitmap_map| bitmap_map.get(decision_depth as usize))
             .copied() // Dereference Option<&&Value> to Option<&Value>
     }
@@ -58,7 +187,10 @@ fn try_get_mcdc_condition_bitmap(
 
 impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
     pub(crate) fn coverageinfo_finalize(&self) {
-        mapgen::finalize(self)
+        mapgen::finalize(self);
+        dll_unload::generate_dll_unload_hook(self);
+        abort_flush::generate_abort_flush_hook(self);
+        boolean_counters::generate_profile_version_var(self);
     }
 
     /// Returns the section name to use when embedding per-function coverage information
@@ -84,20 +216,409 @@ fn get_pgo_func_name_var(&self, instance: Instance<'tcx>) -> &'ll llvm::Value {
         let mut pgo_func_name_var_map = self.coverage_cx().pgo_func_name_var_map.borrow_mut();
         pgo_func_name_var_map.entry(instance).or_insert_with(|| {
             let llfn = self.get_fn(instance);
-            let mangled_fn_name: &str = self.tcx.symbol_name(instance).name;
+            let mangled_fn_name: &str = coverage_function_name(self.tcx, instance);
             llvm_cov::create_pgo_func_name_var(llfn, mangled_fn_name)
         })
     }
+
+    /// Returns the thread-local global holding this thread's shadow copy of
+    /// the given instance's counters, creating it on first use.
+    fn get_thread_local_counters(
+        &self,
+        instance: Instance<'tcx>,
+        num_counters: u32,
+    ) -> &'ll llvm::Value {
+        let mut thread_local_counters = self.coverage_cx().thread_local_counters.borrow_mut();
+        let &mut (counters, _) = thread_local_counters.entry(instance).or_insert_with(|| {
+            let ty = self.type_array(self.type_i64(), u64::from(num_counters));
+            let counters = self.define_private_global(ty);
+            llvm::set_initializer(counters, self.const_null(ty));
+            llvm::set_thread_local_mode(counters, self.tls_model);
+            (counters, num_counters)
+        });
+        counters
+    }
+
+    /// Returns the global record holding the first-hit timestamps of the given
+    /// instance's counters, creating it on first use.
+    ///
+    /// The record is placed in the `__rustc_covts` section, and has the layout
+    /// `{ i64 name_ref, i64 func_hash, i64 num_counters, [num_counters x i64] }`.
+    fn get_first_hit_timestamps(
+        &self,
+        instance: Instance<'tcx>,
+        function_source_hash: u64,
+        num_counters: u32,
+    ) -> &'ll llvm::Value {
+        let mut first_hit_timestamps = self.coverage_cx().first_hit_timestamps.borrow_mut();
+        first_hit_timestamps.entry(instance).or_insert_with(|| {
+            let func_name_hash = self.coverage_name_hash(instance);
+            let timestamps_ty = self.type_array(self.type_i64(), u64::from(num_counters));
+            let record = self.const_struct(
+                &[
+                    self.const_u64(func_name_hash),
+                    self.const_u64(function_source_hash),
+                    self.const_u64(u64::from(num_counters)),
+                    self.const_null(timestamps_ty),
+                ],
+                false,
+            );
+            self.define_shared_record(
+                format!("__covts_{func_name_hash:X}"),
+                rustc_cov_section_name(
+                    self,
+                    c"__DATA,__rustc_covts",
+                    c".rcovts$M",
+                    c"__rustc_covts",
+                ),
+                record,
+            )
+        })
+    }
+
+    /// Returns the global record holding the counts of the given instance's
+    /// acyclic paths, creating it on first use.
+    ///
+    /// The record is placed in the `__rustc_covpathcnt` section, and has the
+    /// layout `{ i64 name_ref, i64 func_hash, i64 num_paths, [num_paths x i64] }`.
+    fn get_path_counts(
+        &self,
+        instance: Instance<'tcx>,
+        function_source_hash: u64,
+        num_paths: u64,
+    ) -> &'ll llvm::Value {
+        let mut path_counts = self.coverage_cx().path_counts.borrow_mut();
+        path_counts.entry(instance).or_insert_with(|| {
+            let func_name_hash = self.coverage_name_hash(instance);
+            let counts_ty = self.type_array(self.type_i64(), num_paths);
+            let record = self.const_struct(
+                &[
+                    self.const_u64(func_name_hash),
+                    self.const_u64(function_source_hash),
+                    self.const_u64(num_paths),
+                    self.const_null(counts_ty),
+                ],
+                false,
+            );
+            self.define_shared_record(
+                format!("__covpathcnt_{func_name_hash:X}"),
+                rustc_cov_section_name(
+                    self,
+                    c"__DATA,__rustc_covpathcnt",
+                    c".rcovpc$M",
+                    c"__rustc_covpathcnt",
+                ),
+                record,
+            )
+        })
+    }
+
+    /// Returns the global record holding the counts of the given instance's
+    /// switch-arm slots, creating it on first use.
+    ///
+    /// The record is placed in the `__rustc_covarmcnt` section, and has the
+    /// layout `{ i64 name_ref, i64 func_hash, i64 num_slots, [num_slots x i64] }`.
+    fn get_switch_arm_counts(
+        &self,
+        instance: Instance<'tcx>,
+        function_source_hash: u64,
+        num_slots: u64,
+    ) -> &'ll llvm::Value {
+        let mut switch_arm_counts = self.coverage_cx().switch_arm_counts.borrow_mut();
+        switch_arm_counts.entry(instance).or_insert_with(|| {
+            let func_name_hash = self.coverage_name_hash(instance);
+            let counts_ty = self.type_array(self.type_i64(), num_slots);
+            let record = self.const_struct(
+                &[
+                    self.const_u64(func_name_hash),
+                    self.const_u64(function_source_hash),
+                    self.const_u64(num_slots),
+                    self.const_null(counts_ty),
+                ],
+                false,
+            );
+            self.define_shared_record(
+                format!("__covarmcnt_{func_name_hash:X}"),
+                rustc_cov_section_name(
+                    self,
+                    c"__DATA,__rustc_covarmcnt",
+                    c".rcovac$M",
+                    c"__rustc_covarmcnt",
+                ),
+                record,
+            )
+        })
+    }
+
+    /// Returns the global record holding the overflow flag of the given
+    /// instance, creating it on first use.
+    ///
+    /// The record is placed in the `__rustc_covovf` section, and has the
+    /// layout `{ i64 name_ref, i64 func_hash, i64 overflowed }`.
+    fn get_overflow_record(
+        &self,
+        instance: Instance<'tcx>,
+        function_source_hash: u64,
+    ) -> &'ll llvm::Value {
+        let mut overflow_records = self.coverage_cx().overflow_records.borrow_mut();
+        overflow_records.entry(instance).or_insert_with(|| {
+            let func_name_hash = self.coverage_name_hash(instance);
+            let record = self.const_struct(
+                &[
+                    self.const_u64(func_name_hash),
+                    self.const_u64(function_source_hash),
+                    self.const_u64(0),
+                ],
+                false,
+            );
+            self.define_shared_record(
+                format!("__covovf_{func_name_hash:X}"),
+                rustc_cov_section_name(
+                    self,
+                    c"__DATA,__rustc_covovf",
+                    c".rcovov$M",
+                    c"__rustc_covovf",
+                ),
+                record,
+            )
+        })
+    }
+
+    /// Returns the global record counting calls from `caller` to `callee`,
+    /// creating it on first use.
+    ///
+    /// The record is placed in the `__rustc_covcall` section, and has the
+    /// layout `{ i64 caller_name_ref, i64 callee_name_ref, i64 count }`.
+    fn get_call_edge(&self, caller: Instance<'tcx>, callee: Instance<'tcx>) -> &'ll llvm::Value {
+        let mut call_edges = self.coverage_cx().call_edges.borrow_mut();
+        call_edges.entry((caller, callee)).or_insert_with(|| {
+            let caller_name_hash = self.coverage_name_hash(caller);
+            let callee_name_hash = self.coverage_name_hash(callee);
+            let record = self.const_struct(
+                &[
+                    self.const_u64(caller_name_hash),
+                    self.const_u64(callee_name_hash),
+                    self.const_u64(0),
+                ],
+                false,
+            );
+            self.define_shared_record(
+                format!("__covcall_{caller_name_hash:X}_{callee_name_hash:X}"),
+                rustc_cov_section_name(
+                    self,
+                    c"__DATA,__rustc_covcall",
+                    c".rcovcl$M",
+                    c"__rustc_covcall",
+                ),
+                record,
+            )
+        })
+    }
+
+    /// Returns the hash of the instance's coverage name, which identifies the
+    /// instance in its `__llvm_prf_data` record and in rustc's own records.
+    fn coverage_name_hash(&self, instance: Instance<'tcx>) -> u64 {
+        llvm_cov::hash_bytes(coverage_function_name(self.tcx, instance).as_bytes())
+    }
+
+    /// Defines a mutable record global named `name` in `section`, initialized
+    /// to `record`, for an instance (or pair of instances) of this CGU.
+    ///
+    /// Instances that are used in several CGUs share a single record, in the
+    /// same way that their covfun records are deduplicated, so the global has
+    /// `linkonce_odr` linkage, hidden visibility and its own COMDAT.
+    fn define_shared_record(
+        &self,
+        name: String,
+        section: &CStr,
+        record: &'ll llvm::Value,
+    ) -> &'ll llvm::Value {
+        let name = CString::new(name).unwrap();
+        let global = llvm::add_global(self.llmod, self.val_ty(record), &name);
+        llvm::set_initializer(global, record);
+        llvm::set_linkage(global, llvm::Linkage::LinkOnceODRLinkage);
+        llvm::set_visibility(global, llvm::Visibility::Hidden);
+        llvm::set_section(global, section);
+        llvm::set_alignment(global, Align::EIGHT);
+        if self.target_spec().supports_comdat() {
+            llvm::set_comdat(self.llmod, global, &name);
+        }
+        self.add_used_global(global);
+        global
+    }
+
+    /// Returns the logical clock that is advanced each time a counter is
+    /// incremented for the first time, under
+    /// `-Zcoverage-options=first-hit-timestamps`.
+    fn get_first_hit_clock(&self) -> &'ll llvm::Value {
+        self.coverage_cx().first_hit_clock.get_or_init(|| {
+            let name = c"__rustc_covts_clock";
+            let ty = self.type_i64();
+            let global = llvm::add_global(self.llmod, ty, name);
+            llvm::set_initializer(global, self.const_null(ty));
+            llvm::set_linkage(global, llvm::Linkage::LinkOnceODRLinkage);
+            llvm::set_visibility(global, llvm::Visibility::Hidden);
+            if self.target_spec().supports_comdat() {
+                llvm::set_comdat(self.llmod, global, name);
+            }
+            global
+        })
+    }
 }
 
-impl<'tcx> CoverageInfoBuilderMethods<'tcx> for Builder<'_, '_, 'tcx> {
-    fn init_coverage(&mut self, instance: Instance<'tcx>) {
-        let Some(function_coverage_info) =
-            self.tcx.instance_mir(instance.def).function_coverage_info.as_deref()
-        else {
+/// Returns the name of one of rustc's own coverage record sections, according
+/// to the target's object file format: `mach_o` on Apple targets (including
+/// the `__DATA,` segment prefix), `coff` on Windows (including the `$M`
+/// sorting suffix), and `elf` elsewhere.
+pub(crate) fn rustc_cov_section_name(
+    cx: &CodegenCx<'_, '_>,
+    mach_o: &'static CStr,
+    coff: &'static CStr,
+    elf: &'static CStr,
+) -> &'static CStr {
+    let target = &cx.tcx.sess.target;
+    if target.is_like_osx {
+        mach_o
+    } else if target.is_like_windows {
+        coff
+    } else {
+        elf
+    }
+}
+
+/// LLVM's value kind for profiling the targets of indirect calls
+/// (`IPVK_IndirectCallTarget` in `InstrProfData.inc`).
+const IPVK_INDIRECT_CALL_TARGET: u32 = 0;
+
+impl<'ll, 'tcx> Builder<'_, 'll, 'tcx> {
+    /// If this is the first time the given counter has been incremented,
+    /// stores the next value of the first-hit clock in its timestamp slot.
+    ///
+    /// Threads that race on the same first hit may each store a timestamp,
+    /// so the recorded value is only guaranteed to be one of theirs.
+    fn record_first_hit_timestamp(
+        &mut self,
+        instance: Instance<'tcx>,
+        function_coverage_info: &FunctionCoverageInfo,
+        id: CounterId,
+    ) {
+        let num_counters = self.tcx.coverage_ids_info(instance).num_counters_after_mir_opts();
+        let record = self.get_first_hit_timestamps(
+            instance,
+            function_coverage_info.function_source_hash,
+            num_counters,
+        );
+
+        // Skip the three header fields to find this counter's timestamp.
+        let i64_ty = self.type_i64();
+        let align = self.tcx.data_layout.i64_align.abi;
+        let offset = self.const_usize(8 * (3 + u64::from(id.as_u32())));
+        let slot = self.inbounds_ptradd(record, offset);
+        let timestamp = self.load(i64_ty, slot, align);
+        let is_first_hit = self.icmp(IntPredicate::IntEQ, timestamp, self.const_u64(0));
+
+        let record_bb = self.append_sibling_block("covts.record");
+        let next_bb = self.append_sibling_block("covts.next");
+        self.cond_br_with_expect(is_first_hit, record_bb, next_bb, Some(false));
+
+        self.switch_to_block(record_bb);
+        let clock = self.get_first_hit_clock();
+        let one = self.const_u64(1);
+        let previous =
+            self.atomic_rmw(AtomicRmwBinOp::AtomicAdd, clock, one, AtomicOrdering::Relaxed);
+        let now = self.add(previous, one);
+        self.store(now, slot, align);
+        self.br(next_bb);
+
+        self.switch_to_block(next_bb);
+    }
+
+    /// Makes LLVM create the `__llvm_prf_data` record and shared counters of
+    /// the given instance in this module, even if none of its counters are
+    /// incremented with `instrprof.increment`, by emitting a zero-step
+    /// increment that doesn't change any counts.
+    fn declare_profile_counters(
+        &mut self,
+        instance: Instance<'tcx>,
+        function_coverage_info: &FunctionCoverageInfo,
+    ) {
+        let num_counters = self.tcx.coverage_ids_info(instance).num_counters_after_mir_opts();
+        if num_counters == 0 {
             return;
-        };
+        }
+        let fn_name = self.get_pgo_func_name_var(instance);
+        let hash = self.const_u64(function_coverage_info.function_source_hash);
+        let num_counters = self.const_u32(num_counters);
+        let index = self.const_u32(0);
+        let step = self.const_u64(0);
+        self.instrprof_increment_step(fn_name, hash, num_counters, index, step);
+    }
 
+    /// Returns the address of the given counter in the instance's shared
+    /// `__llvm_prf_cnts` counters.
+    ///
+    /// Those counters are only created when LLVM lowers the profiling
+    /// intrinsics, so this emits a call to `__rustc_coverage_counter_address`,
+    /// which the LLVM wrapper replaces with the counter's address right after
+    /// lowering. The instance must have profile counters in this module; see
+    /// `declare_profile_counters`.
+    fn coverage_counter_address(
+        &mut self,
+        instance: Instance<'tcx>,
+        function_source_hash: u64,
+        id: CounterId,
+    ) -> &'ll llvm::Value {
+        let func_name_hash = self.coverage_name_hash(instance);
+        let fn_ty =
+            self.type_func(&[self.type_i64(), self.type_i64(), self.type_i32()], self.type_ptr());
+        let address_fn =
+            self.declare_cfn("__rustc_coverage_counter_address", llvm::UnnamedAddr::No, fn_ty);
+        let args = [
+            self.const_u64(func_name_hash),
+            self.const_u64(function_source_hash),
+            self.const_u32(id.as_u32()),
+        ];
+        self.call(fn_ty, None, None, address_fn, &args, None, None)
+    }
+
+    /// Increments the counter that `counter` points to, unless it already
+    /// holds the largest count that it can represent, in which case the
+    /// counter stays saturated. If `record` is given, its overflow flag is
+    /// also set when that happens.
+    fn increment_counter_saturating(
+        &mut self,
+        record: Option<&'ll llvm::Value>,
+        counter: &'ll llvm::Value,
+    ) {
+        let i64_ty = self.type_i64();
+        let align = self.tcx.data_layout.i64_align.abi;
+        let count = self.load(i64_ty, counter, align);
+        let is_saturated = self.icmp(IntPredicate::IntEQ, count, self.const_u64(u64::MAX));
+        let incremented = self.add(count, self.const_u64(1));
+        let count = self.select(is_saturated, count, incremented);
+        self.store(count, counter, align);
+
+        let Some(record) = record else { return };
+        let overflow_bb = self.append_sibling_block("covovf.overflow");
+        let next_bb = self.append_sibling_block("covovf.next");
+        self.cond_br_with_expect(is_saturated, overflow_bb, next_bb, Some(false));
+
+        // Skip the two header fields before the overflow flag.
+        self.switch_to_block(overflow_bb);
+        let flag = self.inbounds_ptradd(record, self.const_usize(8 * 2));
+        self.store(self.const_u64(1), flag, align);
+        self.br(next_bb);
+
+        self.switch_to_block(next_bb);
+    }
+
+    /// Declares the MC/DC bitmap of the given instance, and creates its
+    /// condition bitmaps as locals of the function being codegenned.
+    fn init_mcdc_condition_bitmaps(
+        &mut self,
+        instance: Instance<'tcx>,
+        function_coverage_info: &FunctionCoverageInfo,
+    ) {
         // If there are no MC/DC bitmaps to set up, return immediately.
         if function_coverage_info.mcdc_bitmap_bits == 0 {
             return;
@@ -120,7 +641,131 @@ fn init_coverage(&mut self, instance: Instance<'tcx>) {
             cond_bitmaps.push(cond_bitmap);
         }
 
-        self.coverage_cx().mcdc_condition_bitmap_map.borrow_mut().insert(instance, cond_bitmaps);
+        let key = (self.llfn(), instance);
+        self.coverage_cx().mcdc_condition_bitmap_map.borrow_mut().insert(key, cond_bitmaps);
+    }
+}
+
+impl<'tcx> CoverageInfoBuilderMethods<'tcx> for Builder<'_, '_, 'tcx> {
+    fn init_coverage(&mut self, instance: Instance<'tcx>) {
+        let Some(function_coverage_info) =
+            self.tcx.instance_mir(instance.def).function_coverage_info.as_deref()
+        else {
+            return;
+        };
+
+        if self.tcx.sess.coverage_thread_local_counters()
+            || self.tcx.sess.coverage_saturating_counters()
+            || self.tcx.sess.coverage_call_graph()
+        {
+            // With thread-local or saturating counters, rustc updates the
+            // counters itself, so LLVM won't see any `instrprof.increment` for
+            // this function, and would not create its `__llvm_prf_data` record
+            // or shared counters. Value profiling of indirect calls needs that
+            // record too, even if MIR optimizations removed all of the
+            // increments.
+            self.declare_profile_counters(instance, function_coverage_info);
+        }
+
+        // Create a path register named `covpath.reg`, which accumulates the
+        // number of the acyclic path being executed.
+        if function_coverage_info.num_paths > 0 {
+            let align = self.tcx.data_layout.i32_align.abi;
+            let register = self.alloca(Size::from_bytes(4), align);
+            llvm::set_value_name(register, b"covpath.reg");
+            self.store(self.const_i32(0), register, align);
+            let key = (self.llfn(), instance);
+            self.coverage_cx().path_registers.borrow_mut().insert(key, register);
+        }
+
+        self.init_mcdc_condition_bitmaps(instance, function_coverage_info);
+    }
+
+    fn init_inlined_coverage(&mut self, instance: Instance<'tcx>) {
+        // Inlined coverage statements are discarded in crates that aren't
+        // instrumented for coverage, so there's nothing to set up for them.
+        if self.cx.coverage_cx.is_none() {
+            return;
+        }
+        let Some(function_coverage_info) =
+            self.tcx.instance_mir(instance.def).function_coverage_info.as_deref()
+        else {
+            return;
+        };
+
+        // Inlined counter increments refer to the callee's own counters, so
+        // they usually need no setup here. Saturating increments do need the
+        // callee's shared counters to exist in this module, though. The
+        // callee's MC/DC decisions are built up in condition bitmaps, which
+        // have to be locals of this function. Path registers aren't set up,
+        // because the paths of inlined code aren't counted.
+        if self.tcx.sess.coverage_saturating_counters()
+            && !self.tcx.sess.coverage_thread_local_counters()
+        {
+            self.declare_profile_counters(instance, function_coverage_info);
+        }
+        self.init_mcdc_condition_bitmaps(instance, function_coverage_info);
+    }
+
+    fn coverage_profile_counters(&mut self, instance: Instance<'tcx>) -> Option<Vec<u64>> {
+        let profile = self.cx.coverage_profile.as_ref()?;
+        let function_coverage_info =
+            self.tcx.instance_mir(instance.def).function_coverage_info.as_deref()?;
+
+        // Coverage instrumentation names each function's profile record after
+        // its symbol (see `coverage_function_name`), and uses the coverage hash
+        // as the record's hash.
+        let mangled_fn_name: &str = coverage_function_name(self.tcx, instance);
+        let counters =
+            profile.function_counters(mangled_fn_name, function_coverage_info.function_source_hash);
+        debug!(?instance, found = counters.is_some(), "coverage profile lookup");
+        counters
+    }
+
+    #[instrument(level = "debug", skip(self, fn_ptr))]
+    fn record_coverage_call(
+        &mut self,
+        caller: Instance<'tcx>,
+        callee: Option<Instance<'tcx>>,
+        fn_ptr: &'ll llvm::Value,
+    ) {
+        let Some(coverage_cx) = &self.cx.coverage_cx else { return };
+        // Only calls made by instrumented functions are recorded. Calls from
+        // code that was inlined into such a function are attributed to it.
+        let Some(function_coverage_info) =
+            self.tcx.instance_mir(caller.def).function_coverage_info.as_deref()
+        else {
+            return;
+        };
+        coverage_cx.instances_used.borrow_mut().insert(caller);
+
+        match callee {
+            Some(callee) => {
+                // Skip the two header fields to find the edge's count.
+                let record = self.get_call_edge(caller, callee);
+                let i64_ty = self.type_i64();
+                let align = self.tcx.data_layout.i64_align.abi;
+                let slot = self.inbounds_ptradd(record, self.const_usize(16));
+                let count = self.load(i64_ty, slot, align);
+                let count = self.add(count, self.const_u64(1));
+                self.store(count, slot, align);
+            }
+            None => {
+                let index = {
+                    let mut indirect_call_sites = coverage_cx.indirect_call_sites.borrow_mut();
+                    let next_index = indirect_call_sites.entry(caller).or_insert(0);
+                    let index = *next_index;
+                    *next_index += 1;
+                    index
+                };
+                let fn_name = self.get_pgo_func_name_var(caller);
+                let hash = self.const_u64(function_coverage_info.function_source_hash);
+                let target = self.ptrtoint(fn_ptr, self.type_i64());
+                let value_kind = self.const_u32(IPVK_INDIRECT_CALL_TARGET);
+                let index = self.const_u32(index);
+                self.instrprof_value_profile(fn_name, hash, target, value_kind, index);
+            }
+        }
     }
 
     #[instrument(level = "debug", skip(self))]
@@ -134,8 +779,9 @@ fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind) {
 
         let bx = self;
 
-        // Due to LocalCopy instantiation or MIR inlining, coverage statements
-        // can end up in a crate that isn't doing coverage instrumentation.
+        // Due to LocalCopy instantiation (or forced MIR inlining), coverage
+        // statements can end up in a crate that isn't doing coverage
+        // instrumentation.
         // When that happens, we currently just discard those statements, so
         // the corresponding code will be undercounted.
         // FIXME(Zalathar): Find a better solution for mixed-coverage builds.
@@ -163,13 +809,60 @@ fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind) {
                 // if some high-numbered counters were removed by MIR optimizations.
                 // If so, LLVM's profiler runtime will use fewer physical counters.
                 let num_counters =
-                    bx.tcx().coverage_ids_info(instance.def).num_counters_after_mir_opts();
+                    bx.tcx().coverage_ids_info(instance).num_counters_after_mir_opts();
                 assert!(
                     num_counters as usize <= function_coverage_info.num_counters,
                     "num_counters disagreement: query says {num_counters} but function info only has {}",
                     function_coverage_info.num_counters
                 );
 
+                if bx.tcx.sess.coverage_thread_local_counters() {
+                    // Increment this thread's shadow copy of the counter. The
+                    // standard library merges the shadow copies into the
+                    // shared counters; see `covtls.rs`.
+                    let counters = bx.get_thread_local_counters(instance, num_counters);
+                    let i64_ty = bx.type_i64();
+                    let align = bx.tcx.data_layout.i64_align.abi;
+                    let counter = bx.inbounds_gep(
+                        i64_ty,
+                        counters,
+                        &[bx.const_usize(u64::from(id.as_u32()))],
+                    );
+                    if bx.tcx.sess.coverage_saturating_counters() {
+                        let record = bx.tcx.sess.coverage_overflow_checks().then(|| {
+                            bx.get_overflow_record(
+                                instance,
+                                function_coverage_info.function_source_hash,
+                            )
+                        });
+                        bx.increment_counter_saturating(record, counter);
+                        return;
+                    }
+                    let count = bx.load(i64_ty, counter, align);
+                    let count = bx.add(count, bx.const_u64(1));
+                    bx.store(count, counter, align);
+                    return;
+                }
+
+                if bx.tcx.sess.coverage_saturating_counters() {
+                    // Increment the shared counter in place, with saturating
+                    // arithmetic. Only the overflow flag, if any, is kept in a
+                    // record of its own.
+                    let function_source_hash = function_coverage_info.function_source_hash;
+                    let counter = bx.coverage_counter_address(instance, function_source_hash, id);
+                    let record = bx
+                        .tcx
+                        .sess
+                        .coverage_overflow_checks()
+                        .then(|| bx.get_overflow_record(instance, function_source_hash));
+                    bx.increment_counter_saturating(record, counter);
+
+                    if bx.tcx.sess.coverage_first_hit_timestamps() {
+                        bx.record_first_hit_timestamp(instance, function_coverage_info, id);
+                    }
+                    return;
+                }
+
                 let fn_name = bx.get_pgo_func_name_var(instance);
                 let hash = bx.const_u64(function_coverage_info.function_source_hash);
                 let num_counters = bx.const_u32(num_counters);
@@ -178,7 +871,17 @@ fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind) {
                     "codegen intrinsic instrprof.increment(fn_name={:?}, hash={:?}, num_counters={:?}, index={:?})",
                     fn_name, hash, num_counters, index,
                 );
-                bx.instrprof_increment(fn_name, hash, num_counters, index);
+                if bx.tcx.sess.coverage_boolean_counters() {
+                    // Only record that the counter was reached, in a single
+                    // byte that LLVM clears instead of incrementing.
+                    bx.instrprof_cover(fn_name, hash, num_counters, index);
+                } else {
+                    bx.instrprof_increment(fn_name, hash, num_counters, index);
+                }
+
+                if bx.tcx.sess.coverage_first_hit_timestamps() {
+                    bx.record_first_hit_timestamp(instance, function_coverage_info, id);
+                }
             }
             CoverageKind::ExpressionUsed { id: _ } => {
                 // Expression-used statements are markers that are handled by
@@ -186,14 +889,15 @@ fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind) {
             }
             CoverageKind::CondBitmapUpdate { index, decision_depth } => {
                 let cond_bitmap = coverage_cx
-                    .try_get_mcdc_condition_bitmap(&instance, decision_depth)
+                    .try_get_mcdc_condition_bitmap(bx.llfn(), instance, decision_depth)
                     .expect("mcdc cond bitmap should have been allocated for updating");
                 let cond_index = bx.const_i32(index as i32);
                 bx.mcdc_condbitmap_update(cond_index, cond_bitmap);
             }
             CoverageKind::TestVectorBitmapUpdate { bitmap_idx, decision_depth } => {
-                let cond_bitmap =
-                    coverage_cx.try_get_mcdc_condition_bitmap(&instance, decision_depth).expect(
+                let cond_bitmap = coverage_cx
+                    .try_get_mcdc_condition_bitmap(bx.llfn(), instance, decision_depth)
+                    .expect(
                         "mcdc cond bitmap should have been allocated for merging \
                         into the global bitmap",
                     );
@@ -208,6 +912,95 @@ fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind) {
                 bx.mcdc_tvbitmap_update(fn_name, hash, bitmap_index, cond_bitmap);
                 bx.mcdc_condbitmap_reset(cond_bitmap);
             }
+            CoverageKind::PathRegisterAdd { value } => {
+                // Path statements that were inlined into another function have
+                // no register there, so they are discarded, and the paths that
+                // they belong to go uncounted.
+                let Some(&register) =
+                    coverage_cx.path_registers.borrow().get(&(bx.llfn(), instance))
+                else {
+                    return;
+                };
+                let i32_ty = bx.type_i32();
+                let align = bx.tcx.data_layout.i32_align.abi;
+                let path = bx.load(i32_ty, register, align);
+                let path = bx.add(path, bx.const_u32(value));
+                bx.store(path, register, align);
+            }
+            CoverageKind::PathCount { add, reset } => {
+                let Some(&register) =
+                    coverage_cx.path_registers.borrow().get(&(bx.llfn(), instance))
+                else {
+                    return;
+                };
+                let record = bx.get_path_counts(
+                    instance,
+                    function_coverage_info.function_source_hash,
+                    function_coverage_info.num_paths as u64,
+                );
+
+                // Skip the three header fields to find the path's count.
+                let i32_ty = bx.type_i32();
+                let i64_ty = bx.type_i64();
+                let i32_align = bx.tcx.data_layout.i32_align.abi;
+                let i64_align = bx.tcx.data_layout.i64_align.abi;
+                let path = bx.load(i32_ty, register, i32_align);
+                let path = bx.add(path, bx.const_u32(3 + add));
+                let path = bx.zext(path, bx.type_isize());
+                let slot = bx.inbounds_gep(i64_ty, record, &[path]);
+                let count = bx.load(i64_ty, slot, i64_align);
+                let count = bx.add(count, bx.const_u64(1));
+                bx.store(count, slot, i64_align);
+                bx.store(bx.const_u32(reset), register, i32_align);
+            }
+            CoverageKind::SwitchArmCount { .. } => {
+                // The arm is counted along with the switch that follows, in
+                // `add_coverage_switch_arm`, since it depends on the switch's
+                // discriminant.
+            }
         }
     }
+
+    #[instrument(level = "debug", skip(self, discr))]
+    fn add_coverage_switch_arm(
+        &mut self,
+        instance: Instance<'tcx>,
+        kind: &CoverageKind,
+        discr: &'ll llvm::Value,
+    ) {
+        let CoverageKind::SwitchArmCount { first_slot, min, num_values } = *kind else {
+            bug!("expected a switch-arm count, found {kind:?}");
+        };
+        let Some(coverage_cx) = &self.cx.coverage_cx else { return };
+        let Some(function_coverage_info) =
+            self.tcx.instance_mir(instance.def).function_coverage_info.as_deref()
+        else {
+            return;
+        };
+        coverage_cx.instances_used.borrow_mut().insert(instance);
+
+        let record = self.get_switch_arm_counts(
+            instance,
+            function_coverage_info.function_source_hash,
+            function_coverage_info.num_switch_arm_slots as u64,
+        );
+
+        // Values outside the switch's range all take the `otherwise` arm,
+        // whose slot comes after those of the values.
+        let discr_ty = self.val_ty(discr);
+        let arm = self.sub(discr, self.const_uint_big(discr_ty, min));
+        let in_range =
+            self.icmp(IntPredicate::IntULT, arm, self.const_uint_big(discr_ty, num_values.into()));
+        let arm = self.intcast(arm, self.type_isize(), false);
+        let arm = self.select(in_range, arm, self.const_usize(num_values.into()));
+
+        // Skip the three header fields, and the slots of earlier switches.
+        let i64_ty = self.type_i64();
+        let align = self.tcx.data_layout.i64_align.abi;
+        let slot = self.add(arm, self.const_usize(3 + u64::from(first_slot)));
+        let slot = self.inbounds_gep(i64_ty, record, &[slot]);
+        let count = self.load(i64_ty, slot, align);
+        let count = self.add(count, self.const_u64(1));
+        self.store(count, slot, align);
+    }
 }
@@ -193,6 +193,27 @@ pub(crate) struct WriteBytecode<'a> {
     pub err: std::io::Error,
 }
 
+#[derive(Diagnostic)]
+#[diag(codegen_llvm_write_const_eval_profile)]
+pub(crate) struct WriteConstEvalProfile<'a> {
+    pub path: &'a Path,
+    pub err: std::io::Error,
+}
+
+#[derive(Diagnostic)]
+#[diag(codegen_llvm_read_coverage_profile)]
+pub(crate) struct ReadCoverageProfile<'a> {
+    pub path: &'a Path,
+    pub err: String,
+}
+
+#[derive(Diagnostic)]
+#[diag(codegen_llvm_read_doctest_line_table)]
+pub(crate) struct ReadDoctestLineTable<'a> {
+    pub path: &'a Path,
+    pub err: String,
+}
+
 #[derive(Diagnostic)]
 #[diag(codegen_llvm_copy_bitcode)]
 pub(crate) struct CopyBitcode {
@@ -270,7 +270,8 @@ fn init(&self, sess: &Session) {
 
     fn provide(&self, providers: &mut Providers) {
         providers.global_backend_features =
-            |tcx, ()| llvm_util::global_llvm_features(tcx.sess, true, false)
+            |tcx, ()| llvm_util::global_llvm_features(tcx.sess, true, false);
+        coverageinfo::provide(providers);
     }
 
     fn print(&self, req: &PrintRequest, out: &mut String, sess: &Session) {
@@ -335,6 +336,7 @@ fn print(&self, req: &PrintRequest, out: &mut String, sess: &Session) {
                 )
                 .unwrap();
             }
+            PrintKind::CoverageFormat => coverageinfo::print_coverage_format(out),
             _other => llvm_util::print(req, out, sess),
         }
     }
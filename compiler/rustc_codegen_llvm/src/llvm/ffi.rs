@@ -524,6 +524,9 @@ pub struct SanitizerOptions {
     pub sanitize_hwaddress_recover: bool,
     pub sanitize_kernel_address: bool,
     pub sanitize_kernel_address_recover: bool,
+    pub sanitize_coverage_inline_8bit_counters: bool,
+    pub sanitize_coverage_stack_depth: bool,
+    pub sanitize_coverage_control_flow: bool,
 }
 
 /// LLVMRustRelocModel
@@ -689,6 +692,7 @@ struct InvariantOpaque<'a> {
     pub type Pass;
     pub type TargetMachine;
     pub type Archive;
+    pub type InstrProfReader;
 }
 #[repr(C)]
 pub struct ArchiveIterator<'a>(InvariantOpaque<'a>);
@@ -1803,6 +1807,12 @@ pub(crate) fn LLVMRustCoverageWriteFunctionMappingsToBuffer(
         NumMCDCBranchRegions: size_t,
         MCDCDecisionRegions: *const crate::coverageinfo::ffi::MCDCDecisionRegion,
         NumMCDCDecisionRegions: size_t,
+        ExpansionRegions: *const crate::coverageinfo::ffi::ExpansionRegion,
+        NumExpansionRegions: size_t,
+        GapRegions: *const crate::coverageinfo::ffi::GapRegion,
+        NumGapRegions: size_t,
+        SkippedRegions: *const crate::coverageinfo::ffi::SkippedRegion,
+        NumSkippedRegions: size_t,
         BufferOut: &RustString,
     );
 
@@ -1820,6 +1830,22 @@ pub(crate) fn LLVMRustCoverageCreatePGOFuncNameVar(
     pub(crate) fn LLVMRustCoverageWriteCovmapVarNameToString(OutStr: &RustString);
 
     pub(crate) fn LLVMRustCoverageMappingVersion() -> u32;
+
+    pub(crate) fn LLVMRustInstrProfRawVersion() -> u64;
+    pub(crate) fn LLVMRustInstrProfIndexedVersion() -> u64;
+    pub(crate) fn LLVMRustInstrProfByteCoverageVariantMask() -> u64;
+
+    pub(crate) fn LLVMRustCoverageOpenProfile(
+        Path: *const c_char,
+    ) -> Option<&'static mut InstrProfReader>;
+    pub(crate) fn LLVMRustCoverageCloseProfile(Reader: &'static mut InstrProfReader);
+    pub(crate) fn LLVMRustCoverageReadFunctionCounts(
+        Reader: &InstrProfReader,
+        FuncName: *const c_char,
+        FuncNameLen: size_t,
+        FuncHash: u64,
+        OutCounts: &RustString,
+    ) -> bool;
     pub fn LLVMRustDebugMetadataVersion() -> u32;
     pub fn LLVMRustVersionMajor() -> u32;
     pub fn LLVMRustVersionMinor() -> u32;
@@ -2258,6 +2284,7 @@ pub fn LLVMRustOptimize<'a>(
         PGOUsePath: *const c_char,
         InstrumentCoverage: bool,
         InstrProfileOutput: *const c_char,
+        AtomicCoverageCounters: bool,
         PGOSampleUsePath: *const c_char,
         DebugInfoForProfiling: bool,
         llvm_selfprofiler: *mut c_void,
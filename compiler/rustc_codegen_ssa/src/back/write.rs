@@ -30,7 +30,8 @@
 use rustc_middle::ty::TyCtxt;
 use rustc_session::Session;
 use rustc_session::config::{
-    self, CrateType, Lto, OutFileName, OutputFilenames, OutputType, Passes, SwitchWithOptPath,
+    self, CrateType, Lto, OutFileName, OutputFilenames, OutputType, Passes,
+    SanitizerCoverageOptions, SwitchWithOptPath,
 };
 use rustc_span::source_map::SourceMap;
 use rustc_span::{FileName, InnerSpan, Span, SpanData, sym};
@@ -89,11 +90,18 @@ pub struct ModuleConfig {
     pub pgo_sample_use: Option<PathBuf>,
     pub debug_info_for_profiling: bool,
     pub instrument_coverage: bool,
+    /// Default filename pattern for the profiles written by the profiler
+    /// runtime under `-Cinstrument-coverage`.
+    pub instr_profile_output: Option<String>,
+    /// Whether `-Cinstrument-coverage` counters are incremented with atomic
+    /// read-modify-write operations (see `-Zcoverage-options=non-atomic`).
+    pub atomic_coverage_counters: bool,
 
     pub sanitizer: SanitizerSet,
     pub sanitizer_recover: SanitizerSet,
     pub sanitizer_dataflow_abilist: Vec<String>,
     pub sanitizer_memory_track_origins: usize,
+    pub sanitizer_coverage: SanitizerCoverageOptions,
 
     // Flags indicating which outputs to produce.
     pub emit_pre_lto_bc: bool,
@@ -178,10 +186,22 @@ macro_rules! if_regular {
                 sess.opts.cg.profile_generate.clone(),
                 SwitchWithOptPath::Disabled
             ),
-            pgo_use: if_regular!(sess.opts.cg.profile_use.clone(), None),
+            // Under `-Zprofile-use-coverage` and `-Zcoverage-options=profile-guided`,
+            // rustc reads the coverage profile itself, so LLVM must not try to apply
+            // it as IR PGO data.
+            pgo_use: if_regular!(
+                sess.opts
+                    .cg
+                    .profile_use
+                    .clone()
+                    .filter(|_| !sess.profile_use_coverage() && !sess.coverage_profile_guided()),
+                None
+            ),
             pgo_sample_use: if_regular!(sess.opts.unstable_opts.profile_sample_use.clone(), None),
             debug_info_for_profiling: sess.opts.unstable_opts.debug_info_for_profiling,
             instrument_coverage: if_regular!(sess.instrument_coverage(), false),
+            instr_profile_output: if_regular!(instr_profile_output(tcx), None),
+            atomic_coverage_counters: !sess.coverage_non_atomic(),
 
             sanitizer: if_regular!(sess.opts.unstable_opts.sanitizer, SanitizerSet::empty()),
             sanitizer_dataflow_abilist: if_regular!(
@@ -196,6 +216,10 @@ macro_rules! if_regular {
                 sess.opts.unstable_opts.sanitizer_memory_track_origins,
                 0
             ),
+            sanitizer_coverage: if_regular!(
+                sess.sanitizer_coverage(),
+                SanitizerCoverageOptions::default()
+            ),
 
             emit_pre_lto_bc: if_regular!(
                 save_temps || need_pre_lto_bitcode_for_incr_comp(sess),
@@ -277,6 +301,25 @@ pub fn bitcode_needed(&self) -> bool {
     }
 }
 
+/// Returns the default filename pattern for `-Cinstrument-coverage` profiles,
+/// if coverage is enabled.
+///
+/// A Windows DLL is usually loaded into a host process that has no idea it is
+/// instrumented, so its profiles are named after the DLL's crate instead of
+/// sharing the host's default name.
+fn instr_profile_output(tcx: TyCtxt<'_>) -> Option<String> {
+    if !tcx.sess.instrument_coverage() {
+        return None;
+    }
+    let is_windows_dll = tcx.sess.target.is_like_windows
+        && tcx.crate_types().iter().any(|ct| matches!(ct, CrateType::Dylib | CrateType::Cdylib));
+    if is_windows_dll {
+        Some(format!("{}_%m_%p.profraw", tcx.crate_name(LOCAL_CRATE)))
+    } else {
+        Some("default_%m_%p.profraw".to_owned())
+    }
+}
+
 /// Configuration passed to the function returned by the `target_machine_factory`.
 pub struct TargetMachineFactoryConfig {
     /// Split DWARF is enabled in LLVM by checking that `TM.MCOptions.SplitDwarfFile` isn't empty,
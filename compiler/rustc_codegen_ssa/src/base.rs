@@ -623,6 +623,14 @@ pub fn codegen_crate<B: ExtraBackendMethods>(
     // codegen units.
     let codegen_units = tcx.collect_and_partition_mono_items(()).1;
 
+    // Under `-Zcoverage-options=const-eval`, the const evaluation that can
+    // reach this crate's code has been done by the mono item collector. Freeze
+    // the counts before any CGU can be codegenned on another thread, so that
+    // every CGU sees the same counts.
+    if tcx.sess.coverage_const_eval() {
+        tcx.freeze_const_eval_coverage();
+    }
+
     // Force all codegen_unit queries so they are already either red or green
     // when compile_codegen_unit accesses them. We are not able to re-execute
     // the codegen_unit query from just the DepNode, so an unknown color would
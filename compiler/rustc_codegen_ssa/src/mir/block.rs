@@ -365,6 +365,7 @@ fn codegen_switchint_terminator(
         let discr = self.codegen_operand(bx, discr);
         let discr_value = discr.immediate();
         let switch_ty = discr.layout.ty;
+        self.codegen_switch_arm_count(bx, helper.bb, discr_value);
         // If our discriminant is a constant we can branch directly
         if let Some(const_discr) = bx.const_to_opt_u128(discr_value, false) {
             let target = targets.target_for_value(const_discr);
@@ -386,23 +387,34 @@ fn codegen_switchint_terminator(
             // so there is no expectation. If they differ, the `target` branch is expected
             // when the `otherwise` branch is cold.
             let expect = if target_cold == otherwise_cold { None } else { Some(otherwise_cold) };
-            if switch_ty == bx.tcx().types.bool {
+            // Counts from a profile take precedence over the expectation, if known.
+            let weights = self
+                .switch_edge_counts(helper.bb, targets)
+                .map(|counts| (counts[0], counts[1]));
+            let (cond, then_llbb, else_llbb, expect, weights) = if switch_ty == bx.tcx().types.bool
+            {
                 // Don't generate trivial icmps when switching on bool.
                 match test_value {
-                    0 => {
-                        let expect = expect.map(|e| !e);
-                        bx.cond_br_with_expect(discr_value, llotherwise, lltarget, expect);
-                    }
-                    1 => {
-                        bx.cond_br_with_expect(discr_value, lltarget, llotherwise, expect);
-                    }
+                    0 => (
+                        discr_value,
+                        llotherwise,
+                        lltarget,
+                        expect.map(|e| !e),
+                        weights.map(|(target, otherwise)| (otherwise, target)),
+                    ),
+                    1 => (discr_value, lltarget, llotherwise, expect, weights),
                     _ => bug!(),
                 }
             } else {
                 let switch_llty = bx.immediate_backend_type(bx.layout_of(switch_ty));
                 let llval = bx.const_uint_big(switch_llty, test_value);
                 let cmp = bx.icmp(IntPredicate::IntEQ, discr_value, llval);
-                bx.cond_br_with_expect(cmp, lltarget, llotherwise, expect);
+                (cmp, lltarget, llotherwise, expect, weights)
+            };
+            if let Some((then_weight, else_weight)) = weights {
+                bx.cond_br_with_weights(cond, then_llbb, else_llbb, then_weight, else_weight);
+            } else {
+                bx.cond_br_with_expect(cond, then_llbb, else_llbb, expect);
             }
         } else if self.cx.sess().opts.optimize == OptLevel::No
             && target_iter.len() == 2
@@ -428,6 +440,16 @@ fn codegen_switchint_terminator(
             let llval = bx.const_uint_big(switch_llty, test_value1);
             let cmp = bx.icmp(IntPredicate::IntEQ, discr_value, llval);
             bx.cond_br(cmp, ll1, ll2);
+        } else if let Some(counts) = self.switch_edge_counts(helper.bb, targets) {
+            let (&otherwise_count, target_counts) = counts.split_last().unwrap();
+            bx.switch_with_weights(
+                discr_value,
+                helper.llbb_with_cleanup(self, targets.otherwise()),
+                otherwise_count,
+                target_iter.zip(target_counts).map(|((value, target), &count)| {
+                    (value, helper.llbb_with_cleanup(self, target), count)
+                }),
+            );
         } else {
             bx.switch(
                 discr_value,
@@ -1125,6 +1147,11 @@ fn codegen_call_terminator(
             (_, Some(llfn)) => llfn,
             _ => span_bug!(span, "no instance or llfn for call"),
         };
+        if bx.tcx().sess.coverage_call_graph() {
+            // Only direct calls have a callee that is known at compile time.
+            let callee = if llfn.is_none() { instance } else { None };
+            bx.record_coverage_call(self.instance, callee, fn_ptr);
+        }
         helper.do_call(
             self,
             bx,
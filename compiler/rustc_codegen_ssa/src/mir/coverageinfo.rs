@@ -1,19 +1,200 @@
-use rustc_middle::mir::SourceScope;
-use rustc_middle::mir::coverage::CoverageKind;
+use rustc_data_structures::fx::FxIndexSet;
+use rustc_index::{IndexSlice, IndexVec};
+use rustc_middle::mir::coverage::{CovTerm, CoverageKind, Expression, ExpressionId, Op};
+use rustc_middle::mir::{self, BasicBlock, SourceScope, StatementKind, SwitchTargets};
+use rustc_middle::ty::Instance;
 
 use super::FunctionCx;
 use crate::traits::*;
 
 impl<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>> FunctionCx<'a, 'tcx, Bx> {
     pub(crate) fn codegen_coverage(&self, bx: &mut Bx, kind: &CoverageKind, scope: SourceScope) {
-        // Determine the instance that coverage data was originally generated for.
-        let instance = if let Some(inlined) = scope.inlined_instance(&self.mir.source_scopes) {
+        let instance = self.coverage_instance(scope);
+
+        // Handle the coverage info in a backend-specific way.
+        bx.add_coverage(instance, kind);
+    }
+
+    /// Determines the instance that coverage data in the given scope was
+    /// originally generated for.
+    fn coverage_instance(&self, scope: SourceScope) -> Instance<'tcx> {
+        if let Some(inlined) = scope.inlined_instance(&self.mir.source_scopes) {
             self.monomorphize(inlined)
         } else {
             self.instance
-        };
+        }
+    }
 
-        // Handle the coverage info in a backend-specific way.
-        bx.add_coverage(instance, kind);
+    /// Returns each distinct instance that was MIR-inlined into this function,
+    /// since coverage statements in the inlined code belong to that instance.
+    pub(crate) fn inlined_coverage_instances(&self) -> Vec<Instance<'tcx>> {
+        if !self.cx.sess().instrument_coverage() {
+            return vec![];
+        }
+        let mut instances = FxIndexSet::default();
+        for scope_data in &self.mir.source_scopes {
+            if let Some((inlined, _)) = scope_data.inlined {
+                instances.insert(self.monomorphize(inlined));
+            }
+        }
+        instances.into_iter().collect()
+    }
+
+    /// Under `-Zcoverage-options=switch-arms`, counts the arm of the switch
+    /// terminating `bb` that `discr` selects, if the switch was marked by the
+    /// coverage instrumentor.
+    pub(crate) fn codegen_switch_arm_count(&self, bx: &mut Bx, bb: BasicBlock, discr: Bx::Value) {
+        if !bx.tcx().sess.coverage_switch_arms() {
+            return;
+        }
+        for statement in &self.mir[bb].statements {
+            if let StatementKind::Coverage(kind @ CoverageKind::SwitchArmCount { .. }) =
+                &statement.kind
+            {
+                let instance = self.coverage_instance(statement.source_info.scope);
+                bx.add_coverage_switch_arm(instance, kind, discr);
+            }
+        }
+    }
+
+    /// Under `-Zprofile-use-coverage`, returns the profiled execution count of
+    /// each edge out of the `SwitchInt` terminating `bb`, in the order of
+    /// [`SwitchTargets::all_targets`], if all of them are known.
+    pub(crate) fn switch_edge_counts(
+        &self,
+        bb: BasicBlock,
+        targets: &SwitchTargets,
+    ) -> Option<Vec<u64>> {
+        let block_counts = self.block_counts.as_ref()?;
+        let predecessors = self.mir.basic_blocks.predecessors();
+
+        // A target's count is only the count of the edge leading to it if the
+        // target can't be reached in any other way.
+        let mut edge_counts = targets
+            .all_targets()
+            .iter()
+            .map(|&target| if predecessors[target][..] == [bb] { block_counts[target] } else { None })
+            .collect::<Vec<_>>();
+
+        // If exactly one edge is unknown, it accounts for whatever the other
+        // edges don't.
+        if let Some(total) = block_counts[bb]
+            && let [unknown] = edge_counts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, count)| count.is_none().then_some(i))
+                .collect::<Vec<_>>()[..]
+        {
+            let known = edge_counts.iter().flatten().fold(0u64, |a, &b| a.saturating_add(b));
+            edge_counts[unknown] = Some(total.saturating_sub(known));
+        }
+
+        edge_counts.into_iter().collect()
+    }
+}
+
+/// Under `-Zprofile-use-coverage`, derives an execution count for as many MIR
+/// blocks as possible from the counters that a `-Cinstrument-coverage` build
+/// of the same code recorded in the `-Cprofile-use` profile.
+///
+/// The `InstrumentCoverage` MIR pass also runs in this mode, so blocks that
+/// would have incremented a counter, or that correspond to a counter
+/// expression, still contain the corresponding coverage statements.
+pub(super) fn profiled_block_counts<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
+    bx: &mut Bx,
+    instance: Instance<'tcx>,
+    mir: &mir::Body<'tcx>,
+) -> Option<IndexVec<BasicBlock, Option<u64>>> {
+    if !bx.tcx().sess.profile_use_coverage() {
+        return None;
+    }
+    let fn_cov_info = mir.function_coverage_info.as_deref()?;
+    let counters = bx.coverage_profile_counters(instance)?;
+
+    let mut evaluator = TermEvaluator {
+        counters: &counters,
+        expressions: &fn_cov_info.expressions,
+        expression_values: IndexVec::from_elem(None, &fn_cov_info.expressions),
+    };
+
+    let mut block_counts = IndexVec::from_elem(None, &mir.basic_blocks);
+    for (bb, data) in mir.basic_blocks.iter_enumerated() {
+        for statement in &data.statements {
+            let StatementKind::Coverage(kind) = &statement.kind else { continue };
+            // Statements inlined from other functions refer to their counters.
+            if statement.source_info.scope.inlined_instance(&mir.source_scopes).is_some() {
+                continue;
+            }
+            block_counts[bb] = match *kind {
+                CoverageKind::CounterIncrement { id } => {
+                    Some(evaluator.evaluate(CovTerm::Counter(id)))
+                }
+                CoverageKind::ExpressionUsed { id } => {
+                    Some(evaluator.evaluate(CovTerm::Expression(id)))
+                }
+                _ => continue,
+            };
+        }
+    }
+
+    // Spread the known counts along straight-line control flow, ignoring
+    // unwind edges, since unwinding is assumed to be rare.
+    let predecessors = mir.basic_blocks.predecessors();
+    let single_successor = |bb: BasicBlock| {
+        let is_cleanup = mir[bb].is_cleanup;
+        let mut successors =
+            mir[bb].terminator().successors().filter(|&succ| mir[succ].is_cleanup == is_cleanup);
+        let succ = successors.next()?;
+        successors.next().is_none().then_some(succ)
+    };
+    for &bb in mir.basic_blocks.reverse_postorder() {
+        if block_counts[bb].is_none()
+            && let [pred] = predecessors[bb][..]
+            && single_successor(pred) == Some(bb)
+        {
+            block_counts[bb] = block_counts[pred];
+        }
+    }
+    for &bb in mir.basic_blocks.postorder() {
+        if block_counts[bb].is_none()
+            && let Some(succ) = single_successor(bb)
+            && predecessors[succ][..] == [bb]
+        {
+            block_counts[bb] = block_counts[succ];
+        }
+    }
+
+    Some(block_counts)
+}
+
+/// Evaluates coverage terms against the counter values read from a profile.
+struct TermEvaluator<'a> {
+    counters: &'a [u64],
+    expressions: &'a IndexSlice<ExpressionId, Expression>,
+    expression_values: IndexVec<ExpressionId, Option<u64>>,
+}
+
+impl TermEvaluator<'_> {
+    fn evaluate(&mut self, term: CovTerm) -> u64 {
+        match term {
+            CovTerm::Zero => 0,
+            // Counters that were removed by MIR optimizations in the
+            // instrumented build aren't in the profile, and were never
+            // incremented.
+            CovTerm::Counter(id) => self.counters.get(id.as_usize()).copied().unwrap_or(0),
+            CovTerm::Expression(id) => {
+                if let Some(value) = self.expression_values[id] {
+                    return value;
+                }
+                let Expression { lhs, op, rhs } = self.expressions[id];
+                let (lhs, rhs) = (self.evaluate(lhs), self.evaluate(rhs));
+                let value = match op {
+                    Op::Add => lhs.saturating_add(rhs),
+                    Op::Subtract => lhs.saturating_sub(rhs),
+                };
+                self.expression_values[id] = Some(value);
+                value
+            }
+        }
     }
 }
@@ -96,6 +96,10 @@ pub struct FunctionCx<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>> {
     /// A cold block is a block that is unlikely to be executed at runtime.
     cold_blocks: IndexVec<mir::BasicBlock, bool>,
 
+    /// Under `-Zprofile-use-coverage`, the number of times each basic block
+    /// was executed according to the profile, where that can be determined.
+    block_counts: Option<IndexVec<mir::BasicBlock, Option<u64>>>,
+
     /// The location where each MIR arg/var/tmp/ret is stored. This is
     /// usually an `PlaceRef` representing an alloca, but not always:
     /// sometimes we can skip the alloca and just store the value
@@ -204,6 +208,8 @@ pub fn codegen_mir<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
             })
             .collect();
 
+    let block_counts = coverageinfo::profiled_block_counts(&mut start_bx, instance, mir);
+
     let mut fx = FunctionCx {
         instance,
         mir,
@@ -218,6 +224,7 @@ pub fn codegen_mir<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
         landing_pads: IndexVec::from_elem(None, &mir.basic_blocks),
         funclets: IndexVec::from_fn_n(|_| None, mir.basic_blocks.len()),
         cold_blocks: find_cold_blocks(cx.tcx(), mir),
+        block_counts,
         locals: locals::Locals::empty(),
         debug_context,
         per_local_var_debug_info: None,
@@ -288,6 +295,9 @@ pub fn codegen_mir<'a, 'tcx, Bx: BuilderMethods<'a, 'tcx>>(
     // If the backend supports coverage, and coverage is enabled for this function,
     // do any necessary start-of-function codegen (e.g. locals for MC/DC bitmaps).
     start_bx.init_coverage(instance);
+    for inlined in fx.inlined_coverage_instances() {
+        start_bx.init_inlined_coverage(inlined);
+    }
 
     // The builders will be created separately for each basic block at `codegen_block`.
     // So drop the builder of `start_llbb` to avoid having two at the same time.
@@ -104,12 +104,43 @@ fn cond_br_with_expect(
         self.cond_br(cond, then_llbb, else_llbb)
     }
 
+    // Conditional with branch weights taken from a profile.
+    //
+    // This function is opt-in for back ends.
+    //
+    // The default implementation ignores the weights and calls `self.cond_br()`.
+    fn cond_br_with_weights(
+        &mut self,
+        cond: Self::Value,
+        then_llbb: Self::BasicBlock,
+        else_llbb: Self::BasicBlock,
+        _then_weight: u64,
+        _else_weight: u64,
+    ) {
+        self.cond_br(cond, then_llbb, else_llbb)
+    }
+
     fn switch(
         &mut self,
         v: Self::Value,
         else_llbb: Self::BasicBlock,
         cases: impl ExactSizeIterator<Item = (u128, Self::BasicBlock)>,
     );
+
+    // Switch with branch weights taken from a profile.
+    //
+    // This function is opt-in for back ends.
+    //
+    // The default implementation ignores the weights and calls `self.switch()`.
+    fn switch_with_weights(
+        &mut self,
+        v: Self::Value,
+        else_llbb: Self::BasicBlock,
+        _else_weight: u64,
+        cases: impl ExactSizeIterator<Item = (u128, Self::BasicBlock, u64)>,
+    ) {
+        self.switch(v, else_llbb, cases.map(|(value, llbb, _weight)| (value, llbb)))
+    }
     fn invoke(
         &mut self,
         llty: Self::Type,
@@ -1,15 +1,58 @@
 use rustc_middle::mir::coverage::CoverageKind;
 use rustc_middle::ty::Instance;
 
-pub trait CoverageInfoBuilderMethods<'tcx> {
+use super::BackendTypes;
+
+pub trait CoverageInfoBuilderMethods<'tcx>: BackendTypes {
     /// Performs any start-of-function codegen needed for coverage instrumentation.
     ///
     /// Can be a no-op in backends that don't support coverage instrumentation.
     fn init_coverage(&mut self, _instance: Instance<'tcx>) {}
 
+    /// Performs any start-of-function codegen needed for coverage statements
+    /// that were MIR-inlined from the given instance into this function.
+    ///
+    /// Can be a no-op in backends that don't support coverage instrumentation.
+    fn init_inlined_coverage(&mut self, _instance: Instance<'tcx>) {}
+
     /// Handle the MIR coverage info in a backend-specific way.
     ///
     /// This can potentially be a no-op in backends that don't support
     /// coverage instrumentation.
     fn add_coverage(&mut self, instance: Instance<'tcx>, kind: &CoverageKind);
+
+    /// Under `-Zcoverage-options=call-graph`, records a call made by `caller`,
+    /// either to the statically known `callee`, or else to whichever function
+    /// `fn_ptr` points to at runtime.
+    ///
+    /// Can be a no-op in backends that don't support coverage instrumentation.
+    fn record_coverage_call(
+        &mut self,
+        _caller: Instance<'tcx>,
+        _callee: Option<Instance<'tcx>>,
+        _fn_ptr: Self::Value,
+    ) {
+    }
+
+    /// Under `-Zcoverage-options=switch-arms`, counts the arm that `discr`
+    /// selects in a switch marked by a [`CoverageKind::SwitchArmCount`]
+    /// statement, just before the switch itself is emitted.
+    ///
+    /// Can be a no-op in backends that don't support coverage instrumentation.
+    fn add_coverage_switch_arm(
+        &mut self,
+        _instance: Instance<'tcx>,
+        _kind: &CoverageKind,
+        _discr: Self::Value,
+    ) {
+    }
+
+    /// Returns the counter values recorded for `instance` in the profile given
+    /// to `-Cprofile-use`, under `-Zprofile-use-coverage`, indexed by counter ID.
+    ///
+    /// Returns `None` in backends that can't read coverage profiles, or if the
+    /// profile has no matching record for this function.
+    fn coverage_profile_counters(&mut self, _instance: Instance<'tcx>) -> Option<Vec<u64>> {
+        None
+    }
 }
@@ -584,6 +584,26 @@ fn increment_const_eval_counter(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<
         interp_ok(())
     }
 
+    fn coverage_counter_increment(
+        ecx: &mut InterpCx<'tcx, Self>,
+        instance: ty::Instance<'tcx>,
+        id: mir::coverage::CounterId,
+    ) -> InterpResult<'tcx> {
+        if !ecx.tcx.sess.coverage_const_eval() {
+            return interp_ok(());
+        }
+        // Only count functions whose counters will also appear in this crate's
+        // coverage map. MIR for const eval is never inlined, so the counter
+        // belongs to the current frame's body.
+        if let ty::InstanceKind::Item(def_id) = instance.def
+            && let Some(def_id) = def_id.as_local()
+            && let Some(fn_cov_info) = ecx.body().function_coverage_info.as_deref()
+        {
+            ecx.tcx.record_const_eval_coverage(def_id, id, fn_cov_info.num_counters);
+        }
+        interp_ok(())
+    }
+
     #[inline(always)]
     fn expose_provenance(
         _ecx: &InterpCx<'tcx, Self>,
@@ -294,6 +294,18 @@ fn increment_const_eval_counter(_ecx: &mut InterpCx<'tcx, Self>) -> InterpResult
         interp_ok(())
     }
 
+    /// Called when the interpreter encounters a `CoverageKind::CounterIncrement`
+    /// statement. `instance` is the function that the counter belongs to, which
+    /// differs from the current frame's function if the statement was inlined.
+    #[inline]
+    fn coverage_counter_increment(
+        _ecx: &mut InterpCx<'tcx, Self>,
+        _instance: ty::Instance<'tcx>,
+        _id: mir::coverage::CounterId,
+    ) -> InterpResult<'tcx> {
+        interp_ok(())
+    }
+
     /// Called before a global allocation is accessed.
     /// `def_id` is `Some` if this is the "lazy" allocation of a static.
     #[inline]
@@ -126,17 +126,23 @@ pub fn eval_statement(&mut self, stmt: &mir::Statement<'tcx>) -> InterpResult<'t
             // an operational effect.
             AscribeUserType(..) => {}
 
-            // Currently, Miri discards Coverage statements. Coverage statements are only injected
-            // via an optional compile time MIR pass and have no side effects. Since Coverage
-            // statements don't exist at the source level, it is safe for Miri to ignore them, even
-            // for undefined behavior (UB) checks.
-            //
-            // A coverage counter inside a const expression (for example, a counter injected in a
-            // const function) is discarded when the const is evaluated at compile time. Whether
-            // this should change, and/or how to implement a const eval counter, is a subject of the
-            // following issue:
-            //
-            // FIXME(#73156): Handle source code coverage in const eval
+            // Coverage statements are only injected via an optional compile time MIR pass and
+            // have no side effects. Since Coverage statements don't exist at the source level, it
+            // is safe to ignore them, even for undefined behavior (UB) checks. Machines can still
+            // observe counter increments, e.g. to report coverage of const-evaluated code under
+            // `-Zcoverage-options=const-eval`.
+            Coverage(mir::coverage::CoverageKind::CounterIncrement { id }) => {
+                // A counter inlined from another function belongs to that function.
+                let instance =
+                    match stmt.source_info.scope.inlined_instance(&self.body().source_scopes) {
+                        Some(inlined) => self
+                            .instantiate_from_current_frame_and_normalize_erasing_regions(
+                                inlined,
+                            )?,
+                        None => self.frame().instance(),
+                    };
+                M::coverage_counter_increment(self, instance, *id)?;
+            }
             Coverage(..) => {}
 
             ConstEvalCounter => {
@@ -9,9 +9,12 @@
 //! Thomas Lengauer and Robert Endre Tarjan.
 //! <https://www.cs.princeton.edu/courses/archive/spr03/cs423/download/dominators.pdf>
 
+use std::ops::Range;
+
+use either::Either;
 use rustc_index::{Idx, IndexSlice, IndexVec};
 
-use super::ControlFlowGraph;
+use super::{ControlFlowGraph, DirectedGraph, Predecessors, StartNode, Successors};
 
 #[cfg(test)]
 mod tests;
@@ -34,7 +37,9 @@ pub struct Dominators<Node: Idx> {
 #[derive(Clone, Debug)]
 enum Kind<Node: Idx> {
     /// A representation optimized for a small path graphs.
-    Path,
+    Path {
+        num_nodes: usize,
+    },
     General(Inner<Node>),
 }
 
@@ -42,12 +47,79 @@ pub fn dominators<G: ControlFlowGraph>(g: &G) -> Dominators<G::Node> {
     // We often encounter MIR bodies with 1 or 2 basic blocks. Special case the dominators
     // computation and representation for those cases.
     if is_small_path_graph(g) {
-        Dominators { kind: Kind::Path }
+        Dominators { kind: Kind::Path { num_nodes: g.num_nodes() } }
     } else {
         Dominators { kind: Kind::General(dominators_impl(g)) }
     }
 }
 
+/// Computes the post-dominators of `g`, i.e. the dominators of the reversed
+/// graph. Every node without successors is treated as an exit, so functions
+/// with several returns (or diverging calls) are handled by joining all exits
+/// into a single virtual exit node.
+///
+/// Nodes that cannot reach any exit (e.g. nodes inside an infinite loop) are
+/// considered unreachable, and have no post-dominators.
+pub fn post_dominators<G: DirectedGraph + Successors + Predecessors>(
+    g: &G,
+) -> PostDominators<G::Node> {
+    post_dominators_with_exits(g, |n| g.successors(n).next().is_none())
+}
+
+/// Like [`post_dominators`], but treats every node for which `is_exit` returns
+/// true as an exit, even if it has successors. This is useful for graphs where
+/// execution can also stop at some inner nodes (e.g. at a coroutine yield).
+pub fn post_dominators_with_exits<G: DirectedGraph + Successors + Predecessors>(
+    g: &G,
+    is_exit: impl Fn(G::Node) -> bool,
+) -> PostDominators<G::Node> {
+    let exits = (0..g.num_nodes()).map(G::Node::new).filter(|&n| is_exit(n)).collect();
+    let reversed = ReversedWithVirtualExit { graph: g, exits };
+    PostDominators { dominators: dominators(&reversed), virtual_exit: reversed.start_node() }
+}
+
+/// Adapts a graph by reversing all of its edges, and adding a virtual start
+/// node (numbered after all real nodes) whose successors are the real exits.
+struct ReversedWithVirtualExit<'g, G: DirectedGraph> {
+    graph: &'g G,
+    /// The real exit nodes, in ascending order.
+    exits: Vec<G::Node>,
+}
+
+impl<G: DirectedGraph> DirectedGraph for ReversedWithVirtualExit<'_, G> {
+    type Node = G::Node;
+
+    fn num_nodes(&self) -> usize {
+        self.graph.num_nodes() + 1
+    }
+}
+
+impl<G: DirectedGraph> StartNode for ReversedWithVirtualExit<'_, G> {
+    fn start_node(&self) -> G::Node {
+        G::Node::new(self.graph.num_nodes())
+    }
+}
+
+impl<G: Predecessors> Successors for ReversedWithVirtualExit<'_, G> {
+    fn successors(&self, node: G::Node) -> impl Iterator<Item = G::Node> {
+        if node == self.start_node() {
+            Either::Left(self.exits.iter().copied())
+        } else {
+            Either::Right(self.graph.predecessors(node))
+        }
+    }
+}
+
+impl<G: Successors> Predecessors for ReversedWithVirtualExit<'_, G> {
+    fn predecessors(&self, node: G::Node) -> impl Iterator<Item = G::Node> {
+        if node == self.start_node() {
+            return Either::Left(None.into_iter());
+        }
+        let is_exit = self.exits.binary_search_by_key(&node.index(), |n| n.index()).is_ok();
+        Either::Right(self.graph.successors(node).chain(is_exit.then(|| self.start_node())))
+    }
+}
+
 fn is_small_path_graph<G: ControlFlowGraph>(g: &G) -> bool {
     if g.start_node().index() != 0 {
         return false;
@@ -271,9 +343,10 @@ fn dominators_impl<G: ControlFlowGraph>(graph: &G) -> Inner<G::Node> {
     let start_node = graph.start_node();
     immediate_dominators[start_node] = None;
 
-    let time = compute_access_time(start_node, &immediate_dominators);
+    let children = Children::new(&immediate_dominators);
+    let time = compute_access_time(start_node, &children, graph.num_nodes());
 
-    Inner { immediate_dominators, time }
+    Inner { immediate_dominators, children, time }
 }
 
 /// Evaluate the link-eval virtual forest, providing the currently minimum semi
@@ -343,14 +416,59 @@ struct Inner<N: Idx> {
     // possible to get its full list of dominators by looking up the dominator
     // of each dominator.
     immediate_dominators: IndexVec<N, Option<N>>,
+    children: Children<N>,
     time: IndexVec<N, Time>,
 }
 
+/// The children of each node in the dominator tree, i.e. the transpose of
+/// `immediate_dominators`.
+#[derive(Clone, Debug)]
+struct Children<N: Idx> {
+    /// For each node, the range of `nodes` where its children can be found.
+    ranges: IndexVec<N, Range<u32>>,
+    /// Contains the children of all nodes, concatenated.
+    nodes: Vec<N>,
+}
+
+impl<N: Idx> Children<N> {
+    fn new(immediate_dominators: &IndexSlice<N, Option<N>>) -> Self {
+        // Count the children of each node, and use the counts to give each
+        // node a range of `nodes`. Filling each range from the end while
+        // visiting children in index order leaves each range sorted.
+        let mut ranges: IndexVec<N, Range<u32>> = IndexVec::from_elem(0..0, immediate_dominators);
+        for &idom in immediate_dominators.iter() {
+            if let Some(idom) = idom {
+                ranges[idom].end += 1;
+            }
+        }
+        let mut m = 0;
+        for r in ranges.iter_mut() {
+            m += r.end;
+            r.start = m;
+            r.end = m;
+        }
+        let mut nodes = vec![N::new(0); m as usize];
+        for (i, &idom) in immediate_dominators.iter_enumerated().rev() {
+            if let Some(idom) = idom {
+                ranges[idom].start -= 1;
+                nodes[ranges[idom].start as usize] = i;
+            }
+        }
+
+        Children { ranges, nodes }
+    }
+
+    fn of(&self, node: N) -> &[N] {
+        let Range { start, end } = self.ranges[node];
+        &self.nodes[start as usize..end as usize]
+    }
+}
+
 impl<Node: Idx> Dominators<Node> {
     /// Returns true if node is reachable from the start node.
     pub fn is_reachable(&self, node: Node) -> bool {
         match &self.kind {
-            Kind::Path => true,
+            Kind::Path { .. } => true,
             Kind::General(g) => g.time[node].start != 0,
         }
     }
@@ -358,7 +476,7 @@ pub fn is_reachable(&self, node: Node) -> bool {
     /// Returns the immediate dominator of node, if any.
     pub fn immediate_dominator(&self, node: Node) -> Option<Node> {
         match &self.kind {
-            Kind::Path => {
+            Kind::Path { .. } => {
                 if 0 < node.index() {
                     Some(Node::new(node.index() - 1))
                 } else {
@@ -377,7 +495,7 @@ pub fn immediate_dominator(&self, node: Node) -> Option<Node> {
     #[inline]
     pub fn dominates(&self, a: Node, b: Node) -> bool {
         match &self.kind {
-            Kind::Path => a.index() <= b.index(),
+            Kind::Path { .. } => a.index() <= b.index(),
             Kind::General(g) => {
                 let a = g.time[a];
                 let b = g.time[b];
@@ -386,6 +504,62 @@ pub fn dominates(&self, a: Node, b: Node) -> bool {
             }
         }
     }
+
+    /// Returns the children of `node` in the dominator tree, i.e. the nodes
+    /// whose immediate dominator is `node`, in ascending order.
+    pub fn children(&self, node: Node) -> impl DoubleEndedIterator<Item = Node> + '_ {
+        let (path_child, children) = match &self.kind {
+            Kind::Path { num_nodes } => {
+                let child = node.index() + 1;
+                ((child < *num_nodes).then(|| Node::new(child)), &[][..])
+            }
+            Kind::General(g) => (None, g.children.of(node)),
+        };
+        path_child.into_iter().chain(children.iter().copied())
+    }
+
+    /// Returns `node` followed by every node that it dominates, in a
+    /// depth-first preorder walk of the dominator tree.
+    pub fn dominated_nodes(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        let mut stack = vec![node];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            // Push in reverse, so that children are visited in ascending order.
+            stack.extend(self.children(node).rev());
+            Some(node)
+        })
+    }
+}
+
+/// The post-dominator tree of a graph, as computed by [`post_dominators`].
+#[derive(Clone, Debug)]
+pub struct PostDominators<Node: Idx> {
+    dominators: Dominators<Node>,
+    virtual_exit: Node,
+}
+
+impl<Node: Idx> PostDominators<Node> {
+    /// Returns true if some exit node is reachable from `node`.
+    pub fn is_reachable(&self, node: Node) -> bool {
+        self.dominators.is_reachable(node)
+    }
+
+    /// Returns the immediate post-dominator of `node`, if any. Exit nodes,
+    /// and nodes whose paths to an exit diverge, have none.
+    pub fn immediate_post_dominator(&self, node: Node) -> Option<Node> {
+        self.dominators.immediate_dominator(node).filter(|&n| n != self.virtual_exit)
+    }
+
+    /// Returns true if `a` post-dominates `b`, i.e. every path from `b` to an
+    /// exit passes through `a`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no exit is reachable from `b`.
+    #[inline]
+    pub fn post_dominates(&self, a: Node, b: Node) -> bool {
+        self.dominators.dominates(a, b)
+    }
 }
 
 /// Describes the number of vertices discovered at the time when processing of a particular vertex
@@ -398,35 +572,17 @@ struct Time {
 
 fn compute_access_time<N: Idx>(
     start_node: N,
-    immediate_dominators: &IndexSlice<N, Option<N>>,
+    children: &Children<N>,
+    num_nodes: usize,
 ) -> IndexVec<N, Time> {
-    // Transpose the dominator tree edges, so that child nodes of vertex v are stored in
-    // node[edges[v].start..edges[v].end].
-    let mut edges: IndexVec<N, std::ops::Range<u32>> =
-        IndexVec::from_elem(0..0, immediate_dominators);
-    for &idom in immediate_dominators.iter() {
-        if let Some(idom) = idom {
-            edges[idom].end += 1;
-        }
-    }
-    let mut m = 0;
-    for e in edges.iter_mut() {
-        m += e.end;
-        e.start = m;
-        e.end = m;
-    }
-    let mut node = IndexVec::from_elem_n(Idx::new(0), m.try_into().unwrap());
-    for (i, &idom) in immediate_dominators.iter_enumerated() {
-        if let Some(idom) = idom {
-            edges[idom].start -= 1;
-            node[edges[idom].start] = i;
-        }
-    }
+    // For each node, the range of its children that have not been visited yet.
+    let mut edges = children.ranges.clone();
+    let node = &children.nodes;
 
     // Perform a depth-first search of the dominator tree. Record the number of vertices discovered
     // when vertex v is discovered first as time[v].start, and when its processing is finished as
     // time[v].finish.
-    let mut time: IndexVec<N, Time> = IndexVec::from_elem(Time::default(), immediate_dominators);
+    let mut time: IndexVec<N, Time> = IndexVec::from_elem_n(Time::default(), num_nodes);
     let mut stack = Vec::new();
 
     let mut discovered = 1;
@@ -440,7 +596,7 @@ fn compute_access_time<N: Idx>(
             time[i].finish = discovered;
             stack.pop();
         } else {
-            let j = node[e.start];
+            let j = node[e.start as usize];
             e.start += 1;
             // Start processing vertex j.
             discovered += 1;
@@ -89,3 +89,64 @@ fn transitive_dominator() {
     assert_eq!(d.immediate_dominator(2), Some(0));
     assert_eq!(d.immediate_dominator(3), Some(0)); // This used to return Some(1).
 }
+
+#[test]
+fn children() {
+    let graph = TestGraph::new(0, &[(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (1, 5)]);
+
+    let d = dominators(&graph);
+    assert_eq!(d.children(0).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(d.children(1).collect::<Vec<_>>(), [5]);
+    assert_eq!(d.children(2).next(), None);
+    assert_eq!(d.children(3).collect::<Vec<_>>(), [4]);
+    assert_eq!(d.dominated_nodes(0).collect::<Vec<_>>(), [0, 1, 5, 2, 3, 4]);
+    assert_eq!(d.dominated_nodes(3).collect::<Vec<_>>(), [3, 4]);
+}
+
+#[test]
+fn children_small_path() {
+    let graph = TestGraph::new(0, &[(0, 1)]);
+
+    let d = dominators(&graph);
+    assert_eq!(d.children(0).collect::<Vec<_>>(), [1]);
+    assert_eq!(d.children(1).next(), None);
+    assert_eq!(d.dominated_nodes(0).collect::<Vec<_>>(), [0, 1]);
+}
+
+#[test]
+fn post_dominators_multiple_exits() {
+    // Node 2 and node 4 both exit, so only 0 itself post-dominates 0.
+    let graph = TestGraph::new(0, &[(0, 1), (0, 2), (1, 3), (3, 4), (1, 4)]);
+
+    let pd = post_dominators(&graph);
+    assert_eq!(pd.immediate_post_dominator(0), None);
+    assert_eq!(pd.immediate_post_dominator(1), Some(4));
+    assert_eq!(pd.immediate_post_dominator(3), Some(4));
+    assert_eq!(pd.immediate_post_dominator(2), None);
+    assert_eq!(pd.immediate_post_dominator(4), None);
+    assert!(pd.post_dominates(4, 1));
+    assert!(!pd.post_dominates(4, 0));
+}
+
+#[test]
+fn post_dominators_infinite_loop() {
+    let graph = TestGraph::new(0, &[(0, 1), (0, 2), (1, 1), (2, 3)]);
+
+    let pd = post_dominators(&graph);
+    assert!(!pd.is_reachable(1));
+    assert!(pd.is_reachable(0));
+    assert_eq!(pd.immediate_post_dominator(0), Some(2));
+    assert_eq!(pd.immediate_post_dominator(2), Some(3));
+}
+
+#[test]
+fn post_dominators_inner_exit() {
+    // Execution can also stop at node 1, so node 2 no longer post-dominates it.
+    let graph = TestGraph::new(0, &[(0, 1), (1, 2), (2, 3)]);
+
+    let pd = post_dominators_with_exits(&graph, |n| n == 1 || n == 3);
+    assert_eq!(pd.immediate_post_dominator(0), Some(1));
+    assert_eq!(pd.immediate_post_dominator(1), None);
+    assert_eq!(pd.immediate_post_dominator(2), Some(3));
+    assert!(!pd.post_dominates(2, 1));
+}
@@ -0,0 +1,148 @@
+//! Identifying the natural loops of a control-flow graph, and how they nest.
+//!
+//! A node is a *loop header* if it dominates one or more of its predecessors;
+//! each such in-edge is a *back edge*. The natural loop of a header consists of
+//! the header itself, plus every node that can reach one of its back-edge
+//! sources without passing through the header. Natural loops with distinct
+//! headers are either disjoint or strictly nested, so together they form a
+//! forest, which is what [`LoopForest`] records.
+//!
+//! Irreducible cycles (which have no single dominating entry node) are not
+//! considered to be loops.
+
+use rustc_index::{Idx, IndexVec};
+
+use super::dominators::Dominators;
+use super::{ControlFlowGraph, iterate};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Debug)]
+pub struct LoopForest<N: Idx> {
+    /// For each node, the header of the innermost loop that contains it, if
+    /// any. A loop header is always the innermost header of its own loop.
+    innermost_header: IndexVec<N, Option<N>>,
+    /// For each loop header, the header of the next loop out, if any.
+    parent_header: IndexVec<N, Option<N>>,
+    /// All loop headers, in reverse postorder (so outer loops come before the
+    /// loops nested within them).
+    headers: Vec<N>,
+}
+
+impl<N: Idx> LoopForest<N> {
+    /// Computes the loop forest of `graph`, whose dominators must already have
+    /// been computed. Nodes that are unreachable from the start node are never
+    /// part of any loop.
+    pub fn new<G: ControlFlowGraph<Node = N>>(graph: &G, dominators: &Dominators<N>) -> Self {
+        let num_nodes = graph.num_nodes();
+        let mut innermost_header = IndexVec::from_elem_n(None, num_nodes);
+        let mut parent_header = IndexVec::from_elem_n(None, num_nodes);
+
+        // An edge is a back edge if its target dominates its source.
+        let back_edge_sources = move |header: N| {
+            graph.predecessors(header).filter(move |&pred| {
+                dominators.is_reachable(pred) && dominators.dominates(header, pred)
+            })
+        };
+
+        let rpo = iterate::reverse_post_order(graph, graph.start_node());
+        let headers = rpo
+            .into_iter()
+            .filter(|&node| back_edge_sources(node).next().is_some())
+            .collect::<Vec<_>>();
+        for &header in &headers {
+            innermost_header[header] = Some(header);
+        }
+
+        // Walk the headers so that inner loops are discovered before the loops
+        // that enclose them. A dominated node always comes after its dominator
+        // in reverse postorder, so iterating backwards visits inner headers
+        // first.
+        let mut stack = vec![];
+        for &header in headers.iter().rev() {
+            stack.extend(back_edge_sources(header));
+
+            while let Some(node) = stack.pop() {
+                if node == header || !dominators.is_reachable(node) {
+                    continue;
+                }
+                match innermost_header[node] {
+                    None => {
+                        innermost_header[node] = Some(header);
+                        stack.extend(graph.predecessors(node));
+                    }
+                    Some(inner) => {
+                        // The node is already part of a loop that was
+                        // discovered earlier. Find the outermost loop known
+                        // so far, and if it isn't this one, adopt it as a
+                        // child and continue the search from its entry.
+                        let mut outermost = inner;
+                        while let Some(parent) = parent_header[outermost] {
+                            outermost = parent;
+                        }
+                        if outermost != header {
+                            parent_header[outermost] = Some(header);
+                            stack.extend(graph.predecessors(outermost));
+                        }
+                    }
+                }
+            }
+        }
+
+        Self { innermost_header, parent_header, headers }
+    }
+
+    /// Returns true if `node` is the header of some loop.
+    pub fn is_loop_header(&self, node: N) -> bool {
+        self.innermost_header[node] == Some(node)
+    }
+
+    /// Returns all loop headers, such that the header of an enclosing loop
+    /// comes before the headers of the loops nested within it.
+    pub fn loop_headers(&self) -> &[N] {
+        &self.headers
+    }
+
+    /// Returns the header of the innermost loop containing `node`. If `node`
+    /// is itself a loop header, that is the node itself.
+    pub fn innermost_loop(&self, node: N) -> Option<N> {
+        self.innermost_header[node]
+    }
+
+    /// Returns the header of the loop that immediately encloses the loop
+    /// headed by `header`, if any.
+    pub fn parent_loop(&self, header: N) -> Option<N> {
+        debug_assert!(self.is_loop_header(header));
+        self.parent_header[header]
+    }
+
+    /// For each loop that contains `node`, yields the header of that loop,
+    /// from innermost to outermost. If `node` is itself a loop header, it is
+    /// yielded first.
+    pub fn loops_containing(&self, node: N) -> impl Iterator<Item = N> + '_ {
+        let mut next = self.innermost_header[node];
+        std::iter::from_fn(move || {
+            let header = next?;
+            next = self.parent_header[header];
+            Some(header)
+        })
+    }
+
+    /// Returns the number of loops that contain `node`.
+    pub fn loop_depth(&self, node: N) -> usize {
+        self.loops_containing(node).count()
+    }
+
+    /// Returns true if `node` is part of the loop headed by `header`,
+    /// including loops nested within it.
+    pub fn loop_contains(&self, header: N, node: N) -> bool {
+        self.loops_containing(node).any(|h| h == header)
+    }
+
+    /// Yields every node in the body of the loop headed by `header`,
+    /// including the header itself and the bodies of nested loops.
+    pub fn loop_body(&self, header: N) -> impl Iterator<Item = N> + '_ {
+        self.innermost_header.indices().filter(move |&node| self.loop_contains(header, node))
+    }
+}
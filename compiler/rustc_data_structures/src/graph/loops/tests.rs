@@ -0,0 +1,84 @@
+use super::super::dominators::dominators;
+use super::super::tests::TestGraph;
+use super::*;
+
+fn loop_forest(graph: &TestGraph) -> LoopForest<usize> {
+    LoopForest::new(graph, &dominators(graph))
+}
+
+#[test]
+fn acyclic() {
+    let graph = TestGraph::new(0, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+    let loops = loop_forest(&graph);
+
+    assert!(loops.loop_headers().is_empty());
+    for node in 0..4 {
+        assert!(!loops.is_loop_header(node));
+        assert_eq!(loops.innermost_loop(node), None);
+        assert_eq!(loops.loop_depth(node), 0);
+    }
+}
+
+#[test]
+fn self_loop() {
+    let graph = TestGraph::new(0, &[(0, 1), (1, 1), (1, 2)]);
+    let loops = loop_forest(&graph);
+
+    assert_eq!(loops.loop_headers(), &[1]);
+    assert_eq!(loops.innermost_loop(0), None);
+    assert_eq!(loops.innermost_loop(1), Some(1));
+    assert_eq!(loops.innermost_loop(2), None);
+    assert_eq!(loops.loop_body(1).collect::<Vec<_>>(), [1]);
+}
+
+#[test]
+fn nested() {
+    // 0 -> 1 (outer header) -> 2 (inner header) -> 3 -> 2
+    //                                2 -> 4 -> 1
+    //                                1 -> 5
+    let graph = TestGraph::new(0, &[(0, 1), (1, 2), (2, 3), (3, 2), (2, 4), (4, 1), (1, 5)]);
+    let loops = loop_forest(&graph);
+
+    assert_eq!(loops.loop_headers(), &[1, 2]);
+    assert_eq!(loops.parent_loop(1), None);
+    assert_eq!(loops.parent_loop(2), Some(1));
+
+    assert_eq!(loops.loops_containing(3).collect::<Vec<_>>(), [2, 1]);
+    assert_eq!(loops.loops_containing(2).collect::<Vec<_>>(), [2, 1]);
+    assert_eq!(loops.loops_containing(4).collect::<Vec<_>>(), [1]);
+    assert_eq!(loops.loop_depth(5), 0);
+
+    assert_eq!(loops.loop_body(1).collect::<Vec<_>>(), [1, 2, 3, 4]);
+    assert_eq!(loops.loop_body(2).collect::<Vec<_>>(), [2, 3]);
+}
+
+#[test]
+fn after_loop_is_not_in_loop() {
+    // Node 3 is dominated by the loop header 1, but is not part of its loop.
+    let graph = TestGraph::new(0, &[(0, 1), (1, 2), (2, 1), (1, 3), (3, 4)]);
+    let loops = loop_forest(&graph);
+
+    assert!(loops.is_loop_header(1));
+    assert!(loops.loop_contains(1, 2));
+    assert!(!loops.loop_contains(1, 3));
+    assert_eq!(loops.loop_depth(4), 0);
+}
+
+#[test]
+fn irreducible() {
+    // The cycle between 1 and 2 has two entries, so neither node dominates
+    // the other and there is no natural loop.
+    let graph = TestGraph::new(0, &[(0, 1), (0, 2), (1, 2), (2, 1)]);
+    let loops = loop_forest(&graph);
+
+    assert!(loops.loop_headers().is_empty());
+}
+
+#[test]
+fn unreachable_predecessor() {
+    let graph = TestGraph::new(0, &[(0, 1), (1, 2), (2, 1), (3, 2)]);
+    let loops = loop_forest(&graph);
+
+    assert_eq!(loops.loop_body(1).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(loops.innermost_loop(3), None);
+}
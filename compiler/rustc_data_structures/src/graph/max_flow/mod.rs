@@ -0,0 +1,164 @@
+//! Maximum flow and minimum cut on a capacitated directed graph.
+//!
+//! This uses the Edmonds-Karp algorithm (Ford-Fulkerson with breadth-first
+//! augmenting paths), which runs in `O(V * E^2)` time. That is plenty for
+//! control-flow-sized graphs, and the simplicity makes it easy to trust when
+//! experimenting with placement strategies that are phrased as min-cut
+//! problems.
+
+use std::collections::VecDeque;
+
+use rustc_index::bit_set::DenseBitSet;
+use rustc_index::{Idx, IndexVec};
+
+#[cfg(test)]
+mod tests;
+
+/// A directed graph whose edges have non-negative integer capacities.
+///
+/// Edges are identified by the order in which they were added, starting from 0.
+/// Parallel edges and self-loops are permitted. A capacity of `u64::MAX` can be
+/// used to represent an edge that must never be cut.
+#[derive(Clone, Debug)]
+pub struct FlowNetwork<N: Idx> {
+    /// Each added edge is stored as a pair of arcs: the forward arc at index
+    /// `2 * edge`, and its residual reverse arc at index `2 * edge + 1`.
+    arcs: Vec<Arc<N>>,
+    /// For each node, the indices of arcs that leave it.
+    outgoing: IndexVec<N, Vec<usize>>,
+}
+
+#[derive(Clone, Debug)]
+struct Arc<N> {
+    target: N,
+    capacity: u64,
+}
+
+/// The result of [`FlowNetwork::max_flow`].
+#[derive(Clone, Debug)]
+pub struct MaxFlow<N: Idx> {
+    value: u64,
+    edge_flows: Vec<u64>,
+    edge_endpoints: Vec<(N, N)>,
+    source_side: DenseBitSet<N>,
+}
+
+impl<N: Idx> FlowNetwork<N> {
+    pub fn new(num_nodes: usize) -> Self {
+        Self { arcs: vec![], outgoing: IndexVec::from_elem_n(vec![], num_nodes) }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    pub fn num_edges(&self) -> usize {
+        self.arcs.len() / 2
+    }
+
+    /// Adds an edge from `source` to `target`, and returns its index.
+    pub fn add_edge(&mut self, source: N, target: N, capacity: u64) -> usize {
+        let edge = self.num_edges();
+        self.outgoing[source].push(self.arcs.len());
+        self.arcs.push(Arc { target, capacity });
+        self.outgoing[target].push(self.arcs.len());
+        self.arcs.push(Arc { target: source, capacity: 0 });
+        edge
+    }
+
+    /// Computes a maximum flow from `source` to `sink`, along with the
+    /// corresponding minimum cut.
+    ///
+    /// If the maximum flow would exceed `u64::MAX` (i.e. there is a path of
+    /// uncuttable edges), its value saturates.
+    pub fn max_flow(&self, source: N, sink: N) -> MaxFlow<N> {
+        assert_ne!(source, sink, "source and sink must be distinct");
+
+        // Remaining capacity of each arc in the residual graph.
+        let mut residual = self.arcs.iter().map(|arc| arc.capacity).collect::<Vec<_>>();
+        let mut value = 0u64;
+        // The arc used to reach each node in the current breadth-first search.
+        let mut via_arc: IndexVec<N, Option<usize>> = IndexVec::from_elem_n(None, self.num_nodes());
+        let mut queue = VecDeque::new();
+
+        let source_side = loop {
+            via_arc.iter_mut().for_each(|a| *a = None);
+            queue.clear();
+            queue.push_back(source);
+            let mut visited = DenseBitSet::new_empty(self.num_nodes());
+            visited.insert(source);
+
+            'search: while let Some(node) = queue.pop_front() {
+                for &arc in &self.outgoing[node] {
+                    let target = self.arcs[arc].target;
+                    if residual[arc] > 0 && visited.insert(target) {
+                        via_arc[target] = Some(arc);
+                        if target == sink {
+                            break 'search;
+                        }
+                        queue.push_back(target);
+                    }
+                }
+            }
+
+            if !visited.contains(sink) {
+                // No augmenting path remains, so the nodes still reachable
+                // from the source in the residual graph form the source side
+                // of a minimum cut.
+                break visited;
+            }
+
+            // Find the bottleneck along the augmenting path, then push that
+            // much flow along it. Every augmentation saturates at least one
+            // arc, so this terminates even with `u64::MAX` capacities.
+            let path = || {
+                let via_arc = &via_arc;
+                let mut node = sink;
+                std::iter::from_fn(move || {
+                    let arc = via_arc[node]?;
+                    node = self.arcs[arc ^ 1].target;
+                    Some(arc)
+                })
+            };
+            let bottleneck = path().map(|arc| residual[arc]).min().unwrap();
+            for arc in path() {
+                residual[arc] -= bottleneck;
+                residual[arc ^ 1] = residual[arc ^ 1].saturating_add(bottleneck);
+            }
+            value = value.saturating_add(bottleneck);
+        };
+
+        // The flow along an edge is the capacity accumulated by its reverse arc.
+        let edge_flows = (0..self.num_edges()).map(|edge| residual[2 * edge + 1]).collect();
+        let edge_endpoints = (0..self.num_edges())
+            .map(|edge| (self.arcs[2 * edge + 1].target, self.arcs[2 * edge].target))
+            .collect();
+        MaxFlow { value, edge_flows, edge_endpoints, source_side }
+    }
+}
+
+impl<N: Idx> MaxFlow<N> {
+    /// The total amount of flow from the source to the sink, which is also
+    /// the total capacity of the minimum cut.
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// The amount of flow along the given edge.
+    pub fn edge_flow(&self, edge: usize) -> u64 {
+        self.edge_flows[edge]
+    }
+
+    /// Returns true if `node` is on the source side of the minimum cut.
+    pub fn is_source_side(&self, node: N) -> bool {
+        self.source_side.contains(node)
+    }
+
+    /// Yields the indices of edges that cross the minimum cut, i.e. edges
+    /// from a source-side node to a sink-side node.
+    pub fn cut_edges(&self) -> impl Iterator<Item = usize> + '_ {
+        self.edge_endpoints.iter().enumerate().filter_map(|(edge, &(source, target))| {
+            (self.is_source_side(source) && !self.is_source_side(target)).then_some(edge)
+        })
+    }
+}
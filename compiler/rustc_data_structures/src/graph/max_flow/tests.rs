@@ -0,0 +1,88 @@
+use super::*;
+
+#[test]
+fn single_edge() {
+    let mut network = FlowNetwork::<usize>::new(2);
+    let e = network.add_edge(0, 1, 7);
+
+    let flow = network.max_flow(0, 1);
+    assert_eq!(flow.value(), 7);
+    assert_eq!(flow.edge_flow(e), 7);
+    assert_eq!(flow.cut_edges().collect::<Vec<_>>(), [e]);
+}
+
+#[test]
+fn disconnected() {
+    let mut network = FlowNetwork::<usize>::new(3);
+    network.add_edge(0, 1, 5);
+
+    let flow = network.max_flow(0, 2);
+    assert_eq!(flow.value(), 0);
+    assert!(flow.is_source_side(0));
+    assert!(flow.is_source_side(1));
+    assert!(!flow.is_source_side(2));
+    assert_eq!(flow.cut_edges().next(), None);
+}
+
+#[test]
+fn textbook() {
+    // The example network from CLRS, with a maximum flow of 23.
+    let mut network = FlowNetwork::<usize>::new(6);
+    let edges = [
+        (0, 1, 16),
+        (0, 2, 13),
+        (1, 3, 12),
+        (2, 1, 4),
+        (2, 4, 14),
+        (3, 2, 9),
+        (3, 5, 20),
+        (4, 3, 7),
+        (4, 5, 4),
+    ];
+    for (source, target, capacity) in edges {
+        network.add_edge(source, target, capacity);
+    }
+
+    let flow = network.max_flow(0, 5);
+    assert_eq!(flow.value(), 23);
+
+    // The cut capacity equals the flow value.
+    let cut_capacity: u64 = flow.cut_edges().map(|e| edges[e].2).sum();
+    assert_eq!(cut_capacity, 23);
+
+    // Flow is conserved at every interior node, and respects capacities.
+    for node in 1..5 {
+        let inflow: u64 =
+            (0..edges.len()).filter(|&e| edges[e].1 == node).map(|e| flow.edge_flow(e)).sum();
+        let outflow: u64 =
+            (0..edges.len()).filter(|&e| edges[e].0 == node).map(|e| flow.edge_flow(e)).sum();
+        assert_eq!(inflow, outflow);
+    }
+    for (e, &(_, _, capacity)) in edges.iter().enumerate() {
+        assert!(flow.edge_flow(e) <= capacity);
+    }
+}
+
+#[test]
+fn uncuttable_edges() {
+    // The middle edge can't be cut, so the cut must use an outer edge.
+    let mut network = FlowNetwork::<usize>::new(4);
+    let a = network.add_edge(0, 1, 3);
+    network.add_edge(1, 2, u64::MAX);
+    let b = network.add_edge(2, 3, 5);
+
+    let flow = network.max_flow(0, 3);
+    assert_eq!(flow.value(), 3);
+    assert_eq!(flow.cut_edges().collect::<Vec<_>>(), [a]);
+    assert_eq!(flow.edge_flow(b), 3);
+}
+
+#[test]
+fn infinite_path() {
+    let mut network = FlowNetwork::<usize>::new(3);
+    network.add_edge(0, 1, u64::MAX);
+    network.add_edge(1, 2, u64::MAX);
+
+    let flow = network.max_flow(0, 2);
+    assert_eq!(flow.value(), u64::MAX);
+}
@@ -3,6 +3,8 @@
 pub mod dominators;
 pub mod implementation;
 pub mod iterate;
+pub mod loops;
+pub mod max_flow;
 mod reference;
 pub mod scc;
 pub mod vec_graph;
@@ -147,6 +147,40 @@ pub fn successors(&self, scc: S) -> &[S] {
         self.scc_data.successors(scc)
     }
 
+    /// Computes the member nodes of every SCC, in a single pass over the
+    /// nodes of the graph.
+    ///
+    /// The adjacency between SCCs is available directly, via
+    /// [`Self::successors`] and [`Self::reverse`].
+    pub fn members(&self) -> SccMembers<N, S> {
+        // Count the members of each SCC, then use those counts to assign each
+        // SCC a contiguous range of `all_members` (i.e. a counting sort).
+        let mut counts = IndexVec::<S, usize>::from_elem_n(0, self.num_sccs());
+        for &scc in &self.scc_indices {
+            counts[scc] += 1;
+        }
+
+        let mut start = 0;
+        let ranges = counts
+            .into_iter()
+            .map(|count| {
+                let range = start..start + count;
+                start += count;
+                range
+            })
+            .collect::<IndexVec<S, _>>();
+
+        // Nodes are visited in index order, so each SCC's members end up sorted.
+        let mut next_slot = ranges.iter().map(|range| range.start).collect::<IndexVec<S, _>>();
+        let mut all_members = vec![N::new(0); self.scc_indices.len()];
+        for (node, &scc) in self.scc_indices.iter_enumerated() {
+            all_members[next_slot[scc]] = node;
+            next_slot[scc] += 1;
+        }
+
+        SccMembers { ranges, all_members }
+    }
+
     /// Construct the reverse graph of the SCC graph.
     pub fn reverse(&self) -> VecGraph<S> {
         VecGraph::new(
@@ -160,6 +194,30 @@ pub fn reverse(&self) -> VecGraph<S> {
     }
 }
 
+/// The member nodes of each SCC in an [`Sccs`], as returned by [`Sccs::members`].
+pub struct SccMembers<N: Idx, S: Idx> {
+    /// For each SCC, the range of `all_members` where its members can be found.
+    ranges: IndexVec<S, Range<usize>>,
+
+    /// Contains the members of all the SCCs, concatenated.
+    all_members: Vec<N>,
+}
+
+impl<N: Idx, S: Idx> SccMembers<N, S> {
+    /// Returns the nodes that belong to the given SCC, in ascending order.
+    pub fn members(&self, scc: S) -> &[N] {
+        &self.all_members[self.ranges[scc].clone()]
+    }
+
+    /// Returns true if the given SCC consists of a single node.
+    ///
+    /// Note that such an SCC can still contain a cycle, if that node has an
+    /// edge to itself.
+    pub fn is_singleton(&self, scc: S) -> bool {
+        self.ranges[scc].len() == 1
+    }
+}
+
 impl<N: Idx, S: Idx + Ord, A: Annotation> DirectedGraph for Sccs<N, S, A> {
     type Node = S;
 
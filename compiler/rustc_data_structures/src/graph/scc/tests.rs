@@ -104,6 +104,33 @@ fn test_three_sccs() {
     assert_eq!(sccs.successors(2), &[0]);
 }
 
+#[test]
+fn test_members() {
+    /*
+        0
+        |
+        v
+    +-> 1    3
+    |   |    |
+    |   v    |
+    +-- 2 <--+
+         */
+    let graph = TestGraph::new(0, &[(0, 1), (1, 2), (2, 1), (3, 2)]);
+    let sccs: UsizeSccs = Sccs::new(&graph);
+    let members = sccs.members();
+    assert_eq!(members.members(sccs.scc(0)), &[0]);
+    assert_eq!(members.members(sccs.scc(1)), &[1, 2]);
+    assert_eq!(members.members(sccs.scc(3)), &[3]);
+    assert!(members.is_singleton(sccs.scc(0)));
+    assert!(!members.is_singleton(sccs.scc(2)));
+
+    // Every node appears in exactly one SCC.
+    let mut all_members =
+        sccs.all_sccs().flat_map(|scc| members.members(scc).to_vec()).collect::<Vec<_>>();
+    all_members.sort();
+    assert_eq!(all_members, [0, 1, 2, 3]);
+}
+
 #[test]
 fn test_find_state_2() {
     // The order in which things will be visited is important to this
@@ -834,7 +834,8 @@ fn print_crate_info(
             | TlsModels
             | TargetCPUs
             | StackProtectorStrategies
-            | TargetFeatures => {
+            | TargetFeatures
+            | CoverageFormat => {
                 codegen_backend.print(req, &mut crate_info, sess);
             }
             // Any output here interferes with Cargo's parsing of other printed output
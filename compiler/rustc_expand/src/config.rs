@@ -426,7 +426,7 @@ pub(crate) fn maybe_emit_expr_attr_err(&self, attr: &Attribute) {
     #[instrument(level = "trace", skip(self))]
     pub fn configure_expr(&self, expr: &mut P<ast::Expr>, method_receiver: bool) {
         if !method_receiver {
-            for attr in expr.attrs.iter() {
+            for attr in expr.attrs.iter().filter(|attr| !is_ungated_expr_attr(expr, attr)) {
                 self.maybe_emit_expr_attr_err(attr);
             }
         }
@@ -475,3 +475,12 @@ pub fn parse_cfg<'a>(meta_item: &'a MetaItem, sess: &Session) -> Option<&'a Meta
 fn is_cfg(attr: &Attribute) -> bool {
     attr.has_name(sym::cfg)
 }
+
+/// Returns true if `attr` may be applied to `expr` without enabling
+/// `stmt_expr_attributes`. This is the case for `#[coverage]` on closure and
+/// async-block expressions, so that a single closure can be excluded from
+/// coverage instrumentation without moving it into a named function.
+pub(crate) fn is_ungated_expr_attr(expr: &ast::Expr, attr: &Attribute) -> bool {
+    attr.has_name(sym::coverage)
+        && matches!(expr.kind, ast::ExprKind::Closure(..) | ast::ExprKind::Gen(..))
+}
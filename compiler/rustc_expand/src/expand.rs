@@ -33,7 +33,7 @@
 use smallvec::SmallVec;
 
 use crate::base::*;
-use crate::config::StripUnconfigured;
+use crate::config::{StripUnconfigured, is_ungated_expr_attr};
 use crate::errors::{
     EmptyDelegationMac, GlobDelegationOutsideImpls, GlobDelegationTraitlessQpath, IncompleteParse,
     RecursionLimitReached, RemoveExprNotSupported, RemoveNodeNotSupported, UnsupportedKeyValue,
@@ -2186,7 +2186,7 @@ fn visit_pat(&mut self, node: &mut P<ast::Pat>) {
 
     fn visit_expr(&mut self, node: &mut P<ast::Expr>) {
         // FIXME: Feature gating is performed inconsistently between `Expr` and `OptExpr`.
-        if let Some(attr) = node.attrs.first() {
+        if let Some(attr) = node.attrs.iter().find(|attr| !is_ungated_expr_attr(node, attr)) {
             self.cfg().maybe_emit_expr_attr_err(attr);
         }
         self.visit_node(node)
@@ -1023,6 +1023,11 @@ pub struct BuiltinAttribute {
         rustc_force_inline, Normal, template!(Word, NameValueStr: "reason"), WarnFollowing, EncodeCrossCrate::Yes,
         "#![rustc_force_inline] forces a free function to be inlined"
     ),
+    rustc_attr!(
+        rustc_coverage_exclude, Normal, template!(Word), WarnFollowing, EncodeCrossCrate::No,
+        "#[rustc_coverage_exclude] unconditionally excludes an item and its contents from coverage \
+        instrumentation, even if they are marked with `#[coverage(on)]`"
+    ),
 
     // ==========================================================================
     // Internal attributes, Testing:
@@ -250,6 +250,47 @@ pub fn iter(&self) -> BitIter<'_, T> {
         BitIter::new(&self.words)
     }
 
+    /// Returns the number of elements in the set that are strictly less than
+    /// `elem`. This is the position that `elem` has (or would have) in the
+    /// sorted sequence of elements, which makes it useful for indexing compact
+    /// side tables that only have an entry for each element of the set.
+    ///
+    /// `elem` may be equal to the domain size, in which case this is the same
+    /// as [`Self::count`].
+    pub fn rank(&self, elem: T) -> usize {
+        let elem = elem.index();
+        assert!(elem <= self.domain_size);
+        let (word_index, bit) = (elem / WORD_BITS, elem % WORD_BITS);
+        // Summing whole words first keeps the hot loop simple enough to be
+        // vectorized.
+        let whole: usize = self.words[..word_index].iter().map(|w| w.count_ones() as usize).sum();
+        let partial = match self.words.get(word_index) {
+            Some(&word) if bit > 0 => (word & ((1 << bit) - 1)).count_ones() as usize,
+            _ => 0,
+        };
+        whole + partial
+    }
+
+    /// Returns the `n`th smallest element of the set (counting from zero), or
+    /// `None` if the set has `n` or fewer elements. This is the inverse of
+    /// [`Self::rank`], i.e. `set.rank(set.select(n).unwrap()) == n`.
+    pub fn select(&self, mut n: usize) -> Option<T> {
+        for (word_index, &word) in self.words.iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if n < ones {
+                // Clear the lowest `n` set bits; the answer is then the
+                // lowest remaining bit.
+                let mut word = word;
+                for _ in 0..n {
+                    word &= word - 1;
+                }
+                return Some(T::new(word_index * WORD_BITS + word.trailing_zeros() as usize));
+            }
+            n -= ones;
+        }
+        None
+    }
+
     pub fn last_set_in(&self, range: impl RangeBounds<T>) -> Option<T> {
         let (start, end) = inclusive_start_end(range, self.domain_size)?;
         let (start_word_index, _) = word_index_and_mask(start);
@@ -656,6 +656,38 @@ fn cmp(set: &DenseBitSet<usize>, needle: impl RangeBounds<usize> + Clone + std::
     }
 }
 
+#[test]
+fn dense_rank_select() {
+    let mut set: DenseBitSet<usize> = DenseBitSet::new_empty(300);
+    assert_eq!(set.rank(0), 0);
+    assert_eq!(set.rank(300), 0);
+    assert_eq!(set.select(0), None);
+
+    let elems = [0, 5, WORD_BITS - 1, WORD_BITS, WORD_BITS + 1, 200, 299];
+    for &e in &elems {
+        set.insert(e);
+    }
+    for (n, &e) in elems.iter().enumerate() {
+        assert_eq!(set.rank(e), n);
+        assert_eq!(set.rank(e + 1), n + 1);
+        assert_eq!(set.select(n), Some(e));
+    }
+    assert_eq!(set.rank(300), elems.len());
+    assert_eq!(set.select(elems.len()), None);
+
+    // Compare against a naive implementation for every position.
+    for i in 0..=300 {
+        assert_eq!(set.rank(i), set.iter().filter(|&e| e < i).count());
+    }
+
+    let full: DenseBitSet<usize> = DenseBitSet::new_filled(WORD_BITS * 2);
+    for i in 0..WORD_BITS * 2 {
+        assert_eq!(full.rank(i), i);
+        assert_eq!(full.select(i), Some(i));
+    }
+    assert_eq!(full.rank(WORD_BITS * 2), WORD_BITS * 2);
+}
+
 #[bench]
 fn bench_insert(b: &mut Bencher) {
     let mut bs = DenseBitSet::new_filled(99999usize);
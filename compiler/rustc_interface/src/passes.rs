@@ -1155,6 +1155,12 @@ pub(crate) fn start_codegen<'tcx>(
         tcx.sess.code_stats.print_vtable_sizes(crate_name);
     }
 
+    // Coverage records are built while each CGU is codegenned, so this must
+    // run after codegen.
+    if tcx.sess.opts.unstable_opts.coverage_stats {
+        tcx.sess.code_stats.print_coverage_stats(tcx.crate_name(LOCAL_CRATE));
+    }
+
     codegen
 }
 
@@ -10,12 +10,12 @@
 use rustc_session::config::{
     BranchProtection, CFGuard, Cfg, CollapseMacroDebuginfo, CoverageLevel, CoverageOptions,
     DebugInfo, DumpMonoStatsFormat, ErrorOutputType, ExternEntry, ExternLocation, Externs,
-    FmtDebug, FunctionReturn, InliningThreshold, Input, InstrumentCoverage, InstrumentXRay,
-    LinkSelfContained, LinkerPluginLto, LocationDetail, LtoCli, MirIncludeSpans, NextSolverConfig,
-    OomStrategy, Options, OutFileName, OutputType, OutputTypes, PAuthKey, PacRet, Passes,
-    PatchableFunctionEntry, Polonius, ProcMacroExecutionStrategy, Strip, SwitchWithOptPath,
-    SymbolManglingVersion, WasiExecModel, build_configuration, build_session_options,
-    rustc_optgroups,
+    FmtDebug, FoldedBranches, FunctionReturn, InliningThreshold, Input, InstrumentCoverage,
+    InstrumentXRay, LinkSelfContained, LinkerPluginLto, LocationDetail, LtoCli, MirIncludeSpans,
+    NextSolverConfig, OomStrategy, Options, OutFileName, OutputType, OutputTypes, PAuthKey, PacRet,
+    Passes, PatchableFunctionEntry, Polonius, ProcMacroExecutionStrategy, SanitizerCoverageOptions,
+    Strip, SubexprGranularity, SwitchWithOptPath, SymbolManglingVersion, WasiExecModel,
+    build_configuration, build_session_options, rustc_optgroups,
 };
 use rustc_session::lint::Level;
 use rustc_session::search_paths::SearchPath;
@@ -685,7 +685,9 @@ macro_rules! untracked {
     // Make sure that changing an [UNTRACKED] option leaves the hash unchanged.
     // tidy-alphabetical-start
     untracked!(assert_incr_state, Some(String::from("loaded")));
+    untracked!(coverage_stats, true);
     untracked!(deduplicate_diagnostics, false);
+    untracked!(dump_coverage_graph, true);
     untracked!(dump_dep_graph, true);
     untracked!(dump_mir, Some(String::from("abc")));
     untracked!(dump_mir_dataflow, true);
@@ -739,6 +741,7 @@ macro_rules! untracked {
     untracked!(ui_testing, true);
     untracked!(unpretty, Some("expanded".to_string()));
     untracked!(unstable_options, true);
+    untracked!(validate_coverage_graph, true);
     untracked!(validate_mir, true);
     untracked!(write_long_types_to_disk, false);
     // tidy-alphabetical-end
@@ -767,10 +770,55 @@ macro_rules! tracked {
         })
     );
     tracked!(codegen_backend, Some("abc".to_string()));
+    tracked!(coverage_baseline, Some(PathBuf::from("abc")));
+    tracked!(coverage_max_expression_terms, Some(8));
+    tracked!(coverage_mcdc_max_test_vectors, Some(64));
     tracked!(coverage_options, CoverageOptions {
         level: CoverageLevel::Mcdc,
         no_mir_spans: true,
-        discard_all_spans_in_codegen: true
+        discard_all_spans_in_codegen: true,
+        sysroot_local_paths: true,
+        stable_ids: true,
+        thread_local_counters: true,
+        first_hit_timestamps: true,
+        counter_metadata: true,
+        post_inline: true,
+        split_at_panics: true,
+        instrument_shims: true,
+        const_eval: true,
+        counter_regions: true,
+        test_harness: true,
+        subexpr: Some(SubexprGranularity::All),
+        paths: true,
+        call_graph: true,
+        assertions: true,
+        no_abort_flush: true,
+        overflow_checks: true,
+        post_dominators: true,
+        unwind_paths: true,
+        coroutine_drops: true,
+        static_unreachable: true,
+        spanning_tree: true,
+        boolean_counters: true,
+        profile_guided: true,
+        switch_arms: true,
+        non_atomic: true,
+        saturating: true,
+        expansions: true,
+        macro_definitions: true,
+        gap_regions: true,
+        skipped_regions: true,
+        line_only: true,
+        recover_spans: true,
+        folded_branches: Some(FoldedBranches::Mark),
+        collapse_external_macros: true,
+        raw_spans: true,
+        instrument_derives: true,
+        char_columns: true,
+        per_instance: true,
+        shared_file_table: true,
+        loop_depth_order: true,
+        shared_counters: true,
     });
     tracked!(crate_attr, vec!["abc".to_string()]);
     tracked!(cross_crate_inline_threshold, InliningThreshold::Always);
@@ -779,6 +827,7 @@ macro_rules! tracked {
     tracked!(default_visibility, Some(rustc_target::spec::SymbolVisibility::Hidden));
     tracked!(dep_info_omit_d_target, true);
     tracked!(direct_access_external_data, Some(true));
+    tracked!(doctest_line_table, Some(PathBuf::from("abc")));
     tracked!(dual_proc_macros, true);
     tracked!(dwarf_version, Some(5));
     tracked!(embed_source, true);
@@ -796,8 +845,10 @@ macro_rules! tracked {
     tracked!(incremental_ignore_spans, true);
     tracked!(inline_in_all_cgus, Some(true));
     tracked!(inline_mir, Some(true));
+    tracked!(inline_mir_coverage_penalty, Some(123));
     tracked!(inline_mir_hint_threshold, Some(123));
     tracked!(inline_mir_threshold, Some(123));
+    tracked!(instrument_function_entry_exit, true);
     tracked!(instrument_mcount, true);
     tracked!(instrument_xray, Some(InstrumentXRay::default()));
     tracked!(link_directives, false);
@@ -837,6 +888,7 @@ macro_rules! tracked {
     tracked!(polonius, Polonius::Legacy);
     tracked!(precise_enum_drop_elaboration, false);
     tracked!(profile_sample_use, Some(PathBuf::from("abc")));
+    tracked!(profile_use_coverage, true);
     tracked!(profiler_runtime, "abc".to_string());
     tracked!(reg_struct_return, true);
     tracked!(regparm, Some(3));
@@ -846,6 +898,12 @@ macro_rules! tracked {
     tracked!(sanitizer_cfi_canonical_jump_tables, None);
     tracked!(sanitizer_cfi_generalize_pointers, Some(true));
     tracked!(sanitizer_cfi_normalize_integers, Some(true));
+    tracked!(sanitizer_coverage, SanitizerCoverageOptions {
+        inline_8bit_counters: true,
+        stack_depth: true,
+        control_flow: true,
+    });
+    tracked!(sanitizer_coverage_inline_8bit_counters, true);
     tracked!(sanitizer_dataflow_abilist, vec![String::from("/rustc/abc")]);
     tracked!(sanitizer_memory_track_origins, 2);
     tracked!(sanitizer_recover, SanitizerSet::ADDRESS);
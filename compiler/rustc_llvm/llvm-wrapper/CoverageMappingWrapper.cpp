@@ -7,6 +7,8 @@
 #include "llvm/ProfileData/Coverage/CoverageMapping.h"
 #include "llvm/ProfileData/Coverage/CoverageMappingWriter.h"
 #include "llvm/ProfileData/InstrProf.h"
+#include "llvm/ProfileData/InstrProfReader.h"
+#include "llvm/Support/VirtualFileSystem.h"
 
 using namespace llvm;
 
@@ -101,6 +103,25 @@ struct LLVMRustCoverageMCDCDecisionRegion {
   LLVMRustMCDCDecisionParameters MCDCDecisionParams;
 };
 
+// Must match the layout of `rustc_codegen_llvm::coverageinfo::ffi::GapRegion`.
+struct LLVMRustCoverageGapRegion {
+  LLVMRustCoverageSpan Span;
+  LLVMRustCounter Count;
+};
+
+// Must match the layout of
+// `rustc_codegen_llvm::coverageinfo::ffi::SkippedRegion`.
+struct LLVMRustCoverageSkippedRegion {
+  LLVMRustCoverageSpan Span;
+};
+
+// Must match the layout of
+// `rustc_codegen_llvm::coverageinfo::ffi::ExpansionRegion`.
+struct LLVMRustCoverageExpansionRegion {
+  LLVMRustCoverageSpan Span;
+  uint32_t ExpandedFileID;
+};
+
 // FFI equivalent of enum `llvm::coverage::CounterExpression::ExprKind`
 // https://github.com/rust-lang/llvm-project/blob/ea6fa9c2/llvm/include/llvm/ProfileData/Coverage/CoverageMapping.h#L154
 enum class LLVMRustCounterExprKind {
@@ -155,7 +176,11 @@ extern "C" void LLVMRustCoverageWriteFunctionMappingsToBuffer(
     const LLVMRustCoverageMCDCBranchRegion *MCDCBranchRegions,
     size_t NumMCDCBranchRegions,
     const LLVMRustCoverageMCDCDecisionRegion *MCDCDecisionRegions,
-    size_t NumMCDCDecisionRegions, RustStringRef BufferOut) {
+    size_t NumMCDCDecisionRegions,
+    const LLVMRustCoverageExpansionRegion *ExpansionRegions,
+    size_t NumExpansionRegions, const LLVMRustCoverageGapRegion *GapRegions,
+    size_t NumGapRegions, const LLVMRustCoverageSkippedRegion *SkippedRegions,
+    size_t NumSkippedRegions, RustStringRef BufferOut) {
   // Convert from FFI representation to LLVM representation.
 
   // Expressions:
@@ -170,7 +195,9 @@ extern "C" void LLVMRustCoverageWriteFunctionMappingsToBuffer(
 
   std::vector<coverage::CounterMappingRegion> MappingRegions;
   MappingRegions.reserve(NumCodeRegions + NumBranchRegions +
-                         NumMCDCBranchRegions + NumMCDCDecisionRegions);
+                         NumMCDCBranchRegions + NumMCDCDecisionRegions +
+                         NumExpansionRegions + NumGapRegions +
+                         NumSkippedRegions);
 
   // Code regions:
   for (const auto &Region : ArrayRef(CodeRegions, NumCodeRegions)) {
@@ -207,6 +234,27 @@ extern "C" void LLVMRustCoverageWriteFunctionMappingsToBuffer(
   }
 #endif
 
+  // Expansion regions:
+  for (const auto &Region : ArrayRef(ExpansionRegions, NumExpansionRegions)) {
+    MappingRegions.push_back(coverage::CounterMappingRegion::makeExpansion(
+        Region.Span.FileID, Region.ExpandedFileID, Region.Span.LineStart,
+        Region.Span.ColumnStart, Region.Span.LineEnd, Region.Span.ColumnEnd));
+  }
+
+  // Gap regions:
+  for (const auto &Region : ArrayRef(GapRegions, NumGapRegions)) {
+    MappingRegions.push_back(coverage::CounterMappingRegion::makeGapRegion(
+        fromRust(Region.Count), Region.Span.FileID, Region.Span.LineStart,
+        Region.Span.ColumnStart, Region.Span.LineEnd, Region.Span.ColumnEnd));
+  }
+
+  // Skipped regions:
+  for (const auto &Region : ArrayRef(SkippedRegions, NumSkippedRegions)) {
+    MappingRegions.push_back(coverage::CounterMappingRegion::makeSkipped(
+        Region.Span.FileID, Region.Span.LineStart, Region.Span.ColumnStart,
+        Region.Span.LineEnd, Region.Span.ColumnEnd));
+  }
+
   // Write the converted expressions and mappings to a byte buffer.
   auto CoverageMappingWriter = coverage::CoverageMappingWriter(
       ArrayRef<unsigned>(VirtualFileMappingIDs, NumVirtualFileMappingIDs),
@@ -262,3 +310,51 @@ extern "C" uint32_t LLVMRustCoverageMappingVersion() {
   // want to override the version number we _emit_, do it on the Rust side.
   return coverage::CovMapVersion::CurrentVersion;
 }
+
+extern "C" uint64_t LLVMRustInstrProfRawVersion() {
+  return INSTR_PROF_RAW_VERSION;
+}
+
+extern "C" uint64_t LLVMRustInstrProfIndexedVersion() {
+  return IndexedInstrProf::ProfVersion::CurrentVersion;
+}
+
+extern "C" uint64_t LLVMRustInstrProfByteCoverageVariantMask() {
+  return VARIANT_MASK_BYTE_COVERAGE;
+}
+
+// Opens an indexed profile (`.profdata`) produced from a `-Cinstrument-coverage`
+// build, for `-Zprofile-use-coverage`. Returns null and sets the last error on
+// failure.
+extern "C" IndexedInstrProfReader *
+LLVMRustCoverageOpenProfile(const char *Path) {
+  auto ReaderOrErr =
+      IndexedInstrProfReader::create(Path, *vfs::getRealFileSystem());
+  if (!ReaderOrErr) {
+    LLVMRustSetLastError(toString(ReaderOrErr.takeError()).c_str());
+    return nullptr;
+  }
+  return ReaderOrErr->release();
+}
+
+extern "C" void LLVMRustCoverageCloseProfile(IndexedInstrProfReader *Reader) {
+  delete Reader;
+}
+
+// Writes the counter values that the profile records for the given function
+// to `OutCounts`, as native-endian 64-bit integers. Returns false if the
+// profile has no record for that function name and hash.
+extern "C" bool LLVMRustCoverageReadFunctionCounts(
+    IndexedInstrProfReader *Reader, const char *FuncName, size_t FuncNameLen,
+    uint64_t FuncHash, RustStringRef OutCounts) {
+  std::vector<uint64_t> Counts;
+  if (Error E = Reader->getFunctionCounts(StringRef(FuncName, FuncNameLen),
+                                          FuncHash, Counts)) {
+    consumeError(std::move(E));
+    return false;
+  }
+  auto OS = RawRustStringOstream(OutCounts);
+  OS.write(reinterpret_cast<const char *>(Counts.data()),
+           Counts.size() * sizeof(uint64_t));
+  return true;
+}
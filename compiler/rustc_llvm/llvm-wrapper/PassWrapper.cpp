@@ -10,6 +10,7 @@
 #include "llvm/CodeGen/CommandFlags.h"
 #include "llvm/IR/AssemblyAnnotationWriter.h"
 #include "llvm/IR/AutoUpgrade.h"
+#include "llvm/IR/IRBuilder.h"
 #include "llvm/IR/LegacyPassManager.h"
 #include "llvm/IR/PassManager.h"
 #include "llvm/IR/Verifier.h"
@@ -20,6 +21,7 @@
 #include "llvm/Passes/PassBuilder.h"
 #include "llvm/Passes/PassPlugin.h"
 #include "llvm/Passes/StandardInstrumentations.h"
+#include "llvm/ProfileData/InstrProf.h"
 #include "llvm/Support/CBindingWrapping.h"
 #include "llvm/Support/FileSystem.h"
 #include "llvm/Support/TimeProfiler.h"
@@ -35,6 +37,7 @@
 #include "llvm/Transforms/Instrumentation/HWAddressSanitizer.h"
 #include "llvm/Transforms/Instrumentation/InstrProfiling.h"
 #include "llvm/Transforms/Instrumentation/MemorySanitizer.h"
+#include "llvm/Transforms/Instrumentation/SanitizerCoverage.h"
 #include "llvm/Transforms/Instrumentation/ThreadSanitizer.h"
 #include "llvm/Transforms/Utils/CanonicalizeAliases.h"
 #include "llvm/Transforms/Utils/FunctionImportUtils.h"
@@ -662,6 +665,72 @@ void LLVMSelfProfileInitializeCallbacks(
       });
 }
 
+// Returns the profile counters global that a `__llvm_prf_data` record's
+// counter pointer refers to.
+static GlobalVariable *findProfileCounters(Constant *C, GlobalVariable *Data) {
+  if (auto *GV = dyn_cast<GlobalVariable>(C))
+    return GV == Data ? nullptr : GV;
+  for (Use &Op : C->operands())
+    if (auto *GV = findProfileCounters(cast<Constant>(Op), Data))
+      return GV;
+  return nullptr;
+}
+
+// Under `-Zcoverage-options=saturating` (and `overflow-checks`), rustc updates
+// counters itself, through the address returned by calls to
+// `__rustc_coverage_counter_address(name_ref, func_hash, index)`. The counters
+// only exist once `InstrProfilingLoweringPass` has run, so this pass then
+// replaces each call with the address of the counter in the `__profc_*`
+// global of the function's `__llvm_prf_data` record.
+struct RustCoverageCounterAddressPass
+    : public PassInfoMixin<RustCoverageCounterAddressPass> {
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &) {
+    Function *AddressFn = M.getFunction("__rustc_coverage_counter_address");
+    if (!AddressFn)
+      return PreservedAnalyses::all();
+
+    Triple TargetTriple(M.getTargetTriple());
+    std::string DataSection =
+        getInstrProfSectionName(IPSK_data, TargetTriple.getObjectFormat());
+    DenseMap<std::pair<uint64_t, uint64_t>, GlobalVariable *> CountersByFunc;
+    for (GlobalVariable &Data : M.globals()) {
+      if (Data.getSection() != DataSection || !Data.hasInitializer())
+        continue;
+      auto *Record = dyn_cast<ConstantStruct>(Data.getInitializer());
+      if (!Record)
+        continue;
+      auto *NameRef = dyn_cast<ConstantInt>(Record->getOperand(0));
+      auto *FuncHash = dyn_cast<ConstantInt>(Record->getOperand(1));
+      GlobalVariable *Counters =
+          findProfileCounters(Record->getOperand(2), &Data);
+      if (NameRef && FuncHash && Counters)
+        CountersByFunc[{NameRef->getZExtValue(), FuncHash->getZExtValue()}] =
+            Counters;
+    }
+
+    for (User *U : make_early_inc_range(AddressFn->users())) {
+      auto *Call = cast<CallInst>(U);
+      uint64_t NameRef =
+          cast<ConstantInt>(Call->getArgOperand(0))->getZExtValue();
+      uint64_t FuncHash =
+          cast<ConstantInt>(Call->getArgOperand(1))->getZExtValue();
+      uint64_t Index =
+          cast<ConstantInt>(Call->getArgOperand(2))->getZExtValue();
+      GlobalVariable *Counters = CountersByFunc.lookup({NameRef, FuncHash});
+      if (!Counters)
+        report_fatal_error("coverage counter address refers to a function "
+                           "without profile counters");
+      IRBuilder<> Builder(Call);
+      Value *Address = Builder.CreateConstInBoundsGEP2_64(
+          Counters->getValueType(), Counters, 0, Index);
+      Call->replaceAllUsesWith(Address);
+      Call->eraseFromParent();
+    }
+    AddressFn->eraseFromParent();
+    return PreservedAnalyses::none();
+  }
+};
+
 enum class LLVMRustOptStage {
   PreLinkNoLTO,
   PreLinkThinLTO,
@@ -686,6 +755,9 @@ struct LLVMRustSanitizerOptions {
   bool SanitizeHWAddressRecover;
   bool SanitizeKernelAddress;
   bool SanitizeKernelAddressRecover;
+  bool SanitizeCoverageInline8bitCounters;
+  bool SanitizeCoverageStackDepth;
+  bool SanitizeCoverageControlFlow;
 };
 
 extern "C" LLVMRustResult LLVMRustOptimize(
@@ -696,8 +768,9 @@ extern "C" LLVMRustResult LLVMRustOptimize(
     bool SLPVectorize, bool LoopVectorize, bool DisableSimplifyLibCalls,
     bool EmitLifetimeMarkers, LLVMRustSanitizerOptions *SanitizerOptions,
     const char *PGOGenPath, const char *PGOUsePath, bool InstrumentCoverage,
-    const char *InstrProfileOutput, const char *PGOSampleUsePath,
-    bool DebugInfoForProfiling, void *LlvmSelfProfiler,
+    const char *InstrProfileOutput, bool AtomicCoverageCounters,
+    const char *PGOSampleUsePath, bool DebugInfoForProfiling,
+    void *LlvmSelfProfiler,
     LLVMRustSelfProfileBeforePassCallback BeforePassCallback,
     LLVMRustSelfProfileAfterPassCallback AfterPassCallback,
     const char *ExtraPasses, size_t ExtraPassesLen, const char *LLVMPlugins,
@@ -834,19 +907,48 @@ extern "C" LLVMRustResult LLVMRustOptimize(
 
   if (InstrumentCoverage) {
     PipelineStartEPCallbacks.push_back(
-        [InstrProfileOutput](ModulePassManager &MPM, OptimizationLevel Level) {
+        [InstrProfileOutput, AtomicCoverageCounters](ModulePassManager &MPM,
+                                                     OptimizationLevel Level) {
           InstrProfOptions Options;
           if (InstrProfileOutput) {
             Options.InstrProfileOutput = InstrProfileOutput;
           }
           // cargo run tests in multhreading mode by default
-          // so use atomics for coverage counters
-          Options.Atomic = true;
+          // so use atomics for coverage counters, unless the user asked
+          // for plain increments with `-Zcoverage-options=non-atomic`
+          Options.Atomic = AtomicCoverageCounters;
           MPM.addPass(InstrProfilingLoweringPass(Options, false));
+          MPM.addPass(RustCoverageCounterAddressPass());
         });
   }
 
   if (SanitizerOptions) {
+    // SanitizerCoverage runs after optimization, so by the time it sees the
+    // module, any `-Cinstrument-coverage` counters have already been lowered
+    // into ordinary loads and stores. The blocks that rustc created for edge
+    // counters are not critical edges, so SanitizerCoverage will instrument
+    // them in place instead of splitting them again.
+    if (SanitizerOptions->SanitizeCoverageInline8bitCounters ||
+        SanitizerOptions->SanitizeCoverageStackDepth ||
+        SanitizerOptions->SanitizeCoverageControlFlow) {
+      SanitizerCoverageOptions Options;
+      Options.CoverageType = SanitizerCoverageOptions::SCK_Edge;
+      Options.Inline8bitCounters =
+          SanitizerOptions->SanitizeCoverageInline8bitCounters;
+      Options.StackDepth = SanitizerOptions->SanitizeCoverageStackDepth;
+      Options.CollectControlFlow =
+          SanitizerOptions->SanitizeCoverageControlFlow;
+      OptimizerLastEPCallbacks.push_back(
+#if LLVM_VERSION_GE(20, 0)
+          [Options](ModulePassManager &MPM, OptimizationLevel Level,
+                    ThinOrFullLTOPhase phase) {
+#else
+          [Options](ModulePassManager &MPM, OptimizationLevel Level) {
+#endif
+            MPM.addPass(SanitizerCoveragePass(Options));
+          });
+    }
+
     if (SanitizerOptions->SanitizeDataFlow) {
       std::vector<std::string> ABIListFiles(
           SanitizerOptions->SanitizeDataFlowABIList,
@@ -75,6 +75,14 @@ fn clone(&self) -> Self { *self }
     /// (Eligible functions might nevertheless be skipped for other reasons.)
     hook is_eligible_for_coverage(key: LocalDefId) -> bool;
 
+    /// Under `-Zcoverage-options=profile-guided`, returns the counter values
+    /// that the coverage profile passed with `-Cprofile-use` recorded for the
+    /// given non-generic function, if it has a record with the given hash.
+    ///
+    /// The profile can only be read by the codegen backend, so backends that
+    /// don't support it leave this returning `None`.
+    hook coverage_profile_counters(key: LocalDefId, function_source_hash: u64) -> Option<Vec<u64>>;
+
     /// Create the MIR for a given `DefId` - this includes
     /// unreachable code.
     /// You do not want to call this yourself, instead use the cached version
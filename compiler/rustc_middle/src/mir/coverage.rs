@@ -7,6 +7,8 @@
 use rustc_macros::{HashStable, TyDecodable, TyEncodable, TypeFoldable, TypeVisitable};
 use rustc_span::Span;
 
+use crate::mir::instrumentation::{InstrumentationPoint, Preservation};
+
 rustc_index::newtype_index! {
     /// Used by [`CoverageKind::BlockMarker`] to mark blocks during THIR-to-MIR
     /// lowering, so that those blocks can be identified later.
@@ -134,6 +136,25 @@ pub enum CoverageKind {
     ///
     /// This is eventually lowered to `llvm.instrprof.mcdc.tvbitmap.update` in LLVM IR.
     TestVectorBitmapUpdate { bitmap_idx: u32, decision_depth: u16 },
+
+    /// Marks a point in MIR control flow where `-Zcoverage-options=paths`
+    /// adds a Ball-Larus edge increment to the function's path register.
+    PathRegisterAdd { value: u32 },
+
+    /// Marks the end of an acyclic path under `-Zcoverage-options=paths`.
+    ///
+    /// This is lowered to an increment of the count for path number
+    /// `register + add`, followed by setting the path register to `reset`.
+    PathCount { add: u32, reset: u32 },
+
+    /// Marks a block whose `SwitchInt` terminator has its arms counted under
+    /// `-Zcoverage-options=switch-arms`. The switch's values are the range of
+    /// `num_values` integers starting at `min`.
+    ///
+    /// This is lowered along with the terminator, to an increment of the count
+    /// in slot `first_slot + (discr - min)`, or in slot `first_slot + num_values`
+    /// for the `otherwise` arm.
+    SwitchArmCount { first_slot: u32, min: u128, num_values: u32 },
 }
 
 impl Debug for CoverageKind {
@@ -150,6 +171,41 @@ fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
             TestVectorBitmapUpdate { bitmap_idx, decision_depth } => {
                 write!(fmt, "TestVectorUpdate({:?}, depth={:?})", bitmap_idx, decision_depth)
             }
+            PathRegisterAdd { value } => write!(fmt, "PathRegisterAdd({value:?})"),
+            PathCount { add, reset } => write!(fmt, "PathCount(add={add:?}, reset={reset:?})"),
+            SwitchArmCount { first_slot, min, num_values } => write!(
+                fmt,
+                "SwitchArmCount(first_slot={first_slot:?}, min={min:?}, num_values={num_values:?})"
+            ),
+        }
+    }
+}
+
+impl InstrumentationPoint for CoverageKind {
+    fn preservation(&self) -> Preservation {
+        use CoverageKind::*;
+        match self {
+            SpanMarker | BlockMarker { .. } => Preservation::UntilAnalysis,
+            CounterIncrement { .. }
+            | ExpressionUsed { .. }
+            | CondBitmapUpdate { .. }
+            | TestVectorBitmapUpdate { .. }
+            | PathRegisterAdd { .. }
+            | PathCount { .. }
+            | SwitchArmCount { .. } => Preservation::UntilCodegen,
+        }
+    }
+
+    fn has_runtime_effect(&self) -> bool {
+        use CoverageKind::*;
+        match self {
+            SpanMarker | BlockMarker { .. } | ExpressionUsed { .. } => false,
+            CounterIncrement { .. }
+            | CondBitmapUpdate { .. }
+            | TestVectorBitmapUpdate { .. }
+            | PathRegisterAdd { .. }
+            | PathCount { .. }
+            | SwitchArmCount { .. } => true,
         }
     }
 }
@@ -190,6 +246,18 @@ pub enum MappingKind {
     MCDCBranch { true_term: CovTerm, false_term: CovTerm, mcdc_params: ConditionInfo },
     /// Associates a decision region with a bitmap and number of conditions.
     MCDCDecision(DecisionInfo),
+    /// Associates a panicking guard (e.g. an `assert!` or a bounds check) with
+    /// separate counters for the times it passed and the times it failed,
+    /// under `-Zcoverage-options=assertions`.
+    Assertion { passed_term: CovTerm, failed_term: CovTerm },
+    /// Associates the code after an early exit (e.g. `return`) with the count
+    /// of the code that follows it, under `-Zcoverage-options=gap-regions`.
+    /// Only used for line counts, on lines where no other region starts.
+    Gap(CovTerm),
+    /// Marks a nested item or closure that is excluded by `#[coverage(off)]`,
+    /// under `-Zcoverage-options=skipped-regions`, so that it can be told apart
+    /// from code that simply has no regions. Has no count.
+    Skipped,
 }
 
 impl MappingKind {
@@ -207,6 +275,12 @@ pub fn map_terms(&self, map_fn: impl Fn(CovTerm) -> CovTerm) -> Self {
                 mcdc_params,
             },
             Self::MCDCDecision(param) => Self::MCDCDecision(param),
+            Self::Assertion { passed_term, failed_term } => Self::Assertion {
+                passed_term: map_fn(passed_term),
+                failed_term: map_fn(failed_term),
+            },
+            Self::Gap(term) => Self::Gap(map_fn(term)),
+            Self::Skipped => Self::Skipped,
         }
     }
 }
@@ -226,6 +300,9 @@ pub struct Mapping {
 pub struct FunctionCoverageInfo {
     pub function_source_hash: u64,
     pub body_span: Span,
+    /// Number of nodes in the function's coverage graph, as reported by
+    /// `-Zcoverage-stats`.
+    pub num_bcbs: usize,
     pub num_counters: usize,
     pub mcdc_bitmap_bits: usize,
     pub expressions: IndexVec<ExpressionId, Expression>,
@@ -233,6 +310,77 @@ pub struct FunctionCoverageInfo {
     /// The depth of the deepest decision is used to know how many
     /// temp condbitmaps should be allocated for the function.
     pub mcdc_num_condition_bitmaps: usize,
+    /// Static properties of each physical counter's site in the coverage graph.
+    /// Only populated under `-Zcoverage-options=counter-metadata`, and empty
+    /// otherwise.
+    pub counter_metadata: IndexVec<CounterId, CounterMetadata>,
+    /// Number of acyclic paths numbered by `-Zcoverage-options=paths`, or 0 if
+    /// the function isn't path-profiled.
+    pub num_paths: usize,
+    /// The spans along each numbered path, in path order.
+    pub path_mappings: Vec<PathMapping>,
+    /// Number of switch-arm count slots used by `-Zcoverage-options=switch-arms`,
+    /// or 0 if none of the function's switches have their arms counted.
+    pub num_switch_arm_slots: usize,
+    /// The spans of the arm that each switch-arm slot counts, in slot order.
+    pub switch_arm_mappings: Vec<SwitchArmMapping>,
+    /// Code regions inside the expansions of macros invoked by the function
+    /// body, under `-Zcoverage-options=expansions`, and empty otherwise.
+    pub expansion_mappings: Vec<ExpansionMapping>,
+    /// Code regions inside the definitions of local macros, at every level of
+    /// expansion, under `-Zcoverage-options=macro-definitions`, and empty
+    /// otherwise.
+    pub macro_definition_mappings: Vec<ExpansionMapping>,
+}
+
+/// The code regions inside one expansion of a macro, under
+/// `-Zcoverage-options=expansions` or `macro-definitions`.
+///
+/// The mappings' spans are in the macro's definition. For `expansions` they
+/// are embedded in a virtual file of their own, which is linked to the
+/// function's main file by an expansion region at the call site. For
+/// `macro-definitions` they are embedded directly in the file that contains
+/// the definition.
+#[derive(Clone, Debug)]
+#[derive(TyEncodable, TyDecodable, Hash, HashStable, TypeFoldable, TypeVisitable)]
+pub struct ExpansionMapping {
+    /// The span of the macro invocation, which for `macro-definitions` can be
+    /// inside the definition of another macro.
+    pub call_site: Span,
+    /// Ordinary code mappings for the code that this invocation expanded to.
+    pub mappings: Vec<Mapping>,
+}
+
+/// Associates a span with one of the acyclic paths numbered by
+/// `-Zcoverage-options=paths`. A path usually has several of these.
+#[derive(Clone, Debug)]
+#[derive(TyEncodable, TyDecodable, Hash, HashStable, TypeFoldable, TypeVisitable)]
+pub struct PathMapping {
+    pub path: u32,
+    pub span: Span,
+}
+
+/// Associates a span with one of the switch-arm count slots allocated by
+/// `-Zcoverage-options=switch-arms`. A slot usually has several of these.
+#[derive(Clone, Debug)]
+#[derive(TyEncodable, TyDecodable, Hash, HashStable, TypeFoldable, TypeVisitable)]
+pub struct SwitchArmMapping {
+    pub slot: u32,
+    pub span: Span,
+}
+
+/// Static properties of the coverage graph node or edge where a physical
+/// counter is incremented, exported so that tools such as corpus minimizers
+/// can weight counters without reconstructing the control-flow graph.
+///
+/// For an edge counter, both properties describe the edge's source node.
+#[derive(Clone, Copy, Debug)]
+#[derive(TyEncodable, TyDecodable, Hash, HashStable, TypeFoldable, TypeVisitable)]
+pub struct CounterMetadata {
+    /// Number of loops that contain the counter's node.
+    pub loop_depth: u16,
+    /// Number of successors of the counter's node.
+    pub fan_out: u16,
 }
 
 /// Coverage information for a function, recorded during MIR building and
@@ -294,12 +442,21 @@ pub struct MCDCDecisionSpan {
     pub end_markers: Vec<BlockMarkerId>,
     pub decision_depth: u16,
     pub num_conditions: usize,
+    /// A match guard is lowered once for each alternative of an or-pattern.
+    /// For the decisions of every lowering after the first, this is the index
+    /// in [`CoverageInfoHi::mcdc_spans`] of the corresponding decision in the
+    /// first lowering, which they share a test vector bitmap with.
+    pub first_lowering: Option<usize>,
 }
 
 /// Summarizes coverage IDs inserted by the `InstrumentCoverage` MIR pass
 /// (for compiler option `-Cinstrument-coverage`), after MIR optimizations
 /// have had a chance to potentially remove some of them.
 ///
+/// Counters that are only incremented in blocks that are unreachable after
+/// monomorphization (e.g. the false arm of `if T::CONST`) are treated as
+/// unused, so their mappings become constant zero.
+///
 /// Used by the `coverage_ids_info` query.
 #[derive(Clone, TyEncodable, TyDecodable, Debug, HashStable)]
 pub struct CoverageIdsInfo {
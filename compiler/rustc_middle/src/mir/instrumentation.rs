@@ -0,0 +1,77 @@
+//! Shared rules for *instrumentation points*: statements that exist only so
+//! that some later stage (a MIR pass, or codegen) can observe or count the
+//! execution of a particular point in the control-flow graph.
+//!
+//! Coverage is currently the only client (see [`CoverageKind`]), but the
+//! rules here are phrased independently of it, so that other kinds of probes
+//! can reuse the same ID allocation and be treated consistently by MIR
+//! optimizations.
+//!
+//! [`CoverageKind`]: crate::mir::coverage::CoverageKind
+
+use std::marker::PhantomData;
+
+use rustc_index::Idx;
+
+/// Describes how long an instrumentation point must survive, and therefore
+/// what MIR passes are allowed to do with it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Preservation {
+    /// The point only carries information from MIR building to an analysis or
+    /// instrumentation pass that runs before borrowck finishes. It must be
+    /// kept until then, and is erased by `CleanupPostBorrowck`. It must not
+    /// exist in MIR after `AnalysisPhase::PostCleanup`.
+    UntilAnalysis,
+    /// The point is consumed by codegen. Optimizations may remove it only when
+    /// they can prove that its block never executes, and may duplicate it
+    /// only together with the rest of its block; they must never move it to
+    /// a different point in control flow.
+    UntilCodegen,
+}
+
+/// Common interface for the statement payloads that act as instrumentation
+/// points, e.g. [`CoverageKind`](crate::mir::coverage::CoverageKind).
+pub trait InstrumentationPoint {
+    /// How long MIR passes must preserve this point.
+    fn preservation(&self) -> Preservation;
+
+    /// Returns true if this point is lowered to code that runs, as opposed to
+    /// being pure metadata. Passes that estimate code size (e.g. the inliner)
+    /// should account for points with a runtime effect.
+    fn has_runtime_effect(&self) -> bool;
+
+    /// Returns true if this point must be erased once MIR analysis is done.
+    fn is_erased_after_analysis(&self) -> bool {
+        self.preservation() == Preservation::UntilAnalysis
+    }
+}
+
+/// Allocates IDs for instrumentation points in a body, in ascending order
+/// starting from 0, so that side tables can refer to each point by an ID that
+/// remains stable while the MIR is transformed.
+#[derive(Debug)]
+pub struct PointIdGen<I: Idx> {
+    num_ids: usize,
+    _marker: PhantomData<I>,
+}
+
+impl<I: Idx> Default for PointIdGen<I> {
+    fn default() -> Self {
+        Self { num_ids: 0, _marker: PhantomData }
+    }
+}
+
+impl<I: Idx> PointIdGen<I> {
+    /// Returns a new ID that has not been returned before.
+    pub fn next_id(&mut self) -> I {
+        let id = I::new(self.num_ids);
+        self.num_ids += 1;
+        id
+    }
+
+    /// Returns the number of IDs allocated so far, which is one more than the
+    /// highest ID allocated.
+    pub fn num_ids(&self) -> usize {
+        self.num_ids
+    }
+}
@@ -48,6 +48,7 @@
 mod generic_graph;
 pub mod generic_graphviz;
 pub mod graphviz;
+pub mod instrumentation;
 pub mod interpret;
 pub mod mono;
 pub mod patch;
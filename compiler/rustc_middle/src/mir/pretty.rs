@@ -565,7 +565,13 @@ fn write_coverage_info_hi(
     }
 
     for (
-        coverage::MCDCDecisionSpan { span, end_markers, decision_depth, num_conditions: _ },
+        coverage::MCDCDecisionSpan {
+            span,
+            end_markers,
+            decision_depth,
+            num_conditions: _,
+            first_lowering: _,
+        },
         conditions,
     ) in mcdc_spans
     {
@@ -597,8 +603,14 @@ fn write_function_coverage_info(
     function_coverage_info: &coverage::FunctionCoverageInfo,
     w: &mut dyn io::Write,
 ) -> io::Result<()> {
-    let coverage::FunctionCoverageInfo { body_span, expressions, mappings, .. } =
-        function_coverage_info;
+    let coverage::FunctionCoverageInfo {
+        body_span,
+        expressions,
+        mappings,
+        expansion_mappings,
+        macro_definition_mappings,
+        ..
+    } = function_coverage_info;
 
     writeln!(w, "{INDENT}coverage body span: {body_span:?}")?;
     for (id, expression) in expressions.iter_enumerated() {
@@ -607,6 +619,18 @@ fn write_function_coverage_info(
     for coverage::Mapping { kind, span } in mappings {
         writeln!(w, "{INDENT}coverage {kind:?} => {span:?};")?;
     }
+    for coverage::ExpansionMapping { call_site, mappings } in expansion_mappings {
+        writeln!(w, "{INDENT}coverage expansion at {call_site:?}:")?;
+        for coverage::Mapping { kind, span } in mappings {
+            writeln!(w, "{INDENT}{INDENT}coverage {kind:?} => {span:?};")?;
+        }
+    }
+    for coverage::ExpansionMapping { call_site, mappings } in macro_definition_mappings {
+        writeln!(w, "{INDENT}coverage macro definition for {call_site:?}:")?;
+        for coverage::Mapping { kind, span } in mappings {
+            writeln!(w, "{INDENT}{INDENT}coverage {kind:?} => {span:?};")?;
+        }
+    }
     writeln!(w)?;
 
     Ok(())
@@ -41,7 +41,7 @@
 use rustc_session::lint::LintExpectationId;
 use rustc_span::def_id::LOCAL_CRATE;
 use rustc_span::source_map::Spanned;
-use rustc_span::{DUMMY_SP, Span, Symbol};
+use rustc_span::{DUMMY_SP, Span, StableSourceFileId, Symbol};
 use rustc_target::spec::PanicStrategy;
 use {rustc_abi as abi, rustc_ast as ast, rustc_attr_parsing as attr, rustc_hir as hir};
 
@@ -617,12 +617,39 @@
 
     /// Summarizes coverage IDs inserted by the `InstrumentCoverage` MIR pass
     /// (for compiler option `-Cinstrument-coverage`), after MIR optimizations
-    /// have had a chance to potentially remove some of them.
-    query coverage_ids_info(key: ty::InstanceKind<'tcx>) -> &'tcx mir::coverage::CoverageIdsInfo {
+    /// have had a chance to potentially remove some of them, and ignoring any
+    /// code that is unreachable in this particular monomorphization.
+    query coverage_ids_info(key: ty::Instance<'tcx>) -> &'tcx mir::coverage::CoverageIdsInfo {
         desc { |tcx| "retrieving coverage IDs info from MIR for `{}`", tcx.def_path_str(key.def_id()) }
         arena_cache
     }
 
+    /// The functions in this crate that were instrumented for coverage, but
+    /// won't be codegenned, and so need "unused function" coverage records.
+    /// This looks at every codegen unit, so it is computed once for the whole
+    /// crate, and each codegen unit then builds the records for its share.
+    query coverage_unused_functions(_: ()) -> &'tcx [LocalDefId] {
+        desc { "finding functions that need unused-function coverage records" }
+    }
+
+    /// Under `-Zcoverage-options=shared-file-table`, the files referred to by
+    /// the coverage mappings of every codegen unit in this crate, in the order
+    /// of their global file IDs. Each file is identified by its stable ID,
+    /// plus the index of a merged doctest for files of merged doctests.
+    query coverage_crate_file_table(_: ()) -> &'tcx [(StableSourceFileId, Option<usize>)] {
+        desc { "building the crate-wide coverage file table" }
+    }
+
+    /// The coverage hashes listed in the baseline file passed with
+    /// `-Zcoverage-baseline`, or an empty set if no baseline was passed.
+    /// Functions whose structural hash is in this set are only given an
+    /// entry counter.
+    query coverage_baseline_hashes(_: ()) -> &'tcx UnordSet<u64> {
+        arena_cache
+        eval_always
+        desc { "loading the coverage baseline" }
+    }
+
     /// The `DefId` is the `DefId` of the containing MIR body. Promoteds do not have their own
     /// `DefId`. This function returns all promoteds in the specified body. The body references
     /// promoteds by the `DefId` and the `mir::Promoted` index. This is necessary, because
@@ -62,6 +62,7 @@
 use crate::metadata::ModChild;
 use crate::middle::codegen_fn_attrs::CodegenFnAttrs;
 use crate::middle::{resolve_bound_vars, stability};
+use crate::mir::coverage::CounterId;
 use crate::mir::interpret::{self, Allocation, ConstAllocation};
 use crate::mir::{Body, Local, Place, PlaceElem, ProjectionKind, Promoted};
 use crate::query::plumbing::QuerySystem;
@@ -1347,6 +1348,13 @@ pub struct GlobalCtxt<'tcx> {
 
     /// Stores memory for globals (statics/consts).
     pub(crate) alloc_map: Lock<interpret::AllocMap<'tcx>>,
+
+    /// Under `-Zcoverage-options=const-eval`, the number of times that const
+    /// evaluation incremented each coverage counter of each local function.
+    const_eval_coverage: Lock<FxHashMap<LocalDefId, IndexVec<CounterId, u64>>>,
+    /// The final contents of `const_eval_coverage`, fixed by
+    /// [`TyCtxt::freeze_const_eval_coverage`].
+    frozen_const_eval_coverage: OnceLock<FxHashMap<LocalDefId, IndexVec<CounterId, u64>>>,
 }
 
 /// This is used to get a reference to a `GlobalCtxt` if one is available.
@@ -1537,6 +1545,8 @@ pub fn create_global_ctxt<T>(
             canonical_param_env_cache: Default::default(),
             data_layout,
             alloc_map: Lock::new(interpret::AllocMap::new()),
+            const_eval_coverage: Default::default(),
+            frozen_const_eval_coverage: OnceLock::new(),
         });
 
         let icx = tls::ImplicitCtxt::new(&gcx);
@@ -1701,12 +1711,48 @@ pub fn needs_crate_hash(self) -> bool {
         //   `rustc_query_system::query::plumbing::execute_job`.
         // - incremental: for query lookups.
         // - needs_metadata: for putting into crate metadata.
-        // - instrument_coverage: for putting into coverage data (see
+        // - coverage_analysis: for putting into coverage data (see
         //   `hash_mir_source`).
         cfg!(debug_assertions)
             || self.sess.opts.incremental.is_some()
             || self.needs_metadata()
-            || self.sess.instrument_coverage()
+            || self.sess.coverage_analysis()
+    }
+
+    /// Records that const evaluation incremented coverage counter `id` of
+    /// `def_id`, whose coverage info has `num_counters` counters.
+    ///
+    /// These counts are not tracked by the query system, so const evaluation
+    /// whose result was loaded from the incremental cache isn't counted. Nor is
+    /// const evaluation after [`Self::freeze_const_eval_coverage`].
+    pub fn record_const_eval_coverage(
+        self,
+        def_id: LocalDefId,
+        id: CounterId,
+        num_counters: usize,
+    ) {
+        if self.frozen_const_eval_coverage.get().is_some() {
+            return;
+        }
+        let mut counts = self.const_eval_coverage.lock();
+        let counters =
+            counts.entry(def_id).or_insert_with(|| IndexVec::from_elem_n(0, num_counters));
+        counters[id] += 1;
+    }
+
+    /// Stops recording const-eval coverage, so that every later call to
+    /// [`Self::const_eval_coverage`] returns the same counts. Codegen calls
+    /// this before codegen units are built in parallel, so that the counts
+    /// don't depend on how const evaluation during codegen was scheduled.
+    pub fn freeze_const_eval_coverage(self) {
+        self.const_eval_coverage();
+    }
+
+    /// Returns the coverage counter values recorded by
+    /// [`Self::record_const_eval_coverage`], freezing them if that hasn't
+    /// happened yet.
+    pub fn const_eval_coverage(self) -> &'tcx FxHashMap<LocalDefId, IndexVec<CounterId, u64>> {
+        self.gcx.frozen_const_eval_coverage.get_or_init(|| self.const_eval_coverage.lock().clone())
     }
 
     #[inline]
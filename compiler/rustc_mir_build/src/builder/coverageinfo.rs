@@ -3,9 +3,11 @@
 
 use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::mir::coverage::{BlockMarkerId, BranchSpan, CoverageInfoHi, CoverageKind};
+use rustc_middle::mir::instrumentation::PointIdGen;
 use rustc_middle::mir::{self, BasicBlock, SourceInfo, UnOp};
 use rustc_middle::thir::{ExprId, ExprKind, Pat, Thir};
 use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
 use rustc_span::def_id::LocalDefId;
 
 use crate::builder::coverageinfo::mcdc::MCDCInfoBuilder;
@@ -44,23 +46,17 @@ struct NotInfo {
 
 #[derive(Default)]
 struct BlockMarkerGen {
-    num_block_markers: usize,
+    ids: PointIdGen<BlockMarkerId>,
 }
 
 impl BlockMarkerGen {
-    fn next_block_marker_id(&mut self) -> BlockMarkerId {
-        let id = BlockMarkerId::from_usize(self.num_block_markers);
-        self.num_block_markers += 1;
-        id
-    }
-
     fn inject_block_marker(
         &mut self,
         cfg: &mut CFG<'_>,
         source_info: SourceInfo,
         block: BasicBlock,
     ) -> BlockMarkerId {
-        let id = self.next_block_marker_id();
+        let id = self.ids.next_id();
         let marker_statement = mir::Statement {
             source_info,
             kind: mir::StatementKind::Coverage(CoverageKind::BlockMarker { id }),
@@ -75,7 +71,7 @@ impl CoverageInfoBuilder {
     /// Creates a new coverage info builder, but only if coverage instrumentation
     /// is enabled and `def_id` represents a function that is eligible for coverage.
     pub(crate) fn new_if_enabled(tcx: TyCtxt<'_>, def_id: LocalDefId) -> Option<Self> {
-        if !tcx.sess.instrument_coverage() || !tcx.is_eligible_for_coverage(def_id) {
+        if !tcx.sess.coverage_analysis() || !tcx.is_eligible_for_coverage(def_id) {
             return None;
         }
 
@@ -155,6 +151,18 @@ fn register_two_way_branch<'tcx>(
             return;
         }
 
+        self.register_branch_outside_decisions(cfg, source_info, true_block, false_block);
+    }
+
+    /// Records a branch that can't be a condition of an MC/DC decision, even
+    /// when MC/DC is enabled, so it is only reported as an ordinary branch.
+    fn register_branch_outside_decisions(
+        &mut self,
+        cfg: &mut CFG<'_>,
+        source_info: SourceInfo,
+        true_block: BasicBlock,
+        false_block: BasicBlock,
+    ) {
         // Bail out if branch coverage is not enabled.
         let Some(branch_info) = self.branch_info.as_mut() else { return };
 
@@ -169,8 +177,7 @@ fn register_two_way_branch<'tcx>(
     }
 
     pub(crate) fn into_done(self) -> Box<CoverageInfoHi> {
-        let Self { nots: _, markers: BlockMarkerGen { num_block_markers }, branch_info, mcdc_info } =
-            self;
+        let Self { nots: _, markers: BlockMarkerGen { ids }, branch_info, mcdc_info } = self;
 
         let branch_spans =
             branch_info.map(|branch_info| branch_info.branch_spans).unwrap_or_default();
@@ -181,7 +188,7 @@ pub(crate) fn into_done(self) -> Box<CoverageInfoHi> {
         // For simplicity, always return an info struct (without Option), even
         // if there's nothing interesting in it.
         Box::new(CoverageInfoHi {
-            num_block_markers,
+            num_block_markers: ids.num_ids(),
             branch_spans,
             mcdc_degraded_branch_spans,
             mcdc_spans,
@@ -307,4 +314,33 @@ pub(crate) fn visit_coverage_conditional_let(
             false_block,
         );
     }
+
+    /// If branch coverage is enabled, inject marker statements into the two
+    /// arms of the match that a `?` operator or `.await` desugars to, and
+    /// record them as a branch on the `?` or `await` token.
+    ///
+    /// For `?`, the true side continues with the unwrapped value, and the false
+    /// side returns (or breaks out of a `try` block) early. For `.await`, the
+    /// true side is taken when the awaited future is ready, and the false side
+    /// whenever it is pending, so the coroutine suspends.
+    ///
+    /// Neither is a boolean condition, so the branch is never treated as a
+    /// condition of an enclosing MC/DC decision.
+    pub(crate) fn visit_coverage_desugared_branch(
+        &mut self,
+        span: Span, // Span of the `?` or `await` token, marked as desugared
+        true_block: BasicBlock,
+        false_block: BasicBlock,
+    ) {
+        // Bail out if coverage is not enabled for this function.
+        let Some(coverage_info) = self.coverage_info.as_mut() else { return };
+
+        let source_info = SourceInfo { span, scope: self.source_scope };
+        coverage_info.register_branch_outside_decisions(
+            &mut self.cfg,
+            source_info,
+            true_block,
+            false_block,
+        );
+    }
 }
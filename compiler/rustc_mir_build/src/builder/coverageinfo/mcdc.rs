@@ -1,11 +1,12 @@
 use std::collections::VecDeque;
 
+use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::bug;
 use rustc_middle::mir::coverage::{
     BlockMarkerId, ConditionId, ConditionInfo, MCDCBranchSpan, MCDCDecisionSpan,
 };
 use rustc_middle::mir::{BasicBlock, SourceInfo};
-use rustc_middle::thir::LogicalOp;
+use rustc_middle::thir::{ExprId, LogicalOp};
 use rustc_middle::ty::TyCtxt;
 use rustc_span::Span;
 
@@ -103,6 +104,7 @@ fn record_conditions(&mut self, op: LogicalOp, span: Span) {
                 num_conditions: 0,
                 end_markers: vec![],
                 decision_depth,
+                first_lowering: None,
             }),
         };
 
@@ -208,11 +210,22 @@ pub(crate) struct MCDCInfoBuilder {
     degraded_spans: Vec<MCDCBranchSpan>,
     mcdc_spans: Vec<(MCDCDecisionSpan, Vec<MCDCBranchSpan>)>,
     state: MCDCState,
+    /// Indices of the decisions built by the first lowering of each match guard.
+    guard_decisions: FxHashMap<ExprId, Vec<usize>>,
+    /// Match guards currently being lowered (innermost last), with the indices
+    /// of the decisions built so far by this lowering.
+    guard_stack: Vec<(ExprId, Vec<usize>)>,
 }
 
 impl MCDCInfoBuilder {
     pub(crate) fn new() -> Self {
-        Self { degraded_spans: vec![], mcdc_spans: vec![], state: MCDCState::new() }
+        Self {
+            degraded_spans: vec![],
+            mcdc_spans: vec![],
+            state: MCDCState::new(),
+            guard_decisions: FxHashMap::default(),
+            guard_stack: vec![],
+        }
     }
 
     pub(crate) fn visit_evaluated_condition(
@@ -245,6 +258,9 @@ pub(crate) fn visit_evaluated_condition(
                     unreachable!("Decision with no condition is not expected");
                 }
                 1..=MAX_CONDITIONS_IN_DECISION => {
+                    if let Some((_, decisions)) = self.guard_stack.last_mut() {
+                        decisions.push(self.mcdc_spans.len());
+                    }
                     self.mcdc_spans.push((decision, conditions));
                 }
                 _ => {
@@ -260,6 +276,27 @@ pub(crate) fn visit_evaluated_condition(
         }
     }
 
+    fn finish_guard(&mut self) {
+        let Some((guard, decisions)) = self.guard_stack.pop() else {
+            bug!("Unexpected empty guard stack");
+        };
+        // The guard of an or-pattern's arm is lowered again for each of its
+        // alternatives, with exactly the same decisions each time. Point the
+        // repeated decisions at the ones from the first lowering, so that the
+        // mapping builder reports each decision once.
+        match self.guard_decisions.get(&guard) {
+            Some(first_decisions) if first_decisions.len() == decisions.len() => {
+                for (&first, &idx) in std::iter::zip(first_decisions, &decisions) {
+                    self.mcdc_spans[idx].0.first_lowering = Some(first);
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.guard_decisions.insert(guard, decisions);
+            }
+        }
+    }
+
     pub(crate) fn into_done(
         self,
     ) -> (Vec<(MCDCDecisionSpan, Vec<MCDCBranchSpan>)>, Vec<MCDCBranchSpan>) {
@@ -276,6 +313,24 @@ pub(crate) fn visit_coverage_branch_operation(&mut self, logical_op: LogicalOp,
         }
     }
 
+    /// Called before lowering a match arm's guard, so that the decisions in the
+    /// guard can be related to those in other lowerings of the same guard.
+    pub(crate) fn mcdc_enter_guard_if_enabled(&mut self, guard: ExprId) {
+        if let Some(coverage_info) = self.coverage_info.as_mut()
+            && let Some(mcdc_info) = coverage_info.mcdc_info.as_mut()
+        {
+            mcdc_info.guard_stack.push((guard, vec![]));
+        }
+    }
+
+    pub(crate) fn mcdc_exit_guard_if_enabled(&mut self) {
+        if let Some(coverage_info) = self.coverage_info.as_mut()
+            && let Some(mcdc_info) = coverage_info.mcdc_info.as_mut()
+        {
+            mcdc_info.finish_guard();
+        }
+    }
+
     pub(crate) fn mcdc_increment_depth_if_enabled(&mut self) {
         if let Some(coverage_info) = self.coverage_info.as_mut()
             && let Some(mcdc_info) = coverage_info.mcdc_info.as_mut()
@@ -8,7 +8,7 @@
 use rustc_abi::VariantIdx;
 use rustc_data_structures::fx::FxIndexMap;
 use rustc_data_structures::stack::ensure_sufficient_stack;
-use rustc_hir::{BindingMode, ByRef};
+use rustc_hir::{BindingMode, ByRef, MatchSource};
 use rustc_middle::bug;
 use rustc_middle::middle::region;
 use rustc_middle::mir::{self, *};
@@ -166,7 +166,7 @@ fn then_else_break_inner(
                     this.in_if_then_scope(local_scope, expr_span, |this| {
                         // Help out coverage instrumentation by injecting a dummy statement with
                         // the original condition's span (including `!`). This fixes #115468.
-                        if this.tcx.sess.instrument_coverage() {
+                        if this.tcx.sess.coverage_analysis() {
                             this.cfg.push_coverage_span_marker(block, this.source_info(expr_span));
                         }
                         this.then_else_break_inner(block, arg, ThenElseArgs {
@@ -355,6 +355,7 @@ pub(crate) fn match_expr(
         mut block: BasicBlock,
         scrutinee_id: ExprId,
         arms: &[ArmId],
+        match_source: MatchSource,
         span: Span,
         scrutinee_span: Span,
     ) -> BlockAnd<()> {
@@ -380,6 +381,35 @@ pub(crate) fn match_expr(
             false,
         );
 
+        // Branch coverage reports the `?` operator and `.await` as branches on
+        // their `?` or `await` token, by instrumenting the two arms of the
+        // match that they desugar to.
+        if let [first_branch, second_branch] = &built_tree.branches[..]
+            && let [first] = &first_branch.sub_branches[..]
+            && let [second] = &second_branch.sub_branches[..]
+        {
+            match match_source {
+                // `match Try::branch(..) { Break(residual) => return .., Continue(val) => val }`
+                MatchSource::TryDesugar(_) => {
+                    self.visit_coverage_desugared_branch(
+                        first.span,
+                        second.success_block,
+                        first.success_block,
+                    );
+                }
+                // `match poll(..) { Ready(result) => break result, Pending => {} }`,
+                // where the pending arm goes on to suspend the coroutine.
+                MatchSource::AwaitDesugar => {
+                    self.visit_coverage_desugared_branch(
+                        first.span,
+                        first.success_block,
+                        second.success_block,
+                    );
+                }
+                _ => {}
+            }
+        }
+
         self.lower_match_arms(
             destination,
             scrutinee_place,
@@ -2343,7 +2373,12 @@ pub(crate) fn lower_let_expr(
         emit_storage_live: EmitStorageLive,
     ) -> BlockAnd<()> {
         let expr_span = self.thir[expr_id].span;
+        // The scrutinee is not part of any decision that this `let` belongs to
+        // (e.g. in a let-chain), so increment the decision depth in case we
+        // encounter boolean expressions in it.
+        self.mcdc_increment_depth_if_enabled();
         let scrutinee = unpack!(block = self.lower_scrutinee(block, expr_id, expr_span));
+        self.mcdc_decrement_depth_if_enabled();
         let built_tree = self.lower_match_tree(
             block,
             expr_span,
@@ -2453,6 +2488,7 @@ fn bind_and_guard_matched_candidate(
 
             let mut guard_span = rustc_span::DUMMY_SP;
 
+            self.mcdc_enter_guard_if_enabled(guard);
             let (post_guard_block, otherwise_post_guard_block) =
                 self.in_if_then_scope(match_scope, guard_span, |this| {
                     guard_span = this.thir[guard].span;
@@ -2464,6 +2500,7 @@ fn bind_and_guard_matched_candidate(
                         DeclareLetBindings::No, // For guards, `let` bindings are declared separately
                     )
                 });
+            self.mcdc_exit_guard_if_enabled();
 
             let source_info = self.source_info(guard_span);
             let guard_end = self.source_info(tcx.sess.source_map().end_point(guard_span));
@@ -688,7 +688,7 @@ pub(crate) fn break_scope(
                 panic!("`return`, `become` and `break` with value and must have a destination")
             }
             (None, None) => {
-                if self.tcx.sess.instrument_coverage() {
+                if self.tcx.sess.coverage_analysis() {
                     // Normally we wouldn't build any MIR in this case, but that makes it
                     // harder for coverage instrumentation to extract a relevant span for
                     // `continue` expressions. So here we inject a dummy statement with the
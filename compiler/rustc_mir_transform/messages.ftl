@@ -9,6 +9,13 @@ mir_transform_const_mut_borrow = taking a mutable reference to a `const` item
     .note2 = the mutable reference will refer to this temporary, not the original `const` item
     .note3 = mutable reference created due to call to this method
 
+mir_transform_coverage_baseline_invalid_line = invalid line {$line_number} in coverage baseline `{$path}`: expected a hexadecimal function hash, found `{$line}`
+
+mir_transform_coverage_baseline_read_failed = failed to read coverage baseline `{$path}`: {$err}
+
+mir_transform_exceeds_mcdc_decision_test_vector_limit = number of test vectors in decision ({$num_test_vectors}) exceeds limit ({$max_num_test_vectors}), so MC/DC analysis ignores it
+    .note = the limit can be changed with `-Zcoverage-mcdc-max-test-vectors`
+
 mir_transform_exceeds_mcdc_test_vector_limit = number of total test vectors in one function will exceed limit ({$max_num_test_vectors}) if this decision is instrumented, so MC/DC analysis ignores it
 
 mir_transform_ffi_unwind_call = call to {$foreign ->
@@ -5,7 +5,7 @@
 //!   - [`AscribeUserType`]
 //!   - [`FakeRead`]
 //!   - [`Assign`] statements with a [`Fake`] borrow
-//!   - [`Coverage`] statements of kind [`BlockMarker`] or [`SpanMarker`]
+//!   - [`Coverage`] statements that are only needed until analysis (see [`Preservation`])
 //!
 //! [`AscribeUserType`]: rustc_middle::mir::StatementKind::AscribeUserType
 //! [`Assign`]: rustc_middle::mir::StatementKind::Assign
@@ -13,10 +13,9 @@
 //! [`Nop`]: rustc_middle::mir::StatementKind::Nop
 //! [`Fake`]: rustc_middle::mir::BorrowKind::Fake
 //! [`Coverage`]: rustc_middle::mir::StatementKind::Coverage
-//! [`BlockMarker`]: rustc_middle::mir::coverage::CoverageKind::BlockMarker
-//! [`SpanMarker`]: rustc_middle::mir::coverage::CoverageKind::SpanMarker
+//! [`Preservation`]: rustc_middle::mir::instrumentation::Preservation
 
-use rustc_middle::mir::coverage::CoverageKind;
+use rustc_middle::mir::instrumentation::InstrumentationPoint;
 use rustc_middle::mir::{Body, BorrowKind, CastKind, Rvalue, StatementKind, TerminatorKind};
 use rustc_middle::ty::TyCtxt;
 use rustc_middle::ty::adjustment::PointerCoercion;
@@ -30,12 +29,12 @@ fn run_pass(&self, _tcx: TyCtxt<'tcx>, body: &mut Body<'tcx>) {
                 match statement.kind {
                     StatementKind::AscribeUserType(..)
                     | StatementKind::Assign(box (_, Rvalue::Ref(_, BorrowKind::Fake(_), _)))
-                    | StatementKind::Coverage(
-                        // These kinds of coverage statements are markers inserted during
-                        // MIR building, and are not needed after InstrumentCoverage.
-                        CoverageKind::BlockMarker { .. } | CoverageKind::SpanMarker { .. },
-                    )
                     | StatementKind::FakeRead(..) => statement.make_nop(),
+                    // Markers inserted during MIR building are not needed after
+                    // InstrumentCoverage.
+                    StatementKind::Coverage(ref kind) if kind.is_erased_after_analysis() => {
+                        statement.make_nop()
+                    }
                     StatementKind::Assign(box (
                         _,
                         Rvalue::Cast(
@@ -1,4 +1,5 @@
 use rustc_middle::bug;
+use rustc_middle::mir::instrumentation::InstrumentationPoint;
 use rustc_middle::mir::visit::*;
 use rustc_middle::mir::*;
 use rustc_middle::ty::{self, Ty, TyCtxt};
@@ -9,6 +10,7 @@
 const RESUME_PENALTY: usize = 45;
 const LARGE_SWITCH_PENALTY: usize = 20;
 const CONST_SWITCH_BONUS: usize = 10;
+const COVERAGE_COUNTER_PENALTY: usize = 10;
 
 /// Verify that the callee body is compatible with the caller.
 #[derive(Clone)]
@@ -87,6 +89,18 @@ fn visit_statement(&mut self, statement: &Statement<'tcx>, location: Location) {
                     NonDivergingIntrinsic::CopyNonOverlapping(..) => CALL_PENALTY,
                 };
             }
+            // Each copy of a counter update costs code size, and every inlined
+            // copy of a function also brings along its coverage mappings.
+            // These statements only exist when instrumenting for coverage.
+            StatementKind::Coverage(ref kind) if kind.has_runtime_effect() => {
+                self.penalty += self
+                    .tcx
+                    .sess
+                    .opts
+                    .unstable_opts
+                    .inline_mir_coverage_penalty
+                    .unwrap_or(COVERAGE_COUNTER_PENALTY);
+            }
             _ => self.super_statement(statement, location),
         }
     }
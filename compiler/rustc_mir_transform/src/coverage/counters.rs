@@ -9,17 +9,21 @@
 use rustc_middle::mir::coverage::{CounterId, CovTerm, Expression, ExpressionId, Op};
 use tracing::{debug, debug_span, instrument};
 
-use crate::coverage::graph::{BasicCoverageBlock, CoverageGraph, ReadyFirstTraversal};
+use crate::coverage::graph::{
+    BasicCoverageBlock, BcbEdge, CountEquivalenceClasses, CoverageGraph, ReadyFirstTraversal,
+};
 
+mod spanning_tree;
 #[cfg(test)]
 mod tests;
 
 /// The coverage counter or counter expression associated with a particular
-/// BCB node or BCB edge.
+/// BCB node or BCB edge, or zero if it is statically known to never run.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum BcbCounter {
     Counter { id: CounterId },
     Expression { id: ExpressionId },
+    Zero,
 }
 
 impl BcbCounter {
@@ -27,6 +31,7 @@ fn as_term(&self) -> CovTerm {
         match *self {
             BcbCounter::Counter { id, .. } => CovTerm::Counter(id),
             BcbCounter::Expression { id, .. } => CovTerm::Expression(id),
+            BcbCounter::Zero => CovTerm::Zero,
         }
     }
 }
@@ -36,6 +41,7 @@ fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Counter { id, .. } => write!(fmt, "Counter({:?})", id.index()),
             Self::Expression { id } => write!(fmt, "Expression({:?})", id.index()),
+            Self::Zero => write!(fmt, "Zero"),
         }
     }
 }
@@ -51,7 +57,7 @@ struct BcbExpression {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(super) enum Site {
     Node { bcb: BasicCoverageBlock },
-    Edge { from_bcb: BasicCoverageBlock, to_bcb: BasicCoverageBlock },
+    Edge { edge: BcbEdge },
 }
 
 /// Generates and stores coverage counter and coverage expression information
@@ -70,6 +76,69 @@ pub(super) struct CoverageCounters {
     /// Remember expressions that have already been created (or simplified),
     /// so that we don't create unnecessary duplicates.
     expressions_memo: FxHashMap<BcbExpression, BcbCounter>,
+
+    /// Restrictions on the counter expressions that can be given to nodes.
+    limits: ExpressionLimits,
+}
+
+/// Restrictions on the counter expressions that [`CoverageCounters`] may give
+/// to nodes. A node whose counter would break them gets a physical counter of
+/// its own instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct ExpressionLimits {
+    /// If set, the maximum number of physical counters that a node's counter
+    /// may combine (see `-Zcoverage-max-expression-terms`).
+    pub(super) max_terms: Option<usize>,
+    /// If true, node counters may only add physical counters together, never
+    /// subtract them, so that they stay meaningful when each physical counter
+    /// only records whether it was reached at all (see
+    /// `-Zcoverage-options=boolean-counters`).
+    pub(super) no_subtraction: bool,
+}
+
+/// Opt-in changes to where [`CoverageCounters`] places physical counters,
+/// which change the counters of existing functions, so each is enabled by its
+/// own `-Zcoverage-options` value.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct CounterPlacement {
+    /// If true, visit nodes in shallower loops first when choosing which nodes
+    /// get physical counters (see `-Zcoverage-options=loop-depth-order`).
+    pub(super) loop_depth_order: bool,
+    /// If true, a node can reuse the counter of any node that it is known to
+    /// have the same count as, instead of only its control-equivalent
+    /// dominator (see `-Zcoverage-options=shared-counters`).
+    pub(super) shared_counters: bool,
+}
+
+/// Execution counts of nodes and edges of the coverage graph, as recorded in
+/// the profile of an earlier instrumented build (see
+/// `-Zcoverage-options=profile-guided`).
+#[derive(Debug)]
+pub(super) struct ProfiledCounts {
+    site_counts: FxHashMap<Site, u64>,
+}
+
+impl ProfiledCounts {
+    fn node_count(&self, bcb: BasicCoverageBlock) -> Option<u64> {
+        self.site_counts.get(&Site::Node { bcb }).copied()
+    }
+
+    /// Returns the recorded count of the given edge, if the profile determines
+    /// it directly or via one of the edge's endpoints.
+    fn edge_count(&self, graph: &CoverageGraph, edge: BcbEdge) -> Option<u64> {
+        let from_bcb = graph.edge_source(edge);
+        let to_bcb = graph.edge_target(edge);
+        self.site_counts
+            .get(&Site::Edge { edge })
+            .copied()
+            .or_else(|| {
+                self.node_count(to_bcb).filter(|_| graph.sole_in_edge(to_bcb) == Some(edge))
+            })
+            .or_else(|| {
+                self.node_count(from_bcb)
+                    .filter(|_| graph.simple_successor(from_bcb) == Some(to_bcb))
+            })
+    }
 }
 
 impl CoverageCounters {
@@ -78,19 +147,54 @@ impl CoverageCounters {
     pub(super) fn make_bcb_counters(
         graph: &CoverageGraph,
         bcb_needs_counter: &DenseBitSet<BasicCoverageBlock>,
+        limits: ExpressionLimits,
+        placement: CounterPlacement,
     ) -> Self {
-        let mut builder = CountersBuilder::new(graph, bcb_needs_counter);
+        let mut builder = CountersBuilder::new(graph, bcb_needs_counter, placement);
         builder.make_bcb_counters();
 
-        builder.into_coverage_counters()
+        builder.into_coverage_counters(limits)
+    }
+
+    /// Like [`Self::make_bcb_counters`], but only gives physical counters to
+    /// edges off a maximal spanning tree of the coverage graph, for
+    /// `-Zcoverage-options=spanning-tree`.
+    pub(super) fn make_spanning_tree_counters(
+        graph: &CoverageGraph,
+        bcb_needs_counter: &DenseBitSet<BasicCoverageBlock>,
+        limits: ExpressionLimits,
+        placement: CounterPlacement,
+    ) -> Self {
+        spanning_tree::make_counters(graph, bcb_needs_counter, limits, placement, None)
     }
 
-    fn with_num_bcbs(num_bcbs: usize) -> Self {
+    /// Like [`Self::make_spanning_tree_counters`], but weighs the edges of the
+    /// spanning tree by the counts recorded in a profile, so that the edges
+    /// that actually ran most often are the ones without physical counters.
+    /// Edges whose counts are unknown fall back to the structural estimate.
+    pub(super) fn make_profile_guided_counters(
+        graph: &CoverageGraph,
+        bcb_needs_counter: &DenseBitSet<BasicCoverageBlock>,
+        limits: ExpressionLimits,
+        placement: CounterPlacement,
+        profiled_counts: &ProfiledCounts,
+    ) -> Self {
+        spanning_tree::make_counters(
+            graph,
+            bcb_needs_counter,
+            limits,
+            placement,
+            Some(profiled_counts),
+        )
+    }
+
+    fn with_num_bcbs(num_bcbs: usize, limits: ExpressionLimits) -> Self {
         Self {
             counter_increment_sites: IndexVec::new(),
             node_counters: IndexVec::from_elem_n(None, num_bcbs),
             expressions: IndexVec::new(),
             expressions_memo: FxHashMap::default(),
+            limits,
         }
     }
 
@@ -102,12 +206,121 @@ fn make_phys_counter(&mut self, site: Site) -> BcbCounter {
 
     fn make_expression(&mut self, lhs: BcbCounter, op: Op, rhs: BcbCounter) -> BcbCounter {
         let new_expr = BcbExpression { lhs, op, rhs };
+        if let Some(simplified) = self.simplify_expression(new_expr) {
+            return simplified;
+        }
         *self.expressions_memo.entry(new_expr).or_insert_with(|| {
             let id = self.expressions.push(new_expr);
             BcbCounter::Expression { id }
         })
     }
 
+    /// Uses basic algebra to find an existing counter with the same value as
+    /// the given expression, so that trivially redundant expressions never
+    /// make it into the coverage mapping.
+    fn simplify_expression(&self, expr: BcbExpression) -> Option<BcbCounter> {
+        let BcbExpression { lhs, op, rhs } = expr;
+        let operands = |counter| match counter {
+            BcbCounter::Expression { id } => Some(self.expressions[id]),
+            BcbCounter::Counter { .. } | BcbCounter::Zero => None,
+        };
+
+        match (lhs, op, rhs) {
+            // `X + 0` and `X - 0` are `X`, and `0 + X` is `X`.
+            (_, _, BcbCounter::Zero) => return Some(lhs),
+            (BcbCounter::Zero, Op::Add, _) => return Some(rhs),
+            // Counts can't be negative, so `0 - X` and `X - X` are zero.
+            (BcbCounter::Zero, Op::Subtract, _) => return Some(BcbCounter::Zero),
+            (_, Op::Subtract, _) if lhs == rhs => return Some(BcbCounter::Zero),
+            _ => {}
+        }
+
+        // Cancel out a term that the LHS already adds or subtracts:
+        // `(A + B) - B` and `(A - B) + B` are `A`, and `(A + B) - A` is `B`.
+        if let Some(BcbExpression { lhs: a, op: inner_op, rhs: b }) = operands(lhs) {
+            match (inner_op, op) {
+                (Op::Add, Op::Subtract) if b == rhs => return Some(a),
+                (Op::Add, Op::Subtract) if a == rhs => return Some(b),
+                (Op::Subtract, Op::Add) if b == rhs => return Some(a),
+                _ => {}
+            }
+        }
+
+        // Likewise for the RHS: `B + (A - B)` is `A`, and `A - (A - B)` is `B`.
+        if let Some(BcbExpression { lhs: a, op: Op::Subtract, rhs: b }) = operands(rhs) {
+            match op {
+                Op::Add if b == lhs => return Some(a),
+                Op::Subtract if a == lhs => return Some(b),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Creates a counter for the given node/edge site, whose value is the sum
+    /// of the physical counts of the `pos` sites, minus the sum of the physical
+    /// counts of the `neg` sites. Physical counters for those sites are created
+    /// as needed, and remembered in `phys_counter_for_site`.
+    fn make_counter_for_sites(
+        &mut self,
+        phys_counter_for_site: &mut FxHashMap<Site, BcbCounter>,
+        site: Site,
+        pos: Vec<Site>,
+        neg: Vec<Site>,
+    ) -> BcbCounter {
+        // Simplify by cancelling out sites that appear on both sides.
+        let (mut pos, mut neg) = sort_and_cancel(pos, neg);
+
+        if pos.is_empty() {
+            // If we somehow end up with no positive terms after cancellation,
+            // fall back to creating a physical counter. There's no known way
+            // for this to happen, but it's hard to confidently rule it out.
+            debug_assert!(false, "{site:?} has no positive counter terms");
+            pos = vec![Some(site)];
+            neg = vec![];
+        }
+
+        // A counter expression that combines too many physical counters makes
+        // the coverage mapping larger and slower to evaluate than a counter of
+        // its own would, so give the site its own counter instead.
+        let num_terms = pos.iter().chain(&neg).flatten().count();
+        if let Some(max_terms) = self.limits.max_terms
+            && num_terms > max_terms
+        {
+            debug!(?site, num_terms, max_terms, "too many terms; using a physical counter instead");
+            pos = vec![Some(site)];
+            neg = vec![];
+        }
+
+        // Likewise if the counter would need a subtraction that isn't allowed.
+        if self.limits.no_subtraction && neg.iter().flatten().next().is_some() {
+            debug!(?site, "subtraction not allowed; using a physical counter instead");
+            pos = vec![Some(site)];
+            neg = vec![];
+        }
+
+        let mut new_counters_for_sites = |sites: Vec<Option<Site>>| {
+            sites
+                .into_iter()
+                .filter_map(|site| try {
+                    let site = site?;
+                    *phys_counter_for_site
+                        .entry(site)
+                        .or_insert_with(|| self.make_phys_counter(site))
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut pos = new_counters_for_sites(pos);
+        let mut neg = new_counters_for_sites(neg);
+
+        pos.sort();
+        neg.sort();
+
+        let pos_counter = self.make_sum(&pos).expect("`pos` should not be empty");
+        self.make_subtracted_sum(pos_counter, &neg)
+    }
+
     /// Creates a counter that is the sum of the given counters.
     ///
     /// Returns `None` if the given list of counters was empty.
@@ -128,6 +341,10 @@ pub(super) fn num_counters(&self) -> usize {
         self.counter_increment_sites.len()
     }
 
+    pub(super) fn num_expressions(&self) -> usize {
+        self.expressions.len()
+    }
+
     fn set_node_counter(&mut self, bcb: BasicCoverageBlock, counter: BcbCounter) -> BcbCounter {
         let existing = self.node_counters[bcb].replace(counter);
         assert!(
@@ -141,6 +358,94 @@ pub(super) fn term_for_bcb(&self, bcb: BasicCoverageBlock) -> Option<CovTerm> {
         self.node_counters[bcb].map(|counter| counter.as_term())
     }
 
+    /// Returns a term for the count of `lhs_bcb` minus the count of `rhs_bcb`,
+    /// creating an expression for it if necessary. Returns `None` if either
+    /// node has no counter, or if counters can't be subtracted.
+    pub(super) fn make_node_difference(
+        &mut self,
+        lhs_bcb: BasicCoverageBlock,
+        rhs_bcb: BasicCoverageBlock,
+    ) -> Option<CovTerm> {
+        if self.limits.no_subtraction {
+            return None;
+        }
+        let lhs = self.node_counters[lhs_bcb]?;
+        let rhs = self.node_counters[rhs_bcb]?;
+        Some(self.make_expression(lhs, Op::Subtract, rhs).as_term())
+    }
+
+    /// Given the values that a profile recorded for these physical counters,
+    /// returns the counts of the nodes and edges that they determine. Returns
+    /// `None` if the number of values doesn't match, which means the profile
+    /// was recorded with a different counter layout.
+    pub(super) fn profiled_counts(&self, counter_values: &[u64]) -> Option<ProfiledCounts> {
+        if counter_values.len() != self.num_counters() {
+            return None;
+        }
+
+        // Expressions only refer to counters and to earlier expressions. The
+        // arithmetic saturates, since profiles of multithreaded programs can
+        // lose increments and so be slightly inconsistent.
+        let mut expression_values = IndexVec::<ExpressionId, u64>::new();
+        let value_of =
+            |counter: BcbCounter, expression_values: &IndexVec<ExpressionId, u64>| match counter {
+                BcbCounter::Counter { id } => counter_values[id.as_usize()],
+                BcbCounter::Expression { id } => expression_values[id],
+                BcbCounter::Zero => 0,
+            };
+        for &BcbExpression { lhs, op, rhs } in &self.expressions {
+            let lhs = value_of(lhs, &expression_values);
+            let rhs = value_of(rhs, &expression_values);
+            expression_values.push(match op {
+                Op::Add => lhs.saturating_add(rhs),
+                Op::Subtract => lhs.saturating_sub(rhs),
+            });
+        }
+
+        let mut site_counts = FxHashMap::default();
+        for (id, &site) in self.counter_increment_sites.iter_enumerated() {
+            site_counts.insert(site, counter_values[id.as_usize()]);
+        }
+        for (bcb, &counter) in self.node_counters.iter_enumerated() {
+            if let Some(counter) = counter {
+                site_counts.insert(Site::Node { bcb }, value_of(counter, &expression_values));
+            }
+        }
+        Some(ProfiledCounts { site_counts })
+    }
+
+    /// Renumbers the physical counters in order of the given key, so that their
+    /// IDs only depend on their sites, and not on the order in which the
+    /// counters happened to be created.
+    pub(super) fn sort_counters_by_key<K: Ord>(&mut self, key: impl Fn(Site) -> K) {
+        let mut order = self.counter_increment_sites.indices().collect::<Vec<_>>();
+        order.sort_by_cached_key(|&id| key(self.counter_increment_sites[id]));
+
+        let mut new_ids = IndexVec::from_elem_n(CounterId::ZERO, order.len());
+        for (new_id, &old_id) in (0..).map(CounterId::from_u32).zip(&order) {
+            new_ids[old_id] = new_id;
+        }
+        let renumber = |counter: &mut BcbCounter| {
+            if let BcbCounter::Counter { id } = counter {
+                *id = new_ids[*id];
+            }
+        };
+
+        self.counter_increment_sites =
+            order.iter().map(|&old_id| self.counter_increment_sites[old_id]).collect();
+        self.node_counters.iter_mut().flatten().for_each(renumber);
+        for expr in &mut self.expressions {
+            renumber(&mut expr.lhs);
+            renumber(&mut expr.rhs);
+        }
+        // The memo is keyed by the expressions' operands, which have changed.
+        self.expressions_memo = self
+            .expressions
+            .iter_enumerated()
+            .map(|(id, &expr)| (expr, BcbCounter::Expression { id }))
+            .collect();
+    }
+
     /// Returns an iterator over all the nodes/edges in the coverage graph that
     /// should have a counter-increment statement injected into MIR, along with
     /// each site's corresponding counter ID.
@@ -158,8 +463,8 @@ pub(super) fn bcb_nodes_with_coverage_expressions(
         self.node_counters.iter_enumerated().filter_map(|(bcb, &counter)| match counter {
             // Yield the BCB along with its associated expression ID.
             Some(BcbCounter::Expression { id }) => Some((bcb, id)),
-            // This BCB is associated with a counter or nothing, so skip it.
-            Some(BcbCounter::Counter { .. }) | None => None,
+            // This BCB is associated with a counter, zero or nothing, so skip it.
+            Some(BcbCounter::Counter { .. } | BcbCounter::Zero) | None => None,
         })
     }
 
@@ -183,37 +488,59 @@ pub(super) fn into_expressions(self) -> IndexVec<ExpressionId, Expression> {
 }
 
 /// Symbolic representation of the coverage counter to be used for a particular
-/// node or edge in the coverage graph. The same site counter can be used for
-/// multiple sites, if they have been determined to have the same count.
+/// node in the coverage graph. Wherever possible, a node's count is derived
+/// from the counts of its in-edges.
 #[derive(Clone, Copy, Debug)]
-enum SiteCounter {
-    /// A physical counter at some node/edge.
-    Phys { site: Site },
-    /// A counter expression for a node that takes the sum of all its in-edge
+enum NodeCounter {
+    /// A physical counter at the node itself.
+    Phys,
+    /// A counter expression that takes the sum of all the node's in-edge
     /// counters.
-    NodeSumExpr { bcb: BasicCoverageBlock },
-    /// A counter expression for an edge that takes the counter of its source
-    /// node, and subtracts the counters of all its sibling out-edges.
-    EdgeDiffExpr { from_bcb: BasicCoverageBlock, to_bcb: BasicCoverageBlock },
+    SumOfInEdges,
+    /// The counter of a dominating node that always runs as many times as
+    /// this node.
+    SameAs { bcb: BasicCoverageBlock },
+    /// The node is statically known to never run, so it needs no counter at
+    /// all.
+    Zero,
+}
+
+/// Symbolic representation of the coverage counter to be used for a particular
+/// edge in the coverage graph. Each edge is either counted physically, or has
+/// the same count as one of its endpoints, or is given a counter expression.
+#[derive(Clone, Copy, Debug)]
+enum EdgeCounter {
+    /// A physical counter at the edge itself.
+    Phys,
+    /// The counter of the edge's target node, whose sole in-edge this is.
+    SameAsTarget,
+    /// The counter of the edge's source node, whose sole out-edge this is.
+    SameAsSource,
+    /// A counter expression that takes the counter of the edge's source node,
+    /// and subtracts the counters of all its sibling out-edges. This is only
+    /// used if the source node's out-edges sum to its count.
+    SourceMinusSiblings,
+    /// The edge is statically known to never be taken, so it needs no counter
+    /// at all.
+    Zero,
 }
 
-/// Yields the graph successors of `from_bcb` that aren't `to_bcb`. This is
-/// used when creating a counter expression for [`SiteCounter::EdgeDiffExpr`].
+/// Yields the other out-edges of the source node of `edge`. This is used when
+/// creating a counter expression for [`EdgeCounter::SourceMinusSiblings`].
 ///
-/// For example, in this diagram the sibling out-edge targets of edge `AC` are
-/// the nodes `B` and `D`.
+/// For example, in this diagram the sibling out-edges of edge `AC` are the
+/// edges `AB` and `AD`.
 ///
 /// ```text
 ///    A
 ///  / | \
 /// B  C  D
 /// ```
-fn sibling_out_edge_targets(
+fn sibling_out_edges(
     graph: &CoverageGraph,
-    from_bcb: BasicCoverageBlock,
-    to_bcb: BasicCoverageBlock,
-) -> impl Iterator<Item = BasicCoverageBlock> + Captures<'_> {
-    graph.successors[from_bcb].iter().copied().filter(move |&t| t != to_bcb)
+    edge: BcbEdge,
+) -> impl Iterator<Item = BcbEdge> + Captures<'_> {
+    graph.edges_from(graph.edge_source(edge)).filter(move |&e| e != edge)
 }
 
 /// Helper struct that allows counter creation to inspect the BCB graph, and
@@ -221,17 +548,45 @@ fn sibling_out_edge_targets(
 struct CountersBuilder<'a> {
     graph: &'a CoverageGraph,
     bcb_needs_counter: &'a DenseBitSet<BasicCoverageBlock>,
+    placement: CounterPlacement,
+    /// Groups of nodes that are known to have the same execution count, so
+    /// that they can share one counter.
+    count_classes: CountEquivalenceClasses,
 
-    site_counters: FxHashMap<Site, SiteCounter>,
+    node_counters: IndexVec<BasicCoverageBlock, Option<NodeCounter>>,
+    edge_counters: FxHashMap<BcbEdge, EdgeCounter>,
 }
 
 impl<'a> CountersBuilder<'a> {
     fn new(
         graph: &'a CoverageGraph,
         bcb_needs_counter: &'a DenseBitSet<BasicCoverageBlock>,
+        placement: CounterPlacement,
     ) -> Self {
         assert_eq!(graph.num_nodes(), bcb_needs_counter.domain_size());
-        Self { graph, bcb_needs_counter, site_counters: FxHashMap::default() }
+
+        // Nodes and edges that can never run already have a (zero) counter,
+        // so they are never given physical counters, and expressions that
+        // would include them can leave them out.
+        let mut node_counters = IndexVec::from_elem_n(None, graph.num_nodes());
+        let mut edge_counters = FxHashMap::default();
+        for (bcb, _) in graph.iter_enumerated() {
+            if graph.is_unreachable(bcb) {
+                node_counters[bcb] = Some(NodeCounter::Zero);
+            }
+            for edge in graph.edges_from(bcb).filter(|&edge| graph.is_untaken_edge(edge)) {
+                edge_counters.insert(edge, EdgeCounter::Zero);
+            }
+        }
+
+        Self {
+            graph,
+            bcb_needs_counter,
+            placement,
+            count_classes: graph.count_equivalence_classes(),
+            node_counters,
+            edge_counters,
+        }
     }
 
     fn make_bcb_counters(&mut self) {
@@ -239,7 +594,7 @@ fn make_bcb_counters(&mut self) {
 
         // Traverse the coverage graph, ensuring that every node that needs a
         // coverage counter has one.
-        for bcb in ReadyFirstTraversal::new(self.graph) {
+        for bcb in ReadyFirstTraversal::new(self.graph, self.placement.loop_depth_order) {
             let _span = debug_span!("traversal", ?bcb).entered();
             if self.bcb_needs_counter.contains(bcb) {
                 self.make_node_counter_and_out_edge_counters(bcb);
@@ -255,69 +610,86 @@ fn make_node_counter_and_out_edge_counters(&mut self, from_bcb: BasicCoverageBlo
         // We might also use that counter to compute one of the out-edge counters.
         self.get_or_make_node_counter(from_bcb);
 
-        // If this node's out-edges won't sum to the node's counter,
-        // then there's no reason to create edge counters here.
+        // If this node's out-edges won't sum to the node's counter (because it
+        // yields, might panic, or has no out-edges at all), then none of them
+        // can be given an expression, so there's no reason to count them here.
         if !self.graph[from_bcb].is_out_summable {
             return;
         }
 
         // When choosing which out-edge should be given a counter expression, ignore edges that
         // already have counters, or could use the existing counter of their target node.
-        let out_edge_has_counter = |to_bcb| {
-            if self.site_counters.contains_key(&Site::Edge { from_bcb, to_bcb }) {
+        let out_edge_has_counter = |edge| {
+            if self.edge_counters.contains_key(&edge) {
                 return true;
             }
-            self.graph.sole_predecessor(to_bcb) == Some(from_bcb)
-                && self.site_counters.contains_key(&Site::Node { bcb: to_bcb })
+            let to_bcb = self.graph.edge_target(edge);
+            self.graph.sole_in_edge(to_bcb) == Some(edge) && self.node_counters[to_bcb].is_some()
         };
 
         // Determine the set of out-edges that could benefit from being given an expression.
-        let candidate_successors = self.graph.successors[from_bcb]
-            .iter()
-            .copied()
-            .filter(|&to_bcb| !out_edge_has_counter(to_bcb))
+        let candidate_edges = self
+            .graph
+            .edges_from(from_bcb)
+            .filter(|&edge| !out_edge_has_counter(edge))
             .collect::<Vec<_>>();
-        debug!(?candidate_successors);
+        debug!(?candidate_edges);
 
         // If there are out-edges without counters, choose one to be given an expression
         // (computed from this node and the other out-edges) instead of a physical counter.
-        let Some(to_bcb) = self.choose_out_edge_for_expression(from_bcb, &candidate_successors)
-        else {
+        let Some(edge) = self.choose_out_edge_for_expression(from_bcb, &candidate_edges) else {
             return;
         };
 
         // For each out-edge other than the one that was chosen to get an expression,
         // ensure that it has a counter (existing counter/expression or a new counter).
-        for target in sibling_out_edge_targets(self.graph, from_bcb, to_bcb) {
-            self.get_or_make_edge_counter(from_bcb, target);
+        for sibling in sibling_out_edges(self.graph, edge) {
+            self.get_or_make_edge_counter(sibling);
         }
 
         // Now create an expression for the chosen edge, by taking the counter
         // for its source node and subtracting the sum of its sibling out-edges.
-        let counter = SiteCounter::EdgeDiffExpr { from_bcb, to_bcb };
-        self.site_counters.insert(Site::Edge { from_bcb, to_bcb }, counter);
+        self.edge_counters.insert(edge, EdgeCounter::SourceMinusSiblings);
     }
 
     #[instrument(level = "debug", skip(self))]
-    fn get_or_make_node_counter(&mut self, bcb: BasicCoverageBlock) -> SiteCounter {
+    fn get_or_make_node_counter(&mut self, bcb: BasicCoverageBlock) -> NodeCounter {
         // If the BCB already has a counter, return it.
-        if let Some(&counter) = self.site_counters.get(&Site::Node { bcb }) {
+        if let Some(counter) = self.node_counters[bcb] {
             debug!("{bcb:?} already has a counter: {counter:?}");
             return counter;
         }
 
         let counter = self.make_node_counter_inner(bcb);
-        self.site_counters.insert(Site::Node { bcb }, counter);
+        self.node_counters[bcb] = Some(counter);
         counter
     }
 
-    fn make_node_counter_inner(&mut self, bcb: BasicCoverageBlock) -> SiteCounter {
-        // If the node's sole in-edge already has a counter, use that.
-        if let Some(sole_pred) = self.graph.sole_predecessor(bcb)
-            && let Some(&edge_counter) =
-                self.site_counters.get(&Site::Edge { from_bcb: sole_pred, to_bcb: bcb })
+    fn make_node_counter_inner(&mut self, bcb: BasicCoverageBlock) -> NodeCounter {
+        // If the node's sole in-edge already has a counter, the node's count is
+        // just that edge's count.
+        if let Some(edge) = self.graph.sole_in_edge(bcb)
+            && self.edge_counters.contains_key(&edge)
         {
-            return edge_counter;
+            return NodeCounter::SumOfInEdges;
+        }
+
+        // If a dominating node always runs as many times as this node, and
+        // already has a counter, then this node can just reuse that counter.
+        let equivalent_bcb = if self.placement.shared_counters {
+            self.count_classes
+                .same_count_ancestors(bcb)
+                .find(|&equivalent_bcb| self.node_counters[equivalent_bcb].is_some())
+        } else {
+            self.graph
+                .control_equivalent_dominator(bcb)
+                .filter(|&equivalent_bcb| self.node_counters[equivalent_bcb].is_some())
+        };
+        if let Some(equivalent_bcb) = equivalent_bcb {
+            let link = self.count_classes.link(bcb);
+            let representative = self.count_classes.representative(bcb);
+            debug!(?bcb, ?equivalent_bcb, ?link, ?representative, "node shares a counter");
+            return NodeCounter::SameAs { bcb: equivalent_bcb };
         }
 
         let predecessors = self.graph.predecessors[bcb].as_slice();
@@ -328,89 +700,94 @@ fn make_node_counter_inner(&mut self, bcb: BasicCoverageBlock) -> SiteCounter {
         //   trying to get the in-edge counts would require this node's counter,
         //   leading to infinite recursion.
         if predecessors.len() <= 1 || predecessors.contains(&bcb) {
-            debug!(?bcb, ?predecessors, "node has <=1 predecessors or is its own predecessor");
-            let counter = SiteCounter::Phys { site: Site::Node { bcb } };
-            debug!(?bcb, ?counter, "node gets a physical counter");
-            return counter;
+            debug!(?bcb, ?predecessors, "node gets a physical counter");
+            return NodeCounter::Phys;
         }
 
         // A BCB with multiple incoming edges can compute its count by ensuring that counters
         // exist for each of those edges, and then adding them up to get a total count.
-        for &from_bcb in predecessors {
-            self.get_or_make_edge_counter(from_bcb, bcb);
+        for edge in self.graph.edges_into(bcb) {
+            self.get_or_make_edge_counter(edge);
         }
-        let sum_of_in_edges = SiteCounter::NodeSumExpr { bcb };
-
-        debug!("{bcb:?} gets a new counter (sum of predecessor counters): {sum_of_in_edges:?}");
-        sum_of_in_edges
+        debug!(?bcb, "node gets a new counter (sum of in-edge counters)");
+        NodeCounter::SumOfInEdges
     }
 
     #[instrument(level = "debug", skip(self))]
-    fn get_or_make_edge_counter(
-        &mut self,
-        from_bcb: BasicCoverageBlock,
-        to_bcb: BasicCoverageBlock,
-    ) -> SiteCounter {
+    fn get_or_make_edge_counter(&mut self, edge: BcbEdge) -> EdgeCounter {
         // If the edge already has a counter, return it.
-        if let Some(&counter) = self.site_counters.get(&Site::Edge { from_bcb, to_bcb }) {
-            debug!("Edge {from_bcb:?}->{to_bcb:?} already has a counter: {counter:?}");
+        if let Some(&counter) = self.edge_counters.get(&edge) {
+            debug!("Edge {edge:?} already has a counter: {counter:?}");
             return counter;
         }
 
-        let counter = self.make_edge_counter_inner(from_bcb, to_bcb);
-        self.site_counters.insert(Site::Edge { from_bcb, to_bcb }, counter);
+        let counter = self.make_edge_counter_inner(edge);
+        self.edge_counters.insert(edge, counter);
         counter
     }
 
-    fn make_edge_counter_inner(
-        &mut self,
-        from_bcb: BasicCoverageBlock,
-        to_bcb: BasicCoverageBlock,
-    ) -> SiteCounter {
+    fn make_edge_counter_inner(&mut self, edge: BcbEdge) -> EdgeCounter {
+        let from_bcb = self.graph.edge_source(edge);
+        let to_bcb = self.graph.edge_target(edge);
+
         // If the target node has exactly one in-edge (i.e. this one), then just
         // use the node's counter, since it will have the same value.
-        if let Some(sole_pred) = self.graph.sole_predecessor(to_bcb) {
-            assert_eq!(sole_pred, from_bcb);
-            // This call must take care not to invoke `get_or_make_edge` for
-            // this edge, since that would result in infinite recursion!
-            return self.get_or_make_node_counter(to_bcb);
+        if let Some(sole_in_edge) = self.graph.sole_in_edge(to_bcb) {
+            assert_eq!(sole_in_edge, edge);
+            // The edge has no counter yet, so the node's counter won't be
+            // derived from it, which would be circular.
+            self.get_or_make_node_counter(to_bcb);
+            return EdgeCounter::SameAsTarget;
         }
 
         // If the source node has exactly one out-edge (i.e. this one) and would have
         // the same execution count as that edge, then just use the node's counter.
         if let Some(simple_succ) = self.graph.simple_successor(from_bcb) {
             assert_eq!(simple_succ, to_bcb);
-            return self.get_or_make_node_counter(from_bcb);
+            self.get_or_make_node_counter(from_bcb);
+            return EdgeCounter::SameAsSource;
         }
 
         // Make a new counter to count this edge.
-        let counter = SiteCounter::Phys { site: Site::Edge { from_bcb, to_bcb } };
-        debug!(?from_bcb, ?to_bcb, ?counter, "edge gets a physical counter");
-        counter
+        debug!(?from_bcb, ?to_bcb, "edge gets a physical counter");
+        EdgeCounter::Phys
     }
 
-    /// Given a set of candidate out-edges (represented by their successor node),
-    /// choose one to be given a counter expression instead of a physical counter.
+    /// Given a set of candidate out-edges, choose one to be given a counter
+    /// expression instead of a physical counter.
     fn choose_out_edge_for_expression(
         &self,
         from_bcb: BasicCoverageBlock,
-        candidate_successors: &[BasicCoverageBlock],
-    ) -> Option<BasicCoverageBlock> {
+        candidate_edges: &[BcbEdge],
+    ) -> Option<BcbEdge> {
+        // Reloop edges are found by looking at natural loops, which don't
+        // describe irreducible control flow. Within an irreducible region,
+        // prefer an edge that stays in the region, since it can be taken
+        // repeatedly, much like a reloop edge.
+        if let Some(region) = self.graph.irreducible_region(from_bcb)
+            && let Some(edge) = candidate_edges.iter().copied().find(|&edge| {
+                self.graph.irreducible_region(self.graph.edge_target(edge)) == Some(region)
+            })
+        {
+            debug!(?region, "Selecting in-region edge {edge:?} to get an expression");
+            return Some(edge);
+        }
+
         // Try to find a candidate that leads back to the top of a loop,
         // because reloop edges tend to be executed more times than loop-exit edges.
-        if let Some(reloop_target) = self.find_good_reloop_edge(from_bcb, &candidate_successors) {
-            debug!("Selecting reloop target {reloop_target:?} to get an expression");
-            return Some(reloop_target);
+        if let Some(reloop_edge) = self.find_good_reloop_edge(from_bcb, candidate_edges) {
+            debug!("Selecting reloop edge {reloop_edge:?} to get an expression");
+            return Some(reloop_edge);
         }
 
         // We couldn't identify a "good" edge, so just choose an arbitrary one.
-        let arbitrary_target = candidate_successors.first().copied()?;
-        debug!(?arbitrary_target, "selecting arbitrary out-edge to get an expression");
-        Some(arbitrary_target)
+        let arbitrary_edge = candidate_edges.first().copied()?;
+        debug!(?arbitrary_edge, "selecting arbitrary out-edge to get an expression");
+        Some(arbitrary_edge)
     }
 
-    /// Given a set of candidate out-edges (represented by their successor node),
-    /// tries to find one that leads back to the top of a loop.
+    /// Given a set of candidate out-edges, tries to find one that leads back
+    /// to the top of a loop.
     ///
     /// Reloop edges are good candidates for counter expressions, because they
     /// will tend to be executed more times than a loop-exit edge, so it's nice
@@ -418,26 +795,27 @@ fn choose_out_edge_for_expression(
     fn find_good_reloop_edge(
         &self,
         from_bcb: BasicCoverageBlock,
-        candidate_successors: &[BasicCoverageBlock],
-    ) -> Option<BasicCoverageBlock> {
+        candidate_edges: &[BcbEdge],
+    ) -> Option<BcbEdge> {
         // If there are no candidates, avoid iterating over the loop stack.
-        if candidate_successors.is_empty() {
+        if candidate_edges.is_empty() {
             return None;
         }
 
         // Consider each loop on the current traversal context stack, top-down.
         for loop_header_node in self.graph.loop_headers_containing(from_bcb) {
             // Try to find a candidate edge that doesn't exit this loop.
-            for &target_bcb in candidate_successors {
+            for &edge in candidate_edges {
                 // An edge is a reloop edge if its target dominates any BCB that has
                 // an edge back to the loop header. (Otherwise it's an exit edge.)
+                let target_bcb = self.graph.edge_target(edge);
                 let is_reloop_edge = self
                     .graph
                     .reloop_predecessors(loop_header_node)
                     .any(|reloop_bcb| self.graph.dominates(target_bcb, reloop_bcb));
                 if is_reloop_edge {
                     // We found a good out-edge to be given an expression.
-                    return Some(target_bcb);
+                    return Some(edge);
                 }
             }
 
@@ -448,8 +826,8 @@ fn find_good_reloop_edge(
         None
     }
 
-    fn into_coverage_counters(self) -> CoverageCounters {
-        Transcriber::new(&self).transcribe_counters()
+    fn into_coverage_counters(self, limits: ExpressionLimits) -> CoverageCounters {
+        Transcriber::new(&self, limits).transcribe_counters()
     }
 }
 
@@ -461,95 +839,135 @@ struct Transcriber<'a> {
 }
 
 impl<'a> Transcriber<'a> {
-    fn new(old: &'a CountersBuilder<'a>) -> Self {
+    fn new(old: &'a CountersBuilder<'a>, limits: ExpressionLimits) -> Self {
         Self {
             old,
-            new: CoverageCounters::with_num_bcbs(old.graph.num_nodes()),
+            new: CoverageCounters::with_num_bcbs(old.graph.num_nodes(), limits),
             phys_counter_for_site: FxHashMap::default(),
         }
     }
 
     fn transcribe_counters(mut self) -> CoverageCounters {
+        let mut class_counters = ClassCounters::new(self.old.placement);
         for bcb in self.old.bcb_needs_counter.iter() {
-            let site = Site::Node { bcb };
-            let site_counter = self.site_counter(site);
+            if let NodeCounter::Zero = self.node_counter(bcb) {
+                self.new.set_node_counter(bcb, BcbCounter::Zero);
+                continue;
+            }
 
-            // Resolve the site counter into flat lists of nodes/edges whose
+            // A node with the same count as another node that already has a
+            // counter can share it, even if the two were given symbolic
+            // counters that look different.
+            let representative = self.old.count_classes.representative(bcb);
+            if let Some(counter) = class_counters.get(representative) {
+                self.new.set_node_counter(bcb, counter);
+                continue;
+            }
+
+            // Resolve the node counter into flat lists of nodes/edges whose
             // physical counts contribute to the counter for this node.
             // Distinguish between counts that will be added vs subtracted.
             let mut pos = vec![];
             let mut neg = vec![];
-            self.push_resolved_sites(site_counter, &mut pos, &mut neg);
-
-            // Simplify by cancelling out sites that appear on both sides.
-            let (mut pos, mut neg) = sort_and_cancel(pos, neg);
-
-            if pos.is_empty() {
-                // If we somehow end up with no positive terms after cancellation,
-                // fall back to creating a physical counter. There's no known way
-                // for this to happen, but it's hard to confidently rule it out.
-                debug_assert!(false, "{site:?} has no positive counter terms");
-                pos = vec![Some(site)];
-                neg = vec![];
-            }
-
-            let mut new_counters_for_sites = |sites: Vec<Option<Site>>| {
-                sites
-                    .into_iter()
-                    .filter_map(|id| try { self.ensure_phys_counter(id?) })
-                    .collect::<Vec<_>>()
-            };
-            let mut pos = new_counters_for_sites(pos);
-            let mut neg = new_counters_for_sites(neg);
+            self.push_node_sites(bcb, &mut pos, &mut neg);
 
-            pos.sort();
-            neg.sort();
-
-            let pos_counter = self.new.make_sum(&pos).expect("`pos` should not be empty");
-            let new_counter = self.new.make_subtracted_sum(pos_counter, &neg);
+            let site = Site::Node { bcb };
+            let new_counter =
+                self.new.make_counter_for_sites(&mut self.phys_counter_for_site, site, pos, neg);
             self.new.set_node_counter(bcb, new_counter);
+            class_counters.insert(representative, new_counter);
         }
 
         self.new
     }
 
-    fn site_counter(&self, site: Site) -> SiteCounter {
-        self.old.site_counters.get(&site).copied().unwrap_or_else(|| {
-            // We should have already created all necessary site counters.
-            // But if we somehow didn't, avoid crashing in release builds,
-            // and just use an extra physical counter instead.
-            debug_assert!(false, "{site:?} should have a counter");
-            SiteCounter::Phys { site }
+    /// Returns the counter of the given node. We should have already created
+    /// all necessary counters. But if we somehow didn't, avoid crashing in
+    /// release builds, and just use an extra physical counter instead.
+    fn node_counter(&self, bcb: BasicCoverageBlock) -> NodeCounter {
+        self.old.node_counters[bcb].unwrap_or_else(|| {
+            debug_assert!(false, "{bcb:?} should have a counter");
+            NodeCounter::Phys
         })
     }
 
-    fn ensure_phys_counter(&mut self, site: Site) -> BcbCounter {
-        *self.phys_counter_for_site.entry(site).or_insert_with(|| self.new.make_phys_counter(site))
+    /// Like [`Self::node_counter`], but for an edge.
+    fn edge_counter(&self, edge: BcbEdge) -> EdgeCounter {
+        self.old.edge_counters.get(&edge).copied().unwrap_or_else(|| {
+            debug_assert!(false, "{edge:?} should have a counter");
+            EdgeCounter::Phys
+        })
     }
 
-    /// Resolves the given counter into flat lists of nodes/edges, whose counters
-    /// will then be added and subtracted to form a counter expression.
-    fn push_resolved_sites(&self, counter: SiteCounter, pos: &mut Vec<Site>, neg: &mut Vec<Site>) {
-        match counter {
-            SiteCounter::Phys { site } => pos.push(site),
-            SiteCounter::NodeSumExpr { bcb } => {
-                for &from_bcb in &self.old.graph.predecessors[bcb] {
-                    let edge_counter = self.site_counter(Site::Edge { from_bcb, to_bcb: bcb });
-                    self.push_resolved_sites(edge_counter, pos, neg);
+    /// Resolves the counter of the given node into flat lists of nodes/edges,
+    /// whose counters will then be added and subtracted to form a counter
+    /// expression.
+    fn push_node_sites(&self, bcb: BasicCoverageBlock, pos: &mut Vec<Site>, neg: &mut Vec<Site>) {
+        match self.node_counter(bcb) {
+            NodeCounter::Phys => pos.push(Site::Node { bcb }),
+            NodeCounter::SumOfInEdges => {
+                for edge in self.old.graph.edges_into(bcb) {
+                    self.push_edge_sites(edge, pos, neg);
                 }
             }
-            SiteCounter::EdgeDiffExpr { from_bcb, to_bcb } => {
-                // First, add the count for `from_bcb`.
-                let node_counter = self.site_counter(Site::Node { bcb: from_bcb });
-                self.push_resolved_sites(node_counter, pos, neg);
+            NodeCounter::SameAs { bcb: equivalent_bcb } => {
+                self.push_node_sites(equivalent_bcb, pos, neg)
+            }
+            NodeCounter::Zero => {}
+        }
+    }
+
+    /// Like [`Self::push_node_sites`], but for the counter of an edge.
+    fn push_edge_sites(&self, edge: BcbEdge, pos: &mut Vec<Site>, neg: &mut Vec<Site>) {
+        let graph = self.old.graph;
+        match self.edge_counter(edge) {
+            EdgeCounter::Phys => pos.push(Site::Edge { edge }),
+            EdgeCounter::SameAsTarget => self.push_node_sites(graph.edge_target(edge), pos, neg),
+            EdgeCounter::SameAsSource => self.push_node_sites(graph.edge_source(edge), pos, neg),
+            EdgeCounter::SourceMinusSiblings => {
+                // First, add the count for the edge's source node.
+                self.push_node_sites(graph.edge_source(edge), pos, neg);
 
                 // Then subtract the counts for the other out-edges.
-                for target in sibling_out_edge_targets(self.old.graph, from_bcb, to_bcb) {
-                    let edge_counter = self.site_counter(Site::Edge { from_bcb, to_bcb: target });
+                for sibling in sibling_out_edges(graph, edge) {
                     // Swap `neg` and `pos` so that the counter is subtracted.
-                    self.push_resolved_sites(edge_counter, neg, pos);
+                    self.push_edge_sites(sibling, neg, pos);
                 }
             }
+            EdgeCounter::Zero => {}
+        }
+    }
+}
+
+/// The counters given to classes of nodes that are known to have the same
+/// execution count (see [`CountEquivalenceClasses`]), keyed by each class's
+/// representative.
+///
+/// Once one node in a class has a counter, every other node in the class that
+/// needs one reuses it, so that all of the class's spans refer to a single
+/// counter instead of each node adding counters or expressions of its own.
+/// This is only done under `-Zcoverage-options=shared-counters`.
+#[derive(Debug)]
+struct ClassCounters {
+    enabled: bool,
+    counters: FxHashMap<BasicCoverageBlock, BcbCounter>,
+}
+
+impl ClassCounters {
+    fn new(placement: CounterPlacement) -> Self {
+        Self { enabled: placement.shared_counters, counters: FxHashMap::default() }
+    }
+
+    fn get(&self, representative: BasicCoverageBlock) -> Option<BcbCounter> {
+        if !self.enabled {
+            return None;
+        }
+        self.counters.get(&representative).copied()
+    }
+
+    fn insert(&mut self, representative: BasicCoverageBlock, counter: BcbCounter) {
+        if self.enabled {
+            self.counters.insert(representative, counter);
         }
     }
 }
@@ -0,0 +1,342 @@
+//! Counter placement based on a maximal spanning tree of the coverage graph,
+//! for `-Zcoverage-options=spanning-tree`.
+//!
+//! This is the classic approach described by Knuth, and by Ball and Larus,
+//! and also used by gcov. The coverage graph is extended with a virtual exit
+//! node, so that execution counts are conserved at every node: the total
+//! count of a node's in-edges equals the total count of its out-edges. Given
+//! any spanning tree of that extended graph (ignoring edge directions), the
+//! count of each edge on the tree can be derived from the counts of the edges
+//! off the tree, so only the latter need physical counters.
+//!
+//! Choosing a spanning tree of maximal estimated weight keeps the edges that
+//! are likely to run most often (those in the innermost loops) on the tree,
+//! so that they never need a counter increment. Under
+//! `-Zcoverage-options=profile-guided`, the counts recorded by an earlier
+//! build's profile take precedence over that estimate, wherever they're known.
+
+use std::cmp::Reverse;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::graph::DirectedGraph;
+use rustc_index::bit_set::DenseBitSet;
+use rustc_index::{Idx, IndexVec};
+use tracing::debug;
+
+use super::{
+    BcbCounter, ClassCounters, CounterPlacement, CoverageCounters, ExpressionLimits,
+    ProfiledCounts, Site, sort_and_cancel,
+};
+use crate::coverage::graph::{BasicCoverageBlock, BcbEdge, CoverageGraph, START_BCB};
+
+rustc_index::newtype_index! {
+    /// A node in the extended graph: one of the two halves of a coverage graph
+    /// node (see [`FlowGraph`]), or the virtual exit node.
+    #[debug_format = "flow{}"]
+    struct FlowNode {}
+}
+
+rustc_index::newtype_index! {
+    #[orderable]
+    #[debug_format = "flow_edge{}"]
+    struct FlowEdge {}
+}
+
+/// What an edge of the extended graph stands for.
+#[derive(Clone, Copy, Debug)]
+enum FlowEdgeKind {
+    /// An edge of the coverage graph.
+    Real(BcbEdge),
+    /// Runs from a node whose out-edges don't sum to its count into the exit,
+    /// and is taken each time that node runs.
+    NodeExit(BasicCoverageBlock),
+    /// Runs from the exit to the out-half of a node whose out-edges don't sum
+    /// to its count, and is taken as often as those out-edges in total.
+    NodeResume(BasicCoverageBlock),
+    /// Runs from the exit to the start node, and is taken on each call.
+    Entry,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FlowEdgeData {
+    from: FlowNode,
+    to: FlowNode,
+    kind: FlowEdgeKind,
+}
+
+/// The coverage graph, extended with a virtual exit node so that counts are
+/// conserved at every node.
+///
+/// Each coverage graph node whose count is the sum of its out-edges is a
+/// single node here. Any other node is split into an in-half, which only has
+/// the node's in-edges and an edge into the exit, and an out-half, which only
+/// has the node's out-edges and an edge from the exit. Edges (and nodes) that
+/// are statically known to never run are left out, since they have no count.
+struct FlowGraph {
+    in_node: IndexVec<BasicCoverageBlock, FlowNode>,
+    num_nodes: usize,
+    edges: IndexVec<FlowEdge, FlowEdgeData>,
+}
+
+impl FlowGraph {
+    fn new(graph: &CoverageGraph) -> Self {
+        let mut num_nodes = graph.num_nodes();
+        let in_node =
+            IndexVec::from_fn_n(|bcb: BasicCoverageBlock| FlowNode::new(bcb.index()), num_nodes);
+        let out_node = IndexVec::from_fn_n(
+            |bcb| {
+                if graph[bcb].is_out_summable {
+                    in_node[bcb]
+                } else {
+                    num_nodes += 1;
+                    FlowNode::new(num_nodes - 1)
+                }
+            },
+            graph.num_nodes(),
+        );
+        let exit = FlowNode::new(num_nodes);
+        num_nodes += 1;
+
+        let mut edges = IndexVec::new();
+        edges.push(FlowEdgeData { from: exit, to: in_node[START_BCB], kind: FlowEdgeKind::Entry });
+        for (bcb, data) in graph.iter_enumerated() {
+            if graph.is_unreachable(bcb) {
+                continue;
+            }
+            if !data.is_out_summable {
+                let kind = FlowEdgeKind::NodeExit(bcb);
+                edges.push(FlowEdgeData { from: in_node[bcb], to: exit, kind });
+                if !graph.successors[bcb].is_empty() {
+                    let kind = FlowEdgeKind::NodeResume(bcb);
+                    edges.push(FlowEdgeData { from: exit, to: out_node[bcb], kind });
+                }
+            }
+            for edge in graph.edges_from(bcb).filter(|&edge| !graph.is_untaken_edge(edge)) {
+                let (from, to) = (out_node[bcb], in_node[graph.edge_target(edge)]);
+                edges.push(FlowEdgeData { from, to, kind: FlowEdgeKind::Real(edge) });
+            }
+        }
+
+        Self { in_node, num_nodes, edges }
+    }
+}
+
+/// The physical counter sites whose counts add up to (`pos`), or are
+/// subtracted from (`neg`), the count of some edge or node.
+#[derive(Clone, Debug, Default)]
+struct SiteSum {
+    pos: Vec<Site>,
+    neg: Vec<Site>,
+}
+
+impl SiteSum {
+    fn add(&mut self, other: &SiteSum) {
+        self.pos.extend_from_slice(&other.pos);
+        self.neg.extend_from_slice(&other.neg);
+    }
+
+    fn subtract(&mut self, other: &SiteSum) {
+        self.pos.extend_from_slice(&other.neg);
+        self.neg.extend_from_slice(&other.pos);
+    }
+
+    /// Cancels out sites that appear on both sides, to keep sums from growing
+    /// as they are combined.
+    fn simplify(self) -> Self {
+        let (pos, neg) = sort_and_cancel(self.pos, self.neg);
+        Self { pos: pos.into_iter().flatten().collect(), neg: neg.into_iter().flatten().collect() }
+    }
+}
+
+/// Returns the site where a physical counter for the given edge should be
+/// placed. If the edge always has the same count as one of its endpoints,
+/// counting that node avoids having to insert a new block for the edge.
+fn edge_site(graph: &CoverageGraph, edge: BcbEdge) -> Site {
+    let from_bcb = graph.edge_source(edge);
+    let to_bcb = graph.edge_target(edge);
+    if graph.sole_in_edge(to_bcb) == Some(edge) {
+        Site::Node { bcb: to_bcb }
+    } else if graph.simple_successor(from_bcb) == Some(to_bcb) {
+        Site::Node { bcb: from_bcb }
+    } else {
+        Site::Edge { edge }
+    }
+}
+
+/// Minimal union-find over the nodes of the extended graph, for building the
+/// spanning tree.
+struct DisjointSets {
+    parent: IndexVec<FlowNode, FlowNode>,
+}
+
+impl DisjointSets {
+    fn new(num_nodes: usize) -> Self {
+        Self { parent: IndexVec::from_fn_n(|node| node, num_nodes) }
+    }
+
+    fn find(&mut self, mut node: FlowNode) -> FlowNode {
+        while self.parent[node] != node {
+            // Path halving keeps later lookups short.
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    /// Merges the sets containing `a` and `b`, and returns false if they were
+    /// already the same set.
+    fn union(&mut self, a: FlowNode, b: FlowNode) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b;
+        a != b
+    }
+}
+
+pub(super) fn make_counters(
+    graph: &CoverageGraph,
+    bcb_needs_counter: &DenseBitSet<BasicCoverageBlock>,
+    limits: ExpressionLimits,
+    placement: CounterPlacement,
+    profiled_counts: Option<&ProfiledCounts>,
+) -> CoverageCounters {
+    let flow = FlowGraph::new(graph);
+
+    // Build a spanning tree with Kruskal's algorithm, adding the heaviest
+    // edges first. Edges from the exit can't be counted directly, so they
+    // always go on the tree. They all start at the exit and end at distinct
+    // nodes, so they can't form a cycle. Edges with a known profiled count
+    // come first, hottest first, and the rest are ordered by loop depth.
+    let mut sets = DisjointSets::new(flow.num_nodes);
+    let mut on_tree = DenseBitSet::new_empty(flow.edges.len());
+    let mut candidates = vec![];
+    for (e, &FlowEdgeData { from, to, kind }) in flow.edges.iter_enumerated() {
+        match kind {
+            FlowEdgeKind::Entry | FlowEdgeKind::NodeResume(_) => {
+                assert!(sets.union(from, to), "edges from the exit form a cycle");
+                on_tree.insert(e);
+            }
+            FlowEdgeKind::Real(edge) => {
+                let depth = graph
+                    .loop_depth(graph.edge_source(edge))
+                    .min(graph.loop_depth(graph.edge_target(edge)));
+                // At equal depth, prefer to keep edges that would need a new
+                // block for their counter on the tree.
+                let needs_block = matches!(edge_site(graph, edge), Site::Edge { .. });
+                let count = profiled_counts.and_then(|counts| counts.edge_count(graph, edge));
+                candidates.push((Reverse(count), Reverse(depth), !needs_block, e));
+            }
+            FlowEdgeKind::NodeExit(bcb) => {
+                let count = profiled_counts.and_then(|counts| counts.node_count(bcb));
+                candidates.push((Reverse(count), Reverse(graph.loop_depth(bcb)), true, e));
+            }
+        }
+    }
+    candidates.sort();
+    for (_, _, _, e) in candidates {
+        let FlowEdgeData { from, to, .. } = flow.edges[e];
+        if sets.union(from, to) {
+            on_tree.insert(e);
+        }
+    }
+
+    // Every edge off the tree is counted directly.
+    let mut sums: IndexVec<FlowEdge, Option<SiteSum>> =
+        IndexVec::from_elem_n(None, flow.edges.len());
+    for (e, &FlowEdgeData { kind, .. }) in flow.edges.iter_enumerated() {
+        if on_tree.contains(e) {
+            continue;
+        }
+        let site = match kind {
+            FlowEdgeKind::Real(edge) => edge_site(graph, edge),
+            FlowEdgeKind::NodeExit(bcb) => Site::Node { bcb },
+            FlowEdgeKind::NodeResume(_) | FlowEdgeKind::Entry => {
+                unreachable!("edges from the exit are always on the tree")
+            }
+        };
+        sums[e] = Some(SiteSum { pos: vec![site], neg: vec![] });
+    }
+
+    // Derive the counts of the tree edges, working inwards from the leaves of
+    // the tree: once a node has only one tree edge left whose count is
+    // unknown, that count follows from conservation at that node.
+    let mut in_edges = IndexVec::from_elem_n(vec![], flow.num_nodes);
+    let mut out_edges = IndexVec::from_elem_n(vec![], flow.num_nodes);
+    let mut num_unknown = IndexVec::<FlowNode, usize>::from_elem_n(0, flow.num_nodes);
+    for (e, &FlowEdgeData { from, to, .. }) in flow.edges.iter_enumerated() {
+        out_edges[from].push(e);
+        in_edges[to].push(e);
+        if on_tree.contains(e) {
+            num_unknown[from] += 1;
+            num_unknown[to] += 1;
+        }
+    }
+    let mut ready =
+        num_unknown.indices().filter(|&node| num_unknown[node] == 1).collect::<Vec<_>>();
+    while let Some(node) = ready.pop() {
+        if num_unknown[node] != 1 {
+            continue;
+        }
+        let unknown = |&e: &FlowEdge| sums[e].is_none();
+        let (e, is_in_edge) = match in_edges[node].iter().copied().find(unknown) {
+            Some(e) => (e, true),
+            None => (out_edges[node].iter().copied().find(unknown).unwrap(), false),
+        };
+
+        // The unknown edge's count is the total on the other side of the
+        // node, minus the other known edges on its own side.
+        let (same_side, other_side) = if is_in_edge {
+            (&in_edges[node], &out_edges[node])
+        } else {
+            (&out_edges[node], &in_edges[node])
+        };
+        let mut sum = SiteSum::default();
+        for &other in other_side {
+            sum.add(sums[other].as_ref().unwrap());
+        }
+        for &other in same_side.iter().filter(|&&other| other != e) {
+            sum.subtract(sums[other].as_ref().unwrap());
+        }
+        sums[e] = Some(sum.simplify());
+
+        let FlowEdgeData { from, to, .. } = flow.edges[e];
+        for endpoint in [from, to] {
+            num_unknown[endpoint] -= 1;
+            if num_unknown[endpoint] == 1 {
+                ready.push(endpoint);
+            }
+        }
+    }
+    debug_assert!(sums.iter().all(Option::is_some), "some tree edges have unknown counts");
+
+    // Each node's count is the total of its in-edges, which for the start
+    // node includes the edge from the exit. Under `shared-counters`, nodes that
+    // are known to have the same count share the counter of the first of them
+    // to get one.
+    let mut counters = CoverageCounters::with_num_bcbs(graph.num_nodes(), limits);
+    let mut phys_counter_for_site = FxHashMap::default();
+    let count_classes = graph.count_equivalence_classes();
+    let mut class_counters = ClassCounters::new(placement);
+    for bcb in bcb_needs_counter.iter() {
+        if graph.is_unreachable(bcb) {
+            counters.set_node_counter(bcb, BcbCounter::Zero);
+            continue;
+        }
+        let representative = count_classes.representative(bcb);
+        if let Some(counter) = class_counters.get(representative) {
+            counters.set_node_counter(bcb, counter);
+            continue;
+        }
+        let mut sum = SiteSum::default();
+        for &e in &in_edges[flow.in_node[bcb]] {
+            sum.add(sums[e].as_ref().expect("tree edge counts should all be known"));
+        }
+        debug!(?bcb, ?sum, "node count from spanning tree");
+        let SiteSum { pos, neg } = sum;
+        let site = Site::Node { bcb };
+        let counter = counters.make_counter_for_sites(&mut phys_counter_for_site, site, pos, neg);
+        counters.set_node_counter(bcb, counter);
+        class_counters.insert(representative, counter);
+    }
+
+    counters
+}
@@ -1,6 +1,9 @@
 use std::fmt::Debug;
 
-use super::sort_and_cancel;
+use rustc_middle::mir::coverage::Op;
+
+use super::{BcbCounter, CoverageCounters, ExpressionLimits, Site, sort_and_cancel};
+use crate::coverage::graph::BasicCoverageBlock;
 
 fn flatten<T>(input: Vec<Option<T>>) -> Vec<T> {
     input.into_iter().flatten().collect()
@@ -39,3 +42,31 @@ fn cancellation() {
         check_test_case(neg.to_vec(), pos.to_vec(), neg_expected.to_vec(), pos_expected.to_vec());
     }
 }
+
+#[test]
+fn simplify_expressions() {
+    let mut counters = CoverageCounters::with_num_bcbs(0, ExpressionLimits::default());
+    let mut phys =
+        |i| counters.make_phys_counter(Site::Node { bcb: BasicCoverageBlock::from_u32(i) });
+    let (a, b) = (phys(0), phys(1));
+    let zero = BcbCounter::Zero;
+
+    assert_eq!(counters.make_expression(a, Op::Add, zero), a);
+    assert_eq!(counters.make_expression(zero, Op::Add, a), a);
+    assert_eq!(counters.make_expression(a, Op::Subtract, zero), a);
+    assert_eq!(counters.make_expression(zero, Op::Subtract, a), zero);
+    assert_eq!(counters.make_expression(a, Op::Subtract, a), zero);
+    assert_eq!(counters.num_expressions(), 0);
+
+    let a_plus_b = counters.make_expression(a, Op::Add, b);
+    assert_eq!(counters.make_expression(a_plus_b, Op::Subtract, b), a);
+    assert_eq!(counters.make_expression(a_plus_b, Op::Subtract, a), b);
+    let a_minus_b = counters.make_expression(a, Op::Subtract, b);
+    assert_eq!(counters.make_expression(a_minus_b, Op::Add, b), a);
+    assert_eq!(counters.make_expression(b, Op::Add, a_minus_b), a);
+    assert_eq!(counters.make_expression(a, Op::Subtract, a_minus_b), b);
+
+    // Identical expressions are only created once.
+    assert_eq!(counters.make_expression(a, Op::Add, b), a_plus_b);
+    assert_eq!(counters.num_expressions(), 2);
+}
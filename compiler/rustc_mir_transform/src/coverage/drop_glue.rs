@@ -0,0 +1,89 @@
+//! Coverage instrumentation for drop glue, under
+//! `-Zcoverage-options=instrument-shims`.
+//!
+//! Drop glue has no source code of its own, so the single counter in each
+//! instrumented drop-glue shim is reported on the dropped type's `impl Drop`
+//! block if it has one, or on the type's definition otherwise.
+
+use std::hash::Hash;
+
+use rustc_data_structures::stable_hasher::{Hash64, StableHasher};
+use rustc_index::IndexVec;
+use rustc_middle::mir::coverage::{
+    CounterId, CovTerm, CoverageKind, FunctionCoverageInfo, Mapping, MappingKind,
+};
+use rustc_middle::mir::{self, SourceInfo, Statement, StatementKind};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::def_id::{DefId, LOCAL_CRATE};
+use tracing::debug;
+
+/// Adds a counter to the start of the drop glue for `ty`, if `ty` is an ADT
+/// defined in the current crate.
+pub(crate) fn instrument_drop_glue<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir_body: &mut mir::Body<'tcx>,
+    ty: Ty<'tcx>,
+) {
+    if !tcx.sess.instrument_coverage() || !tcx.sess.coverage_instrument_shims() {
+        return;
+    }
+    if tcx.is_compiler_builtins(LOCAL_CRATE) {
+        return;
+    }
+    let ty::Adt(adt_def, _) = *ty.kind() else { return };
+    let Some(adt_did) = adt_def.did().as_local() else { return };
+
+    // Prefer the `impl Drop` block, since that is where users look for
+    // destructor behaviour. Its span is just the impl header.
+    let destructor = tcx.adt_destructor(adt_did);
+    let span_def = match destructor {
+        Some(destructor) => tcx.parent(destructor.did),
+        None => adt_did.to_def_id(),
+    };
+    let Some(span_def) = span_def.as_local() else { return };
+    if !tcx.coverage_attr_on(span_def) {
+        return;
+    }
+    let span = tcx.def_span(span_def);
+    if span.from_expansion() {
+        debug!("skipping drop glue for {ty:?}: {span:?} is from a macro expansion");
+        return;
+    }
+
+    let counter = CounterId::START;
+    let source_info = SourceInfo::outermost(span);
+    mir_body[mir::START_BLOCK].statements.insert(
+        0,
+        Statement {
+            source_info,
+            kind: StatementKind::Coverage(CoverageKind::CounterIncrement { id: counter }),
+        },
+    );
+
+    mir_body.function_coverage_info = Some(Box::new(FunctionCoverageInfo {
+        function_source_hash: drop_glue_source_hash(tcx, adt_did.to_def_id(), destructor.is_some()),
+        body_span: span,
+        num_bcbs: 1,
+        num_counters: 1,
+        mcdc_bitmap_bits: 0,
+        expressions: IndexVec::new(),
+        mappings: vec![Mapping { kind: MappingKind::Code(CovTerm::Counter(counter)), span }],
+        mcdc_num_condition_bitmaps: 0,
+        counter_metadata: IndexVec::new(),
+        num_paths: 0,
+        path_mappings: vec![],
+        num_switch_arm_slots: 0,
+        switch_arm_mappings: vec![],
+        expansion_mappings: vec![],
+        macro_definition_mappings: vec![],
+    }));
+}
+
+/// Drop glue has no HIR body to hash, so identify it by the dropped type's
+/// def-path, and by whether it calls a user-written destructor.
+fn drop_glue_source_hash(tcx: TyCtxt<'_>, adt_did: DefId, has_destructor: bool) -> u64 {
+    let mut hasher = StableHasher::new();
+    tcx.def_path_hash(adt_did).hash(&mut hasher);
+    has_destructor.hash(&mut hasher);
+    hasher.finish::<Hash64>().as_u64()
+}
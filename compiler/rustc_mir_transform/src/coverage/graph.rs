@@ -1,15 +1,20 @@
-use std::cmp::Ordering;
-use std::collections::VecDeque;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
 use std::ops::{Index, IndexMut};
-use std::{iter, mem, slice};
+use std::{io, iter, mem, slice};
 
+use itertools::Itertools;
 use rustc_data_structures::captures::Captures;
 use rustc_data_structures::fx::FxHashSet;
-use rustc_data_structures::graph::dominators::Dominators;
+use rustc_data_structures::graph::dominators::{Dominators, PostDominators};
+use rustc_data_structures::graph::loops::LoopForest;
+use rustc_data_structures::graph::scc::Sccs;
+use rustc_data_structures::graph::vec_graph::VecGraph;
 use rustc_data_structures::graph::{self, DirectedGraph, StartNode};
 use rustc_index::IndexVec;
 use rustc_index::bit_set::DenseBitSet;
-use rustc_middle::mir::{self, BasicBlock, Terminator, TerminatorKind};
+use rustc_middle::mir::{self, BasicBlock, Terminator, TerminatorKind, UnwindAction};
+use rustc_span::Span;
 use tracing::debug;
 
 /// A coverage-specific simplification of the MIR control flow graph (CFG). The `CoverageGraph`s
@@ -21,103 +26,482 @@ pub(crate) struct CoverageGraph {
     pub(crate) successors: IndexVec<BasicCoverageBlock, Vec<BasicCoverageBlock>>,
     pub(crate) predecessors: IndexVec<BasicCoverageBlock, Vec<BasicCoverageBlock>>,
 
+    /// Every edge in the graph, numbered by source node, and then in the same
+    /// order as that node's successors.
+    edges: IndexVec<BcbEdge, BcbEdgeData>,
+    /// The out-edges of each node, in the same order as its `successors`.
+    out_edges: IndexVec<BasicCoverageBlock, Vec<BcbEdge>>,
+    /// The in-edges of each node, in the same order as its `predecessors`.
+    in_edges: IndexVec<BasicCoverageBlock, Vec<BcbEdge>>,
+
     dominators: Option<Dominators<BasicCoverageBlock>>,
     /// Allows nodes to be compared in some total order such that _if_
     /// `a` dominates `b`, then `a < b`. If neither node dominates the other,
     /// their relative order is consistent but arbitrary.
     dominator_order_rank: IndexVec<BasicCoverageBlock, u32>,
-    /// A loop header is a node that dominates one or more of its predecessors.
-    is_loop_header: DenseBitSet<BasicCoverageBlock>,
-    /// For each node, the loop header node of its nearest enclosing loop.
-    /// This forms a linked list that can be traversed to find all enclosing loops.
-    enclosing_loop_header: IndexVec<BasicCoverageBlock, Option<BasicCoverageBlock>>,
+    /// The natural loops of this graph, and how they nest.
+    loops: Option<LoopForest<BasicCoverageBlock>>,
+    /// For each loop header, the set of nodes in the body of its loop,
+    /// including the header itself and the bodies of nested loops.
+    loop_bodies: IndexVec<BasicCoverageBlock, Option<DenseBitSet<BasicCoverageBlock>>>,
+    /// For each node that is part of a cycle with no single dominating entry
+    /// node, the irreducible region that contains it.
+    irreducible_regions: IndexVec<BasicCoverageBlock, Option<IrreducibleRegion>>,
+    /// Post-dominators, on a virtual exit node that joins every node where
+    /// execution can stop. Only computed by [`Self::compute_post_dominators`].
+    post_dominators: Option<PostDominators<BasicCoverageBlock>>,
+    /// Edges that are statically known to never be taken, including every
+    /// out-edge of an unreachable node. Only populated by
+    /// [`Self::mark_untaken_edges`].
+    untaken_edges: DenseBitSet<BcbEdge>,
+    /// Nodes that can't be reached from the start node without taking an
+    /// untaken edge.
+    unreachable_nodes: DenseBitSet<BasicCoverageBlock>,
+    /// The source spans of each node's statements and terminators, with
+    /// overlapping or adjacent spans in the same syntax context merged.
+    bcb_spans: IndexVec<BasicCoverageBlock, Vec<Span>>,
 }
 
 impl CoverageGraph {
-    pub(crate) fn from_mir(mir_body: &mir::Body<'_>) -> Self {
-        let (bcbs, bb_to_bcb) = Self::compute_basic_coverage_blocks(mir_body);
-
-        // Pre-transform MIR `BasicBlock` successors and predecessors into the BasicCoverageBlock
-        // equivalents. Note that since the BasicCoverageBlock graph has been fully simplified, the
-        // each predecessor of a BCB leader_bb should be in a unique BCB. It is possible for a
-        // `SwitchInt` to have multiple targets to the same destination `BasicBlock`, so
-        // de-duplication is required. This is done without reordering the successors.
-
-        let successors = IndexVec::from_fn_n(
-            |bcb| {
-                let mut seen_bcbs = FxHashSet::default();
-                let terminator = mir_body[bcbs[bcb].last_bb()].terminator();
-                bcb_filtered_successors(terminator)
-                    .into_iter()
-                    .filter_map(|successor_bb| bb_to_bcb[successor_bb])
-                    // Remove duplicate successor BCBs, keeping only the first.
-                    .filter(|&successor_bcb| seen_bcbs.insert(successor_bcb))
-                    .collect::<Vec<_>>()
-            },
-            bcbs.len(),
-        );
+    /// Builds the coverage graph for a MIR body.
+    ///
+    /// Blocks in `panic_sites` end in a terminator that is likely to panic
+    /// instead of continuing to its successor, so the code after them is
+    /// counted separately instead of being chained into the same node.
+    ///
+    /// Edges outside the normal control flow are only kept if `side_edges`
+    /// asks for them, in which case the blocks they lead to become part of
+    /// the graph.
+    pub(crate) fn from_mir(
+        mir_body: &mir::Body<'_>,
+        panic_sites: &DenseBitSet<BasicBlock>,
+        side_edges: SideEdges,
+    ) -> Self {
+        let subgraph =
+            CoverageRelevantSubgraph::new(&mir_body.basic_blocks, panic_sites, side_edges);
+        let (bcbs, bb_to_bcb) = Self::compute_basic_coverage_blocks(mir_body, subgraph);
 
-        let mut predecessors = IndexVec::from_elem(Vec::new(), &bcbs);
-        for (bcb, bcb_successors) in successors.iter_enumerated() {
-            for &successor in bcb_successors {
-                predecessors[successor].push(bcb);
-            }
-        }
+        let bcb_spans =
+            bcbs.iter().map(|data| aggregate_spans(mir_body, &data.basic_blocks)).collect();
 
         let num_nodes = bcbs.len();
         let mut this = Self {
             bcbs,
             bb_to_bcb,
-            successors,
-            predecessors,
+            successors: IndexVec::from_elem_n(Vec::new(), num_nodes),
+            predecessors: IndexVec::new(),
+            edges: IndexVec::new(),
+            out_edges: IndexVec::new(),
+            in_edges: IndexVec::new(),
             dominators: None,
-            dominator_order_rank: IndexVec::from_elem_n(0, num_nodes),
-            is_loop_header: DenseBitSet::new_empty(num_nodes),
-            enclosing_loop_header: IndexVec::from_elem_n(None, num_nodes),
+            dominator_order_rank: IndexVec::new(),
+            loops: None,
+            loop_bodies: IndexVec::new(),
+            irreducible_regions: IndexVec::new(),
+            post_dominators: None,
+            untaken_edges: DenseBitSet::new_empty(0),
+            unreachable_nodes: DenseBitSet::new_empty(0),
+            bcb_spans,
         };
         assert_eq!(num_nodes, this.num_nodes());
 
+        for bcb in this.bcbs.indices() {
+            this.successors[bcb] = this.compute_successors(subgraph, bcb);
+        }
+        this.compute_edges_and_dominators();
+
+        // The coverage graph's entry-point node (bcb0) always starts with bb0,
+        // which never has predecessors. Any other blocks merged into bcb0 can't
+        // have multiple (coverage-relevant) predecessors, so bcb0 always has
+        // zero in-edges.
+        assert!(this[START_BCB].leader_bb() == mir::START_BLOCK);
+        assert!(this.predecessors[START_BCB].is_empty());
+
+        this
+    }
+
+    /// Transforms the MIR `BasicBlock` successors of a node's last block into
+    /// the `BasicCoverageBlock` equivalents.
+    ///
+    /// Each predecessor of a node's leader block should be in a unique node.
+    /// But it is possible for a `SwitchInt` to have multiple targets to the
+    /// same destination `BasicBlock`, so de-duplication is required. This is
+    /// done without reordering the successors.
+    fn compute_successors(
+        &self,
+        subgraph: CoverageRelevantSubgraph<'_, '_>,
+        bcb: BasicCoverageBlock,
+    ) -> Vec<BasicCoverageBlock> {
+        let mut seen_bcbs = FxHashSet::default();
+        subgraph
+            .coverage_successors(self.bcbs[bcb].last_bb())
+            .into_iter()
+            .filter_map(|successor_bb| self.bb_to_bcb[successor_bb])
+            // Remove duplicate successor BCBs, keeping only the first.
+            .filter(|&successor_bcb| seen_bcbs.insert(successor_bcb))
+            .collect()
+    }
+
+    /// Derives the predecessors and edges of every node from its successors,
+    /// and then computes the dominators and loops of the graph. Any analyses
+    /// that were requested separately, such as post-dominators and untaken
+    /// edges, are discarded.
+    fn compute_edges_and_dominators(&mut self) {
+        let num_nodes = self.num_nodes();
+        self.predecessors = IndexVec::from_elem_n(Vec::new(), num_nodes);
+        self.edges = IndexVec::new();
+        self.out_edges = IndexVec::from_elem_n(Vec::new(), num_nodes);
+        self.in_edges = IndexVec::from_elem_n(Vec::new(), num_nodes);
+        for (bcb, bcb_successors) in self.successors.iter_enumerated() {
+            for &successor in bcb_successors {
+                self.predecessors[successor].push(bcb);
+
+                let edge = self.edges.push(BcbEdgeData { from_bcb: bcb, to_bcb: successor });
+                self.out_edges[bcb].push(edge);
+                self.in_edges[successor].push(edge);
+            }
+        }
+
+        self.post_dominators = None;
+        self.untaken_edges = DenseBitSet::new_empty(self.edges.len());
+        self.unreachable_nodes = DenseBitSet::new_empty(num_nodes);
+
         // Set the dominators first, because later init steps rely on them.
-        this.dominators = Some(graph::dominators::dominators(&this));
+        self.dominators = Some(graph::dominators::dominators(&*self));
 
         // Iterate over all nodes, such that dominating nodes are visited before
         // the nodes they dominate. Either preorder or reverse postorder is fine.
-        let dominator_order = graph::iterate::reverse_post_order(&this, this.start_node());
+        let dominator_order = graph::iterate::reverse_post_order(&*self, self.start_node());
         // The coverage graph is created by traversal, so all nodes are reachable.
-        assert_eq!(dominator_order.len(), this.num_nodes());
+        assert_eq!(dominator_order.len(), num_nodes);
+        self.dominator_order_rank = IndexVec::from_elem_n(0, num_nodes);
         for (rank, bcb) in (0u32..).zip(dominator_order) {
             // The dominator rank of each node is its index in a dominator-order traversal.
-            this.dominator_order_rank[bcb] = rank;
+            self.dominator_order_rank[bcb] = rank;
+        }
 
-            // A node is a loop header if it dominates any of its predecessors.
-            if this.reloop_predecessors(bcb).next().is_some() {
-                this.is_loop_header.insert(bcb);
+        let loops = LoopForest::new(&*self, self.dominators());
+        self.loop_bodies = IndexVec::from_elem_n(None, num_nodes);
+        for &header in loops.loop_headers() {
+            self.loop_bodies[header] = Some(DenseBitSet::new_empty(num_nodes));
+        }
+        for bcb in self.bcbs.indices() {
+            for header in loops.loops_containing(bcb) {
+                self.loop_bodies[header].as_mut().unwrap().insert(bcb);
             }
+        }
+        self.loops = Some(loops);
+        self.irreducible_regions = self.compute_irreducible_regions();
+    }
 
-            // If the immediate dominator is a loop header, that's our enclosing loop.
-            // Otherwise, inherit the immediate dominator's enclosing loop.
-            // (Dominator order ensures that we already processed the dominator.)
-            if let Some(dom) = this.dominators().immediate_dominator(bcb) {
-                this.enclosing_loop_header[bcb] = this
-                    .is_loop_header
-                    .contains(dom)
-                    .then_some(dom)
-                    .or_else(|| this.enclosing_loop_header[dom]);
+    /// Updates the graph after blocks were spliced into its MIR body, as the
+    /// MIR inliner does when it replaces a call with the callee's body, without
+    /// rebuilding the nodes that the splice didn't touch.
+    ///
+    /// `changed_bbs` are the existing blocks whose terminators now lead into
+    /// the spliced blocks, which are appended to the body. Each spliced block
+    /// that can be chained after its sole predecessor is merged into that
+    /// predecessor's node, and any other spliced block starts a new node. The
+    /// blocks that were chained after a changed block, or that a spliced block
+    /// now jumps into the middle of, are regrouped in the same way.
+    ///
+    /// Every block that was reachable before the splice must still be
+    /// reachable afterwards. `panic_sites` must cover the whole updated body.
+    ///
+    /// Only the nodes are updated here. Call [`Self::finish_splicing`] after
+    /// the last splice, before querying the edges, dominators or loops.
+    #[cfg_attr(not(test), expect(dead_code, reason = "not called by the inliner yet"))]
+    pub(crate) fn splice_blocks(
+        &mut self,
+        mir_body: &mir::Body<'_>,
+        panic_sites: &DenseBitSet<BasicBlock>,
+        side_edges: SideEdges,
+        changed_bbs: &[BasicBlock],
+    ) {
+        let subgraph =
+            CoverageRelevantSubgraph::new(&mir_body.basic_blocks, panic_sites, side_edges);
+        self.bb_to_bcb.resize(mir_body.basic_blocks.len(), None);
+        // The derived analyses are stale until `finish_splicing`.
+        self.dominators = None;
+        self.loops = None;
+        self.post_dominators = None;
+
+        // Nodes whose blocks changed, so their successors and spans need to be
+        // recomputed.
+        let mut changed_bcbs = vec![];
+
+        // A changed block now ends its node. Any blocks chained after it are
+        // removed from the graph, and added back below if still reachable.
+        for &bb in changed_bbs {
+            let Some(bcb) = self.bb_to_bcb[bb] else { continue };
+            let basic_blocks = &mut self.bcbs[bcb].basic_blocks;
+            let split_at = basic_blocks.iter().position(|&b| b == bb).unwrap() + 1;
+            for removed_bb in basic_blocks.drain(split_at..) {
+                self.bb_to_bcb[removed_bb] = None;
             }
+            changed_bcbs.push(bcb);
         }
 
-        // The coverage graph's entry-point node (bcb0) always starts with bb0,
-        // which never has predecessors. Any other blocks merged into bcb0 can't
-        // have multiple (coverage-relevant) predecessors, so bcb0 always has
-        // zero in-edges.
-        assert!(this[START_BCB].leader_bb() == mir::START_BLOCK);
-        assert!(this.predecessors[START_BCB].is_empty());
+        // Visit the blocks that are reachable from the changed blocks without
+        // passing through a node, adding each one to the graph after its
+        // predecessor, so that chains are grouped as in `from_mir`.
+        let mut visited = DenseBitSet::new_empty(mir_body.basic_blocks.len());
+        let mut stack = changed_bbs.to_vec();
+        while let Some(bb) = stack.pop() {
+            for successor in subgraph.coverage_successors(bb) {
+                if let Some(bcb) = self.bb_to_bcb[successor] {
+                    // A block in the middle of a node now has another
+                    // predecessor, so it has to start a node of its own.
+                    if self.bcbs[bcb].leader_bb() != successor {
+                        let new_bcb = self.split_node_before(bcb, successor);
+                        changed_bcbs.extend([bcb, new_bcb]);
+                    }
+                    continue;
+                }
+                let is_unreachable =
+                    mir_body[successor].terminator().kind == TerminatorKind::Unreachable;
+                if is_unreachable || !visited.insert(successor) {
+                    continue;
+                }
+                changed_bcbs.push(self.add_spliced_block(mir_body, subgraph, successor));
+                stack.push(successor);
+            }
+        }
 
-        this
+        self.successors.resize(self.num_nodes(), Vec::new());
+        self.bcb_spans.resize(self.num_nodes(), Vec::new());
+        for bcb in changed_bcbs.into_iter().unique() {
+            let last_bb = self.bcbs[bcb].last_bb();
+            self.bcbs[bcb].is_out_summable =
+                subgraph.coverage_successors(last_bb).is_out_summable();
+            self.successors[bcb] = self.compute_successors(subgraph, bcb);
+            self.bcb_spans[bcb] = aggregate_spans(mir_body, &self.bcbs[bcb].basic_blocks);
+        }
+    }
+
+    /// Recomputes the edges, dominators and loops of the graph after one or
+    /// more calls to [`Self::splice_blocks`]. As with a newly built graph,
+    /// post-dominators and untaken edges have to be requested again.
+    #[cfg_attr(not(test), expect(dead_code, reason = "not called by the inliner yet"))]
+    pub(crate) fn finish_splicing(&mut self) {
+        self.compute_edges_and_dominators();
+        assert!(self.predecessors[START_BCB].is_empty());
+    }
+
+    /// Moves `bb` and the blocks chained after it in `bcb` into a new node,
+    /// and returns the new node.
+    fn split_node_before(&mut self, bcb: BasicCoverageBlock, bb: BasicBlock) -> BasicCoverageBlock {
+        let basic_blocks = &mut self.bcbs[bcb].basic_blocks;
+        let split_at = basic_blocks.iter().position(|&b| b == bb).unwrap();
+        let tail = basic_blocks.split_off(split_at);
+        let new_bcb =
+            self.bcbs.push(BasicCoverageBlockData { basic_blocks: tail, is_out_summable: false });
+        for &moved_bb in &self.bcbs[new_bcb].basic_blocks {
+            self.bb_to_bcb[moved_bb] = Some(new_bcb);
+        }
+        new_bcb
+    }
+
+    /// Adds a block that isn't in any node yet to the node of its predecessor,
+    /// if it can be chained after it, or else to a new node. Returns the node.
+    fn add_spliced_block(
+        &mut self,
+        mir_body: &mir::Body<'_>,
+        subgraph: CoverageRelevantSubgraph<'_, '_>,
+        bb: BasicBlock,
+    ) -> BasicCoverageBlock {
+        if let &[prev] = mir_body.basic_blocks.predecessors()[bb].as_slice()
+            && let Some(prev_bcb) = self.bb_to_bcb[prev]
+            && self.bcbs[prev_bcb].last_bb() == prev
+            && can_chain(mir_body, subgraph, prev, bb)
+        {
+            self.bcbs[prev_bcb].basic_blocks.push(bb);
+            self.bb_to_bcb[bb] = Some(prev_bcb);
+            return prev_bcb;
+        }
+
+        let bcb = self
+            .bcbs
+            .push(BasicCoverageBlockData { basic_blocks: vec![bb], is_out_summable: false });
+        self.bb_to_bcb[bb] = Some(bcb);
+        bcb
+    }
+
+    /// Checks the structural invariants of a graph that was just built by
+    /// [`Self::from_mir`] with the same arguments, for
+    /// `-Zvalidate-coverage-graph`, and returns a description of each one that
+    /// doesn't hold.
+    pub(crate) fn validate(
+        &self,
+        mir_body: &mir::Body<'_>,
+        panic_sites: &DenseBitSet<BasicBlock>,
+        side_edges: SideEdges,
+    ) -> Vec<String> {
+        let subgraph =
+            CoverageRelevantSubgraph::new(&mir_body.basic_blocks, panic_sites, side_edges);
+        let mut errors = vec![];
+
+        // Every reachable block (other than an unreachable one) belongs to
+        // exactly one node, and no other block belongs to any node.
+        let mut seen_bbs = DenseBitSet::new_empty(mir_body.basic_blocks.len());
+        for (bcb, data) in self.bcbs.iter_enumerated() {
+            if data.basic_blocks.is_empty() {
+                errors.push(format!("{bcb:?} has no basic blocks"));
+            }
+            for &bb in &data.basic_blocks {
+                if !seen_bbs.insert(bb) {
+                    errors.push(format!("{bb:?} is in more than one node, including {bcb:?}"));
+                }
+                if self.bb_to_bcb[bb] != Some(bcb) {
+                    errors.push(format!(
+                        "{bb:?} is in {bcb:?}, but maps to {:?}",
+                        self.bb_to_bcb[bb]
+                    ));
+                }
+            }
+            for (&prev, &bb) in data.basic_blocks.iter().tuple_windows() {
+                if !can_chain(mir_body, subgraph, prev, bb) {
+                    errors.push(format!("{bcb:?} chains {bb:?} after {prev:?}, but can't"));
+                }
+            }
+        }
+        for bb in graph::depth_first_search(subgraph, mir::START_BLOCK) {
+            let ends_unreachable = mir_body[bb].terminator().kind == TerminatorKind::Unreachable;
+            if !ends_unreachable && !seen_bbs.contains(bb) {
+                errors.push(format!("reachable {bb:?} is not in any node"));
+            }
+            seen_bbs.remove(bb);
+        }
+        for bb in seen_bbs.iter() {
+            errors.push(format!("unreachable {bb:?} is in {:?}", self.bb_to_bcb[bb]));
+        }
+        let num_bbs_in_nodes = self.bcbs.iter().map(|data| data.basic_blocks.len()).sum::<usize>();
+        if self.bb_to_bcb.iter().flatten().count() != num_bbs_in_nodes {
+            errors.push("some blocks map to a node that doesn't contain them".to_owned());
+        }
+
+        if self[START_BCB].basic_blocks.first() != Some(&mir::START_BLOCK) {
+            errors.push(format!("{START_BCB:?} doesn't start with {:?}", mir::START_BLOCK));
+        }
+        if !self.predecessors[START_BCB].is_empty() {
+            errors.push(format!("{START_BCB:?} has in-edges: {:?}", self.predecessors[START_BCB]));
+        }
+
+        for (bcb, data) in self.bcbs.iter_enumerated() {
+            let Some(&last_bb) = data.basic_blocks.last() else { continue };
+            let coverage_successors = subgraph.coverage_successors(last_bb);
+
+            // Each node's successors are the nodes of its last block's
+            // coverage-relevant successors, in order and without duplicates.
+            let expected = coverage_successors
+                .into_iter()
+                .filter_map(|bb| self.bb_to_bcb[bb])
+                .unique()
+                .collect::<Vec<_>>();
+            if self.successors[bcb] != expected {
+                errors.push(format!(
+                    "{bcb:?} has successors {:?}, expected {expected:?}",
+                    self.successors[bcb]
+                ));
+            }
+
+            // Successors and predecessors mirror each other, as do edges.
+            for &succ in &self.successors[bcb] {
+                if self.predecessors[succ].iter().filter(|&&pred| pred == bcb).count() != 1 {
+                    errors.push(format!("{bcb:?} -> {succ:?} is not a predecessor edge"));
+                }
+            }
+            for &pred in &self.predecessors[bcb] {
+                if !self.successors[pred].contains(&bcb) {
+                    errors.push(format!("{pred:?} -> {bcb:?} is not a successor edge"));
+                }
+            }
+            let edge_targets = self.edges_from(bcb).map(|e| self.edge_target(e));
+            let edge_sources = self.edges_into(bcb).map(|e| self.edge_source(e));
+            if !edge_targets.eq(self.successors[bcb].iter().copied())
+                || !edge_sources.eq(self.predecessors[bcb].iter().copied())
+            {
+                errors.push(format!("{bcb:?} has edges that don't match its neighbours"));
+            }
+
+            if data.is_out_summable != coverage_successors.is_out_summable() {
+                errors.push(format!(
+                    "{bcb:?} has is_out_summable = {}, but its terminator disagrees",
+                    data.is_out_summable
+                ));
+            }
+
+            // Ranks put every node after its dominators.
+            if let Some(idom) = self.dominators().immediate_dominator(bcb)
+                && self.dominator_order_rank[idom] >= self.dominator_order_rank[bcb]
+            {
+                errors.push(format!("{bcb:?} doesn't rank after its dominator {idom:?}"));
+            }
+        }
+        let mut ranks = self.dominator_order_rank.iter().copied().collect::<Vec<_>>();
+        ranks.sort_unstable();
+        if !ranks.iter().copied().eq(0..self.num_nodes() as u32) {
+            errors
+                .push(format!("dominator ranks are not distinct: {:?}", self.dominator_order_rank));
+        }
+
+        errors
+    }
+
+    /// Finds the nodes that are part of irreducible control flow, which the
+    /// loop forest doesn't describe.
+    ///
+    /// Removing every back edge (an edge whose target dominates its source)
+    /// from a reducible graph leaves it acyclic. So any strongly-connected
+    /// component of the remaining graph with more than one node is a cycle
+    /// that can be entered at more than one point.
+    fn compute_irreducible_regions(
+        &self,
+    ) -> IndexVec<BasicCoverageBlock, Option<IrreducibleRegion>> {
+        let forward_edges = self
+            .edges
+            .iter()
+            .filter(|e| !self.dominates(e.to_bcb, e.from_bcb))
+            .map(|e| (e.from_bcb, e.to_bcb))
+            .collect::<Vec<_>>();
+        let forward_graph = VecGraph::<BasicCoverageBlock>::new(self.num_nodes(), forward_edges);
+
+        let sccs = Sccs::<BasicCoverageBlock, IrreducibleRegion>::new(&forward_graph);
+        let members = sccs.members();
+        self.bcbs
+            .indices()
+            .map(|bcb| {
+                let scc = sccs.scc(bcb);
+                (!members.is_singleton(scc)).then_some(scc)
+            })
+            .collect()
+    }
+
+    /// Returns true if the coverage graph for a MIR body would consist of a
+    /// single node that ends in a return, i.e. if every call of the function
+    /// runs straight through its body.
+    ///
+    /// This only needs to group the body's blocks into nodes, so it is much
+    /// cheaper than building the graph, with its dominators and loops.
+    pub(crate) fn is_straight_line(
+        mir_body: &mir::Body<'_>,
+        panic_sites: &DenseBitSet<BasicBlock>,
+        side_edges: SideEdges,
+    ) -> bool {
+        let subgraph =
+            CoverageRelevantSubgraph::new(&mir_body.basic_blocks, panic_sites, side_edges);
+        let (bcbs, _) = Self::compute_basic_coverage_blocks(mir_body, subgraph);
+        match bcbs.raw.as_slice() {
+            [bcb_data] => {
+                matches!(mir_body[bcb_data.last_bb()].terminator().kind, TerminatorKind::Return)
+            }
+            _ => false,
+        }
     }
 
     fn compute_basic_coverage_blocks(
         mir_body: &mir::Body<'_>,
+        subgraph: CoverageRelevantSubgraph<'_, '_>,
     ) -> (
         IndexVec<BasicCoverageBlock, BasicCoverageBlockData>,
         IndexVec<BasicBlock, Option<BasicCoverageBlock>>,
@@ -136,9 +520,9 @@ fn compute_basic_coverage_blocks(
                 bb_to_bcb[bb] = Some(bcb);
             }
 
-            let is_out_summable = basic_blocks.last().map_or(false, |&bb| {
-                bcb_filtered_successors(mir_body[bb].terminator()).is_out_summable()
-            });
+            let is_out_summable = basic_blocks
+                .last()
+                .map_or(false, |&bb| subgraph.coverage_successors(bb).is_out_summable());
             let bcb_data = BasicCoverageBlockData { basic_blocks, is_out_summable };
             debug!("adding {bcb:?}: {bcb_data:?}");
             bcbs.push(bcb_data);
@@ -152,17 +536,11 @@ fn compute_basic_coverage_blocks(
         // Accumulates a chain of blocks that will be combined into one BCB.
         let mut current_chain = vec![];
 
-        let subgraph = CoverageRelevantSubgraph::new(&mir_body.basic_blocks);
         for bb in graph::depth_first_search(subgraph, mir::START_BLOCK)
             .filter(|&bb| mir_body[bb].terminator().kind != TerminatorKind::Unreachable)
         {
             if let Some(&prev) = current_chain.last() {
-                // Adding a block to a non-empty chain is allowed if the
-                // previous block permits chaining, and the current block has
-                // `prev` as its sole predecessor.
-                let can_chain = subgraph.coverage_successors(prev).is_out_chainable()
-                    && mir_body.basic_blocks.predecessors()[bb].as_slice() == &[prev];
-                if !can_chain {
+                if !can_chain(mir_body, subgraph, prev, bb) {
                     // The current block can't be added to the existing chain, so
                     // flush that chain into a new BCB, and start a new chain.
                     flush_chain_into_new_bcb(&mut current_chain);
@@ -192,6 +570,102 @@ pub(crate) fn bcb_from_bb(&self, bb: BasicBlock) -> Option<BasicCoverageBlock> {
         if bb.index() < self.bb_to_bcb.len() { self.bb_to_bcb[bb] } else { None }
     }
 
+    /// Computes the post-dominators of this graph, for
+    /// `-Zcoverage-options=post-dominators`.
+    ///
+    /// Every node that isn't out-summable is treated as an exit. Besides nodes
+    /// without successors (returns, tail calls and diverging calls), that
+    /// includes yields and panic sites, because execution might not continue
+    /// past them.
+    pub(crate) fn compute_post_dominators(&mut self) {
+        let post_dominators =
+            graph::dominators::post_dominators_with_exits(&*self, |bcb| !self[bcb].is_out_summable);
+        self.post_dominators = Some(post_dominators);
+    }
+
+    /// Records that the given edges are statically known to never be taken,
+    /// for `-Zcoverage-options=static-unreachable`, and finds the nodes that
+    /// can then never run. Every out-edge of such a node is untaken too.
+    pub(crate) fn mark_untaken_edges(&mut self, edges: impl IntoIterator<Item = BcbEdge>) {
+        let mut untaken_edges = DenseBitSet::new_empty(self.edges.len());
+        for edge in edges {
+            untaken_edges.insert(edge);
+        }
+
+        // Only follow edges that can be taken, so that any node not visited
+        // here is unreachable.
+        let mut reachable = DenseBitSet::new_empty(self.num_nodes());
+        reachable.insert(START_BCB);
+        let mut stack = vec![START_BCB];
+        while let Some(bcb) = stack.pop() {
+            for edge in self.edges_from(bcb).filter(|&edge| !untaken_edges.contains(edge)) {
+                let to_bcb = self.edge_target(edge);
+                if reachable.insert(to_bcb) {
+                    stack.push(to_bcb);
+                }
+            }
+        }
+
+        let mut unreachable_nodes = DenseBitSet::new_filled(self.num_nodes());
+        unreachable_nodes.subtract(&reachable);
+        for bcb in unreachable_nodes.iter() {
+            for edge in self.edges_from(bcb) {
+                untaken_edges.insert(edge);
+            }
+        }
+
+        debug!(?untaken_edges, ?unreachable_nodes);
+        self.untaken_edges = untaken_edges;
+        self.unreachable_nodes = unreachable_nodes;
+    }
+
+    /// Returns true if the given edge is statically known to never be taken.
+    /// See [`Self::mark_untaken_edges`].
+    pub(crate) fn is_untaken_edge(&self, edge: BcbEdge) -> bool {
+        self.untaken_edges.contains(edge)
+    }
+
+    /// Returns true if the given node is statically known to never run.
+    /// See [`Self::mark_untaken_edges`].
+    pub(crate) fn is_unreachable(&self, bcb: BasicCoverageBlock) -> bool {
+        self.unreachable_nodes.contains(bcb)
+    }
+
+    /// If post-dominators were computed, returns the nearest strict dominator
+    /// of `bcb` that `bcb` post-dominates, that is in the same loops as `bcb`,
+    /// and that can't be reached again without passing through `bcb`. Such a
+    /// node always runs exactly as many times as `bcb` does, so the two nodes
+    /// can share a counter.
+    ///
+    /// (Without the loop checks, a node that only runs once could be paired
+    /// with a node in a later loop that must be passed through to exit, and a
+    /// node could be paired with a dominator that sometimes skips it by
+    /// jumping straight back to the top of their loop. Nodes in irreducible
+    /// regions are never paired, because they can run repeatedly without
+    /// being in any loop.)
+    pub(crate) fn control_equivalent_dominator(
+        &self,
+        bcb: BasicCoverageBlock,
+    ) -> Option<BasicCoverageBlock> {
+        let post_dominators = self.post_dominators.as_ref()?;
+        if !post_dominators.is_reachable(bcb) || self.irreducible_region(bcb).is_some() {
+            return None;
+        }
+
+        let dominators = self.dominators();
+        iter::successors(dominators.immediate_dominator(bcb), |&dom| {
+            dominators.immediate_dominator(dom)
+        })
+        .find(|&dom| {
+            post_dominators.post_dominates(bcb, dom)
+                && self.irreducible_region(dom).is_none()
+                && self.loop_headers_containing(bcb).eq(self.loop_headers_containing(dom))
+                && self.loop_headers_containing(dom).all(|header| {
+                    self.reloop_predecessors(header).all(|pred| self.dominates(bcb, pred))
+                })
+        })
+    }
+
     #[inline(always)]
     fn dominators(&self) -> &Dominators<BasicCoverageBlock> {
         self.dominators.as_ref().unwrap()
@@ -202,6 +676,18 @@ pub(crate) fn dominates(&self, dom: BasicCoverageBlock, node: BasicCoverageBlock
         self.dominators().dominates(dom, node)
     }
 
+    /// Returns the closest node that dominates both of the given nodes.
+    pub(crate) fn nearest_common_dominator(
+        &self,
+        a: BasicCoverageBlock,
+        b: BasicCoverageBlock,
+    ) -> BasicCoverageBlock {
+        let dominators = self.dominators();
+        iter::successors(Some(a), |&dom| dominators.immediate_dominator(dom))
+            .find(|&dom| self.dominates(dom, b))
+            .unwrap_or(START_BCB)
+    }
+
     #[inline(always)]
     pub(crate) fn cmp_in_dominator_order(
         &self,
@@ -211,15 +697,64 @@ pub(crate) fn cmp_in_dominator_order(
         self.dominator_order_rank[a].cmp(&self.dominator_order_rank[b])
     }
 
+    /// Returns the node's position in a dominator-order traversal of the graph.
+    /// No two nodes have the same rank.
+    pub(crate) fn dominator_rank(&self, bcb: BasicCoverageBlock) -> u32 {
+        self.dominator_order_rank[bcb]
+    }
+
+    /// Returns the out-edges of the given node, in the same order as its
+    /// successors.
+    pub(crate) fn edges_from(
+        &self,
+        from_bcb: BasicCoverageBlock,
+    ) -> impl Iterator<Item = BcbEdge> + Captures<'_> {
+        self.out_edges[from_bcb].iter().copied()
+    }
+
+    /// Returns the in-edges of the given node, in the same order as its
+    /// predecessors.
+    pub(crate) fn edges_into(
+        &self,
+        to_bcb: BasicCoverageBlock,
+    ) -> impl Iterator<Item = BcbEdge> + Captures<'_> {
+        self.in_edges[to_bcb].iter().copied()
+    }
+
+    #[inline(always)]
+    pub(crate) fn edge_source(&self, edge: BcbEdge) -> BasicCoverageBlock {
+        self.edges[edge].from_bcb
+    }
+
+    #[inline(always)]
+    pub(crate) fn edge_target(&self, edge: BcbEdge) -> BasicCoverageBlock {
+        self.edges[edge].to_bcb
+    }
+
+    /// Returns the edge from `from_bcb` to `to_bcb`, if there is one.
+    pub(crate) fn find_edge(
+        &self,
+        from_bcb: BasicCoverageBlock,
+        to_bcb: BasicCoverageBlock,
+    ) -> Option<BcbEdge> {
+        self.edges_from(from_bcb).find(|&edge| self.edge_target(edge) == to_bcb)
+    }
+
+    /// Returns this node's sole in-edge, if it has exactly one. That edge can
+    /// be assumed to have the same execution count as the node itself (in the
+    /// absence of panics).
+    pub(crate) fn sole_in_edge(&self, to_bcb: BasicCoverageBlock) -> Option<BcbEdge> {
+        // Unlike `simple_successor`, there is no need for extra checks here.
+        if let &[edge] = self.in_edges[to_bcb].as_slice() { Some(edge) } else { None }
+    }
+
     /// Returns the source of this node's sole in-edge, if it has exactly one.
-    /// That edge can be assumed to have the same execution count as the node
-    /// itself (in the absence of panics).
+    /// See [`Self::sole_in_edge`].
     pub(crate) fn sole_predecessor(
         &self,
         to_bcb: BasicCoverageBlock,
     ) -> Option<BasicCoverageBlock> {
-        // Unlike `simple_successor`, there is no need for extra checks here.
-        if let &[from_bcb] = self.predecessors[to_bcb].as_slice() { Some(from_bcb) } else { None }
+        self.sole_in_edge(to_bcb).map(|edge| self.edge_source(edge))
     }
 
     /// Returns the target of this node's sole out-edge, if it has exactly
@@ -247,16 +782,52 @@ pub(crate) fn loop_headers_containing(
         &self,
         bcb: BasicCoverageBlock,
     ) -> impl Iterator<Item = BasicCoverageBlock> + Captures<'_> {
-        let self_if_loop_header = self.is_loop_header.contains(bcb).then_some(bcb).into_iter();
+        self.loops.as_ref().unwrap().loops_containing(bcb)
+    }
+
+    /// Returns true if the given node is the header of some loop.
+    pub(crate) fn is_loop_header(&self, bcb: BasicCoverageBlock) -> bool {
+        self.loop_bodies[bcb].is_some()
+    }
+
+    /// If the given node is a loop header, returns the set of nodes in the
+    /// body of its loop, including the header itself and any nested loops.
+    pub(crate) fn loop_body(
+        &self,
+        header: BasicCoverageBlock,
+    ) -> Option<&DenseBitSet<BasicCoverageBlock>> {
+        self.loop_bodies[header].as_ref()
+    }
+
+    /// Returns the number of loops that contain the given node, so a node
+    /// outside of any loop has depth 0, and a loop header is counted as part
+    /// of its own loop.
+    pub(crate) fn loop_depth(&self, bcb: BasicCoverageBlock) -> usize {
+        self.loops.as_ref().unwrap().loop_depth(bcb)
+    }
 
-        let mut curr = Some(bcb);
-        let strictly_enclosing = iter::from_fn(move || {
-            let enclosing = self.enclosing_loop_header[curr?];
-            curr = enclosing;
-            enclosing
-        });
+    /// If the given node is part of irreducible control flow, returns the
+    /// region that contains it. Such nodes can run repeatedly without being
+    /// in any loop, so loop-based reasoning doesn't apply to them.
+    pub(crate) fn irreducible_region(&self, bcb: BasicCoverageBlock) -> Option<IrreducibleRegion> {
+        self.irreducible_regions[bcb]
+    }
 
-        self_if_loop_header.chain(strictly_enclosing)
+    /// Yields the nodes that contain code overlapping the given span, in node
+    /// order. Spans from macro expansions are compared through their call
+    /// site in the same syntax context as the given span, if there is one.
+    pub(crate) fn bcbs_for_span(
+        &self,
+        span: Span,
+    ) -> impl Iterator<Item = BasicCoverageBlock> + Captures<'_> {
+        self.bcb_spans
+            .iter_enumerated()
+            .filter(move |(_, spans)| {
+                spans.iter().any(|&bcb_span| {
+                    bcb_span.find_ancestor_in_same_ctxt(span).is_some_and(|s| s.overlaps(span))
+                })
+            })
+            .map(|(bcb, _)| bcb)
     }
 
     /// For the given node, yields the subset of its predecessor nodes that
@@ -269,6 +840,98 @@ pub(crate) fn reloop_predecessors(
     ) -> impl Iterator<Item = BasicCoverageBlock> + Captures<'_> {
         self.predecessors[to_bcb].iter().copied().filter(move |&pred| self.dominates(to_bcb, pred))
     }
+
+    /// Partitions the nodes of this graph into classes of nodes that are known
+    /// to always have the same execution count (in the absence of panics).
+    ///
+    /// A node is put in the same class as another node if:
+    /// - the other node is its sole predecessor, and it is the other node's
+    ///   simple successor, so the edge between them has the same count as
+    ///   both of them; or
+    /// - the other node is its [control-equivalent dominator], which is only
+    ///   found if post-dominators have been computed.
+    ///
+    /// In both cases the other node dominates the node, so every class is
+    /// represented by the one node in it that dominates all of the others.
+    ///
+    /// [control-equivalent dominator]: Self::control_equivalent_dominator
+    pub(crate) fn count_equivalence_classes(&self) -> CountEquivalenceClasses {
+        let num_nodes = self.num_nodes();
+        let mut links = IndexVec::from_elem_n(None, num_nodes);
+        let mut representatives = IndexVec::from_fn_n(|bcb| bcb, num_nodes);
+
+        // Visit dominators before the nodes they dominate, so that each linked
+        // node's representative is already known.
+        let mut nodes = self.bcbs.indices().collect::<Vec<_>>();
+        nodes.sort_by_key(|&bcb| self.dominator_order_rank[bcb]);
+        for bcb in nodes {
+            let link = if let Some(pred) = self.sole_predecessor(bcb)
+                && self.simple_successor(pred) == Some(bcb)
+            {
+                Some(CountEquivalence { same_as: pred, reason: CountEquivalenceReason::Chain })
+            } else {
+                self.control_equivalent_dominator(bcb).map(|dom| CountEquivalence {
+                    same_as: dom,
+                    reason: CountEquivalenceReason::ControlEquivalent,
+                })
+            };
+            if let Some(link) = link {
+                representatives[bcb] = representatives[link.same_as];
+            }
+            links[bcb] = link;
+        }
+
+        CountEquivalenceClasses { links, representatives }
+    }
+
+    /// Writes this graph in Graphviz DOT format, for `-Zdump-coverage-graph`.
+    ///
+    /// Each node is labelled with its BCB index, the MIR blocks it contains,
+    /// whether it is a loop header, the irreducible region that contains it
+    /// (if any), its dominator rank, the node it is known to have the same
+    /// count as (if any), and whether it is statically unreachable. Edges that
+    /// jump back to a loop header are dashed, and untaken edges are dotted.
+    pub(crate) fn write_graphviz(
+        &self,
+        w: &mut impl io::Write,
+        graph_name: &str,
+    ) -> io::Result<()> {
+        writeln!(w, "digraph {graph_name} {{")?;
+        writeln!(w, r#"    node [shape="box", fontname="monospace"];"#)?;
+        let count_classes = self.count_equivalence_classes();
+        for (bcb, data) in self.bcbs.iter_enumerated() {
+            let bbs = data.basic_blocks.iter().map(|bb| format!("{bb:?}")).collect::<Vec<_>>();
+            let loop_header = if self.is_loop_header(bcb) { "\\nloop header" } else { "" };
+            let irreducible = match self.irreducible_region(bcb) {
+                Some(region) => format!("\\n{region:?}"),
+                None => String::new(),
+            };
+            let rank = self.dominator_order_rank[bcb];
+            let same_count = match count_classes.link(bcb) {
+                Some(CountEquivalence { same_as, reason }) => {
+                    format!("\\nsame count as {same_as:?} ({})", reason.as_str())
+                }
+                None => String::new(),
+            };
+            let unreachable = if self.is_unreachable(bcb) { "\\nunreachable" } else { "" };
+            let label = format!(
+                r#"{bcb:?}\n{bbs}{loop_header}{irreducible}\nrank {rank}{same_count}{unreachable}"#,
+                bbs = bbs.join(", "),
+            );
+            writeln!(w, r#"    {bcb:?} [label="{label}"];"#)?;
+        }
+        for (edge, &BcbEdgeData { from_bcb, to_bcb }) in self.edges.iter_enumerated() {
+            let style = if self.is_untaken_edge(edge) {
+                r#" [style="dotted"]"#
+            } else if self.dominates(to_bcb, from_bcb) {
+                r#" [style="dashed"]"#
+            } else {
+                ""
+            };
+            writeln!(w, "    {from_bcb:?} -> {to_bcb:?}{style};")?;
+        }
+        writeln!(w, "}}")
+    }
 }
 
 impl Index<BasicCoverageBlock> for CoverageGraph {
@@ -327,6 +990,86 @@ pub(crate) struct BasicCoverageBlock {
     }
 }
 
+rustc_index::newtype_index! {
+    /// An edge in the control-flow graph of CoverageGraph.
+    #[orderable]
+    #[debug_format = "bcbe{}"]
+    pub(crate) struct BcbEdge {}
+}
+
+rustc_index::newtype_index! {
+    /// A strongly-connected region of the coverage graph that has more than
+    /// one entry node, so it isn't a natural loop.
+    #[orderable]
+    #[debug_format = "irreducible{}"]
+    pub(crate) struct IrreducibleRegion {}
+}
+
+/// Records that a node always has the same execution count as another node
+/// that dominates it. See [`CoverageGraph::count_equivalence_classes`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CountEquivalence {
+    pub(crate) same_as: BasicCoverageBlock,
+    pub(crate) reason: CountEquivalenceReason,
+}
+
+/// Why two nodes are known to have the same execution count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CountEquivalenceReason {
+    /// The nodes are joined by an edge that is the only out-edge of one and
+    /// the only in-edge of the other.
+    Chain,
+    /// The nodes dominate and post-dominate each other, and are in the same
+    /// loops.
+    ControlEquivalent,
+}
+
+impl CountEquivalenceReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Chain => "chain",
+            Self::ControlEquivalent => "control-equivalent",
+        }
+    }
+}
+
+/// The result of [`CoverageGraph::count_equivalence_classes`].
+#[derive(Debug)]
+pub(crate) struct CountEquivalenceClasses {
+    links: IndexVec<BasicCoverageBlock, Option<CountEquivalence>>,
+    representatives: IndexVec<BasicCoverageBlock, BasicCoverageBlock>,
+}
+
+impl CountEquivalenceClasses {
+    /// Returns the node that the given node was directly found to have the
+    /// same count as, and why, if any.
+    pub(crate) fn link(&self, bcb: BasicCoverageBlock) -> Option<CountEquivalence> {
+        self.links[bcb]
+    }
+
+    /// Returns the node that represents the given node's class, which
+    /// dominates every other node in that class.
+    pub(crate) fn representative(&self, bcb: BasicCoverageBlock) -> BasicCoverageBlock {
+        self.representatives[bcb]
+    }
+
+    /// Yields the nodes that the given node has the same count as, nearest
+    /// first, following the links up to the class representative.
+    pub(crate) fn same_count_ancestors(
+        &self,
+        bcb: BasicCoverageBlock,
+    ) -> impl Iterator<Item = BasicCoverageBlock> + Captures<'_> {
+        iter::successors(self.links[bcb], |link| self.links[link.same_as]).map(|link| link.same_as)
+    }
+}
+
+/// The endpoints of a [`BcbEdge`].
+#[derive(Clone, Copy, Debug)]
+struct BcbEdgeData {
+    from_bcb: BasicCoverageBlock,
+    to_bcb: BasicCoverageBlock,
+}
+
 /// `BasicCoverageBlockData` holds the data indexed by a `BasicCoverageBlock`.
 ///
 /// A `BasicCoverageBlock` (BCB) represents the maximal-length sequence of MIR `BasicBlock`s without
@@ -340,7 +1083,8 @@ pub(crate) struct BasicCoverageBlock {
 ///     that is injected by the Rust compiler but has no physical source code to count. This also
 ///     means a BasicBlock with a `Call` terminator can be merged into its primary successor target
 ///     block, in the same BCB. (But, note: Issue #78544: "MIR InstrumentCoverage: Improve coverage
-///     of `#[should_panic]` tests and `catch_unwind()` handlers")
+///     of `#[should_panic]` tests and `catch_unwind()` handlers". Unwind edges are kept under
+///     `-Zcoverage-options=unwind-paths`.)
 ///   * Some BasicBlock terminators support Rust-specific concerns--like borrow-checking--that are
 ///     not relevant to coverage analysis. `FalseUnwind`, for example, can be treated the same as
 ///     a `Goto`, and merged with its successor into the same BCB.
@@ -377,6 +1121,60 @@ pub(crate) fn last_bb(&self) -> BasicBlock {
     }
 }
 
+/// Which kinds of edges outside a function's normal control flow are kept in
+/// its coverage graph. By default, none are.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SideEdges {
+    /// Edges that unwind into cleanup blocks, for
+    /// `-Zcoverage-options=unwind-paths`.
+    pub(crate) unwind: bool,
+    /// Edges from a `Yield` to the code that runs if the coroutine is dropped
+    /// while suspended there, for `-Zcoverage-options=coroutine-drops`.
+    pub(crate) coroutine_drop: bool,
+}
+
+/// Collects the source spans of the statements and terminators in the given
+/// blocks, merging spans in the same syntax context that overlap or touch, so
+/// that a typical node is left with only a few spans.
+fn aggregate_spans(mir_body: &mir::Body<'_>, basic_blocks: &[BasicBlock]) -> Vec<Span> {
+    let mut spans = basic_blocks
+        .iter()
+        .flat_map(|&bb| {
+            let data = &mir_body[bb];
+            let statement_spans =
+                data.statements.iter().map(|statement| statement.source_info.span);
+            statement_spans.chain(iter::once(data.terminator().source_info.span))
+        })
+        .filter(|span| !span.is_dummy())
+        .collect::<Vec<_>>();
+
+    spans.sort_by_key(|span| (span.lo(), span.hi()));
+    spans.dedup_by(|b, a| {
+        let can_merge = a.eq_ctxt(*b) && a.overlaps_or_adjacent(*b);
+        if can_merge {
+            *a = a.to(*b);
+        }
+        can_merge
+    });
+    spans
+}
+
+/// Returns true if `bb` can be added to a node right after `prev`, which is
+/// allowed if `prev` permits chaining, and `bb` has `prev` as its sole
+/// predecessor. A tail call always starts a new node, so that the `become`
+/// expression gets a mapping region of its own instead of being folded into the
+/// code before it.
+fn can_chain(
+    mir_body: &mir::Body<'_>,
+    subgraph: CoverageRelevantSubgraph<'_, '_>,
+    prev: BasicBlock,
+    bb: BasicBlock,
+) -> bool {
+    subgraph.coverage_successors(prev).is_out_chainable()
+        && mir_body.basic_blocks.predecessors()[bb].as_slice() == &[prev]
+        && !matches!(mir_body[bb].terminator().kind, TerminatorKind::TailCall { .. })
+}
+
 /// Holds the coverage-relevant successors of a basic block's terminator, and
 /// indicates whether that block can potentially be combined into the same BCB
 /// as its sole successor.
@@ -385,9 +1183,16 @@ struct CoverageSuccessors<'a> {
     /// Coverage-relevant successors of the corresponding terminator.
     /// There might be 0, 1, or multiple targets.
     targets: &'a [BasicBlock],
-    /// `Yield` terminators are not chainable, because their sole out-edge is
+    /// The target of the terminator's side edge, if [`SideEdges`] keeps it:
+    /// either the cleanup block that it unwinds to, or the block that drops a
+    /// coroutine suspended at a `Yield`. Not included in `targets`.
+    side_target: Option<BasicBlock>,
+    /// `Yield` terminators are not chainable, because their resume edge is
     /// only followed if/when the generator is resumed after the yield.
     is_yield: bool,
+    /// Terminators at panic sites are not chainable either, because their sole
+    /// out-edge is only followed if they don't panic.
+    is_panic_site: bool,
 }
 
 impl CoverageSuccessors<'_> {
@@ -396,13 +1201,13 @@ impl CoverageSuccessors<'_> {
     fn is_out_chainable(&self) -> bool {
         // If a terminator is out-summable and has exactly one out-edge, then
         // it is eligible to be chained into its successor block.
-        self.is_out_summable() && self.targets.len() == 1
+        self.is_out_summable() && self.targets.len() == 1 && self.side_target.is_none()
     }
 
     /// Returns true if the terminator itself is assumed to have the same
     /// execution count as the sum of its out-edges (assuming no panics).
     fn is_out_summable(&self) -> bool {
-        !self.is_yield && !self.targets.is_empty()
+        !self.is_yield && !self.is_panic_site && !self.targets.is_empty()
     }
 }
 
@@ -411,15 +1216,20 @@ impl IntoIterator for CoverageSuccessors<'_> {
     type IntoIter = impl DoubleEndedIterator<Item = Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.targets.iter().copied()
+        self.targets.iter().copied().chain(self.side_target)
     }
 }
 
 // Returns the subset of a block's successors that are relevant to the coverage
-// graph, i.e. those that do not represent unwinds or false edges.
+// graph, i.e. those that do not represent unwinds or coroutine drops (unless
+// `side_edges` keeps them) or false edges.
 // FIXME(#78544): MIR InstrumentCoverage: Improve coverage of `#[should_panic]` tests and
 // `catch_unwind()` handlers.
-fn bcb_filtered_successors<'a, 'tcx>(terminator: &'a Terminator<'tcx>) -> CoverageSuccessors<'a> {
+fn bcb_filtered_successors<'a, 'tcx>(
+    terminator: &'a Terminator<'tcx>,
+    is_panic_site: bool,
+    side_edges: SideEdges,
+) -> CoverageSuccessors<'a> {
     use TerminatorKind::*;
     let mut is_yield = false;
     let targets = match &terminator.kind {
@@ -458,7 +1268,21 @@ fn bcb_filtered_successors<'a, 'tcx>(terminator: &'a Terminator<'tcx>) -> Covera
         | UnwindTerminate(_) => &[],
     };
 
-    CoverageSuccessors { targets, is_yield }
+    let side_target = match terminator.kind {
+        // The drop edge of a yield is taken if the coroutine is dropped while
+        // suspended, instead of being resumed.
+        Yield { drop, .. } if side_edges.coroutine_drop => drop,
+        // The unwind edge of `FalseUnwind` is never taken, so it is ignored
+        // even when unwind edges are coverage-relevant.
+        FalseUnwind { .. } => None,
+        _ if side_edges.unwind => match terminator.unwind() {
+            Some(&UnwindAction::Cleanup(cleanup)) => Some(cleanup),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    CoverageSuccessors { targets, side_target, is_yield, is_panic_site }
 }
 
 /// Wrapper around a [`mir::BasicBlocks`] graph that restricts each node's
@@ -467,14 +1291,24 @@ fn bcb_filtered_successors<'a, 'tcx>(terminator: &'a Terminator<'tcx>) -> Covera
 #[derive(Clone, Copy)]
 struct CoverageRelevantSubgraph<'a, 'tcx> {
     basic_blocks: &'a mir::BasicBlocks<'tcx>,
+    panic_sites: &'a DenseBitSet<BasicBlock>,
+    side_edges: SideEdges,
 }
 impl<'a, 'tcx> CoverageRelevantSubgraph<'a, 'tcx> {
-    fn new(basic_blocks: &'a mir::BasicBlocks<'tcx>) -> Self {
-        Self { basic_blocks }
+    fn new(
+        basic_blocks: &'a mir::BasicBlocks<'tcx>,
+        panic_sites: &'a DenseBitSet<BasicBlock>,
+        side_edges: SideEdges,
+    ) -> Self {
+        Self { basic_blocks, panic_sites, side_edges }
     }
 
-    fn coverage_successors(&self, bb: BasicBlock) -> CoverageSuccessors<'_> {
-        bcb_filtered_successors(self.basic_blocks[bb].terminator())
+    fn coverage_successors(&self, bb: BasicBlock) -> CoverageSuccessors<'a> {
+        bcb_filtered_successors(
+            self.basic_blocks[bb].terminator(),
+            self.panic_sites.contains(bb),
+            self.side_edges,
+        )
     }
 }
 impl<'a, 'tcx> graph::DirectedGraph for CoverageRelevantSubgraph<'a, 'tcx> {
@@ -505,8 +1339,61 @@ enum ReadyState {
     Visited,
 }
 
+/// Key that decides which queued node [`ReadyFirstTraversal`] visits next,
+/// under `-Zcoverage-options=loop-depth-order`.
+/// Lower keys are visited first: nodes in fewer loops, and then nodes that come
+/// earlier in dominator order. Dominator ranks are unique, so no two nodes
+/// ever have the same key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct VisitPriority {
+    loop_depth: usize,
+    dominator_rank: u32,
+    node: BasicCoverageBlock,
+}
+
+/// One of the queues of [`ReadyFirstTraversal`].
+enum VisitQueue {
+    /// Yields nodes in the order they were queued.
+    Fifo(VecDeque<BasicCoverageBlock>),
+    /// Yields the node with the lowest [`VisitPriority`] first.
+    ByPriority(BinaryHeap<Reverse<VisitPriority>>),
+}
+
+impl VisitQueue {
+    fn new(loop_depth_order: bool) -> Self {
+        if loop_depth_order {
+            Self::ByPriority(BinaryHeap::new())
+        } else {
+            Self::Fifo(VecDeque::new())
+        }
+    }
+
+    fn push(&mut self, graph: &CoverageGraph, node: BasicCoverageBlock) {
+        match self {
+            Self::Fifo(queue) => queue.push_back(node),
+            Self::ByPriority(queue) => queue.push(Reverse(VisitPriority {
+                loop_depth: graph.loop_depth(node),
+                dominator_rank: graph.dominator_order_rank[node],
+                node,
+            })),
+        }
+    }
+
+    fn pop(&mut self) -> Option<BasicCoverageBlock> {
+        match self {
+            Self::Fifo(queue) => queue.pop_front(),
+            Self::ByPriority(queue) => queue.pop().map(|Reverse(priority)| priority.node),
+        }
+    }
+}
+
 /// Iterator that visits nodes in the coverage graph, in an order that always
 /// prefers "ready" nodes whose predecessors have already been visited.
+///
+/// With `loop_depth_order`, shallower loop depth wins among the nodes in the
+/// same queue, so that nodes in inner loops are visited as late as possible,
+/// and are therefore more likely to be given counter expressions instead of
+/// physical counters.
 pub(crate) struct ReadyFirstTraversal<'a> {
     graph: &'a CoverageGraph,
 
@@ -516,14 +1403,14 @@ pub(crate) struct ReadyFirstTraversal<'a> {
     state: IndexVec<BasicCoverageBlock, ReadyState>,
 
     /// Holds unvisited nodes whose predecessors have all been visited.
-    ready_queue: VecDeque<BasicCoverageBlock>,
+    ready_queue: VisitQueue,
     /// Holds unvisited nodes with some unvisited predecessors.
     /// Also contains stale entries for nodes that were upgraded to ready.
-    fallback_queue: VecDeque<BasicCoverageBlock>,
+    fallback_queue: VisitQueue,
 }
 
 impl<'a> ReadyFirstTraversal<'a> {
-    pub(crate) fn new(graph: &'a CoverageGraph) -> Self {
+    pub(crate) fn new(graph: &'a CoverageGraph, loop_depth_order: bool) -> Self {
         let num_nodes = graph.num_nodes();
 
         let n_unvisited_preds =
@@ -535,22 +1422,24 @@ pub(crate) fn new(graph: &'a CoverageGraph) -> Self {
         debug_assert!(
             n_unvisited_preds.iter_enumerated().all(|(node, &n)| (node == START_BCB) == (n == 0))
         );
-        let ready_queue = VecDeque::from(vec![START_BCB]);
+        let mut ready_queue = VisitQueue::new(loop_depth_order);
+        ready_queue.push(graph, START_BCB);
         state[START_BCB] = ReadyState::InReadyQueue;
 
-        Self { graph, state, n_unvisited_preds, ready_queue, fallback_queue: VecDeque::new() }
+        let fallback_queue = VisitQueue::new(loop_depth_order);
+        Self { graph, state, n_unvisited_preds, ready_queue, fallback_queue }
     }
 
     /// Returns the next node from the ready queue, or else the next unvisited
     /// node from the fallback queue.
     fn next_inner(&mut self) -> Option<BasicCoverageBlock> {
         // Always prefer to yield a ready node if possible.
-        if let Some(node) = self.ready_queue.pop_front() {
+        if let Some(node) = self.ready_queue.pop() {
             assert_eq!(self.state[node], ReadyState::InReadyQueue);
             return Some(node);
         }
 
-        while let Some(node) = self.fallback_queue.pop_front() {
+        while let Some(node) = self.fallback_queue.pop() {
             match self.state[node] {
                 // This entry in the fallback queue is not stale, so yield it.
                 ReadyState::InFallbackQueue => return Some(node),
@@ -592,12 +1481,12 @@ fn mark_visited_and_enqueue_successors(&mut self, node: BasicCoverageBlock) {
                 // the ready queue. If it's already in the fallback queue, that
                 // fallback entry will be ignored later.
                 self.state[succ] = ReadyState::InReadyQueue;
-                self.ready_queue.push_back(succ);
+                self.ready_queue.push(self.graph, succ);
             } else if is_unqueued {
                 // This node has unvisited predecessors, so add it to the
                 // fallback queue in case we run out of ready nodes later.
                 self.state[succ] = ReadyState::InFallbackQueue;
-                self.fallback_queue.push_back(succ);
+                self.fallback_queue.push(self.graph, succ);
             }
         }
     }
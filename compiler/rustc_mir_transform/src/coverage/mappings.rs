@@ -1,21 +1,24 @@
 use std::collections::BTreeSet;
 
-use rustc_data_structures::fx::FxIndexMap;
+use rustc_data_structures::fx::{FxHashSet, FxIndexMap};
 use rustc_data_structures::graph::DirectedGraph;
 use rustc_index::IndexVec;
 use rustc_index::bit_set::DenseBitSet;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::coverage::{
     BlockMarkerId, BranchSpan, ConditionId, ConditionInfo, CoverageInfoHi, CoverageKind,
 };
-use rustc_middle::mir::{self, BasicBlock, StatementKind};
+use rustc_middle::mir::{self, BasicBlock, Operand, Rvalue, StatementKind, TerminatorKind};
 use rustc_middle::ty::TyCtxt;
-use rustc_span::Span;
+use rustc_session::config::FoldedBranches;
+use rustc_span::{DesugaringKind, ExpnKind, Span, sym};
+use tracing::debug;
 
 use crate::coverage::ExtractedHirInfo;
 use crate::coverage::graph::{BasicCoverageBlock, CoverageGraph, START_BCB};
 use crate::coverage::spans::extract_refined_covspans;
 use crate::coverage::unexpand::unexpand_into_body_span;
-use crate::errors::MCDCExceedsTestVectorLimit;
+use crate::errors::{MCDCDecisionExceedsTestVectorLimit, MCDCExceedsTestVectorLimit};
 
 /// Associates an ordinary executable code span with its corresponding BCB.
 #[derive(Debug)]
@@ -24,14 +27,58 @@ pub(super) struct CodeMapping {
     pub(super) bcb: BasicCoverageBlock,
 }
 
+/// The code after a `return`, `break`, `continue` or `become`, up to the start
+/// of the next code mapping, under `-Zcoverage-options=gap-regions`. It has the
+/// count of that next mapping's node, or zero if there is none.
+#[derive(Debug)]
+pub(super) struct GapMapping {
+    pub(super) span: Span,
+    pub(super) bcb: Option<BasicCoverageBlock>,
+}
+
+/// The code mappings for one local macro invocation, under
+/// `-Zcoverage-options=expansions` or `macro-definitions`. Their spans point
+/// into the macro's definition, and are attributed to `call_site`.
+#[derive(Debug)]
+pub(super) struct ExpansionCodeMappings {
+    pub(super) call_site: Span,
+    pub(super) code_mappings: Vec<CodeMapping>,
+}
+
+/// A check that panics when it fails, under `-Zcoverage-options=assertions`:
+/// a built-in check (e.g. bounds or overflow), a call to a `#[track_caller]`
+/// function such as `Option::unwrap`, or the condition of an `assert!`.
+#[derive(Debug)]
+pub(super) struct AssertionGuard {
+    pub(super) span: Span,
+    /// The guard ends this node, so the node's count is the number of times
+    /// that the guard was evaluated.
+    pub(super) evaluated_bcb: BasicCoverageBlock,
+    pub(super) outcome: GuardOutcome,
+}
+
+/// A node that is only entered from its guard, and whose count is therefore
+/// the number of times that the guard passed, or failed.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum GuardOutcome {
+    Passed(BasicCoverageBlock),
+    Failed(BasicCoverageBlock),
+}
+
 /// This is separate from [`MCDCBranch`] to help prepare for larger changes
 /// that will be needed for improved branch coverage in the future.
 /// (See <https://github.com/rust-lang/rust/pull/124217>.)
 #[derive(Debug)]
 pub(super) struct BranchPair {
     pub(super) span: Span,
-    pub(super) true_bcb: BasicCoverageBlock,
-    pub(super) false_bcb: BasicCoverageBlock,
+    /// The BCB of each side of the branch, or `None` if that side's block was
+    /// removed as unreachable (e.g. the false side of an irrefutable `let` in a
+    /// let-chain), so that the side is reported as never taken.
+    pub(super) true_bcb: Option<BasicCoverageBlock>,
+    pub(super) false_bcb: Option<BasicCoverageBlock>,
+    /// The condition is a literal constant, and the branch is to be reported
+    /// as folded, under `-Zcoverage-options=folded-branches-mark`.
+    pub(super) folded: bool,
 }
 
 /// Associates an MC/DC branch span with condition info besides fields for normal branch.
@@ -55,6 +102,10 @@ pub(super) struct MCDCDecision {
     pub(super) bitmap_idx: usize,
     pub(super) num_test_vectors: usize,
     pub(super) decision_depth: u16,
+    /// True if this decision comes from a repeated lowering of a match guard
+    /// (for a later alternative of an or-pattern). It updates the bitmap of
+    /// the first lowering's decision, which alone is reported as a decision.
+    pub(super) is_repeated_guard: bool,
 }
 
 // LLVM uses `i32` to index the bitmap. Thus `i32::MAX` is the hard limit for number of all test vectors
@@ -68,10 +119,17 @@ pub(super) struct ExtractedMappings {
     /// only public so that other code can still use exhaustive destructuring.
     pub(super) num_bcbs: usize,
     pub(super) code_mappings: Vec<CodeMapping>,
+    pub(super) expansion_mappings: Vec<ExpansionCodeMappings>,
+    pub(super) definition_mappings: Vec<ExpansionCodeMappings>,
+    pub(super) gap_mappings: Vec<GapMapping>,
+    /// Spans of closures and nested items excluded by `#[coverage(off)]`,
+    /// under `-Zcoverage-options=skipped-regions`.
+    pub(super) skipped_spans: Vec<Span>,
     pub(super) branch_pairs: Vec<BranchPair>,
     pub(super) mcdc_bitmap_bits: usize,
     pub(super) mcdc_degraded_branches: Vec<MCDCBranch>,
     pub(super) mcdc_mappings: Vec<(MCDCDecision, Vec<MCDCBranch>)>,
+    pub(super) assertion_guards: Vec<AssertionGuard>,
 }
 
 /// Extracts coverage-relevant spans from MIR, and associates them with
@@ -83,6 +141,9 @@ pub(super) fn extract_all_mapping_info_from_mir<'tcx>(
     graph: &CoverageGraph,
 ) -> ExtractedMappings {
     let mut code_mappings = vec![];
+    let mut expansion_mappings = vec![];
+    let mut definition_mappings = vec![];
+    let mut gap_mappings = vec![];
     let mut branch_pairs = vec![];
     let mut mcdc_bitmap_bits = 0;
     let mut mcdc_degraded_branches = vec![];
@@ -102,10 +163,28 @@ pub(super) fn extract_all_mapping_info_from_mir<'tcx>(
         }
     } else {
         // Extract coverage spans from MIR statements/terminators as normal.
-        extract_refined_covspans(mir_body, hir_info, graph, &mut code_mappings);
+        extract_refined_covspans(
+            tcx.sess.source_map(),
+            mir_body,
+            hir_info,
+            graph,
+            tcx.sess.coverage_expansions(),
+            tcx.sess.coverage_macro_definitions(),
+            tcx.sess.coverage_collapse_external_macros(),
+            tcx.sess.coverage_raw_spans(),
+            &mut code_mappings,
+            &mut expansion_mappings,
+            &mut definition_mappings,
+        );
+        gap_mappings.extend(extract_gap_mappings(hir_info, &code_mappings));
     }
 
-    branch_pairs.extend(extract_branch_pairs(mir_body, hir_info, graph));
+    branch_pairs.extend(extract_branch_pairs(
+        mir_body,
+        hir_info,
+        graph,
+        tcx.sess.coverage_folded_branches(),
+    ));
 
     extract_mcdc_mappings(
         mir_body,
@@ -117,13 +196,24 @@ pub(super) fn extract_all_mapping_info_from_mir<'tcx>(
         &mut mcdc_mappings,
     );
 
+    let assertion_guards = if tcx.sess.coverage_assertions() {
+        extract_assertion_guards(tcx, mir_body, hir_info, graph)
+    } else {
+        vec![]
+    };
+
     ExtractedMappings {
         num_bcbs: graph.num_nodes(),
         code_mappings,
+        expansion_mappings,
+        definition_mappings,
+        gap_mappings,
+        skipped_spans: hir_info.skipped_spans.clone(),
         branch_pairs,
         mcdc_bitmap_bits,
         mcdc_degraded_branches,
         mcdc_mappings,
+        assertion_guards,
     }
 }
 
@@ -133,10 +223,17 @@ pub(super) fn all_bcbs_with_counter_mappings(&self) -> DenseBitSet<BasicCoverage
         let Self {
             num_bcbs,
             code_mappings,
+            expansion_mappings,
+            definition_mappings,
+            // Gap mappings only refer to nodes that already have code mappings.
+            gap_mappings: _,
+            // Skipped regions have no count.
+            skipped_spans: _,
             branch_pairs,
             mcdc_bitmap_bits: _,
             mcdc_degraded_branches,
             mcdc_mappings,
+            assertion_guards,
         } = self;
 
         // Identify which BCBs have one or more mappings.
@@ -145,12 +242,16 @@ pub(super) fn all_bcbs_with_counter_mappings(&self) -> DenseBitSet<BasicCoverage
             bcbs_with_counter_mappings.insert(bcb);
         };
 
-        for &CodeMapping { span: _, bcb } in code_mappings {
+        let expansion_code_mappings = expansion_mappings
+            .iter()
+            .chain(definition_mappings)
+            .flat_map(|expansion| &expansion.code_mappings);
+        for &CodeMapping { span: _, bcb } in code_mappings.iter().chain(expansion_code_mappings) {
             insert(bcb);
         }
-        for &BranchPair { true_bcb, false_bcb, .. } in branch_pairs {
-            insert(true_bcb);
-            insert(false_bcb);
+        // Folded branches don't use the counts of their arms.
+        for &BranchPair { true_bcb, false_bcb, .. } in branch_pairs.iter().filter(|b| !b.folded) {
+            true_bcb.into_iter().chain(false_bcb).for_each(&mut insert);
         }
         for &MCDCBranch { true_bcb, false_bcb, .. } in mcdc_degraded_branches
             .iter()
@@ -159,6 +260,12 @@ pub(super) fn all_bcbs_with_counter_mappings(&self) -> DenseBitSet<BasicCoverage
             insert(true_bcb);
             insert(false_bcb);
         }
+        for &AssertionGuard { evaluated_bcb, outcome, .. } in assertion_guards {
+            insert(evaluated_bcb);
+            match outcome {
+                GuardOutcome::Passed(bcb) | GuardOutcome::Failed(bcb) => insert(bcb),
+            }
+        }
 
         // MC/DC decisions refer to BCBs, but don't require those BCBs to have counters.
         if bcbs_with_counter_mappings.is_empty() {
@@ -174,13 +281,46 @@ pub(super) fn all_bcbs_with_counter_mappings(&self) -> DenseBitSet<BasicCoverage
     /// Returns the set of BCBs that have one or more `Code` mappings.
     pub(super) fn bcbs_with_ordinary_code_mappings(&self) -> DenseBitSet<BasicCoverageBlock> {
         let mut bcbs = DenseBitSet::new_empty(self.num_bcbs);
-        for &CodeMapping { span: _, bcb } in &self.code_mappings {
+        let expansion_code_mappings = self
+            .expansion_mappings
+            .iter()
+            .chain(&self.definition_mappings)
+            .flat_map(|expansion| &expansion.code_mappings);
+        for &CodeMapping { span: _, bcb } in
+            self.code_mappings.iter().chain(expansion_code_mappings)
+        {
             bcbs.insert(bcb);
         }
         bcbs
     }
 }
 
+/// Like clang, gives the code after each `return`, `break`, `continue` or
+/// `become` a gap region that extends to the start of the next code mapping,
+/// with the count of that mapping. Gap regions only affect the counts shown for
+/// lines on which no other region starts, so a closing brace after an early
+/// exit is shown with the count of the code that follows it, rather than the
+/// count of the exit.
+fn extract_gap_mappings(
+    hir_info: &ExtractedHirInfo,
+    code_mappings: &[CodeMapping],
+) -> impl Iterator<Item = GapMapping> {
+    let body_span = hir_info.body_span;
+    hir_info.terminator_spans.iter().filter_map(move |&terminator_span| {
+        let next = code_mappings
+            .iter()
+            .filter(|mapping| mapping.span.eq_ctxt(body_span) && body_span.contains(mapping.span))
+            .filter(|mapping| mapping.span.lo() >= terminator_span.hi())
+            .min_by_key(|mapping| mapping.span.lo());
+        let (hi, bcb) = match next {
+            Some(&CodeMapping { span, bcb }) => (span.lo(), Some(bcb)),
+            None => (body_span.hi(), None),
+        };
+        let span = terminator_span.with_lo(terminator_span.hi()).with_hi(hi);
+        (!span.is_empty()).then_some(GapMapping { span, bcb })
+    })
+}
+
 fn resolve_block_markers(
     coverage_info_hi: &CoverageInfoHi,
     mir_body: &mir::Body<'_>,
@@ -212,10 +352,15 @@ pub(super) fn extract_branch_pairs(
     mir_body: &mir::Body<'_>,
     hir_info: &ExtractedHirInfo,
     graph: &CoverageGraph,
+    folded_branches: Option<FoldedBranches>,
 ) -> Vec<BranchPair> {
     let Some(coverage_info_hi) = mir_body.coverage_info_hi.as_deref() else { return vec![] };
 
     let block_markers = resolve_block_markers(coverage_info_hi, mir_body);
+    let folded_targets = match folded_branches {
+        Some(_) => targets_of_constant_switches(mir_body),
+        None => FxHashSet::default(),
+    };
 
     coverage_info_hi
         .branch_spans
@@ -223,21 +368,166 @@ pub(super) fn extract_branch_pairs(
         .filter_map(|&BranchSpan { span: raw_span, true_marker, false_marker }| {
             // For now, ignore any branch span that was introduced by
             // expansion. This makes things like assert macros less noisy.
-            if !raw_span.ctxt().outer_expn_data().is_root() {
-                return None;
+            // The branches on `?` and `await` tokens are kept, because they
+            // only ever come from those tokens' desugarings.
+            match raw_span.ctxt().outer_expn_data().kind {
+                ExpnKind::Root
+                | ExpnKind::Desugaring(DesugaringKind::QuestionMark | DesugaringKind::Await) => {}
+                _ => return None,
             }
             let span = unexpand_into_body_span(raw_span, hir_info.body_span)?;
 
-            let bcb_from_marker = |marker: BlockMarkerId| graph.bcb_from_bb(block_markers[marker]?);
+            // A marker is only missing if its block was removed as unreachable,
+            // but the branch is still worth reporting if its other side is not.
+            let side_bcb = |marker| match block_markers[marker] {
+                Some(bb) => graph.bcb_from_bb(bb).map(Some),
+                None => Some(None),
+            };
+            let true_bcb = side_bcb(true_marker)?;
+            let false_bcb = side_bcb(false_marker)?;
+            if true_bcb.is_none() && false_bcb.is_none() {
+                return None;
+            }
 
-            let true_bcb = bcb_from_marker(true_marker)?;
-            let false_bcb = bcb_from_marker(false_marker)?;
+            let is_folded_target =
+                block_markers[true_marker].is_some_and(|bb| folded_targets.contains(&bb));
+            let folded = match folded_branches {
+                Some(_) if !is_folded_target => false,
+                Some(FoldedBranches::Omit) => return None,
+                Some(FoldedBranches::Mark) => true,
+                None => false,
+            };
 
-            Some(BranchPair { span, true_bcb, false_bcb })
+            Some(BranchPair { span, true_bcb, false_bcb, folded })
         })
         .collect::<Vec<_>>()
 }
 
+/// Returns the targets of each `SwitchInt` whose discriminant is a literal
+/// constant, under `-Zcoverage-options=folded-branches-omit` or `-mark`.
+///
+/// This pass runs before `SimplifyConstCondition`, so a condition like
+/// `if true` is still a real branch here. MIR building stores the condition in
+/// a temporary just before switching on it, so a local that the same block
+/// last assigned a constant counts as constant too. Named constants are not
+/// evaluated, because that could cycle back to this function's MIR.
+fn targets_of_constant_switches(mir_body: &mir::Body<'_>) -> FxHashSet<BasicBlock> {
+    let is_literal = |operand: &Operand<'_>| {
+        operand.constant().is_some_and(|c| c.const_.try_to_scalar_int().is_some())
+    };
+
+    let mut targets = FxHashSet::default();
+    for data in mir_body.basic_blocks.iter() {
+        let TerminatorKind::SwitchInt { discr, targets: switch_targets } = &data.terminator().kind
+        else {
+            continue;
+        };
+        let is_constant = match discr.place() {
+            // Look at the last statement in the block that assigns to the local.
+            Some(place) => data
+                .statements
+                .iter()
+                .rev()
+                .find_map(|statement| match &statement.kind {
+                    StatementKind::Assign(box (lhs, rvalue)) if lhs.local == place.local => {
+                        Some((*lhs, rvalue))
+                    }
+                    _ => None,
+                })
+                .is_some_and(|(lhs, rvalue)| {
+                    lhs == place && matches!(rvalue, Rvalue::Use(operand) if is_literal(operand))
+                }),
+            None => is_literal(discr),
+        };
+        if is_constant {
+            targets.extend(switch_targets.all_targets());
+        }
+    }
+    targets
+}
+
+/// Finds the panicking guards whose evaluations and failures can be counted,
+/// for `-Zcoverage-options=assertions`.
+///
+/// That option also splits coverage nodes after built-in checks and calls to
+/// `#[track_caller]` functions, so that each such guard ends its node, and
+/// the code after it starts a node of its own. The guard's failure count is
+/// then the difference between those nodes' counts. For `assert!`, the
+/// failing arm's node is counted directly instead.
+fn extract_assertion_guards<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir_body: &mir::Body<'tcx>,
+    hir_info: &ExtractedHirInfo,
+    graph: &CoverageGraph,
+) -> Vec<AssertionGuard> {
+    // A node only counts an outcome of the guard if it can't be reached in
+    // any other way.
+    let outcome_bcb = |evaluated_bcb: BasicCoverageBlock, bb: BasicBlock| {
+        let bcb = graph.bcb_from_bb(bb)?;
+        (graph[bcb].leader_bb() == bb && graph.sole_predecessor(bcb) == Some(evaluated_bcb))
+            .then_some(bcb)
+    };
+    let is_assert_macro = |span: Span| {
+        span.macro_backtrace().filter_map(|expn_data| expn_data.macro_def_id).any(|def_id| {
+            matches!(
+                tcx.get_diagnostic_name(def_id),
+                Some(
+                    sym::assert_macro
+                        | sym::assert_eq_macro
+                        | sym::assert_ne_macro
+                        | sym::debug_assert_macro
+                        | sym::debug_assert_eq_macro
+                        | sym::debug_assert_ne_macro
+                )
+            )
+        })
+    };
+
+    let mut guards = vec![];
+    for (bb, data) in mir_body.basic_blocks.iter_enumerated() {
+        let Some(evaluated_bcb) = graph.bcb_from_bb(bb) else { continue };
+        if graph[evaluated_bcb].last_bb() != bb {
+            continue;
+        }
+        let terminator = data.terminator();
+        let outcome = match &terminator.kind {
+            TerminatorKind::Assert { target, .. } => {
+                outcome_bcb(evaluated_bcb, *target).map(GuardOutcome::Passed)
+            }
+            TerminatorKind::Call { func, target: Some(target), .. }
+                if func.const_fn_def().is_some_and(|(def_id, _)| {
+                    tcx.codegen_fn_attrs(def_id).flags.contains(CodegenFnAttrFlags::TRACK_CALLER)
+                }) =>
+            {
+                outcome_bcb(evaluated_bcb, *target).map(GuardOutcome::Passed)
+            }
+            // The failing arm of an `assert!` diverges by calling a panic function.
+            TerminatorKind::SwitchInt { targets, .. }
+                if is_assert_macro(terminator.source_info.span) =>
+            {
+                targets.all_targets().iter().find_map(|&target| {
+                    let bcb = outcome_bcb(evaluated_bcb, target)?;
+                    let last_terminator = mir_body[graph[bcb].last_bb()].terminator();
+                    let diverges = graph.successors[bcb].is_empty()
+                        && matches!(
+                            last_terminator.kind,
+                            TerminatorKind::Call { target: None, .. }
+                        );
+                    diverges.then_some(GuardOutcome::Failed(bcb))
+                })
+            }
+            _ => None,
+        };
+        let Some(outcome) = outcome else { continue };
+        let Some(span) = unexpand_into_body_span(terminator.source_info.span, hir_info.body_span)
+        else {
+            continue;
+        };
+        guards.push(AssertionGuard { span, evaluated_bcb, outcome });
+    }
+    guards
+}
+
 pub(super) fn extract_mcdc_mappings(
     mir_body: &mir::Body<'_>,
     tcx: TyCtxt<'_>,
@@ -295,11 +585,18 @@ pub(super) fn extract_mcdc_mappings(
     mcdc_degraded_branches
         .extend(coverage_info_hi.mcdc_degraded_branch_spans.iter().filter_map(to_mcdc_branch));
 
-    mcdc_mappings.extend(coverage_info_hi.mcdc_spans.iter().filter_map(|(decision, branches)| {
+    let max_decision_test_vectors = tcx.sess.opts.unstable_opts.coverage_mcdc_max_test_vectors;
+    let mut extract_decision = |decision: &mir::coverage::MCDCDecisionSpan,
+                                branches: &[mir::coverage::MCDCBranchSpan],
+                                bitmaps: &[Option<(usize, usize)>]| {
         if branches.len() == 0 {
             return None;
         }
         let decision_span = unexpand_into_body_span(decision.span, body_span)?;
+        debug!(
+            ?decision_span,
+            decision_bcbs = ?graph.bcbs_for_span(decision_span).collect::<Vec<_>>(),
+        );
 
         let end_bcbs = decision
             .end_markers
@@ -312,7 +609,30 @@ pub(super) fn extract_mcdc_mappings(
             return None;
         }
         let num_test_vectors = calc_test_vectors_index(&mut branch_mappings);
-        let Some(bitmap_idx) = get_bitmap_idx(num_test_vectors) else {
+        if let Some(max_num_test_vectors) = max_decision_test_vectors
+            && num_test_vectors > max_num_test_vectors
+        {
+            tcx.dcx().emit_warn(MCDCDecisionExceedsTestVectorLimit {
+                span: decision_span,
+                num_test_vectors,
+                max_num_test_vectors,
+            });
+            mcdc_degraded_branches.extend(branch_mappings);
+            return None;
+        }
+        // A guard decision is only evaluated after the pattern tests of its arm
+        // have succeeded, and its end BCBs are only reachable from its own
+        // conditions, so the pattern tests that fall through to the next arm
+        // don't disturb its condition bitmap. But a guard is lowered again for
+        // each alternative of an or-pattern, and those repeated decisions have
+        // exactly the same conditions as the first one, so they share its bitmap.
+        let shared_bitmap_idx = decision
+            .first_lowering
+            .and_then(|first| bitmaps[first])
+            .filter(|&(_, first_num_test_vectors)| first_num_test_vectors == num_test_vectors)
+            .map(|(bitmap_idx, _)| bitmap_idx);
+        let Some(bitmap_idx) = shared_bitmap_idx.or_else(|| get_bitmap_idx(num_test_vectors))
+        else {
             tcx.dcx().emit_warn(MCDCExceedsTestVectorLimit {
                 span: decision_span,
                 max_num_test_vectors: MCDC_MAX_BITMAP_SIZE,
@@ -339,10 +659,22 @@ pub(super) fn extract_mcdc_mappings(
                 bitmap_idx,
                 num_test_vectors,
                 decision_depth: decision.decision_depth,
+                is_repeated_guard: shared_bitmap_idx.is_some(),
             },
             branch_mappings,
         ))
-    }));
+    };
+
+    // The bitmap index and number of test vectors of each decision that was
+    // extracted, indexed like `mcdc_spans`.
+    let mut bitmaps = Vec::with_capacity(coverage_info_hi.mcdc_spans.len());
+    for (decision, branches) in &coverage_info_hi.mcdc_spans {
+        let mapping = extract_decision(decision, branches, &bitmaps);
+        bitmaps.push(
+            mapping.as_ref().map(|(decision, _)| (decision.bitmap_idx, decision.num_test_vectors)),
+        );
+        mcdc_mappings.extend(mapping);
+    }
 }
 
 // LLVM checks the executed test vector by accumulating indices of tested branches.
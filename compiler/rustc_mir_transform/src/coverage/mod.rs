@@ -1,49 +1,97 @@
 pub(super) mod query;
 
 mod counters;
+pub(crate) mod drop_glue;
 mod graph;
 mod mappings;
+mod paths;
 mod spans;
+mod switch_arms;
 #[cfg(test)]
 mod tests;
 mod unexpand;
 
+use std::hash::Hash;
+use std::{io, iter};
+
+use rustc_data_structures::graph::DirectedGraph;
+use rustc_data_structures::stable_hasher::{Hash64, StableHasher};
 use rustc_hir as hir;
-use rustc_hir::intravisit::{Visitor, walk_expr};
+use rustc_hir::def::DefKind;
+use rustc_hir::intravisit::{Visitor, walk_block, walk_expr, walk_stmt};
+use rustc_index::IndexVec;
+use rustc_index::bit_set::DenseBitSet;
 use rustc_middle::hir::map::Map;
 use rustc_middle::hir::nested_filter;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::coverage::{
-    CoverageKind, DecisionInfo, FunctionCoverageInfo, Mapping, MappingKind,
+    CounterId, CounterMetadata, CovTerm, CoverageKind, DecisionInfo, ExpansionMapping,
+    FunctionCoverageInfo, Mapping, MappingKind, PathMapping, SwitchArmMapping,
 };
 use rustc_middle::mir::{
     self, BasicBlock, BasicBlockData, SourceInfo, Statement, StatementKind, Terminator,
-    TerminatorKind,
+    TerminatorKind, create_dump_file, graphviz_safe_def_name,
 };
+use rustc_middle::span_bug;
 use rustc_middle::ty::TyCtxt;
-use rustc_span::Span;
-use rustc_span::def_id::LocalDefId;
+use rustc_session::config::SubexprGranularity;
+use rustc_span::def_id::{DefId, LocalDefId};
+use rustc_span::{ExpnKind, MacroKind, Span, sym};
+use rustc_target::spec::PanicStrategy;
 use tracing::{debug, debug_span, trace};
 
-use crate::coverage::counters::{CoverageCounters, Site};
-use crate::coverage::graph::CoverageGraph;
-use crate::coverage::mappings::ExtractedMappings;
+use crate::coverage::counters::{
+    CounterPlacement, CoverageCounters, ExpressionLimits, ProfiledCounts, Site,
+};
+use crate::coverage::graph::{BasicCoverageBlock, BcbEdge, CoverageGraph, START_BCB, SideEdges};
+use crate::coverage::mappings::{ExtractedMappings, GuardOutcome};
+use crate::coverage::paths::{PathProfile, PathSite};
+use crate::coverage::switch_arms::SwitchArms;
+use crate::coverage::unexpand::unexpand_into_included_file;
 
 /// Inserts `StatementKind::Coverage` statements that either instrument the binary with injected
 /// counters, via intrinsic `llvm.instrprof.increment`, and/or inject metadata used during codegen
 /// to construct the coverage map.
-pub(super) struct InstrumentCoverage;
+///
+/// Normally this runs once, before borrowck. With `-Zcoverage-options=post-inline` it instead
+/// runs just after the MIR inliner, so that counters reflect the optimized control flow.
+///
+/// Either way, each function's coverage graph is built from the MIR as it stands when this pass
+/// runs. A graph that has to follow the body while calls are inlined into it can be updated in
+/// place with [`CoverageGraph::splice_blocks`], instead of being rebuilt after each call.
+pub(super) enum InstrumentCoverage {
+    Initial,
+    AfterInline,
+}
 
 impl<'tcx> crate::MirPass<'tcx> for InstrumentCoverage {
+    fn name(&self) -> &'static str {
+        match self {
+            InstrumentCoverage::Initial => "InstrumentCoverage",
+            InstrumentCoverage::AfterInline => "InstrumentCoverage-after-inline",
+        }
+    }
+
     fn is_enabled(&self, sess: &rustc_session::Session) -> bool {
-        sess.instrument_coverage()
+        let post_inline = matches!(self, InstrumentCoverage::AfterInline);
+        sess.coverage_analysis() && sess.coverage_post_inline() == post_inline
     }
 
     fn run_pass(&self, tcx: TyCtxt<'tcx>, mir_body: &mut mir::Body<'tcx>) {
         let mir_source = mir_body.source;
 
-        // This pass runs after MIR promotion, but before promoted MIR starts to
-        // be transformed, so it should never see promoted MIR.
-        assert!(mir_source.promoted.is_none());
+        match self {
+            // This pass runs after MIR promotion, but before promoted MIR starts to
+            // be transformed, so it should never see promoted MIR.
+            InstrumentCoverage::Initial => assert!(mir_source.promoted.is_none()),
+            // The optimization pipeline also runs on promoted MIR, which is
+            // never instrumented.
+            InstrumentCoverage::AfterInline => {
+                if mir_source.promoted.is_some() {
+                    return;
+                }
+            }
+        }
 
         let def_id = mir_source.def_id().expect_local();
 
@@ -72,9 +120,51 @@ fn instrument_function_for_coverage<'tcx>(tcx: TyCtxt<'tcx>, mir_body: &mut mir:
 
     let hir_info = extract_hir_info(tcx, def_id.expect_local());
 
+    if tcx.sess.instrument_coverage_function_only() {
+        instrument_function_entry(mir_body, &hir_info);
+        return;
+    }
+
     // Build the coverage graph, which is a simplified view of the MIR control-flow
     // graph that ignores some details not relevant to coverage instrumentation.
-    let graph = CoverageGraph::from_mir(mir_body);
+    let panic_sites = find_panic_sites(tcx, mir_body);
+    let side_edges = SideEdges {
+        // MIR building emits cleanup blocks regardless of the panic strategy,
+        // but with `-Cpanic=abort` they can never run, so keeping their edges
+        // would only split up chains of calls and add useless counters.
+        unwind: tcx.sess.coverage_unwind_paths()
+            && tcx.sess.panic_strategy() == PanicStrategy::Unwind,
+        coroutine_drop: tcx.sess.coverage_coroutine_drops(),
+    };
+    if can_skip_straight_line_graph(tcx, mir_body, &hir_info)
+        && CoverageGraph::is_straight_line(mir_body, &panic_sites, side_edges)
+    {
+        debug!("straight-line function; instrumenting it without a coverage graph");
+        instrument_with_single_counter(mir_body, &hir_info, hir_info.function_source_hash);
+        return;
+    }
+
+    let mut graph = CoverageGraph::from_mir(mir_body, &panic_sites, side_edges);
+    if tcx.sess.opts.unstable_opts.validate_coverage_graph {
+        let errors = graph.validate(mir_body, &panic_sites, side_edges);
+        if !errors.is_empty() {
+            span_bug!(
+                mir_body.span,
+                "broken coverage graph for {def_id:?}:\n{}",
+                errors.join("\n")
+            );
+        }
+    }
+    if tcx.sess.coverage_post_dominators() {
+        graph.compute_post_dominators();
+    }
+    if tcx.sess.coverage_static_unreachable() {
+        let untaken_edges = find_untaken_edges(tcx, mir_body, &graph);
+        graph.mark_untaken_edges(untaken_edges);
+    }
+    if tcx.sess.opts.unstable_opts.dump_coverage_graph {
+        dump_coverage_graph(tcx, mir_body, &graph);
+    }
 
     ////////////////////////////////////////////////////
     // Extract coverage spans and other mapping info from MIR.
@@ -92,20 +182,94 @@ fn instrument_function_for_coverage<'tcx>(tcx: TyCtxt<'tcx>, mir_body: &mut mir:
         return;
     }
 
-    let coverage_counters =
-        CoverageCounters::make_bcb_counters(&graph, &bcbs_with_counter_mappings);
+    let limits = ExpressionLimits {
+        max_terms: tcx.sess.opts.unstable_opts.coverage_max_expression_terms,
+        no_subtraction: tcx.sess.coverage_boolean_counters(),
+    };
+    let placement = CounterPlacement {
+        loop_depth_order: tcx.sess.coverage_loop_depth_order(),
+        shared_counters: tcx.sess.coverage_shared_counters(),
+    };
+    let mut coverage_counters = if tcx.sess.coverage_spanning_tree() {
+        CoverageCounters::make_spanning_tree_counters(
+            &graph,
+            &bcbs_with_counter_mappings,
+            limits,
+            placement,
+        )
+    } else {
+        CoverageCounters::make_bcb_counters(&graph, &bcbs_with_counter_mappings, limits, placement)
+    };
+    // The profile was recorded with the usual layout, so it has to be built
+    // first in order to make sense of the profile's counter values.
+    let profiled_counts = tcx
+        .sess
+        .coverage_profile_guided()
+        .then(|| find_profiled_counts(tcx, def_id.expect_local(), &hir_info, &coverage_counters))
+        .flatten();
+    if let Some(profiled_counts) = &profiled_counts {
+        coverage_counters = CoverageCounters::make_profile_guided_counters(
+            &graph,
+            &bcbs_with_counter_mappings,
+            limits,
+            placement,
+            profiled_counts,
+        );
+    }
+    if tcx.sess.coverage_stable_ids() {
+        coverage_counters.sort_counters_by_key(|site| stable_site_key(&graph, site));
+    }
 
-    let mappings = create_mappings(&extracted_mappings, &coverage_counters);
+    let mut mappings = create_mappings(&extracted_mappings, &coverage_counters);
+    mappings.extend(create_assertion_mappings(&extracted_mappings, &mut coverage_counters));
+    let expansion_mappings =
+        create_expansion_mappings(&extracted_mappings.expansion_mappings, &coverage_counters);
+    let macro_definition_mappings =
+        create_expansion_mappings(&extracted_mappings.definition_mappings, &coverage_counters);
     if mappings.is_empty() {
         // No spans could be converted into valid mappings, so skip this function.
         debug!("no spans could be converted into valid mappings; skipping");
         return;
     }
 
+    let structural_hash = tcx
+        .sess
+        .coverage_stable_ids()
+        .then(|| hash_coverage_structure(tcx, def_id, &graph, &coverage_counters));
+    if let Some(structural_hash) = structural_hash
+        && tcx.coverage_baseline_hashes(()).contains(&structural_hash)
+    {
+        debug!("unchanged since the coverage baseline; only instrumenting the entry");
+        instrument_entry_only(tcx, mir_body, &graph, hir_info.body_span, structural_hash);
+        return;
+    }
+
+    // Switches have to be found before any counter blocks are injected into
+    // their arms.
+    let switch_arms = if tcx.sess.coverage_switch_arms() {
+        switch_arms::find_switch_arms(mir_body, &graph)
+    } else {
+        vec![]
+    };
+
     inject_coverage_statements(mir_body, &graph, &extracted_mappings, &coverage_counters);
 
     inject_mcdc_statements(mir_body, &graph, &extracted_mappings);
 
+    let path_profile = if tcx.sess.coverage_paths() { paths::number_paths(&graph) } else { None };
+    let (num_paths, path_mappings) = match &path_profile {
+        Some(profile) => {
+            inject_path_statements(mir_body, &graph, profile);
+            (profile.num_paths as usize, make_path_mappings(&extracted_mappings, profile))
+        }
+        None => (0, vec![]),
+    };
+
+    inject_switch_arm_statements(mir_body, &switch_arms);
+    let num_switch_arm_slots =
+        switch_arms.last().map_or(0, |switch| (switch.first_slot + switch.num_slots()) as usize);
+    let switch_arm_mappings = make_switch_arm_mappings(&extracted_mappings, &switch_arms);
+
     let mcdc_num_condition_bitmaps = extracted_mappings
         .mcdc_mappings
         .iter()
@@ -113,17 +277,365 @@ fn instrument_function_for_coverage<'tcx>(tcx: TyCtxt<'tcx>, mir_body: &mut mir:
         .max()
         .map_or(0, |max| usize::from(max) + 1);
 
+    let mut function_source_hash = structural_hash.unwrap_or(hir_info.function_source_hash);
+    if profiled_counts.is_some() {
+        // The counter layout differs from the one the profile was recorded
+        // with, so profiles of this build must never be merged with it.
+        let mut hasher = StableHasher::new();
+        function_source_hash.hash(&mut hasher);
+        "profile-guided".hash(&mut hasher);
+        function_source_hash = hasher.finish::<Hash64>().as_u64();
+    }
+
+    let counter_metadata = if tcx.sess.coverage_counter_metadata() {
+        make_counter_metadata(&graph, &coverage_counters)
+    } else {
+        IndexVec::new()
+    };
+
     mir_body.function_coverage_info = Some(Box::new(FunctionCoverageInfo {
-        function_source_hash: hir_info.function_source_hash,
+        function_source_hash,
         body_span: hir_info.body_span,
+        num_bcbs: graph.num_nodes(),
         num_counters: coverage_counters.num_counters(),
         mcdc_bitmap_bits: extracted_mappings.mcdc_bitmap_bits,
         expressions: coverage_counters.into_expressions(),
         mappings,
         mcdc_num_condition_bitmaps,
+        counter_metadata,
+        num_paths,
+        path_mappings,
+        num_switch_arm_slots,
+        switch_arm_mappings,
+        expansion_mappings,
+        macro_definition_mappings,
+    }));
+}
+
+/// Under `-Zcoverage-baseline`, instruments a function whose structural hash
+/// is listed in the baseline with a single counter at its entry, mapped to its
+/// whole body. Reports then still cover the function, but only say whether it
+/// was called, and how often.
+///
+/// The emitted hash is derived from the structural hash, so that profiles of
+/// this reduced instrumentation are never merged with profiles of the fully
+/// instrumented function.
+fn instrument_entry_only<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir_body: &mut mir::Body<'tcx>,
+    graph: &CoverageGraph,
+    body_span: Span,
+    structural_hash: u64,
+) {
+    let extracted_mappings = ExtractedMappings {
+        num_bcbs: graph.num_nodes(),
+        code_mappings: vec![mappings::CodeMapping { span: body_span, bcb: START_BCB }],
+        ..ExtractedMappings::default()
+    };
+    let coverage_counters = CoverageCounters::make_bcb_counters(
+        graph,
+        &extracted_mappings.all_bcbs_with_counter_mappings(),
+        ExpressionLimits::default(),
+        CounterPlacement::default(),
+    );
+    let mappings = create_mappings(&extracted_mappings, &coverage_counters);
+
+    inject_coverage_statements(mir_body, graph, &extracted_mappings, &coverage_counters);
+
+    let mut hasher = StableHasher::new();
+    structural_hash.hash(&mut hasher);
+    "entry-only".hash(&mut hasher);
+    let function_source_hash = hasher.finish::<Hash64>().as_u64();
+
+    let counter_metadata = if tcx.sess.coverage_counter_metadata() {
+        make_counter_metadata(graph, &coverage_counters)
+    } else {
+        IndexVec::new()
+    };
+
+    mir_body.function_coverage_info = Some(Box::new(FunctionCoverageInfo {
+        function_source_hash,
+        body_span,
+        num_bcbs: graph.num_nodes(),
+        num_counters: coverage_counters.num_counters(),
+        mcdc_bitmap_bits: 0,
+        expressions: coverage_counters.into_expressions(),
+        mappings,
+        mcdc_num_condition_bitmaps: 0,
+        counter_metadata,
+        num_paths: 0,
+        path_mappings: vec![],
+        num_switch_arm_slots: 0,
+        switch_arm_mappings: vec![],
+        expansion_mappings: vec![],
+        macro_definition_mappings: vec![],
     }));
 }
 
+/// Under `-Zcoverage-options=profile-guided`, looks up the counts that the
+/// `-Cprofile-use` profile recorded for this function, when it was
+/// instrumented with the usual counter layout `coverage_counters`.
+///
+/// Returns `None` for closures and generic functions, whose profile records
+/// can't be named before monomorphization, and for functions without a record
+/// whose hash and layout match (e.g. because they changed since the profile
+/// was recorded). Those functions just keep the usual layout.
+fn find_profiled_counts(
+    tcx: TyCtxt<'_>,
+    def_id: LocalDefId,
+    hir_info: &ExtractedHirInfo,
+    coverage_counters: &CoverageCounters,
+) -> Option<ProfiledCounts> {
+    if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+        || tcx.generics_of(def_id).requires_monomorphization(tcx)
+    {
+        return None;
+    }
+    let counter_values = tcx.coverage_profile_counters(def_id, hir_info.function_source_hash)?;
+    let profiled_counts = coverage_counters.profiled_counts(&counter_values);
+    debug!(found = profiled_counts.is_some(), "coverage profile lookup");
+    profiled_counts
+}
+
+/// Under `-Zcoverage-options=function`, instruments the function with a single
+/// counter at its entry, mapped to the whole function. This doesn't need the
+/// coverage graph or any coverage spans, so none of them are computed.
+///
+/// Like [`instrument_entry_only`], the emitted hash is derived from the usual
+/// one, so that profiles of this build are never merged with profiles of a
+/// fully instrumented build.
+fn instrument_function_entry(mir_body: &mut mir::Body<'_>, hir_info: &ExtractedHirInfo) {
+    let mut hasher = StableHasher::new();
+    hir_info.function_source_hash.hash(&mut hasher);
+    "function".hash(&mut hasher);
+    let function_source_hash = hasher.finish::<Hash64>().as_u64();
+
+    instrument_with_single_counter(mir_body, hir_info, function_source_hash);
+}
+
+/// Returns true if a straight-line function can be instrumented without
+/// building its coverage graph or extracting its spans from MIR.
+///
+/// The graph of such a function has a single node, whose spans are all merged
+/// into one region running from the start of the signature to the end of the
+/// body, so [`instrument_with_single_counter`] gives the same result. That
+/// doesn't hold if the body has holes (closures or nested items), if its MIR
+/// spans are ignored, if it has branch or MC/DC spans left over from MIR
+/// building, or if an option needs the graph itself.
+fn can_skip_straight_line_graph(
+    tcx: TyCtxt<'_>,
+    mir_body: &mir::Body<'_>,
+    hir_info: &ExtractedHirInfo,
+) -> bool {
+    let sess = tcx.sess;
+    let has_branch_spans = mir_body.coverage_info_hi.as_deref().is_some_and(|info| {
+        !info.branch_spans.is_empty()
+            || !info.mcdc_degraded_branch_spans.is_empty()
+            || !info.mcdc_spans.is_empty()
+    });
+    hir_info.fn_sig_span_extended.is_some()
+        && hir_info.hole_spans.is_empty()
+        && !has_branch_spans
+        && !hir_info.is_async_fn
+        && !sess.coverage_no_mir_spans()
+        && !sess.coverage_stable_ids()
+        && !sess.coverage_profile_guided()
+        && !sess.coverage_counter_metadata()
+        && !sess.coverage_paths()
+        && !sess.coverage_assertions()
+        && !sess.coverage_expansions()
+        && !sess.coverage_macro_definitions()
+        && !sess.coverage_gap_regions()
+        && !sess.coverage_raw_spans()
+        && !sess.opts.unstable_opts.validate_coverage_graph
+        && !sess.opts.unstable_opts.dump_coverage_graph
+        && !body_has_included_code(tcx, mir_body, hir_info.body_span)
+}
+
+/// True if some of the body's code was pulled in from another file by
+/// `include!`. That code is only mapped to its own file when spans are
+/// extracted from MIR, so such functions can't use the single-counter shortcut.
+fn body_has_included_code(tcx: TyCtxt<'_>, mir_body: &mir::Body<'_>, body_span: Span) -> bool {
+    let source_map = tcx.sess.source_map();
+    mir_body.basic_blocks.iter().any(|data| {
+        data.statements
+            .iter()
+            .map(|statement| statement.source_info.span)
+            .chain(iter::once(data.terminator().source_info.span))
+            .any(|span| unexpand_into_included_file(source_map, span, body_span).is_some())
+    })
+}
+
+/// Instruments the function with a single counter at its entry, mapped to the
+/// whole function, and records the given hash for it.
+fn instrument_with_single_counter(
+    mir_body: &mut mir::Body<'_>,
+    hir_info: &ExtractedHirInfo,
+    function_source_hash: u64,
+) {
+    let counter = CounterId::START;
+    inject_statement(mir_body, CoverageKind::CounterIncrement { id: counter }, mir::START_BLOCK);
+
+    let span = match hir_info.fn_sig_span_extended {
+        Some(fn_sig_span) => fn_sig_span.to(hir_info.body_span),
+        None => hir_info.body_span,
+    };
+    let mappings = vec![Mapping { kind: MappingKind::Code(CovTerm::Counter(counter)), span }];
+
+    mir_body.function_coverage_info = Some(Box::new(FunctionCoverageInfo {
+        function_source_hash,
+        body_span: hir_info.body_span,
+        num_bcbs: 1,
+        num_counters: 1,
+        mcdc_bitmap_bits: 0,
+        expressions: IndexVec::new(),
+        mappings,
+        mcdc_num_condition_bitmaps: 0,
+        counter_metadata: IndexVec::new(),
+        num_paths: 0,
+        path_mappings: vec![],
+        num_switch_arm_slots: 0,
+        switch_arm_mappings: vec![],
+        expansion_mappings: vec![],
+        macro_definition_mappings: vec![],
+    }));
+}
+
+/// Under `-Zdump-coverage-graph`, writes the function's coverage graph as a
+/// `.dot` file into the MIR dump directory.
+fn dump_coverage_graph<'tcx>(tcx: TyCtxt<'tcx>, mir_body: &mir::Body<'tcx>, graph: &CoverageGraph) {
+    let _: io::Result<()> = try {
+        let mut file = create_dump_file(tcx, "dot", false, "coverage_graph", &"-----", mir_body)?;
+        graph.write_graphviz(&mut file, &graphviz_safe_def_name(mir_body.source.def_id()))?;
+    };
+}
+
+/// Under `-Zcoverage-options=split-at-panics`, finds the blocks whose terminator
+/// is a likely panic site that would otherwise continue to its successor, so
+/// that spans after the panic site are counted separately from spans before it.
+///
+/// Explicit `panic!()` calls and failed `assert!()`s already end their block
+/// without a successor, so this only needs to look for built-in assertions
+/// (e.g. bounds and overflow checks) and calls to `#[track_caller]` functions
+/// such as `Option::unwrap`, which are the usual way to write a function that
+/// panics on behalf of its caller. Calls to `catch_unwind` are included too,
+/// since that is where a caught panic resumes (unless panics abort, in which
+/// case no panic is ever caught).
+///
+/// The experimental sub-expression modes (`-Zcoverage-options=subexpr-calls`
+/// and `subexpr-all`) reuse this splitting to give sub-expressions their own
+/// counters, by treating every call (and, for `subexpr-all`, every built-in
+/// assertion and drop) as a possible panic site. Chained calls already have
+/// spans that start at the method name, so in `a.min(b).max(c)` the spans
+/// `min(b)` and `max(c)` end up in separate blocks.
+fn find_panic_sites<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir_body: &mir::Body<'tcx>,
+) -> DenseBitSet<BasicBlock> {
+    let mut panic_sites = DenseBitSet::new_empty(mir_body.basic_blocks.len());
+    // Counting assertion failures relies on built-in checks and
+    // `#[track_caller]` calls ending their node.
+    let split_at_panics = tcx.sess.coverage_split_at_panics() || tcx.sess.coverage_assertions();
+    let subexpr = tcx.sess.coverage_subexpr();
+    let panics_unwind = tcx.sess.panic_strategy() == PanicStrategy::Unwind;
+    if !split_at_panics && subexpr.is_none() {
+        return panic_sites;
+    }
+
+    for (bb, data) in mir_body.basic_blocks.iter_enumerated() {
+        let is_panic_site = match &data.terminator().kind {
+            TerminatorKind::Assert { .. } => {
+                split_at_panics || subexpr >= Some(SubexprGranularity::All)
+            }
+            TerminatorKind::Drop { .. } => subexpr >= Some(SubexprGranularity::All),
+            TerminatorKind::Call { target: Some(_), .. } if subexpr.is_some() => true,
+            TerminatorKind::Call { func, target: Some(_), .. } => {
+                func.const_fn_def().is_some_and(|(def_id, _)| {
+                    tcx.codegen_fn_attrs(def_id).flags.contains(CodegenFnAttrFlags::TRACK_CALLER)
+                        || (panics_unwind && is_catch_unwind(tcx, def_id))
+                })
+            }
+            _ => false,
+        };
+        if is_panic_site {
+            panic_sites.insert(bb);
+        }
+    }
+
+    panic_sites
+}
+
+/// Under `-Zcoverage-options=static-unreachable`, finds the coverage graph
+/// edges that a `SwitchInt` on a constant (e.g. `if cfg!(...)` or `while true`)
+/// can never take, so that the code only reachable through them is reported
+/// as never having run, instead of being given counters.
+///
+/// Coverage instrumentation runs before const-propagation and branch
+/// simplification, so this only looks at switches whose discriminant is
+/// already a constant operand.
+fn find_untaken_edges<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mir_body: &mir::Body<'tcx>,
+    graph: &CoverageGraph,
+) -> Vec<BcbEdge> {
+    let typing_env = mir_body.typing_env(tcx);
+    let mut untaken_edges = vec![];
+
+    for (from_bcb, data) in graph.iter_enumerated() {
+        let TerminatorKind::SwitchInt { discr: mir::Operand::Constant(c), targets } =
+            &mir_body[data.last_bb()].terminator().kind
+        else {
+            continue;
+        };
+        let Some(value) = c.const_.try_eval_bits(tcx, typing_env) else { continue };
+        let taken_bcb = graph.bcb_from_bb(targets.target_for_value(value));
+
+        // Several targets can lead to the same node, so an edge is only
+        // untaken if the taken target leads somewhere else.
+        untaken_edges.extend(
+            graph.edges_from(from_bcb).filter(|&edge| Some(graph.edge_target(edge)) != taken_bcb),
+        );
+    }
+
+    untaken_edges
+}
+
+/// Returns true if `def_id` is `std::panic::catch_unwind`, or the intrinsic
+/// that it is built on (which can appear after inlining).
+///
+/// A panic that was caught by such a call lands back in the caller at the
+/// call's return edge, having skipped the rest of the code that led to it.
+/// Treating the call like a panic site gives the code after it its own
+/// counter, so that code is counted by what actually ran after recovery,
+/// instead of being inferred from the code before the call.
+fn is_catch_unwind(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.is_diagnostic_item(sym::catch_unwind, def_id)
+        || tcx.intrinsic(def_id).is_some_and(|intrinsic| intrinsic.name == sym::catch_unwind)
+}
+
+/// For each physical counter, computes static properties of its site in the
+/// coverage graph, for `-Zcoverage-options=counter-metadata`.
+fn make_counter_metadata(
+    graph: &CoverageGraph,
+    coverage_counters: &CoverageCounters,
+) -> IndexVec<CounterId, CounterMetadata> {
+    coverage_counters
+        .counter_increment_sites()
+        .map(|(_, site)| {
+            // Edge counters are attributed to the edge's source node.
+            let bcb = match site {
+                Site::Node { bcb } => bcb,
+                Site::Edge { edge } => graph.edge_source(edge),
+            };
+            let saturate = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+            CounterMetadata {
+                loop_depth: saturate(graph.loop_depth(bcb)),
+                fan_out: saturate(graph.successors[bcb].len()),
+            }
+        })
+        .collect()
+}
+
 /// For each coverage span extracted from MIR, create a corresponding
 /// mapping.
 ///
@@ -140,10 +652,15 @@ fn create_mappings(
     let ExtractedMappings {
         num_bcbs: _,
         code_mappings,
+        expansion_mappings: _,
+        definition_mappings: _,
+        gap_mappings,
+        skipped_spans,
         branch_pairs,
         mcdc_bitmap_bits: _,
         mcdc_degraded_branches,
         mcdc_mappings,
+        assertion_guards: _,
     } = extracted_mappings;
     let mut mappings = Vec::new();
 
@@ -155,10 +672,23 @@ fn create_mappings(
         },
     ));
 
+    mappings.extend(gap_mappings.iter().map(|&mappings::GapMapping { span, bcb }| {
+        let kind = MappingKind::Gap(bcb.map_or(CovTerm::Zero, term_for_bcb));
+        Mapping { kind, span }
+    }));
+
+    mappings.extend(skipped_spans.iter().map(|&span| Mapping { kind: MappingKind::Skipped, span }));
+
     mappings.extend(branch_pairs.iter().map(
-        |&mappings::BranchPair { span, true_bcb, false_bcb }| {
-            let true_term = term_for_bcb(true_bcb);
-            let false_term = term_for_bcb(false_bcb);
+        |&mappings::BranchPair { span, true_bcb, false_bcb, folded }| {
+            // `llvm-cov` reports a branch whose outcomes are both the constant
+            // zero as folded, rather than as not taken.
+            let (true_term, false_term) = if folded {
+                (CovTerm::Zero, CovTerm::Zero)
+            } else {
+                let term_for_side = |bcb: Option<_>| bcb.map_or(CovTerm::Zero, term_for_bcb);
+                (term_for_side(true_bcb), term_for_side(false_bcb))
+            };
             let kind = MappingKind::Branch { true_term, false_term };
             Mapping { kind, span }
         },
@@ -210,7 +740,9 @@ fn create_mappings(
             )
             .collect::<Vec<_>>();
 
-        if conditions.len() == num_conditions as usize {
+        // A repeated lowering of a match guard shares the decision reported for
+        // the first lowering, so only its conditions' counts are reported here.
+        if !decision.is_repeated_guard && conditions.len() == num_conditions as usize {
             // LLVM requires end index for counter mapping regions.
             let kind = MappingKind::MCDCDecision(DecisionInfo {
                 bitmap_idx: (decision.bitmap_idx + decision.num_test_vectors) as u32,
@@ -233,6 +765,57 @@ fn create_mappings(
     mappings
 }
 
+/// Under `-Zcoverage-options=expansions` or `macro-definitions`, creates the
+/// code mappings inside each macro invocation's expansion, grouped by
+/// invocation.
+fn create_expansion_mappings(
+    expansion_mappings: &[mappings::ExpansionCodeMappings],
+    coverage_counters: &CoverageCounters,
+) -> Vec<ExpansionMapping> {
+    let term_for_bcb =
+        |bcb| coverage_counters.term_for_bcb(bcb).expect("all BCBs with spans were given counters");
+
+    expansion_mappings
+        .iter()
+        .map(|mappings::ExpansionCodeMappings { call_site, code_mappings }| {
+            let mappings = code_mappings
+                .iter()
+                .map(|&mappings::CodeMapping { span, bcb }| Mapping {
+                    kind: MappingKind::Code(term_for_bcb(bcb)),
+                    span,
+                })
+                .collect();
+            ExpansionMapping { call_site: *call_site, mappings }
+        })
+        .collect()
+}
+
+/// Under `-Zcoverage-options=assertions`, creates a mapping for each panicking
+/// guard, deriving whichever of its outcomes isn't counted directly from the
+/// number of times that the guard was evaluated.
+fn create_assertion_mappings(
+    extracted_mappings: &ExtractedMappings,
+    coverage_counters: &mut CoverageCounters,
+) -> Vec<Mapping> {
+    extracted_mappings
+        .assertion_guards
+        .iter()
+        .filter_map(|&mappings::AssertionGuard { span, evaluated_bcb, outcome }| {
+            let (passed_term, failed_term) = match outcome {
+                GuardOutcome::Passed(passed_bcb) => (
+                    coverage_counters.term_for_bcb(passed_bcb)?,
+                    coverage_counters.make_node_difference(evaluated_bcb, passed_bcb)?,
+                ),
+                GuardOutcome::Failed(failed_bcb) => (
+                    coverage_counters.make_node_difference(evaluated_bcb, failed_bcb)?,
+                    coverage_counters.term_for_bcb(failed_bcb)?,
+                ),
+            };
+            Some(Mapping { kind: MappingKind::Assertion { passed_term, failed_term }, span })
+        })
+        .collect()
+}
+
 /// For each BCB node or BCB edge that has an associated coverage counter,
 /// inject any necessary coverage statements into MIR.
 fn inject_coverage_statements<'tcx>(
@@ -248,9 +831,11 @@ fn inject_coverage_statements<'tcx>(
         // to create a new block between the two BCBs, and inject into that.
         let target_bb = match site {
             Site::Node { bcb } => graph[bcb].leader_bb(),
-            Site::Edge { from_bcb, to_bcb } => {
+            Site::Edge { edge } => {
                 // Create a new block between the last block of `from_bcb` and
                 // the first block of `to_bcb`.
+                let from_bcb = graph.edge_source(edge);
+                let to_bcb = graph.edge_target(edge);
                 let from_bb = graph[from_bcb].last_bb();
                 let to_bb = graph[to_bcb].leader_bb();
 
@@ -335,6 +920,129 @@ fn inject_mcdc_statements<'tcx>(
     }
 }
 
+/// Under `-Zcoverage-options=paths`, injects statements that accumulate each
+/// path's number in the path register, and count the path at its end.
+fn inject_path_statements<'tcx>(
+    mir_body: &mut mir::Body<'tcx>,
+    graph: &CoverageGraph,
+    profile: &PathProfile,
+) {
+    // Inject path counts first because `inject_statement` always inserts new
+    // statements at the head of a block, and a path must only be counted after
+    // any increments that share its block.
+    for end in &profile.ends {
+        let bb = match end.site {
+            PathSite::Node { bcb } => graph[bcb].leader_bb(),
+            PathSite::Edge { from_bcb, to_bcb } => {
+                path_edge_block(mir_body, graph, from_bcb, to_bcb)
+            }
+        };
+        inject_statement(mir_body, CoverageKind::PathCount { add: end.add, reset: end.reset }, bb);
+    }
+
+    for &(from_bcb, to_bcb, value) in &profile.increments {
+        let bb = path_edge_block(mir_body, graph, from_bcb, to_bcb);
+        inject_statement(mir_body, CoverageKind::PathRegisterAdd { value }, bb);
+    }
+}
+
+/// Returns a block that only runs when control flows along the given edge,
+/// creating one if necessary.
+fn path_edge_block(
+    mir_body: &mut mir::Body<'_>,
+    graph: &CoverageGraph,
+    from_bcb: BasicCoverageBlock,
+    to_bcb: BasicCoverageBlock,
+) -> BasicBlock {
+    if graph.successors[from_bcb].len() == 1 {
+        return graph[from_bcb].leader_bb();
+    }
+    if graph.predecessors[to_bcb].len() == 1 {
+        return graph[to_bcb].leader_bb();
+    }
+
+    let from_bb = graph[from_bcb].last_bb();
+    let to_bb = graph[to_bcb].leader_bb();
+    if mir_body[from_bb].terminator().successors().any(|succ| succ == to_bb) {
+        return inject_edge_counter_basic_block(mir_body, from_bb, to_bb);
+    }
+    // Otherwise the edge already has a block of its own, holding its counter.
+    mir_body[from_bb]
+        .terminator()
+        .successors()
+        .find(|&succ| match mir_body[succ].terminator().kind {
+            TerminatorKind::Goto { target } => target == to_bb,
+            _ => false,
+        })
+        .expect("from_bb should have a successor for to_bb, or for its edge block")
+}
+
+/// Lists the spans of each node's code mappings.
+fn code_spans_for_bcbs(
+    extracted_mappings: &ExtractedMappings,
+) -> IndexVec<BasicCoverageBlock, Vec<Span>> {
+    let mut spans_for_bcb =
+        IndexVec::<BasicCoverageBlock, Vec<Span>>::from_elem_n(vec![], extracted_mappings.num_bcbs);
+    for &mappings::CodeMapping { span, bcb } in &extracted_mappings.code_mappings {
+        spans_for_bcb[bcb].push(span);
+    }
+    spans_for_bcb
+}
+
+/// For each path numbered under `-Zcoverage-options=paths`, lists the spans
+/// of the code mappings along that path.
+fn make_path_mappings(
+    extracted_mappings: &ExtractedMappings,
+    profile: &PathProfile,
+) -> Vec<PathMapping> {
+    let spans_for_bcb = code_spans_for_bcbs(extracted_mappings);
+
+    profile
+        .paths
+        .iter()
+        .enumerate()
+        .flat_map(|(path, bcbs)| {
+            bcbs.iter()
+                .flat_map(|&bcb| &spans_for_bcb[bcb])
+                .map(move |&span| PathMapping { path: path as u32, span })
+        })
+        .collect()
+}
+
+/// Under `-Zcoverage-options=switch-arms`, marks each counted switch's block,
+/// so that the arm taken is counted when its terminator is lowered.
+fn inject_switch_arm_statements(mir_body: &mut mir::Body<'_>, switch_arms: &[SwitchArms]) {
+    for &SwitchArms { bb, first_slot, min, num_values, .. } in switch_arms {
+        inject_statement(
+            mir_body,
+            CoverageKind::SwitchArmCount { first_slot, min, num_values },
+            bb,
+        );
+    }
+}
+
+/// For each slot counted under `-Zcoverage-options=switch-arms`, lists the
+/// spans of the code mappings of the arm that the slot counts.
+fn make_switch_arm_mappings(
+    extracted_mappings: &ExtractedMappings,
+    switch_arms: &[SwitchArms],
+) -> Vec<SwitchArmMapping> {
+    if switch_arms.is_empty() {
+        return vec![];
+    }
+    let spans_for_bcb = code_spans_for_bcbs(extracted_mappings);
+
+    switch_arms
+        .iter()
+        .flat_map(|switch| (switch.first_slot..).zip(&switch.arm_bcbs))
+        .flat_map(|(slot, &bcb)| {
+            bcb.into_iter()
+                .flat_map(|bcb| &spans_for_bcb[bcb])
+                .map(move |&span| SwitchArmMapping { slot, span })
+        })
+        .collect()
+}
+
 /// Given two basic blocks that have a control-flow edge between them, creates
 /// and returns a new block that sits between those blocks.
 fn inject_edge_counter_basic_block(
@@ -349,7 +1057,9 @@ fn inject_edge_counter_basic_block(
             source_info: SourceInfo::outermost(span),
             kind: TerminatorKind::Goto { target: to_bb },
         }),
-        is_cleanup: false,
+        // Under `-Zcoverage-options=unwind-paths`, the edge might unwind into
+        // a cleanup block, in which case the new block is a cleanup block too.
+        is_cleanup: mir_body[to_bb].is_cleanup,
     });
     let edge_ref = mir_body[from_bb]
         .terminator_mut()
@@ -362,8 +1072,13 @@ fn inject_edge_counter_basic_block(
 
 fn inject_statement(mir_body: &mut mir::Body<'_>, counter_kind: CoverageKind, bb: BasicBlock) {
     debug!("  injecting statement {counter_kind:?} for {bb:?}");
+    let mut source_info = mir_body[bb].terminator().source_info;
+    // When instrumenting after inlining, the terminator might belong to an
+    // inlined callee, but the counter belongs to the function being instrumented.
+    if source_info.scope.inlined_instance(&mir_body.source_scopes).is_some() {
+        source_info = SourceInfo::outermost(source_info.span);
+    }
     let data = &mut mir_body[bb];
-    let source_info = data.terminator().source_info;
     let statement = Statement { source_info, kind: StatementKind::Coverage(counter_kind) };
     data.statements.insert(0, statement);
 }
@@ -377,9 +1092,24 @@ struct ExtractedHirInfo {
     /// Must have the same context and filename as the body span.
     fn_sig_span_extended: Option<Span>,
     body_span: Span,
+    /// True if the function was generated by `#[derive(..)]`, under
+    /// `-Zcoverage-options=instrument-derives`. Its `body_span` is then the
+    /// definition of the type that it was derived for, and the derive's own
+    /// spans are mapped to the positions in that definition that they carry.
+    is_derived: bool,
     /// "Holes" are regions within the body span that should not be included in
     /// coverage spans for this function (e.g. closures and nested items).
     hole_spans: Vec<Span>,
+    /// The holes that are closures or nested items excluded by `#[coverage(off)]`,
+    /// under `-Zcoverage-options=skipped-regions`, and empty otherwise.
+    skipped_spans: Vec<Span>,
+    /// Spans of the `return`, `break`, `continue` and `become` expressions in
+    /// the body, under `-Zcoverage-options=gap-regions`, and empty otherwise.
+    terminator_spans: Vec<Span>,
+    /// Spans of the statements and block tail expressions in the body, under
+    /// `-Zcoverage-options=recover-spans`, and empty otherwise. Any of them
+    /// that no MIR span overlaps will still be given a covspan.
+    must_cover_spans: Vec<Span>,
 }
 
 fn extract_hir_info<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> ExtractedHirInfo {
@@ -410,6 +1140,16 @@ fn extract_hir_info<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> ExtractedHir
         body_span = body_span.find_ancestor_in_same_ctxt(fn_decl_span).unwrap_or(body_span);
     }
 
+    let derived_item_span = if tcx.sess.coverage_instrument_derives() {
+        derived_item_span(tcx, def_id, body_span)
+    } else {
+        None
+    };
+    let is_derived = derived_item_span.is_some();
+    if let Some(derived_item_span) = derived_item_span {
+        body_span = derived_item_span;
+    }
+
     // The actual signature span is only used if it has the same context and
     // filename as the body, and precedes the body.
     let fn_sig_span_extended = maybe_fn_sig
@@ -427,34 +1167,119 @@ fn extract_hir_info<'tcx>(tcx: TyCtxt<'tcx>, def_id: LocalDefId) -> ExtractedHir
 
     let function_source_hash = hash_mir_source(tcx, hir_body);
 
-    let hole_spans = extract_hole_spans_from_hir(tcx, body_span, hir_body);
+    let (hole_spans, skipped_spans) = extract_hole_spans_from_hir(tcx, body_span, hir_body);
+    let terminator_spans = if tcx.sess.coverage_gap_regions() {
+        extract_terminator_spans_from_hir(tcx, body_span, hir_body)
+    } else {
+        vec![]
+    };
+    let must_cover_spans = if tcx.sess.coverage_recover_spans() {
+        extract_must_cover_spans_from_hir(tcx, body_span, hir_body)
+    } else {
+        vec![]
+    };
 
     ExtractedHirInfo {
         function_source_hash,
         is_async_fn,
         fn_sig_span_extended,
         body_span,
+        is_derived,
         hole_spans,
+        skipped_spans,
+        terminator_spans,
+        must_cover_spans,
     }
 }
 
+/// Under `-Zcoverage-options=instrument-derives`, if the function is a method
+/// of an impl generated by `#[derive(..)]`, returns the span of the definition
+/// of the type that the impl was derived for. The span is extended back to the
+/// path in the `#[derive(..)]` attribute, which is the call site of the derive.
+fn derived_item_span(tcx: TyCtxt<'_>, def_id: LocalDefId, body_span: Span) -> Option<Span> {
+    let impl_of = tcx.impl_of_method(def_id.to_def_id())?;
+    if !tcx.is_automatically_derived(impl_of) {
+        return None;
+    }
+    let adt_def_id = tcx.type_of(impl_of).instantiate_identity().ty_adt_def()?.did().as_local()?;
+    let adt_span = tcx.hir().span(tcx.local_def_id_to_hir_id(adt_def_id));
+
+    let expn_data = body_span.ctxt().outer_expn_data();
+    let derive_span = match expn_data.kind {
+        ExpnKind::Macro(MacroKind::Derive, _)
+            if expn_data.call_site.eq_ctxt(adt_span)
+                && expn_data.call_site.lo() <= adt_span.lo() =>
+        {
+            expn_data.call_site.to(adt_span)
+        }
+        _ => adt_span,
+    };
+    Some(derive_span)
+}
+
 fn hash_mir_source<'tcx>(tcx: TyCtxt<'tcx>, hir_body: &'tcx hir::Body<'tcx>) -> u64 {
     // FIXME(cjgillot) Stop hashing HIR manually here.
     let owner = hir_body.id().hir_id.owner;
     tcx.hir_owner_nodes(owner).opt_hash_including_bodies.unwrap().to_smaller_hash().as_u64()
 }
 
+/// Under `-Zcoverage-options=stable-ids`, returns the key that physical counters
+/// are numbered by. It only depends on the shape of the coverage graph, so an
+/// unchanged function keeps the same counter IDs, however its counters were
+/// chosen. Dominator ranks are unique, so nodes and edges never tie: each node
+/// comes before the edges into it, which are ordered by their source.
+fn stable_site_key(graph: &CoverageGraph, site: Site) -> (u32, Option<u32>) {
+    match site {
+        Site::Node { bcb } => (graph.dominator_rank(bcb), None),
+        Site::Edge { edge } => (
+            graph.dominator_rank(graph.edge_target(edge)),
+            Some(graph.dominator_rank(graph.edge_source(edge))),
+        ),
+    }
+}
+
+/// Under `-Zcoverage-options=stable-ids`, computes a function hash from the
+/// function's def-path and the structure of its coverage graph and counters.
+///
+/// Unlike the HIR-based hash, this doesn't change when the function's spans
+/// move, or when other parts of the same item change. As long as a function's
+/// def-path and control flow are unchanged, its hash and counter layout will
+/// also be unchanged, so profiles from different builds can still be merged.
+fn hash_coverage_structure(
+    tcx: TyCtxt<'_>,
+    def_id: DefId,
+    graph: &CoverageGraph,
+    coverage_counters: &CoverageCounters,
+) -> u64 {
+    let mut hasher = StableHasher::new();
+    tcx.def_path_hash(def_id).hash(&mut hasher);
+
+    graph.num_nodes().hash(&mut hasher);
+    for (_, successors) in graph.successors.iter_enumerated() {
+        successors.hash(&mut hasher);
+    }
+    for (id, site) in coverage_counters.counter_increment_sites() {
+        (id, site).hash(&mut hasher);
+    }
+    coverage_counters.num_expressions().hash(&mut hasher);
+
+    hasher.finish::<Hash64>().as_u64()
+}
+
+/// Returns the spans of the holes in the body, and (under
+/// `-Zcoverage-options=skipped-regions`) the spans of the holes whose closure
+/// or item is excluded by `#[coverage(off)]`.
 fn extract_hole_spans_from_hir<'tcx>(
     tcx: TyCtxt<'tcx>,
     body_span: Span, // Usually `hir_body.value.span`, but not always
     hir_body: &hir::Body<'tcx>,
-) -> Vec<Span> {
+) -> (Vec<Span>, Vec<Span>) {
     struct HolesVisitor<'hir, F> {
         hir: Map<'hir>,
         visit_hole_span: F,
     }
 
-    impl<'hir, F: FnMut(Span)> Visitor<'hir> for HolesVisitor<'hir, F> {
+    impl<'hir, F: FnMut(Span, Option<LocalDefId>)> Visitor<'hir> for HolesVisitor<'hir, F> {
         /// - We need `NestedFilter::INTRA = true` so that `visit_item` will be called.
         /// - Bodies of nested items don't actually get visited, because of the
         ///   `visit_item` override.
@@ -467,7 +1292,7 @@ fn nested_visit_map(&mut self) -> Self::Map {
         }
 
         fn visit_item(&mut self, item: &'hir hir::Item<'hir>) {
-            (self.visit_hole_span)(item.span);
+            (self.visit_hole_span)(item.span, Some(item.owner_id.def_id));
             // Having visited this item, we don't care about its children,
             // so don't call `walk_item`.
         }
@@ -476,8 +1301,13 @@ fn visit_item(&mut self, item: &'hir hir::Item<'hir>) {
         // visitors, so that we have direct access to the expression span.
         fn visit_expr(&mut self, expr: &'hir hir::Expr<'hir>) {
             match expr.kind {
-                hir::ExprKind::Closure(_) | hir::ExprKind::ConstBlock(_) => {
-                    (self.visit_hole_span)(expr.span);
+                hir::ExprKind::Closure(&hir::Closure { def_id, .. }) => {
+                    (self.visit_hole_span)(expr.span, Some(def_id));
+                    // Having visited this expression, we don't care about its
+                    // children, so don't call `walk_expr`.
+                }
+                hir::ExprKind::ConstBlock(_) => {
+                    (self.visit_hole_span)(expr.span, None);
                     // Having visited this expression, we don't care about its
                     // children, so don't call `walk_expr`.
                 }
@@ -489,16 +1319,128 @@ fn visit_expr(&mut self, expr: &'hir hir::Expr<'hir>) {
     }
 
     let mut hole_spans = vec![];
+    let mut skipped_spans = vec![];
+    let with_skipped = tcx.sess.coverage_skipped_regions();
     let mut visitor = HolesVisitor {
         hir: tcx.hir(),
-        visit_hole_span: |hole_span| {
+        visit_hole_span: |hole_span, def_id: Option<LocalDefId>| {
             // Discard any holes that aren't directly visible within the body span.
             if body_span.contains(hole_span) && body_span.eq_ctxt(hole_span) {
                 hole_spans.push(hole_span);
+                if with_skipped && def_id.is_some_and(|def_id| !tcx.coverage_attr_on(def_id)) {
+                    skipped_spans.push(hole_span);
+                }
             }
         },
     };
 
     visitor.visit_body(hir_body);
-    hole_spans
+    (hole_spans, skipped_spans)
+}
+
+/// Under `-Zcoverage-options=gap-regions`, finds the expressions that leave
+/// their block early (`return`, `break`, `continue` and `become`), so that the
+/// code between them and the next region can be given a gap region. Nested
+/// items and closures are skipped, because they are separate functions.
+fn extract_terminator_spans_from_hir<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body_span: Span,
+    hir_body: &hir::Body<'tcx>,
+) -> Vec<Span> {
+    struct TerminatorsVisitor<'hir> {
+        hir: Map<'hir>,
+        body_span: Span,
+        terminator_spans: Vec<Span>,
+    }
+
+    impl<'hir> Visitor<'hir> for TerminatorsVisitor<'hir> {
+        type NestedFilter = nested_filter::All;
+
+        fn nested_visit_map(&mut self) -> Self::Map {
+            self.hir
+        }
+
+        fn visit_item(&mut self, _item: &'hir hir::Item<'hir>) {}
+
+        fn visit_expr(&mut self, expr: &'hir hir::Expr<'hir>) {
+            match expr.kind {
+                hir::ExprKind::Closure(_) | hir::ExprKind::ConstBlock(_) => return,
+                // Desugarings (e.g. of `?`) have spans in a different context,
+                // and aren't written by the user, so they are discarded here.
+                hir::ExprKind::Ret(_)
+                | hir::ExprKind::Break(..)
+                | hir::ExprKind::Continue(_)
+                | hir::ExprKind::Become(_)
+                    if self.body_span.contains(expr.span) && self.body_span.eq_ctxt(expr.span) =>
+                {
+                    self.terminator_spans.push(expr.span);
+                }
+                _ => {}
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    let mut visitor = TerminatorsVisitor { hir: tcx.hir(), body_span, terminator_spans: vec![] };
+    visitor.visit_body(hir_body);
+    visitor.terminator_spans
+}
+
+/// Under `-Zcoverage-options=recover-spans`, finds the statements and block
+/// tail expressions in the body. Some of them (e.g. a trailing `()`, or an
+/// implicit return of a place) are lowered without any MIR statement carrying
+/// their span, so MIR alone would leave them out of the coverage mappings.
+fn extract_must_cover_spans_from_hir<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body_span: Span,
+    hir_body: &hir::Body<'tcx>,
+) -> Vec<Span> {
+    struct MustCoverVisitor<'hir> {
+        hir: Map<'hir>,
+        body_span: Span,
+        must_cover_spans: Vec<Span>,
+    }
+
+    impl<'hir> MustCoverVisitor<'hir> {
+        fn push_span(&mut self, span: Span) {
+            if self.body_span.contains(span) && self.body_span.eq_ctxt(span) {
+                self.must_cover_spans.push(span);
+            }
+        }
+    }
+
+    impl<'hir> Visitor<'hir> for MustCoverVisitor<'hir> {
+        type NestedFilter = nested_filter::All;
+
+        fn nested_visit_map(&mut self) -> Self::Map {
+            self.hir
+        }
+
+        fn visit_item(&mut self, _item: &'hir hir::Item<'hir>) {}
+
+        fn visit_block(&mut self, block: &'hir hir::Block<'hir>) {
+            if let Some(tail_expr) = block.expr {
+                self.push_span(tail_expr.span);
+            }
+            walk_block(self, block);
+        }
+
+        fn visit_stmt(&mut self, stmt: &'hir hir::Stmt<'hir>) {
+            if !matches!(stmt.kind, hir::StmtKind::Item(_)) {
+                self.push_span(stmt.span);
+            }
+            walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &'hir hir::Expr<'hir>) {
+            match expr.kind {
+                hir::ExprKind::Closure(_) | hir::ExprKind::ConstBlock(_) => {}
+                _ => walk_expr(self, expr),
+            }
+        }
+    }
+
+    let mut visitor = MustCoverVisitor { hir: tcx.hir(), body_span, must_cover_spans: vec![] };
+    visitor.visit_body(hir_body);
+    visitor.must_cover_spans
 }
@@ -0,0 +1,207 @@
+//! Ball-Larus path numbering over the coverage graph, for
+//! `-Zcoverage-options=paths`.
+//!
+//! Removing the back edges of the coverage graph leaves a DAG. Each path
+//! through that DAG from the function's entry (or from a loop header, after
+//! taking a back edge) to a return (or to the source of a back edge) is one
+//! acyclic path. Ball and Larus's numbering gives every DAG edge an increment,
+//! such that summing the increments along a path yields a number that is
+//! unique to that path, and is in `0..num_paths`.
+//!
+//! At runtime, a per-function path register accumulates the increments of the
+//! edges that were taken. Each path end counts the path whose number is in the
+//! register, and then resets the register for the next path.
+//!
+//! See "Efficient Path Profiling", Ball and Larus, MICRO-29 (1996).
+
+use rustc_data_structures::graph::{DirectedGraph, StartNode};
+use rustc_index::IndexVec;
+use rustc_index::bit_set::DenseBitSet;
+use tracing::debug;
+
+use crate::coverage::graph::{BasicCoverageBlock, CoverageGraph};
+
+/// Functions with more acyclic paths than this are not path-profiled, because
+/// the number of paths grows exponentially with the number of branches.
+const MAX_PATHS: u64 = 1 << 12;
+
+/// Where to inject a path-profiling statement.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum PathSite {
+    Node { bcb: BasicCoverageBlock },
+    Edge { from_bcb: BasicCoverageBlock, to_bcb: BasicCoverageBlock },
+}
+
+/// The end of an acyclic path: either a node without successors, or a back
+/// edge.
+#[derive(Debug)]
+pub(super) struct PathEnd {
+    pub(super) site: PathSite,
+    /// Increment of the virtual edge from the site to the exit of the DAG,
+    /// which completes the path number.
+    pub(super) add: u32,
+    /// Value that the path register starts from after this end. For a back
+    /// edge, this is the increment of the virtual edge from the entry of the
+    /// DAG to the loop header.
+    pub(super) reset: u32,
+}
+
+#[derive(Debug)]
+pub(super) struct PathProfile {
+    pub(super) num_paths: u32,
+    /// Edges of the DAG that add a nonzero increment to the path register.
+    pub(super) increments: Vec<(BasicCoverageBlock, BasicCoverageBlock, u32)>,
+    pub(super) ends: Vec<PathEnd>,
+    /// The nodes along each path, indexed by path number.
+    pub(super) paths: Vec<Vec<BasicCoverageBlock>>,
+}
+
+/// An edge of the DAG. A target of `None` is the virtual exit node.
+#[derive(Clone, Copy, Debug)]
+struct DagEdge {
+    target: Option<BasicCoverageBlock>,
+    /// For an edge to the exit that replaces a back edge, the loop header.
+    back_edge_header: Option<BasicCoverageBlock>,
+    increment: u64,
+}
+
+/// Numbers the acyclic paths of the coverage graph, or returns `None` if the
+/// function has too many paths to profile.
+pub(super) fn number_paths(graph: &CoverageGraph) -> Option<PathProfile> {
+    let (back_edges, postorder) = find_back_edges(graph);
+
+    // Build the DAG, replacing each back edge `u -> h` with an edge from `u`
+    // to the exit, and (once per loop header) an edge from the entry to `h`.
+    let mut dag_edges = IndexVec::from_elem_n(vec![], graph.num_nodes());
+    for &bcb in &postorder {
+        let edges = &mut dag_edges[bcb];
+        for &succ in &graph.successors[bcb] {
+            if back_edges.contains(&(bcb, succ)) {
+                edges.push(DagEdge { target: None, back_edge_header: Some(succ), increment: 0 });
+            } else {
+                edges.push(DagEdge { target: Some(succ), back_edge_header: None, increment: 0 });
+            }
+        }
+        if edges.is_empty() {
+            edges.push(DagEdge { target: None, back_edge_header: None, increment: 0 });
+        }
+    }
+    let mut entry_edges =
+        vec![DagEdge { target: Some(graph.start_node()), back_edge_header: None, increment: 0 }];
+    for &(_, header) in &back_edges {
+        if !entry_edges[1..].iter().any(|edge| edge.target == Some(header)) {
+            entry_edges.push(DagEdge {
+                target: Some(header),
+                back_edge_header: None,
+                increment: 0,
+            });
+        }
+    }
+
+    // Visit nodes in postorder, so that the targets of each node's DAG edges
+    // have already been visited (back edges having been removed). Each edge's
+    // increment is the number of paths through the edges before it.
+    let mut num_paths = IndexVec::from_elem_n(0u64, graph.num_nodes());
+    let assign_increments =
+        |edges: &mut [DagEdge], num_paths: &IndexVec<BasicCoverageBlock, u64>| {
+            let mut total = 0u64;
+            for edge in edges {
+                edge.increment = total;
+                total += edge.target.map_or(1, |target| num_paths[target]);
+                if total > MAX_PATHS {
+                    return None;
+                }
+            }
+            Some(total)
+        };
+    for &bcb in &postorder {
+        num_paths[bcb] = assign_increments(&mut dag_edges[bcb], &num_paths)?;
+    }
+    let total_paths = assign_increments(&mut entry_edges, &num_paths)?;
+    debug!(?total_paths, ?back_edges, "numbered acyclic paths");
+
+    // Look past the first entry edge, in case the start node is itself a loop
+    // header, so that paths after a back edge are numbered separately.
+    let entry_increment = |header| {
+        entry_edges[1..].iter().find(|edge| edge.target == Some(header)).unwrap().increment as u32
+    };
+    let mut increments = vec![];
+    let mut ends = vec![];
+    for (bcb, edges) in dag_edges.iter_enumerated() {
+        for edge in edges {
+            match (edge.target, edge.back_edge_header) {
+                (Some(target), _) => {
+                    if edge.increment != 0 {
+                        increments.push((bcb, target, edge.increment as u32));
+                    }
+                }
+                (None, Some(header)) => ends.push(PathEnd {
+                    site: PathSite::Edge { from_bcb: bcb, to_bcb: header },
+                    add: edge.increment as u32,
+                    reset: entry_increment(header),
+                }),
+                (None, None) => ends.push(PathEnd {
+                    site: PathSite::Node { bcb },
+                    add: edge.increment as u32,
+                    reset: 0,
+                }),
+            }
+        }
+    }
+
+    // Recover the nodes along each path, by following the edge with the
+    // largest increment that doesn't exceed what is left of the path number.
+    let paths = (0..total_paths)
+        .map(|path| {
+            let mut nodes = vec![];
+            let mut remaining = path;
+            let mut edges = &entry_edges[..];
+            loop {
+                let edge = edges.iter().rev().find(|edge| edge.increment <= remaining).unwrap();
+                remaining -= edge.increment;
+                let Some(target) = edge.target else { break };
+                nodes.push(target);
+                edges = &dag_edges[target];
+            }
+            nodes
+        })
+        .collect();
+
+    Some(PathProfile { num_paths: total_paths as u32, increments, ends, paths })
+}
+
+/// Finds the edges that close a cycle during a depth-first search from the
+/// start node, and returns them along with the postorder of that search.
+///
+/// For the reducible control flow that Rust code produces, these are exactly
+/// the edges to a loop header from inside its loop.
+fn find_back_edges(
+    graph: &CoverageGraph,
+) -> (Vec<(BasicCoverageBlock, BasicCoverageBlock)>, Vec<BasicCoverageBlock>) {
+    let mut back_edges = vec![];
+    let mut postorder = vec![];
+    let mut visited = DenseBitSet::new_empty(graph.num_nodes());
+    let mut on_stack = DenseBitSet::new_empty(graph.num_nodes());
+
+    let start = graph.start_node();
+    let mut stack = vec![(start, 0)];
+    visited.insert(start);
+    on_stack.insert(start);
+    while let Some(&mut (bcb, ref mut next_succ)) = stack.last_mut() {
+        let Some(&succ) = graph.successors[bcb].get(*next_succ) else {
+            stack.pop();
+            on_stack.remove(bcb);
+            postorder.push(bcb);
+            continue;
+        };
+        *next_succ += 1;
+        if on_stack.contains(succ) {
+            back_edges.push((bcb, succ));
+        } else if visited.insert(succ) {
+            on_stack.insert(succ);
+            stack.push((succ, 0));
+        }
+    }
+
+    (back_edges, postorder)
+}
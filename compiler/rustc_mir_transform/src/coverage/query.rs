@@ -1,24 +1,32 @@
+use std::{fs, iter};
+
 use rustc_data_structures::captures::Captures;
+use rustc_data_structures::unord::UnordSet;
 use rustc_index::bit_set::DenseBitSet;
 use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::coverage::{
     CounterId, CovTerm, CoverageIdsInfo, CoverageKind, Expression, ExpressionId,
     FunctionCoverageInfo, MappingKind, Op,
 };
-use rustc_middle::mir::{Body, Statement, StatementKind};
+use rustc_middle::mir::{BasicBlock, Body, Statement, StatementKind, traversal};
 use rustc_middle::query::TyCtxtAt;
-use rustc_middle::ty::{self, TyCtxt};
+use rustc_middle::ty::{self, TyCtxt, TypeVisitableExt};
 use rustc_middle::util::Providers;
-use rustc_span::def_id::LocalDefId;
+use rustc_span::def_id::{LOCAL_CRATE, LocalDefId};
 use rustc_span::sym;
 use tracing::trace;
 
+use crate::errors;
+
 /// Registers query/hook implementations related to coverage.
 pub(crate) fn provide(providers: &mut Providers) {
     providers.hooks.is_eligible_for_coverage =
         |TyCtxtAt { tcx, .. }, def_id| is_eligible_for_coverage(tcx, def_id);
+    // Overridden by codegen backends that can read coverage profiles.
+    providers.hooks.coverage_profile_counters = |_, _, _| None;
     providers.queries.coverage_attr_on = coverage_attr_on;
     providers.queries.coverage_ids_info = coverage_ids_info;
+    providers.queries.coverage_baseline_hashes = coverage_baseline_hashes;
 }
 
 /// Hook implementation for [`TyCtxt::is_eligible_for_coverage`].
@@ -35,29 +43,58 @@ fn is_eligible_for_coverage(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
         return false;
     }
 
+    // Check `#[coverage(off)]` before anything that has to compute per-function
+    // information (e.g. `codegen_fn_attrs`), because `coverage_attr_on` is cached
+    // per enclosing item, so large excluded modules are skipped almost for free.
+    if !tcx.coverage_attr_on(def_id) {
+        trace!("InstrumentCoverage skipped for {def_id:?} (`#[coverage(off)]`)");
+        return false;
+    }
+
     // Don't instrument functions with `#[automatically_derived]` on their
     // enclosing impl block, on the assumption that most users won't care about
-    // coverage for derived impls.
-    if let Some(impl_of) = tcx.impl_of_method(def_id.to_def_id())
+    // coverage for derived impls. Authors of derive macros can opt in with
+    // `-Zcoverage-options=instrument-derives`.
+    if !tcx.sess.coverage_instrument_derives()
+        && let Some(impl_of) = tcx.impl_of_method(def_id.to_def_id())
         && tcx.is_automatically_derived(impl_of)
     {
         trace!("InstrumentCoverage skipped for {def_id:?} (automatically derived)");
         return false;
     }
 
-    if tcx.codegen_fn_attrs(def_id).flags.contains(CodegenFnAttrFlags::NAKED) {
-        trace!("InstrumentCoverage skipped for {def_id:?} (`#[naked]`)");
+    // Bodies in `compiler_builtins` and fallback bodies of intrinsics are
+    // never meaningful in coverage reports, so never instrument them.
+    if tcx.is_compiler_builtins(LOCAL_CRATE) {
+        trace!("InstrumentCoverage skipped for {def_id:?} (`#![compiler_builtins]`)");
+        return false;
+    }
+    if tcx.intrinsic(def_id).is_some() {
+        trace!("InstrumentCoverage skipped for {def_id:?} (intrinsic fallback body)");
+        return false;
+    }
+    if is_coverage_excluded(tcx, def_id) {
+        trace!("InstrumentCoverage skipped for {def_id:?} (`#[rustc_coverage_exclude]`)");
         return false;
     }
 
-    if !tcx.coverage_attr_on(def_id) {
-        trace!("InstrumentCoverage skipped for {def_id:?} (`#[coverage(off)]`)");
+    if tcx.codegen_fn_attrs(def_id).flags.contains(CodegenFnAttrFlags::NAKED) {
+        trace!("InstrumentCoverage skipped for {def_id:?} (`#[naked]`)");
         return false;
     }
 
     true
 }
 
+/// Returns `true` if the given def, or any of its enclosing defs (up to and
+/// including the crate root), has the internal `#[rustc_coverage_exclude]`
+/// attribute. Unlike `#[coverage(off)]`, this cannot be overridden by an inner
+/// `#[coverage(on)]`.
+fn is_coverage_excluded(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
+    iter::successors(Some(def_id), |&def_id| tcx.opt_local_parent(def_id))
+        .any(|def_id| tcx.has_attr(def_id, sym::rustc_coverage_exclude))
+}
+
 /// Query implementation for `coverage_attr_on`.
 fn coverage_attr_on(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
     // Check for annotations directly on this def.
@@ -83,12 +120,50 @@ fn coverage_attr_on(tcx: TyCtxt<'_>, def_id: LocalDefId) -> bool {
     }
 }
 
+/// Query implementation for `coverage_baseline_hashes`.
+///
+/// The baseline lists one function hash per line, in hexadecimal with an
+/// optional `0x` prefix. Blank lines and lines starting with `#` are ignored,
+/// so that a baseline can be annotated by hand.
+fn coverage_baseline_hashes(tcx: TyCtxt<'_>, (): ()) -> UnordSet<u64> {
+    let Some(path) = tcx.sess.opts.unstable_opts.coverage_baseline.as_deref() else {
+        return UnordSet::default();
+    };
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            tcx.dcx().emit_err(errors::CoverageBaselineReadFailed { path, err });
+            return UnordSet::default();
+        }
+    };
+
+    let mut hashes = UnordSet::default();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let digits = line.strip_prefix("0x").unwrap_or(line);
+        match u64::from_str_radix(digits, 16) {
+            Ok(hash) => {
+                hashes.insert(hash);
+            }
+            Err(_) => {
+                tcx.dcx().emit_err(errors::CoverageBaselineInvalidLine {
+                    path,
+                    line_number: i + 1,
+                    line,
+                });
+            }
+        }
+    }
+    hashes
+}
+
 /// Query implementation for `coverage_ids_info`.
-fn coverage_ids_info<'tcx>(
-    tcx: TyCtxt<'tcx>,
-    instance_def: ty::InstanceKind<'tcx>,
-) -> CoverageIdsInfo {
-    let mir_body = tcx.instance_mir(instance_def);
+fn coverage_ids_info<'tcx>(tcx: TyCtxt<'tcx>, instance: ty::Instance<'tcx>) -> CoverageIdsInfo {
+    let mir_body = tcx.instance_mir(instance.def);
 
     let Some(fn_cov_info) = mir_body.function_coverage_info.as_deref() else {
         return CoverageIdsInfo {
@@ -103,7 +178,10 @@ fn coverage_ids_info<'tcx>(
     // For each expression ID that is directly used by one or more mappings,
     // mark it as not-yet-seen. This indicates that we expect to see a
     // corresponding `ExpressionUsed` statement during MIR traversal.
-    for mapping in fn_cov_info.mappings.iter() {
+    let expansion_mappings = (fn_cov_info.expansion_mappings.iter())
+        .chain(&fn_cov_info.macro_definition_mappings)
+        .flat_map(|expansion| &expansion.mappings);
+    for mapping in fn_cov_info.mappings.iter().chain(expansion_mappings) {
         // Currently we only worry about ordinary code mappings.
         // For branch and MC/DC mappings, expressions might not correspond
         // to any particular point in the control-flow graph.
@@ -114,7 +192,14 @@ fn coverage_ids_info<'tcx>(
         }
     }
 
-    for kind in all_coverage_in_mir_body(mir_body) {
+    // Codegen skips blocks that are unreachable once constants have been
+    // monomorphized, so any counters that only appear in such blocks will
+    // never be incremented. Unused functions are represented by instances
+    // that still have generic parameters, so there's nothing to prune there.
+    let reachable_blocks = (!instance.args.has_param())
+        .then(|| traversal::mono_reachable_as_bitset(mir_body, tcx, instance));
+
+    for kind in all_coverage_in_mir_body(mir_body, reachable_blocks.as_ref()) {
         match *kind {
             CoverageKind::CounterIncrement { id } => {
                 counters_seen.insert(id);
@@ -134,13 +219,16 @@ fn coverage_ids_info<'tcx>(
 
 fn all_coverage_in_mir_body<'a, 'tcx>(
     body: &'a Body<'tcx>,
+    reachable_blocks: Option<&'a DenseBitSet<BasicBlock>>,
 ) -> impl Iterator<Item = &'a CoverageKind> + Captures<'tcx> {
-    body.basic_blocks.iter().flat_map(|bb_data| &bb_data.statements).filter_map(|statement| {
-        match statement.kind {
+    body.basic_blocks
+        .iter_enumerated()
+        .filter(move |&(bb, _)| reachable_blocks.is_none_or(|reachable| reachable.contains(bb)))
+        .flat_map(|(_, bb_data)| &bb_data.statements)
+        .filter_map(|statement| match statement.kind {
             StatementKind::Coverage(ref kind) if !is_inlined(body, statement) => Some(kind),
             _ => None,
-        }
-    })
+        })
 }
 
 fn is_inlined(body: &Body<'_>, statement: &Statement<'_>) -> bool {
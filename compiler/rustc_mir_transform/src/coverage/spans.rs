@@ -1,29 +1,74 @@
 use std::collections::VecDeque;
 
 use rustc_data_structures::captures::Captures;
-use rustc_data_structures::fx::FxHashSet;
+use rustc_data_structures::fx::{FxHashSet, FxIndexMap};
 use rustc_middle::mir;
+use rustc_span::source_map::SourceMap;
 use rustc_span::{DesugaringKind, ExpnKind, MacroKind, Span};
 use tracing::{debug, debug_span, instrument};
 
 use crate::coverage::graph::{BasicCoverageBlock, CoverageGraph};
 use crate::coverage::spans::from_mir::{
-    ExtractedCovspans, Hole, SpanFromMir, extract_covspans_from_mir,
+    ExpansionCovspan, ExtractedCovspans, Hole, SpanFromMir, extract_covspans_from_mir,
 };
 use crate::coverage::{ExtractedHirInfo, mappings};
 
 mod from_mir;
 
 pub(super) fn extract_refined_covspans(
+    source_map: &SourceMap,
     mir_body: &mir::Body<'_>,
     hir_info: &ExtractedHirInfo,
     graph: &CoverageGraph,
+    with_expansions: bool,
+    with_definitions: bool,
+    with_external_macros: bool,
+    raw_spans: bool,
     code_mappings: &mut impl Extend<mappings::CodeMapping>,
+    expansion_mappings: &mut impl Extend<mappings::ExpansionCodeMappings>,
+    definition_mappings: &mut impl Extend<mappings::ExpansionCodeMappings>,
 ) {
-    let ExtractedCovspans { mut covspans } = extract_covspans_from_mir(mir_body, hir_info, graph);
+    let ExtractedCovspans {
+        mut covspans,
+        expansion_covspans,
+        definition_covspans,
+        included_covspans,
+        external_macro_call_sites,
+    } = extract_covspans_from_mir(
+        source_map,
+        mir_body,
+        hir_info,
+        graph,
+        with_expansions,
+        with_definitions,
+        with_external_macros,
+    );
+    expansion_mappings.extend(refine_expansion_covspans(graph, expansion_covspans));
+    definition_mappings.extend(refine_expansion_covspans(graph, definition_covspans));
+
+    // Under `-Zcoverage-options=raw-spans`, emit one region per extracted span,
+    // exactly as it was unexpanded from MIR, for debugging the refinement below.
+    if raw_spans {
+        let raw_covspans =
+            covspans.into_iter().map(SpanFromMir::into_covspan).chain(included_covspans);
+        code_mappings
+            .extend(raw_covspans.map(|Covspan { span, bcb }| mappings::CodeMapping { span, bcb }));
+        return;
+    }
+
+    // Code included from another file can't overlap the body's own spans, so
+    // it is refined on its own and then emitted as ordinary code mappings.
+    let included_covspans = refine_covspans_without_holes(graph, included_covspans);
+    debug!(?included_covspans, "refined included spans");
+    code_mappings.extend(
+        included_covspans
+            .into_iter()
+            .map(|Covspan { span, bcb }| mappings::CodeMapping { span, bcb }),
+    );
 
     // First, perform the passes that need macro information.
     covspans.sort_by(|a, b| graph.cmp_in_dominator_order(a.bcb, b.bcb));
+    collapse_external_macro_spans(&mut covspans, &external_macro_call_sites);
     remove_unwanted_expansion_spans(&mut covspans);
     split_visible_macro_spans(&mut covspans);
 
@@ -37,6 +82,15 @@ pub(super) fn extract_refined_covspans(
     };
     covspans.sort_by(compare_covspans);
 
+    // Give a covspan to any statement or tail expression that HIR says must be
+    // covered, but that lowering left without a MIR span of its own.
+    if !hir_info.must_cover_spans.is_empty() {
+        let recovered_covspans = recover_uncovered_spans(graph, &covspans, hir_info);
+        debug!(?recovered_covspans);
+        covspans.extend(recovered_covspans);
+        covspans.sort_by(compare_covspans);
+    }
+
     // Among covspans with the same span, keep only one,
     // preferring the one with the most-dominated BCB.
     // (Ideally we should try to preserve _all_ non-dominating BCBs, but that
@@ -70,6 +124,115 @@ pub(super) fn extract_refined_covspans(
     }
 }
 
+/// Groups the spans found inside macro expansions by the invocation that they
+/// were expanded from, and refines each group separately, much like a bucket of
+/// body spans. Holes don't need to be carved out, because closures and nested
+/// items in a macro's expansion are part of the body that invokes it.
+///
+/// Keeping invocations apart means that a macro invoked twice yields the same
+/// definition spans twice, once per invocation, and `llvm-cov` adds up the
+/// counts of such identical regions.
+fn refine_expansion_covspans(
+    graph: &CoverageGraph,
+    expansion_covspans: Vec<ExpansionCovspan>,
+) -> Vec<mappings::ExpansionCodeMappings> {
+    let mut covspans_by_call_site = FxIndexMap::<Span, Vec<Covspan>>::default();
+    for ExpansionCovspan { call_site, covspan } in expansion_covspans {
+        covspans_by_call_site.entry(call_site).or_default().push(covspan);
+    }
+
+    covspans_by_call_site
+        .into_iter()
+        .map(|(call_site, covspans)| {
+            let covspans = refine_covspans_without_holes(graph, covspans);
+            debug!(?call_site, ?covspans, "refined expansion spans");
+
+            let code_mappings = covspans
+                .into_iter()
+                .map(|Covspan { span, bcb }| mappings::CodeMapping { span, bcb })
+                .collect();
+            mappings::ExpansionCodeMappings { call_site, code_mappings }
+        })
+        .collect()
+}
+
+/// Returns a covspan for each of the function's must-cover spans that no
+/// existing covspan overlaps. Each one is attributed to the nearest common
+/// dominator of the covspans just before and just after it, which executes
+/// whenever the surrounding code does, so it at least inherits a count.
+///
+/// The covspans must be sorted by span.
+fn recover_uncovered_spans(
+    graph: &CoverageGraph,
+    sorted_covspans: &[Covspan],
+    hir_info: &ExtractedHirInfo,
+) -> Vec<Covspan> {
+    hir_info
+        .must_cover_spans
+        .iter()
+        .filter_map(|&span| {
+            // Only the covspans that start before this span ends can overlap it.
+            let end = sorted_covspans.partition_point(|covspan| covspan.span.lo() < span.hi());
+            let (earlier, later) = sorted_covspans.split_at(end);
+            if earlier.iter().any(|covspan| covspan.span.overlaps(span)) {
+                return None;
+            }
+
+            let prev = earlier.iter().max_by_key(|covspan| covspan.span.hi());
+            let bcb = match (prev, later.first()) {
+                (Some(prev), Some(next)) => graph.nearest_common_dominator(prev.bcb, next.bcb),
+                (Some(only), None) | (None, Some(only)) => only.bcb,
+                (None, None) => return None,
+            };
+            Some(Covspan { span, bcb })
+        })
+        .collect()
+}
+
+/// Refines a group of spans that has no holes to carve out, in the same way
+/// as a single bucket of body spans.
+fn refine_covspans_without_holes(
+    graph: &CoverageGraph,
+    mut covspans: Vec<Covspan>,
+) -> Vec<Covspan> {
+    covspans.sort_by(|a, b| {
+        compare_spans(a.span, b.span)
+            .then_with(|| graph.cmp_in_dominator_order(a.bcb, b.bcb).reverse())
+    });
+    covspans.dedup_by(|b, a| a.span.source_equal(b.span));
+    let mut covspans = remove_unwanted_overlapping_spans(covspans);
+    covspans.dedup_by(|b, a| a.merge_if_eligible(b));
+    covspans
+}
+
+/// Under `-Zcoverage-options=collapse-external-macros`, widens every covspan
+/// inside the invocation of a bang-macro from another crate (e.g. `format!`,
+/// `panic!` or `assert_eq!`) to the whole invocation, and marks it as expanded
+/// from that macro. The macro's arguments then no longer get regions of their
+/// own, so the invocation is left with the single region that
+/// [`remove_unwanted_expansion_spans`] keeps, which has the count of the
+/// code that dominates the rest of the expansion.
+fn collapse_external_macro_spans(
+    covspans: &mut [SpanFromMir],
+    external_macro_call_sites: &[(Span, ExpnKind)],
+) {
+    if external_macro_call_sites.is_empty() {
+        return;
+    }
+
+    for covspan in covspans {
+        // With nested invocations, collapse into the outermost one.
+        let outermost = external_macro_call_sites
+            .iter()
+            .filter(|(call_site, _)| call_site.contains(covspan.span))
+            .max_by_key(|(call_site, _)| call_site.hi() - call_site.lo());
+        if let Some((call_site, expn_kind)) = outermost {
+            covspan.span = *call_site;
+            covspan.expn_kind = Some(expn_kind.clone());
+        }
+    }
+}
+
 /// Macros that expand into branches (e.g. `assert!`, `trace!`) tend to generate
 /// multiple condition/consequent blocks that have the span of the whole macro
 /// invocation, which is unhelpful. Keeping only the first such span seems to
@@ -1,39 +1,85 @@
 use rustc_middle::bug;
 use rustc_middle::mir::coverage::CoverageKind;
 use rustc_middle::mir::{
-    self, FakeReadCause, Statement, StatementKind, Terminator, TerminatorKind,
+    self, FakeReadCause, SourceInfo, Statement, StatementKind, Terminator, TerminatorKind,
 };
-use rustc_span::{ExpnKind, Span};
+use rustc_span::source_map::SourceMap;
+use rustc_span::{ExpnKind, MacroKind, Span};
 
 use crate::coverage::ExtractedHirInfo;
 use crate::coverage::graph::{
     BasicCoverageBlock, BasicCoverageBlockData, CoverageGraph, START_BCB,
 };
 use crate::coverage::spans::Covspan;
-use crate::coverage::unexpand::unexpand_into_body_span_with_expn_kind;
+use crate::coverage::unexpand::{unexpand_into_body_span_with_prev, unexpand_into_included_file};
 
 pub(crate) struct ExtractedCovspans {
     pub(crate) covspans: Vec<SpanFromMir>,
+    /// Spans inside the expansions of local macros invoked by the body, if
+    /// `-Zcoverage-options=expansions` is enabled.
+    pub(crate) expansion_covspans: Vec<ExpansionCovspan>,
+    /// Spans inside local macro definitions, at every level of expansion, if
+    /// `-Zcoverage-options=macro-definitions` is enabled.
+    pub(crate) definition_covspans: Vec<ExpansionCovspan>,
+    /// Spans of code that `include!` pulled into the body from another file.
+    /// They point into the included file rather than the body's own file.
+    pub(crate) included_covspans: Vec<Covspan>,
+    /// Call sites in the body of bang-macros defined in other crates, if
+    /// `-Zcoverage-options=collapse-external-macros` is enabled.
+    pub(crate) external_macro_call_sites: Vec<(Span, ExpnKind)>,
+}
+
+/// A span inside the code that a macro invocation expanded to, under
+/// `-Zcoverage-options=expansions` or `macro-definitions`.
+#[derive(Debug)]
+pub(crate) struct ExpansionCovspan {
+    /// The span of the macro invocation. For `expansions` this is always in
+    /// the body, but for `macro-definitions` it can also be inside the
+    /// definition of another macro.
+    pub(crate) call_site: Span,
+    pub(crate) covspan: Covspan,
 }
 
 /// Traverses the MIR body to produce an initial collection of coverage-relevant
 /// spans, each associated with a node in the coverage graph (BCB) and possibly
 /// other metadata.
 pub(crate) fn extract_covspans_from_mir(
+    source_map: &SourceMap,
     mir_body: &mir::Body<'_>,
     hir_info: &ExtractedHirInfo,
     graph: &CoverageGraph,
+    with_expansions: bool,
+    with_definitions: bool,
+    with_external_macros: bool,
 ) -> ExtractedCovspans {
-    let &ExtractedHirInfo { body_span, .. } = hir_info;
+    let &ExtractedHirInfo { body_span, is_derived, .. } = hir_info;
 
     let mut covspans = vec![];
+    let mut expansion_covspans = with_expansions.then(Vec::new);
+    let mut definition_covspans = with_definitions.then(Vec::new);
+    let mut included_covspans = vec![];
+    let mut external_macro_call_sites = with_external_macros.then(Vec::new);
 
     for (bcb, bcb_data) in graph.iter_enumerated() {
-        bcb_to_initial_coverage_spans(mir_body, body_span, bcb, bcb_data, &mut covspans);
+        bcb_to_initial_coverage_spans(
+            source_map,
+            mir_body,
+            body_span,
+            is_derived,
+            bcb,
+            bcb_data,
+            &mut covspans,
+            expansion_covspans.as_mut(),
+            definition_covspans.as_mut(),
+            &mut included_covspans,
+            external_macro_call_sites.as_mut(),
+        );
     }
 
     // Only add the signature span if we found at least one span in the body.
-    if !covspans.is_empty() {
+    // Code included from another file counts, so that the function still has
+    // a region in its own file for `llvm-cov` to anchor it to.
+    if !covspans.is_empty() || !included_covspans.is_empty() {
         // If there is no usable signature span, add a fake one (before refinement)
         // to avoid an ugly gap between the body start and the first real span.
         // FIXME: Find a more principled way to solve this problem.
@@ -41,7 +87,60 @@ pub(crate) fn extract_covspans_from_mir(
         covspans.push(SpanFromMir::for_fn_sig(fn_sig_span));
     }
 
-    ExtractedCovspans { covspans }
+    ExtractedCovspans {
+        covspans,
+        expansion_covspans: expansion_covspans.unwrap_or_default(),
+        definition_covspans: definition_covspans.unwrap_or_default(),
+        included_covspans,
+        external_macro_call_sites: external_macro_call_sites.unwrap_or_default(),
+    }
+}
+
+/// True if `span` was produced by the expansion of a bang-macro that is
+/// defined in the current crate, i.e. it is a span inside the definition.
+///
+/// Macros from other crates (e.g. the standard library) are skipped, because
+/// their definitions aren't part of the code being measured.
+fn is_in_local_macro_expansion(span: Span) -> bool {
+    let expn_data = span.ctxt().outer_expn_data();
+    matches!(expn_data.kind, ExpnKind::Macro(MacroKind::Bang, _))
+        && expn_data.macro_def_id.is_some_and(|def_id| def_id.is_local())
+        && !span.is_dummy()
+}
+
+/// True if `span` was produced by the expansion of a bang-macro that is
+/// defined in another crate (e.g. `format!` or `assert_eq!`).
+fn is_in_external_macro_expansion(span: Span) -> bool {
+    let expn_data = span.ctxt().outer_expn_data();
+    matches!(expn_data.kind, ExpnKind::Macro(MacroKind::Bang, _))
+        && expn_data.macro_def_id.is_some_and(|def_id| !def_id.is_local())
+}
+
+/// Walks outwards from `expn_span` to the body, through each level of macro
+/// expansion, and records the span at every level that is inside the
+/// definition of a local macro.
+///
+/// Walking by syntax context keeps this hygienic: tokens that were passed to a
+/// macro as arguments keep the context of the invocation, so they are
+/// attributed to the code that wrote them rather than to the macro's arms.
+fn push_macro_definition_covspans(
+    expn_span: Span,
+    body_span: Span,
+    bcb: BasicCoverageBlock,
+    definition_covspans: &mut Vec<ExpansionCovspan>,
+) {
+    let mut span = expn_span;
+    while !span.eq_ctxt(body_span) {
+        let call_site = span.ctxt().outer_expn_data().call_site;
+        if is_in_local_macro_expansion(span) {
+            let covspan = Covspan { span, bcb };
+            definition_covspans.push(ExpansionCovspan { call_site, covspan });
+        }
+        if call_site.is_dummy() {
+            break;
+        }
+        span = call_site;
+    }
 }
 
 // Generate a set of coverage spans from the filtered set of `Statement`s and `Terminator`s of
@@ -50,24 +149,72 @@ pub(crate) fn extract_covspans_from_mir(
 // merge some coverage spans, at which point a coverage span may represent multiple
 // `Statement`s and/or `Terminator`s.)
 fn bcb_to_initial_coverage_spans<'a, 'tcx>(
+    source_map: &SourceMap,
     mir_body: &'a mir::Body<'tcx>,
     body_span: Span,
+    is_derived: bool,
     bcb: BasicCoverageBlock,
     bcb_data: &'a BasicCoverageBlockData,
     initial_covspans: &mut Vec<SpanFromMir>,
+    mut expansion_covspans: Option<&mut Vec<ExpansionCovspan>>,
+    mut definition_covspans: Option<&mut Vec<ExpansionCovspan>>,
+    included_covspans: &mut Vec<Covspan>,
+    mut external_macro_call_sites: Option<&mut Vec<(Span, ExpnKind)>>,
 ) {
     for &bb in &bcb_data.basic_blocks {
         let data = &mir_body[bb];
 
-        let unexpand = move |expn_span| {
-            unexpand_into_body_span_with_expn_kind(expn_span, body_span)
-                // Discard any spans that fill the entire body, because they tend
-                // to represent compiler-inserted code, e.g. implicitly returning `()`.
-                .filter(|(span, _)| !span.source_equal(body_span))
+        let mut unexpand = |expn_span: Span| {
+            // The code that a derive generates usually carries the positions of
+            // the fields and variants that it was generated from, so keep those
+            // positions rather than unexpanding to the `#[derive(..)]` attribute.
+            let expn_span =
+                if is_derived && expn_span.from_expansion() && body_span.contains(expn_span) {
+                    expn_span.with_ctxt(body_span.ctxt())
+                } else {
+                    expn_span
+                };
+            let Some((span, prev)) = unexpand_into_body_span_with_prev(expn_span, body_span) else {
+                // Code included from another file has no ancestor in the body,
+                // so it is set aside to be mapped against its own file.
+                if let Some(span) = unexpand_into_included_file(source_map, expn_span, body_span) {
+                    included_covspans.push(Covspan { span, bcb });
+                }
+                return None;
+            };
+            // Discard any spans that fill the entire body, because they tend
+            // to represent compiler-inserted code, e.g. implicitly returning `()`.
+            if span.source_equal(body_span) {
+                return None;
+            }
+            if let Some(expansion_covspans) = expansion_covspans.as_deref_mut()
+                && let Some(expanded_span) = prev.filter(|&prev| is_in_local_macro_expansion(prev))
+            {
+                let covspan = Covspan { span: expanded_span, bcb };
+                expansion_covspans.push(ExpansionCovspan { call_site: span, covspan });
+            }
+            if let Some(definition_covspans) = definition_covspans.as_deref_mut() {
+                push_macro_definition_covspans(expn_span, body_span, bcb, definition_covspans);
+            }
+            let expn_kind = prev.map(|prev| prev.ctxt().outer_expn_data().kind);
+            if let Some(external_macro_call_sites) = external_macro_call_sites.as_deref_mut()
+                && let Some(expn_kind) = &expn_kind
+                && prev.is_some_and(is_in_external_macro_expansion)
+            {
+                external_macro_call_sites.push((span, expn_kind.clone()));
+            }
+            Some((span, expn_kind))
         };
 
-        let mut extract_statement_span = |statement| {
+        let mut extract_statement_span = |statement: &Statement<'_>| {
+            let callsite_span = inlined_callsite_span(mir_body, statement.source_info);
+            if callsite_span.is_some() && matches!(statement.kind, StatementKind::Coverage(_)) {
+                // Coverage statements inlined from another function belong to
+                // that function's own instrumentation.
+                return None;
+            }
             let expn_span = filtered_statement_span(statement)?;
+            let expn_span = callsite_span.unwrap_or(expn_span);
             let (span, expn_kind) = unexpand(expn_span)?;
 
             initial_covspans.push(SpanFromMir::new(span, expn_kind, bcb));
@@ -77,14 +224,47 @@ fn bcb_to_initial_coverage_spans<'a, 'tcx>(
             extract_statement_span(statement);
         }
 
-        let mut extract_terminator_span = |terminator| {
+        let mut extract_terminator_span = |terminator: &Terminator<'_>| {
             let expn_span = filtered_terminator_span(terminator)?;
+            let expn_span =
+                inlined_callsite_span(mir_body, terminator.source_info).unwrap_or(expn_span);
             let (span, expn_kind) = unexpand(expn_span)?;
 
             initial_covspans.push(SpanFromMir::new(span, expn_kind, bcb));
             Some(())
         };
         extract_terminator_span(data.terminator());
+
+        // An `asm!` block's template lines are executed whenever the block
+        // itself is reached, so give each of them a span in the enclosing BCB.
+        // Otherwise asm-heavy code can be left with holes in its line coverage.
+        if let TerminatorKind::InlineAsm { line_spans, .. } = data.terminator().kind {
+            for &line_span in line_spans {
+                let Some((span, expn_kind)) = unexpand(line_span) else { continue };
+                initial_covspans.push(SpanFromMir::new(span, expn_kind, bcb));
+            }
+        }
+    }
+}
+
+/// If the given source info belongs to code that was inlined from another
+/// function (which only happens with `-Zcoverage-options=post-inline`),
+/// returns the span of the outermost call site in this body.
+fn inlined_callsite_span(mir_body: &mir::Body<'_>, source_info: SourceInfo) -> Option<Span> {
+    let scopes = &mir_body.source_scopes;
+    let mut callsite_span = None;
+    let mut scope = source_info.scope;
+    // Walk outwards through nested inlined scopes, remembering the last call
+    // site seen, which is the one that appears in this body's own code.
+    loop {
+        let data = &scopes[scope];
+        let inlined_scope =
+            if data.inlined.is_some() { Some(scope) } else { data.inlined_parent_scope };
+        let Some(inlined_scope) = inlined_scope else { return callsite_span };
+        let inlined_data = &scopes[inlined_scope];
+        callsite_span = inlined_data.inlined.map(|(_, span)| span);
+        let Some(parent) = inlined_data.parent_scope else { return callsite_span };
+        scope = parent;
     }
 }
 
@@ -140,7 +320,10 @@ fn filtered_statement_span(statement: &Statement<'_>) -> Option<Span> {
             CoverageKind::CounterIncrement { .. }
             | CoverageKind::ExpressionUsed { .. }
             | CoverageKind::CondBitmapUpdate { .. }
-            | CoverageKind::TestVectorBitmapUpdate { .. },
+            | CoverageKind::TestVectorBitmapUpdate { .. }
+            | CoverageKind::PathRegisterAdd { .. }
+            | CoverageKind::PathCount { .. }
+            | CoverageKind::SwitchArmCount { .. },
         ) => bug!(
             "Unexpected coverage statement found during coverage instrumentation: {statement:?}"
         ),
@@ -0,0 +1,76 @@
+//! Per-arm counts for multi-way `SwitchInt` terminators, for
+//! `-Zcoverage-options=switch-arms`.
+//!
+//! Each counted switch is given one count slot per value, plus one for its
+//! `otherwise` arm. At runtime, the switch increments the slot of the arm that
+//! its discriminant selects, so a `match` with many arms needs only a single
+//! increment, no matter which arm is taken.
+//!
+//! Only switches whose values form a contiguous range are counted, because the
+//! slot for a discriminant can then be found with a subtraction and a bounds
+//! check. That covers most matches on fieldless enums and on small integers.
+
+use rustc_middle::mir::{self, BasicBlock, TerminatorKind};
+
+use crate::coverage::graph::{BasicCoverageBlock, CoverageGraph};
+
+/// A `SwitchInt` terminator whose arms are counted.
+#[derive(Debug)]
+pub(super) struct SwitchArms {
+    /// The block terminated by the switch.
+    pub(super) bb: BasicBlock,
+    /// The first of the switch's count slots.
+    pub(super) first_slot: u32,
+    /// The smallest of the switch's values.
+    pub(super) min: u128,
+    pub(super) num_values: u32,
+    /// The coverage graph node that each slot's arm leads to, in slot order,
+    /// ending with the `otherwise` arm. Arms that lead to blocks outside the
+    /// graph (such as an unreachable `otherwise` arm) have no node.
+    pub(super) arm_bcbs: Vec<Option<BasicCoverageBlock>>,
+}
+
+impl SwitchArms {
+    pub(super) fn num_slots(&self) -> u32 {
+        self.num_values + 1
+    }
+}
+
+/// Finds the multi-way switches whose arms can be counted, and gives each of
+/// them a consecutive range of count slots.
+///
+/// This must run before any counter blocks are injected into the MIR, since
+/// those replace the switches' targets with blocks that aren't in the graph.
+pub(super) fn find_switch_arms(mir_body: &mir::Body<'_>, graph: &CoverageGraph) -> Vec<SwitchArms> {
+    let mut switches = vec![];
+    let mut next_slot = 0;
+    for (bb, data) in mir_body.basic_blocks.iter_enumerated() {
+        let TerminatorKind::SwitchInt { ref targets, .. } = data.terminator().kind else {
+            continue;
+        };
+        // Two-way switches are plain branches, which the usual counters
+        // already handle well.
+        if targets.all_values().len() < 2 || graph.bcb_from_bb(bb).is_none() {
+            continue;
+        }
+
+        let mut arms = targets.iter().collect::<Vec<_>>();
+        arms.sort_unstable_by_key(|&(value, _)| value);
+        let min = arms[0].0;
+        if arms.iter().zip(0..).any(|(&(value, _), i)| value.wrapping_sub(min) != i) {
+            continue;
+        }
+
+        let arm_bcbs = arms
+            .iter()
+            .map(|&(_, target)| target)
+            .chain([targets.otherwise()])
+            .map(|target| graph.bcb_from_bb(target))
+            .collect();
+        let switch =
+            SwitchArms { bb, first_slot: next_slot, min, num_values: arms.len() as u32, arm_bcbs };
+        next_slot += switch.num_slots();
+        switches.push(switch);
+    }
+    switches
+}
@@ -26,17 +26,29 @@
 
 use itertools::Itertools;
 use rustc_data_structures::graph::{DirectedGraph, Successors};
+use rustc_index::bit_set::DenseBitSet;
 use rustc_index::{Idx, IndexVec};
 use rustc_middle::mir::*;
 use rustc_middle::{bug, ty};
 use rustc_span::{BytePos, DUMMY_SP, Pos, Span};
 
-use super::graph::{self, BasicCoverageBlock};
+use super::counters::{CounterPlacement, CoverageCounters, ExpressionLimits};
+use super::graph::{self, BasicCoverageBlock, SideEdges};
+use super::{paths, switch_arms};
 
 fn bcb(index: u32) -> BasicCoverageBlock {
     BasicCoverageBlock::from_u32(index)
 }
 
+fn no_panic_sites(mir_body: &Body<'_>) -> DenseBitSet<BasicBlock> {
+    DenseBitSet::new_empty(mir_body.basic_blocks.len())
+}
+
+/// Builds the coverage graph for a mock body, with no panic sites and no side edges.
+fn coverage_graph(mir_body: &Body<'_>) -> graph::CoverageGraph {
+    graph::CoverageGraph::from_mir(mir_body, &no_panic_sites(mir_body), SideEdges::default())
+}
+
 // All `TEMP_BLOCK` targets should be replaced before calling `to_body() -> mir::Body`.
 const TEMP_BLOCK: BasicBlock = BasicBlock::MAX;
 
@@ -311,7 +323,7 @@ fn test_covgraph_goto_switchint() {
     if false {
         eprintln!("basic_blocks = {}", debug_basic_blocks(&mir_body));
     }
-    let graph = graph::CoverageGraph::from_mir(&mir_body);
+    let graph = coverage_graph(&mir_body);
     print_coverage_graphviz("covgraph_goto_switchint ", &mir_body, &graph);
     /*
     ┌──────────────┐     ┌─────────────────┐
@@ -374,7 +386,7 @@ fn switchint_then_loop_else_return<'a>() -> Body<'a> {
 #[test]
 fn test_covgraph_switchint_then_loop_else_return() {
     let mir_body = switchint_then_loop_else_return();
-    let graph = graph::CoverageGraph::from_mir(&mir_body);
+    let graph = coverage_graph(&mir_body);
     print_coverage_graphviz("covgraph_switchint_then_loop_else_return", &mir_body, &graph);
     /*
                        ┌─────────────────┐
@@ -476,7 +488,7 @@ fn switchint_loop_then_inner_loop_else_break<'a>() -> Body<'a> {
 #[test]
 fn test_covgraph_switchint_loop_then_inner_loop_else_break() {
     let mir_body = switchint_loop_then_inner_loop_else_break();
-    let graph = graph::CoverageGraph::from_mir(&mir_body);
+    let graph = coverage_graph(&mir_body);
     print_coverage_graphviz(
         "covgraph_switchint_loop_then_inner_loop_else_break",
         &mir_body,
@@ -523,3 +535,808 @@ fn test_covgraph_switchint_loop_then_inner_loop_else_break() {
     assert_successors(&graph, bcb(5), &[bcb(1)]);
     assert_successors(&graph, bcb(6), &[bcb(4)]);
 }
+
+#[test]
+fn test_paths_switchint_then_loop_else_return() {
+    let mir_body = switchint_then_loop_else_return();
+    let graph = coverage_graph(&mir_body);
+    let profile = paths::number_paths(&graph).expect("graph is small enough to profile");
+
+    // The back edge `bcb3 -> bcb1` ends one path, and starts another at the
+    // loop header, so each path either starts at the entry or at `bcb1`, and
+    // either returns or takes the back edge.
+    assert_eq!(profile.num_paths, 4);
+    let paths = profile.paths.iter().cloned().sorted().collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            vec![bcb(0), bcb(1), bcb(2)],
+            vec![bcb(0), bcb(1), bcb(3)],
+            vec![bcb(1), bcb(2)],
+            vec![bcb(1), bcb(3)],
+        ]
+    );
+    assert_eq!(profile.ends.len(), 2, "ends: {:?}", profile.ends);
+}
+
+#[test]
+fn test_switch_arms_contiguous_values_only() {
+    // A three-way switch over the values 0 and 1, whose arms all lead to a
+    // second switch over the values 0 and 5.
+    let mut blocks = MockBlocks::new();
+    let start = blocks.switchint(None);
+    let zero = blocks.goto(None);
+    let one = blocks.goto(None);
+    let otherwise = blocks.goto(None);
+    blocks.set_branch(start, 0, zero);
+    blocks.set_branch(start, 1, one);
+    blocks.set_branch(start, 2, otherwise);
+    let return_ = blocks.return_(None);
+    let discr = Operand::Move(Place::from(blocks.new_temp()));
+    let sparse = blocks.push(TerminatorKind::SwitchInt {
+        discr,
+        targets: SwitchTargets::new([(0, return_), (5, return_)].into_iter(), return_),
+    });
+    for arm in [zero, one, otherwise] {
+        blocks.link(arm, sparse);
+    }
+    let mir_body = blocks.to_body();
+    let graph = coverage_graph(&mir_body);
+
+    // Only the first switch is counted, since the second one's values aren't
+    // contiguous.
+    let switches = switch_arms::find_switch_arms(&mir_body, &graph);
+    assert_eq!(switches.len(), 1, "switches: {switches:?}");
+    let switch = &switches[0];
+    assert_eq!((switch.bb, switch.first_slot, switch.min, switch.num_slots()), (start, 0, 0, 3));
+    let bcb_of = |bb| graph.bcb_from_bb(bb);
+    assert_eq!(switch.arm_bcbs, [bcb_of(zero), bcb_of(one), bcb_of(otherwise)]);
+}
+
+#[test]
+fn test_covgraph_split_at_panic_site() {
+    let mut blocks = MockBlocks::new();
+    let start = blocks.call(None);
+    let unwrap = blocks.call(Some(start));
+    let after = blocks.goto(Some(unwrap));
+    blocks.return_(Some(after));
+    let mir_body = blocks.to_body();
+
+    // Without panic sites, the whole body is one chain.
+    let graph = coverage_graph(&mir_body);
+    assert_eq!(graph.num_nodes(), 1, "graph: {:?}", graph.iter_enumerated().collect::<Vec<_>>());
+
+    // A panic site ends its node, and the edge out of it isn't assumed to have
+    // the same count as the node, so the code after it gets its own counter.
+    let mut panic_sites = no_panic_sites(&mir_body);
+    panic_sites.insert(unwrap);
+    let graph = graph::CoverageGraph::from_mir(&mir_body, &panic_sites, SideEdges::default());
+    assert_eq!(graph.num_nodes(), 2, "graph: {:?}", graph.iter_enumerated().collect::<Vec<_>>());
+    assert_successors(&graph, bcb(0), &[bcb(1)]);
+    assert_successors(&graph, bcb(1), &[]);
+    assert!(!graph[bcb(0)].is_out_summable);
+    assert_eq!(graph.simple_successor(bcb(0)), None);
+}
+
+#[test]
+fn test_covgraph_is_straight_line() {
+    let mut blocks = MockBlocks::new();
+    let start = blocks.goto(None);
+    let call = blocks.call(Some(start));
+    blocks.return_(Some(call));
+    let mir_body = blocks.to_body();
+    let panic_sites = no_panic_sites(&mir_body);
+    assert!(graph::CoverageGraph::is_straight_line(&mir_body, &panic_sites, SideEdges::default()));
+
+    // A panic site splits the chain into two nodes.
+    let mut panic_sites = no_panic_sites(&mir_body);
+    panic_sites.insert(call);
+    assert!(!graph::CoverageGraph::is_straight_line(&mir_body, &panic_sites, SideEdges::default()));
+
+    let mir_body = goto_switchint();
+    let panic_sites = no_panic_sites(&mir_body);
+    assert!(!graph::CoverageGraph::is_straight_line(&mir_body, &panic_sites, SideEdges::default()));
+}
+
+#[test]
+fn test_covgraph_control_equivalent_dominator() {
+    let mir_body = switchint_then_loop_else_return();
+    let mut graph = coverage_graph(&mir_body);
+
+    // Without post-dominators, no nodes are known to be control-equivalent.
+    assert_eq!(graph.control_equivalent_dominator(bcb(2)), None);
+
+    graph.compute_post_dominators();
+    // The return always runs exactly once per call, just like the entry.
+    assert_eq!(graph.control_equivalent_dominator(bcb(2)), Some(bcb(0)));
+    // The loop header post-dominates the entry, but runs once per iteration.
+    assert_eq!(graph.control_equivalent_dominator(bcb(1)), None);
+    // The loop body doesn't post-dominate the loop header.
+    assert_eq!(graph.control_equivalent_dominator(bcb(3)), None);
+    assert_eq!(graph.control_equivalent_dominator(bcb(0)), None);
+}
+
+#[test]
+fn test_covgraph_edges() {
+    let mir_body = switchint_then_loop_else_return();
+    let graph = coverage_graph(&mir_body);
+
+    // Each node's out-edges lead to its successors, in the same order.
+    for bcb in (0..graph.num_nodes()).map(BasicCoverageBlock::new) {
+        let targets = graph.edges_from(bcb).map(|e| graph.edge_target(e)).collect::<Vec<_>>();
+        assert_eq!(targets, graph.successors[bcb], "out-edges of {bcb:?}");
+        let sources = graph.edges_into(bcb).map(|e| graph.edge_source(e)).collect::<Vec<_>>();
+        assert_eq!(sources, graph.predecessors[bcb], "in-edges of {bcb:?}");
+    }
+
+    let back_edge = graph.find_edge(bcb(3), bcb(1)).expect("back edge should exist");
+    assert_eq!(graph.edge_source(back_edge), bcb(3));
+    assert_eq!(graph.find_edge(bcb(1), bcb(0)), None);
+
+    // The loop header has two in-edges, but the loop body only has one.
+    assert_eq!(graph.sole_in_edge(bcb(1)), None);
+    assert_eq!(graph.sole_in_edge(bcb(3)), graph.find_edge(bcb(1), bcb(3)));
+}
+
+#[test]
+fn test_covgraph_loop_bodies() {
+    let mir_body = switchint_loop_then_inner_loop_else_break();
+    let graph = coverage_graph(&mir_body);
+
+    let body_of = |header| {
+        let body = graph.loop_body(header).expect("node should be a loop header");
+        body.iter().collect::<Vec<_>>()
+    };
+    assert!(graph.is_loop_header(bcb(1)));
+    assert!(graph.is_loop_header(bcb(4)));
+    assert_eq!(graph.loop_body(bcb(0)), None);
+    assert_eq!(graph.loop_body(bcb(5)), None);
+
+    // The outer loop's body includes the whole inner loop.
+    assert_eq!(body_of(bcb(1)), [bcb(1), bcb(3), bcb(4), bcb(5), bcb(6)]);
+    assert_eq!(body_of(bcb(4)), [bcb(4), bcb(6)]);
+
+    let depths = (0..graph.num_nodes())
+        .map(|i| graph.loop_depth(BasicCoverageBlock::new(i)))
+        .collect::<Vec<_>>();
+    assert_eq!(depths, [0, 1, 0, 1, 2, 1, 2]);
+}
+
+#[test]
+fn test_covgraph_control_equivalent_dominator_skipped_by_reloop() {
+    let mut blocks = MockBlocks::new();
+    let start = blocks.call(None);
+    let header = blocks.switchint(Some(start));
+    let early_reloop_goto = blocks.goto(None);
+    blocks.set_branch(header, 0, early_reloop_goto);
+    blocks.link(early_reloop_goto, header);
+    let latch = blocks.switchint(None);
+    blocks.set_branch(header, 1, latch);
+    let reloop_goto = blocks.goto(None);
+    blocks.set_branch(latch, 0, reloop_goto);
+    blocks.link(reloop_goto, header);
+    let return_ = blocks.return_(None);
+    blocks.set_branch(latch, 1, return_);
+    let mir_body = blocks.to_body();
+
+    let mut graph = coverage_graph(&mir_body);
+    graph.compute_post_dominators();
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+
+    // Every path from the loop header to the return passes through the latch,
+    // but the header can also jump straight back to itself and run again
+    // without the latch running, so the two can't share a counter.
+    assert_eq!(graph.control_equivalent_dominator(bcb_of(latch)), None);
+    // The return still runs exactly once per call.
+    assert_eq!(graph.control_equivalent_dominator(bcb_of(return_)), Some(bcb_of(start)));
+}
+
+#[test]
+fn test_covgraph_irreducible_region() {
+    // Two nodes that branch to each other, each of which can also be entered
+    // directly from the start node, so neither one dominates the other.
+    let mut blocks = MockBlocks::new();
+    let start = blocks.switchint(None);
+    let a = blocks.switchint(None);
+    let b = blocks.switchint(None);
+    let return_ = blocks.return_(None);
+    blocks.set_branch(start, 0, a);
+    blocks.set_branch(start, 1, b);
+    blocks.set_branch(a, 0, b);
+    blocks.set_branch(a, 1, return_);
+    blocks.set_branch(b, 0, a);
+    blocks.set_branch(b, 1, return_);
+    let mir_body = blocks.to_body();
+
+    let mut graph = coverage_graph(&mir_body);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+
+    // The cycle between `a` and `b` isn't a natural loop.
+    assert!(!graph.is_loop_header(bcb_of(a)));
+    assert!(!graph.is_loop_header(bcb_of(b)));
+    assert_eq!(graph.loop_depth(bcb_of(a)), 0);
+
+    let region = graph.irreducible_region(bcb_of(a));
+    assert!(region.is_some());
+    assert_eq!(graph.irreducible_region(bcb_of(b)), region);
+    assert_eq!(graph.irreducible_region(bcb_of(start)), None);
+    assert_eq!(graph.irreducible_region(bcb_of(return_)), None);
+
+    graph.compute_post_dominators();
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    // Nodes in the region can run any number of times per call.
+    assert_eq!(graph.control_equivalent_dominator(bcb_of(a)), None);
+    assert_eq!(graph.control_equivalent_dominator(bcb_of(b)), None);
+    assert_eq!(graph.control_equivalent_dominator(bcb_of(return_)), Some(bcb_of(start)));
+}
+
+#[test]
+fn test_covgraph_unwind_paths() {
+    let mut blocks = MockBlocks::new();
+    let call = blocks.call(None);
+    let return_ = blocks.return_(Some(call));
+    let cleanup = blocks.push(TerminatorKind::UnwindResume);
+    blocks.blocks[cleanup].is_cleanup = true;
+    match blocks.blocks[call].terminator_mut().kind {
+        TerminatorKind::Call { ref mut unwind, .. } => *unwind = UnwindAction::Cleanup(cleanup),
+        ref invalid => bug!("Invalid BasicBlock kind: {:?}", invalid),
+    }
+    let mir_body = blocks.to_body();
+
+    // By default, the unwind edge is ignored, so the call is chained into the
+    // block after it, and the cleanup block isn't part of the graph at all.
+    let graph = coverage_graph(&mir_body);
+    assert_eq!(graph.num_nodes(), 1);
+    assert_eq!(graph.bcb_from_bb(call), graph.bcb_from_bb(return_));
+    assert_eq!(graph.bcb_from_bb(cleanup), None);
+
+    // With unwind paths, the cleanup block is a separate successor of the call.
+    let side_edges = SideEdges { unwind: true, ..SideEdges::default() };
+    let graph = graph::CoverageGraph::from_mir(&mir_body, &no_panic_sites(&mir_body), side_edges);
+    assert_eq!(graph.num_nodes(), 3);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    assert_eq!(graph.successors[bcb_of(call)], [bcb_of(return_), bcb_of(cleanup)]);
+    assert!(graph[bcb_of(call)].is_out_summable);
+    assert_successors(&graph, bcb_of(cleanup), &[]);
+}
+
+#[test]
+fn test_ready_first_traversal_prefers_shallow_nodes() {
+    let mir_body = switchint_then_loop_else_return();
+    let graph = coverage_graph(&mir_body);
+
+    // The loop header's first successor is the loop body, but the return is
+    // outside the loop, so with `loop-depth-order` it is visited first.
+    assert_eq!(graph.successors[bcb(1)], [bcb(3), bcb(2)]);
+    let order = graph::ReadyFirstTraversal::new(&graph, true).collect::<Vec<_>>();
+    assert_eq!(order, [bcb(0), bcb(1), bcb(2), bcb(3)]);
+
+    // Without `loop-depth-order`, ready nodes are visited in the order they
+    // were queued.
+    let order = graph::ReadyFirstTraversal::new(&graph, false).collect::<Vec<_>>();
+    assert_eq!(order, [bcb(0), bcb(1), bcb(3), bcb(2)]);
+}
+
+#[test]
+fn test_covgraph_count_equivalence_classes() {
+    let mir_body = switchint_then_loop_else_return();
+    let mut graph = coverage_graph(&mir_body);
+
+    // Without post-dominators, every node in this graph is in a class of its own.
+    let classes = graph.count_equivalence_classes();
+    for bcb in (0..graph.num_nodes()).map(BasicCoverageBlock::new) {
+        assert!(classes.link(bcb).is_none(), "{bcb:?} shouldn't be linked");
+        assert_eq!(classes.representative(bcb), bcb);
+    }
+
+    graph.compute_post_dominators();
+    let classes = graph.count_equivalence_classes();
+    let link = classes.link(bcb(2)).expect("the return should be linked to the entry");
+    assert_eq!(link.same_as, bcb(0));
+    assert_eq!(link.reason, graph::CountEquivalenceReason::ControlEquivalent);
+    assert_eq!(classes.representative(bcb(2)), bcb(0));
+    assert_eq!(classes.same_count_ancestors(bcb(2)).collect::<Vec<_>>(), [bcb(0)]);
+    assert_eq!(classes.representative(bcb(1)), bcb(1));
+    assert_eq!(classes.representative(bcb(3)), bcb(3));
+}
+
+#[test]
+fn test_equal_count_nodes_share_a_counter() {
+    let mir_body = switchint_then_loop_else_return();
+    let mut graph = coverage_graph(&mir_body);
+    graph.compute_post_dominators();
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let limits = ExpressionLimits::default();
+    let placement = CounterPlacement { shared_counters: true, ..CounterPlacement::default() };
+
+    // The return always runs as often as the entry, so both builders should
+    // map them to the same counter.
+    for counters in [
+        CoverageCounters::make_bcb_counters(&graph, &needs_counter, limits, placement),
+        CoverageCounters::make_spanning_tree_counters(&graph, &needs_counter, limits, placement),
+    ] {
+        assert_eq!(counters.term_for_bcb(bcb(2)), counters.term_for_bcb(bcb(0)));
+    }
+}
+
+#[test]
+fn test_covgraph_coroutine_drops() {
+    let mut blocks = MockBlocks::new();
+    let yield_ = blocks.push(TerminatorKind::Yield {
+        value: Operand::Copy(blocks.dummy_place.clone()),
+        resume: TEMP_BLOCK,
+        resume_arg: blocks.dummy_place.clone(),
+        drop: None,
+    });
+    let return_ = blocks.return_(Some(yield_));
+    let drop = blocks.push(TerminatorKind::CoroutineDrop);
+    match blocks.blocks[yield_].terminator_mut().kind {
+        TerminatorKind::Yield { drop: ref mut drop_target, .. } => *drop_target = Some(drop),
+        ref invalid => bug!("Invalid BasicBlock kind: {:?}", invalid),
+    }
+    let mir_body = blocks.to_body();
+
+    // By default, only the resume edge is part of the graph.
+    let graph = coverage_graph(&mir_body);
+    assert_eq!(graph.num_nodes(), 2);
+    assert_eq!(graph.bcb_from_bb(drop), None);
+
+    // With coroutine drops, the drop block is a separate successor of the yield.
+    let side_edges = SideEdges { coroutine_drop: true, ..SideEdges::default() };
+    let graph = graph::CoverageGraph::from_mir(&mir_body, &no_panic_sites(&mir_body), side_edges);
+    assert_eq!(graph.num_nodes(), 3);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    assert_eq!(graph.successors[bcb_of(yield_)], [bcb_of(return_), bcb_of(drop)]);
+    // The coroutine might never be resumed or dropped, so the yield's count
+    // still can't be assumed to be the sum of its out-edges.
+    assert!(!graph[bcb_of(yield_)].is_out_summable);
+
+    // So neither out-edge's count can be derived from the yield's count, and
+    // each node needs a physical counter of its own.
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let limits = ExpressionLimits::default();
+    let counters = CoverageCounters::make_bcb_counters(
+        &graph,
+        &needs_counter,
+        limits,
+        CounterPlacement::default(),
+    );
+    assert_eq!((counters.num_counters(), counters.num_expressions()), (3, 0));
+}
+
+#[test]
+fn test_covgraph_static_unreachable() {
+    // A branch whose second arm can never be taken, and whose arms join again.
+    let mut blocks = MockBlocks::new();
+    let start = blocks.switchint(None);
+    let taken = blocks.goto(None);
+    let untaken = blocks.goto(None);
+    let join = blocks.return_(Some(taken));
+    blocks.link(untaken, join);
+    blocks.set_branch(start, 0, taken);
+    blocks.set_branch(start, 1, untaken);
+    let mir_body = blocks.to_body();
+
+    let mut graph = coverage_graph(&mir_body);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    let untaken_edge = graph.find_edge(bcb_of(start), bcb_of(untaken)).unwrap();
+    graph.mark_untaken_edges([untaken_edge]);
+
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    assert!(graph.is_unreachable(bcb_of(untaken)));
+    assert!(!graph.is_unreachable(bcb_of(taken)));
+    assert!(!graph.is_unreachable(bcb_of(join)));
+    // Edges out of an unreachable node can't be taken either.
+    let edge_from_untaken = graph.find_edge(bcb_of(untaken), bcb_of(join)).unwrap();
+    assert!(graph.is_untaken_edge(edge_from_untaken));
+
+    // Every reachable node runs as often as the start node, so one physical
+    // counter is enough, and the unreachable node is counted as zero.
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let limits = ExpressionLimits::default();
+    let counters = CoverageCounters::make_bcb_counters(
+        &graph,
+        &needs_counter,
+        limits,
+        CounterPlacement::default(),
+    );
+    assert_eq!(counters.num_counters(), 1);
+    assert_eq!(counters.term_for_bcb(bcb_of(untaken)), Some(coverage::CovTerm::Zero));
+}
+
+#[test]
+fn test_spanning_tree_counters() {
+    use rustc_data_structures::fx::FxHashMap;
+    use rustc_middle::mir::coverage::{CovTerm, Expression, ExpressionId, Op};
+
+    use super::counters::Site;
+
+    let mir_body = switchint_loop_then_inner_loop_else_break();
+    let graph = coverage_graph(&mir_body);
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let limits = ExpressionLimits::default();
+    let counters = CoverageCounters::make_spanning_tree_counters(
+        &graph,
+        &needs_counter,
+        limits,
+        CounterPlacement::default(),
+    );
+
+    // Run the function once, going around each loop a few times, and record
+    // how often each node and edge was visited.
+    let path = [0, 1, 3, 4, 6, 4, 6, 4, 5, 1, 3, 4, 5, 1, 2].map(bcb);
+    let mut node_counts = IndexVec::<BasicCoverageBlock, u64>::from_elem_n(0, graph.num_nodes());
+    let mut edge_counts = FxHashMap::default();
+    node_counts[path[0]] += 1;
+    for (from, to) in path.iter().copied().tuple_windows() {
+        let edge = graph.find_edge(from, to).unwrap();
+        *edge_counts.entry(edge).or_insert(0u64) += 1;
+        node_counts[to] += 1;
+    }
+
+    // Every node's counter should evaluate to its real count, given the
+    // physical counts at the sites the counters were placed on.
+    let phys_counts = counters
+        .counter_increment_sites()
+        .map(|(_, site)| match site {
+            Site::Node { bcb } => node_counts[bcb],
+            Site::Edge { edge } => edge_counts.get(&edge).copied().unwrap_or(0),
+        })
+        .collect::<Vec<_>>();
+    let terms = graph.iter_enumerated().map(|(bcb, _)| counters.term_for_bcb(bcb).unwrap());
+    let terms = terms.collect::<Vec<_>>();
+    let expressions = counters.into_expressions();
+    fn eval(term: CovTerm, phys: &[u64], exprs: &IndexVec<ExpressionId, Expression>) -> u64 {
+        match term {
+            CovTerm::Zero => 0,
+            CovTerm::Counter(id) => phys[id.index()],
+            CovTerm::Expression(id) => {
+                let Expression { lhs, op, rhs } = exprs[id];
+                let (lhs, rhs) = (eval(lhs, phys, exprs), eval(rhs, phys, exprs));
+                match op {
+                    Op::Add => lhs + rhs,
+                    Op::Subtract => lhs - rhs,
+                }
+            }
+        }
+    }
+    for (bcb, &term) in graph.iter_enumerated().map(|(bcb, _)| bcb).zip(&terms) {
+        assert_eq!(eval(term, &phys_counts, &expressions), node_counts[bcb], "{bcb:?}: {term:?}");
+    }
+}
+
+#[test]
+fn test_max_expression_terms() {
+    let mir_body = switchint_loop_then_inner_loop_else_break();
+    let graph = coverage_graph(&mir_body);
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+
+    let no_limits = ExpressionLimits::default();
+    let counters = CoverageCounters::make_bcb_counters(
+        &graph,
+        &needs_counter,
+        no_limits,
+        CounterPlacement::default(),
+    );
+    assert_ne!(counters.num_expressions(), 0);
+
+    // With a limit of one term, every node must be counted by a physical counter.
+    let one_term = ExpressionLimits { max_terms: Some(1), ..ExpressionLimits::default() };
+    for counters in [
+        CoverageCounters::make_bcb_counters(
+            &graph,
+            &needs_counter,
+            one_term,
+            CounterPlacement::default(),
+        ),
+        CoverageCounters::make_spanning_tree_counters(
+            &graph,
+            &needs_counter,
+            one_term,
+            CounterPlacement::default(),
+        ),
+    ] {
+        for (bcb, _) in graph.iter_enumerated() {
+            let term = counters.term_for_bcb(bcb);
+            assert!(matches!(term, Some(coverage::CovTerm::Counter(_))), "{bcb:?}: {term:?}");
+        }
+        assert_eq!(counters.num_expressions(), 0);
+    }
+}
+
+#[test]
+fn test_no_subtraction() {
+    let mir_body = switchint_loop_then_inner_loop_else_break();
+    let graph = coverage_graph(&mir_body);
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+
+    // Node counters may still be sums of physical counters, but nothing may
+    // be subtracted.
+    let limits = ExpressionLimits { no_subtraction: true, ..ExpressionLimits::default() };
+    for mut counters in [
+        CoverageCounters::make_bcb_counters(
+            &graph,
+            &needs_counter,
+            limits,
+            CounterPlacement::default(),
+        ),
+        CoverageCounters::make_spanning_tree_counters(
+            &graph,
+            &needs_counter,
+            limits,
+            CounterPlacement::default(),
+        ),
+    ] {
+        for (bcb, _) in graph.iter_enumerated() {
+            assert!(counters.term_for_bcb(bcb).is_some(), "{bcb:?}");
+        }
+        assert_eq!(counters.make_node_difference(bcb(1), bcb(2)), None);
+        let expressions = counters.into_expressions();
+        assert!(expressions.iter().all(|expr| expr.op.is_add()), "{expressions:?}");
+    }
+}
+
+#[test]
+fn test_profile_guided_counters() {
+    use super::counters::Site;
+
+    // A branch whose second arm is taken far more often than its first.
+    let mut blocks = MockBlocks::new();
+    let start = blocks.switchint(None);
+    let cold = blocks.goto(None);
+    let hot = blocks.goto(None);
+    let join = blocks.return_(Some(cold));
+    blocks.link(hot, join);
+    blocks.set_branch(start, 0, cold);
+    blocks.set_branch(start, 1, hot);
+    let mir_body = blocks.to_body();
+
+    let graph = coverage_graph(&mir_body);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let limits = ExpressionLimits::default();
+    let node_count = |bcb: BasicCoverageBlock| match bcb {
+        _ if bcb == bcb_of(cold) => 1,
+        _ if bcb == bcb_of(hot) => 100,
+        _ => 101,
+    };
+    // In a diamond, each edge runs as often as the less frequent of its ends.
+    let site_count = |site: Site| match site {
+        Site::Node { bcb } => node_count(bcb),
+        Site::Edge { edge } => {
+            node_count(graph.edge_source(edge)).min(node_count(graph.edge_target(edge)))
+        }
+    };
+    let cost = |counters: &CoverageCounters| {
+        counters.counter_increment_sites().map(|(_, site)| site_count(site)).sum::<u64>()
+    };
+
+    // Record a profile with the usual layout, and use it to place counters.
+    let usual = CoverageCounters::make_bcb_counters(
+        &graph,
+        &needs_counter,
+        limits,
+        CounterPlacement::default(),
+    );
+    let counter_values =
+        usual.counter_increment_sites().map(|(_, site)| site_count(site)).collect::<Vec<_>>();
+    let profiled_counts = usual.profiled_counts(&counter_values).unwrap();
+    // A profile recorded with a different layout is ignored.
+    assert!(usual.profiled_counts(&counter_values[1..]).is_none());
+
+    // Some counter has to see every call, but the cold arm should get the
+    // only other counter, and the hot arm's count should be derived from it.
+    let guided = CoverageCounters::make_profile_guided_counters(
+        &graph,
+        &needs_counter,
+        limits,
+        CounterPlacement::default(),
+        &profiled_counts,
+    );
+    let sites = guided.counter_increment_sites().map(|(_, site)| site).collect::<Vec<_>>();
+    assert!(sites.contains(&Site::Node { bcb: bcb_of(cold) }), "{sites:?}");
+    assert_eq!(cost(&guided), 101, "{sites:?}");
+    assert!(cost(&guided) <= cost(&usual));
+    for (bcb, _) in graph.iter_enumerated() {
+        assert!(guided.term_for_bcb(bcb).is_some(), "{bcb:?}");
+    }
+}
+
+#[test]
+fn test_sort_counters_by_key() {
+    use super::counters::Site;
+
+    let mir_body = switchint_loop_then_inner_loop_else_break();
+    let graph = coverage_graph(&mir_body);
+    let needs_counter = DenseBitSet::new_filled(graph.num_nodes());
+    let mut counters = CoverageCounters::make_bcb_counters(
+        &graph,
+        &needs_counter,
+        ExpressionLimits::default(),
+        CounterPlacement::default(),
+    );
+
+    let site_of_counter = |counters: &CoverageCounters, term| match term {
+        Some(coverage::CovTerm::Counter(id)) => {
+            counters.counter_increment_sites().find(|&(i, _)| i == id).map(|(_, site)| site)
+        }
+        _ => None,
+    };
+    let sites_before = graph
+        .iter_enumerated()
+        .map(|(bcb, _)| site_of_counter(&counters, counters.term_for_bcb(bcb)))
+        .collect::<Vec<_>>();
+    let num_expressions = counters.num_expressions();
+
+    // Number the counters in reverse dominator order.
+    let key = |site| match site {
+        Site::Node { bcb } => std::cmp::Reverse(graph.dominator_rank(bcb)),
+        Site::Edge { edge } => std::cmp::Reverse(graph.dominator_rank(graph.edge_target(edge))),
+    };
+    counters.sort_counters_by_key(key);
+
+    let keys = counters.counter_increment_sites().map(|(_, site)| key(site)).collect::<Vec<_>>();
+    assert!(keys.is_sorted(), "{keys:?}");
+    // Nodes are still counted at the same sites, without any new expressions.
+    let sites_after = graph
+        .iter_enumerated()
+        .map(|(bcb, _)| site_of_counter(&counters, counters.term_for_bcb(bcb)))
+        .collect::<Vec<_>>();
+    assert_eq!(sites_before, sites_after);
+    assert_eq!(counters.num_expressions(), num_expressions);
+}
+
+#[test]
+fn test_covgraph_validate() {
+    let bodies = [
+        goto_switchint(),
+        switchint_then_loop_else_return(),
+        switchint_loop_then_inner_loop_else_break(),
+    ];
+    for mir_body in &bodies {
+        let panic_sites = no_panic_sites(mir_body);
+        let graph = graph::CoverageGraph::from_mir(mir_body, &panic_sites, SideEdges::default());
+        let errors = graph.validate(mir_body, &panic_sites, SideEdges::default());
+        assert!(errors.is_empty(), "{errors:#?}");
+    }
+}
+
+#[test]
+fn test_covgraph_bcbs_for_span() {
+    // Each mock block's terminator has a 1-byte span just after the previous
+    // block's span, so `bbN` has the span `N+1..N+2`.
+    let mir_body = goto_switchint();
+    let graph = coverage_graph(&mir_body);
+    let bcbs_for_span = |lo, hi| {
+        let span = Span::with_root_ctxt(BytePos(lo), BytePos(hi));
+        graph.bcbs_for_span(span).sorted().collect::<Vec<_>>()
+    };
+    let bcb_of = |bb| graph.bcb_from_bb(BasicBlock::from_u32(bb)).unwrap();
+
+    // `bb0`, `bb1` and `bb2` are chained into one node.
+    assert_eq!(bcbs_for_span(1, 4), [bcb_of(0)]);
+    let expected = [bcb_of(2), bcb_of(3)].into_iter().sorted().collect::<Vec<_>>();
+    assert_eq!(bcbs_for_span(3, 5), expected);
+    assert_eq!(bcbs_for_span(100, 101), []);
+}
+
+#[test]
+fn test_covgraph_tail_call() {
+    let mut blocks = MockBlocks::new();
+    let call = blocks.call(None);
+    let tail_call = blocks.push(TerminatorKind::TailCall {
+        func: Operand::Copy(blocks.dummy_place.clone()),
+        args: [].into(),
+        fn_span: DUMMY_SP,
+    });
+    blocks.link(call, tail_call);
+    let mir_body = blocks.to_body();
+
+    // The tail call isn't chained into the call before it, so it gets its own
+    // node, but still has the same count.
+    let graph = coverage_graph(&mir_body);
+    assert_eq!(graph.num_nodes(), 2);
+    let bcb_of = |bb| graph.bcb_from_bb(bb).unwrap();
+    assert_ne!(bcb_of(call), bcb_of(tail_call));
+    assert_eq!(graph.simple_successor(bcb_of(call)), Some(bcb_of(tail_call)));
+    assert!(graph.successors[bcb_of(tail_call)].is_empty());
+
+    let panic_sites = no_panic_sites(&mir_body);
+    assert!(graph.validate(&mir_body, &panic_sites, SideEdges::default()).is_empty());
+}
+
+/// Builds a body that makes a call and then returns. If `inlined`, the call is
+/// replaced by the body of a callee that branches and then jumps back to the
+/// caller's return, with the callee's blocks appended to the caller's, as the
+/// MIR inliner does.
+fn call_then_return<'a>(inlined: bool) -> Body<'a> {
+    let mut blocks = MockBlocks::new();
+    let call = if inlined { blocks.goto(None) } else { blocks.call(None) };
+    let return_ = blocks.return_(Some(call));
+    if inlined {
+        let callee_start = blocks.switchint(Some(call));
+        let then_goto = blocks.goto(None);
+        let else_goto = blocks.goto(None);
+        blocks.set_branch(callee_start, 0, then_goto);
+        blocks.set_branch(callee_start, 1, else_goto);
+        blocks.link(then_goto, return_);
+        blocks.link(else_goto, return_);
+    }
+    blocks.to_body()
+}
+
+#[test]
+fn test_covgraph_splice_inlined_call() {
+    let mut graph = coverage_graph(&call_then_return(false));
+    // The call and the return are chained into one node.
+    assert_eq!(graph.num_nodes(), 1);
+
+    let mir_body = call_then_return(true);
+    let panic_sites = no_panic_sites(&mir_body);
+    let call = BasicBlock::from_u32(0);
+    graph.splice_blocks(&mir_body, &panic_sites, SideEdges::default(), &[call]);
+    graph.finish_splicing();
+    let errors = graph.validate(&mir_body, &panic_sites, SideEdges::default());
+    assert!(errors.is_empty(), "{errors:#?}");
+    assert_eq!(graph.num_nodes(), coverage_graph(&mir_body).num_nodes());
+
+    let bcb_of = |bb| graph.bcb_from_bb(BasicBlock::from_u32(bb)).unwrap();
+    // The callee's entry is merged into the caller's node, and the return is
+    // split out of it, because it now has two predecessors.
+    assert_eq!(bcb_of(2), bcb_of(0));
+    assert_ne!(bcb_of(1), bcb_of(0));
+    let mut callee_branches = [bcb_of(3), bcb_of(4)];
+    callee_branches.sort_unstable();
+    assert_successors(&graph, bcb_of(0), &callee_branches);
+    assert_successors(&graph, bcb_of(3), &[bcb_of(1)]);
+    assert_successors(&graph, bcb_of(4), &[bcb_of(1)]);
+
+    // The dominators and edges are up to date.
+    assert!(graph.dominates(bcb_of(0), bcb_of(1)));
+    assert_eq!(graph.sole_in_edge(bcb_of(1)), None);
+    assert_eq!(graph.sole_in_edge(bcb_of(3)), graph.find_edge(bcb_of(0), bcb_of(3)));
+}
+
+/// Builds a body that branches to either a chain of blocks that returns, or a
+/// call. If `inlined`, the call is replaced by a callee body that jumps into
+/// the middle of the other branch's chain.
+fn switchint_then_chain_else_call<'a>(inlined: bool) -> Body<'a> {
+    let mut blocks = MockBlocks::new();
+    let start = blocks.switchint(None);
+    let then_goto = blocks.goto(None);
+    let then_return = blocks.return_(Some(then_goto));
+    let else_call = if inlined { blocks.goto(None) } else { blocks.call(None) };
+    let else_return = blocks.return_(Some(else_call));
+    blocks.set_branch(start, 0, then_goto);
+    blocks.set_branch(start, 1, else_call);
+    if inlined {
+        let callee_start = blocks.switchint(Some(else_call));
+        blocks.set_branch(callee_start, 0, else_return);
+        blocks.set_branch(callee_start, 1, then_return);
+    }
+    blocks.to_body()
+}
+
+#[test]
+fn test_covgraph_splice_into_middle_of_node() {
+    let mut graph = coverage_graph(&switchint_then_chain_else_call(false));
+    let bcb_of = |graph: &graph::CoverageGraph, bb| graph.bcb_from_bb(BasicBlock::from_u32(bb));
+    assert_eq!(bcb_of(&graph, 1), bcb_of(&graph, 2));
+
+    let mir_body = switchint_then_chain_else_call(true);
+    let panic_sites = no_panic_sites(&mir_body);
+    let call = BasicBlock::from_u32(3);
+    graph.splice_blocks(&mir_body, &panic_sites, SideEdges::default(), &[call]);
+    graph.finish_splicing();
+    let errors = graph.validate(&mir_body, &panic_sites, SideEdges::default());
+    assert!(errors.is_empty(), "{errors:#?}");
+    assert_eq!(graph.num_nodes(), coverage_graph(&mir_body).num_nodes());
+
+    // The block that the callee jumps to starts a node of its own.
+    assert_ne!(bcb_of(&graph, 1), bcb_of(&graph, 2));
+    assert_eq!(bcb_of(&graph, 3), bcb_of(&graph, 5));
+    assert_ne!(bcb_of(&graph, 4), bcb_of(&graph, 3));
+    let then_return = bcb_of(&graph, 2).unwrap();
+    assert_eq!(graph.predecessors[then_return].len(), 2);
+    assert!(!graph.dominates(bcb_of(&graph, 1).unwrap(), then_return));
+}
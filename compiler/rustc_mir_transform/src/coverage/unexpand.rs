@@ -1,4 +1,5 @@
-use rustc_span::{ExpnKind, Span};
+use rustc_span::Span;
+use rustc_span::source_map::SourceMap;
 
 /// Walks through the expansion ancestors of `original_span` to find a span that
 /// is contained in `body_span` and has the same [syntax context] as `body_span`.
@@ -8,33 +9,18 @@ pub(crate) fn unexpand_into_body_span(original_span: Span, body_span: Span) -> O
     original_span.find_ancestor_inside_same_ctxt(body_span)
 }
 
-/// Walks through the expansion ancestors of `original_span` to find a span that
-/// is contained in `body_span` and has the same [syntax context] as `body_span`.
-///
-/// If the returned span represents a bang-macro invocation (e.g. `foo!(..)`),
-/// the returned symbol will be the name of that macro (e.g. `foo`).
-pub(crate) fn unexpand_into_body_span_with_expn_kind(
-    original_span: Span,
-    body_span: Span,
-) -> Option<(Span, Option<ExpnKind>)> {
-    let (span, prev) = unexpand_into_body_span_with_prev(original_span, body_span)?;
-
-    let expn_kind = prev.map(|prev| prev.ctxt().outer_expn_data().kind);
-
-    Some((span, expn_kind))
-}
-
 /// Walks through the expansion ancestors of `original_span` to find a span that
 /// is contained in `body_span` and has the same [syntax context] as `body_span`.
 /// The ancestor that was traversed just before the matching span (if any) is
-/// also returned.
+/// also returned. That ancestor is a span of the code that a macro invocation
+/// in the body expanded to.
 ///
 /// For example, a return value of `Some((ancestor, Some(prev)))` means that:
 /// - `ancestor == original_span.find_ancestor_inside_same_ctxt(body_span)`
 /// - `prev.parent_callsite() == ancestor`
 ///
 /// [syntax context]: rustc_span::SyntaxContext
-fn unexpand_into_body_span_with_prev(
+pub(crate) fn unexpand_into_body_span_with_prev(
     original_span: Span,
     body_span: Span,
 ) -> Option<(Span, Option<Span>)> {
@@ -53,3 +39,26 @@ fn unexpand_into_body_span_with_prev(
 
     Some((curr, prev))
 }
+
+/// If `original_span` belongs to code that `include!` pulled into the body from
+/// another file, returns the span of that code in the included file.
+///
+/// The parser gives included code the root syntax context, so it has no
+/// expansion ancestor inside the body. Instead, it is recognised by being
+/// root-context code (after walking out of any macros invoked by the included
+/// code itself) that lies in a different file from `body_span`.
+pub(crate) fn unexpand_into_included_file(
+    source_map: &SourceMap,
+    original_span: Span,
+    body_span: Span,
+) -> Option<Span> {
+    let span = original_span.source_callsite();
+    if span.is_dummy() || !span.ctxt().is_root() || body_span.contains(span) {
+        return None;
+    }
+
+    let file_idx = source_map.lookup_source_file_idx(span.lo());
+    let is_included = file_idx != source_map.lookup_source_file_idx(body_span.lo())
+        && file_idx == source_map.lookup_source_file_idx(span.hi());
+    is_included.then_some(span)
+}
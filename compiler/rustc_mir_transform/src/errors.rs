@@ -1,3 +1,6 @@
+use std::io;
+use std::path::Path;
+
 use rustc_errors::codes::*;
 use rustc_errors::{Diag, LintDiagnostic};
 use rustc_macros::{Diagnostic, LintDiagnostic, Subdiagnostic};
@@ -44,6 +47,21 @@ pub(crate) struct UnknownPassName<'a> {
     pub(crate) name: &'a str,
 }
 
+#[derive(Diagnostic)]
+#[diag(mir_transform_coverage_baseline_read_failed)]
+pub(crate) struct CoverageBaselineReadFailed<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) err: io::Error,
+}
+
+#[derive(Diagnostic)]
+#[diag(mir_transform_coverage_baseline_invalid_line)]
+pub(crate) struct CoverageBaselineInvalidLine<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) line_number: usize,
+    pub(crate) line: &'a str,
+}
+
 pub(crate) struct AssertLint<P> {
     pub span: Span,
     pub assert_kind: AssertKind<P>,
@@ -95,6 +113,16 @@ pub(crate) struct FnItemRef {
     pub ident: String,
 }
 
+#[derive(Diagnostic)]
+#[diag(mir_transform_exceeds_mcdc_decision_test_vector_limit)]
+#[note]
+pub(crate) struct MCDCDecisionExceedsTestVectorLimit {
+    #[primary_span]
+    pub(crate) span: Span,
+    pub(crate) num_test_vectors: usize,
+    pub(crate) max_num_test_vectors: usize,
+}
+
 #[derive(Diagnostic)]
 #[diag(mir_transform_exceeds_mcdc_test_vector_limit)]
 pub(crate) struct MCDCExceedsTestVectorLimit {
@@ -591,6 +591,16 @@ fn try_inlining<'tcx, I: Inliner<'tcx>>(
 
     let callee_body = try_instance_mir(tcx, callsite.callee.def)?;
     rustc_mir_build::check_inline::is_inline_valid_on_body(tcx, callee_body)?;
+    // A crate that isn't instrumented for coverage has nowhere to put the
+    // counters of an instrumented callee from another crate, so inlining it
+    // would silently lose the callee's coverage (unless inlining is forced).
+    if callee_body.function_coverage_info.is_some()
+        && !tcx.sess.instrument_coverage()
+        && !tcx.sess.profile_use_coverage()
+        && !matches!(callee_attrs.inline, InlineAttr::Force { .. })
+    {
+        return Err("callee is instrumented for coverage, but the caller is not");
+    }
     inliner.check_callee_mir_body(callsite, callee_body, callee_attrs)?;
 
     let Ok(callee_body) = callsite.callee.try_instantiate_mir_and_normalize_erasing_regions(
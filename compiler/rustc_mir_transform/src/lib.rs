@@ -122,7 +122,7 @@ macro_rules! declare_passes {
 
     mod copy_prop : CopyProp;
     mod coroutine : StateTransform;
-    mod coverage : InstrumentCoverage;
+    mod coverage : InstrumentCoverage { Initial, AfterInline };
     mod ctfe_limit : CtfeLimit;
     mod dataflow_const_prop : DataflowConstProp;
     mod dead_store_elimination : DeadStoreElimination {
@@ -433,7 +433,11 @@ fn mir_promoted(
     pm::run_passes(
         tcx,
         &mut body,
-        &[&promote_pass, &simplify::SimplifyCfg::PromoteConsts, &coverage::InstrumentCoverage],
+        &[
+            &promote_pass,
+            &simplify::SimplifyCfg::PromoteConsts,
+            &coverage::InstrumentCoverage::Initial,
+        ],
         Some(MirPhase::Analysis(AnalysisPhase::Initial)),
     );
 
@@ -627,6 +631,8 @@ fn o1<T>(x: T) -> WithMinOptLevel<T> {
             &inline::ForceInline,
             // Perform inlining, which may add a lot of code.
             &inline::Inline,
+            // With `-Zcoverage-options=post-inline`, instrument the inlined code.
+            &coverage::InstrumentCoverage::AfterInline,
             // Code from other crates may have storage markers, so this needs to happen after
             // inlining.
             &remove_storage_markers::RemoveStorageMarkers,
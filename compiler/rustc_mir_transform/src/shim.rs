@@ -20,8 +20,8 @@
 use tracing::{debug, instrument};
 
 use crate::{
-    abort_unwinding_calls, add_call_guards, add_moves_for_packed_drops, deref_separator, inline,
-    instsimplify, mentioned_items, pass_manager as pm, remove_noop_landing_pads, simplify,
+    abort_unwinding_calls, add_call_guards, add_moves_for_packed_drops, coverage, deref_separator,
+    inline, instsimplify, mentioned_items, pass_manager as pm, remove_noop_landing_pads, simplify,
 };
 
 mod async_destructor_ctor;
@@ -124,7 +124,11 @@ fn make_shim<'tcx>(tcx: TyCtxt<'tcx>, instance: ty::InstanceKind<'tcx>) -> Body<
                 return body;
             }
 
-            build_drop_shim(tcx, def_id, ty)
+            let mut body = build_drop_shim(tcx, def_id, ty);
+            if let Some(ty) = ty {
+                coverage::drop_glue::instrument_drop_glue(tcx, &mut body, ty);
+            }
+            body
         }
         ty::InstanceKind::ThreadLocalShim(..) => build_thread_local_shim(tcx, instance),
         ty::InstanceKind::CloneShim(def_id, ty) => build_clone_shim(tcx, def_id, ty),
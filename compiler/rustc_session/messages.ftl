@@ -8,6 +8,10 @@ session_cannot_mix_and_match_sanitizers = `-Zsanitizer={$first}` is incompatible
 session_cli_feature_diagnostic_help =
     add `-Zcrate-attr="feature({$feature})"` to the command-line options to enable
 
+session_coverage_options_incompatible = `-Zcoverage-options={$first}` is incompatible with `-Zcoverage-options={$second}`
+
+session_coverage_profile_guided_requires_profile_use = `-Zcoverage-options=profile-guided` requires `-Cprofile-use`
+
 session_crate_name_does_not_match = `--crate-name` and `#[crate_name]` are required to match, but `{$s}` != `{$name}`
 
 session_crate_name_empty = crate name must not be empty
@@ -86,6 +90,10 @@ session_octal_float_literal_not_supported = octal float literal is not supported
 
 session_profile_sample_use_file_does_not_exist = file `{$path}` passed to `-C profile-sample-use` does not exist
 
+session_profile_use_coverage_requires_profile_use = `-Zprofile-use-coverage` requires `-Cprofile-use`
+
+session_profile_use_coverage_with_instrument_coverage = `-Zprofile-use-coverage` cannot be combined with `-Cinstrument-coverage`
+
 session_profile_use_file_does_not_exist = file `{$path}` passed to `-C profile-use` does not exist
 
 session_sanitizer_cfi_canonical_jump_tables_requires_cfi = `-Zsanitizer-cfi-canonical-jump-tables` requires `-Zsanitizer=cfi`
@@ -98,6 +106,8 @@ session_sanitizer_cfi_requires_lto = `-Zsanitizer=cfi` requires `-Clto` or `-Cli
 
 session_sanitizer_cfi_requires_single_codegen_unit = `-Zsanitizer=cfi` with `-Clto` requires `-Ccodegen-units=1`
 
+session_sanitizer_coverage_not_supported = `-Zsanitizer-coverage={$option}` is not supported for this target
+
 session_sanitizer_kcfi_requires_panic_abort = `-Z sanitizer=kcfi` requires `-C panic=abort`
 
 session_sanitizer_not_supported = {$us} sanitizer is not supported for this target
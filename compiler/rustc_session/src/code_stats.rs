@@ -90,10 +90,37 @@ pub struct VTableSizeInfo {
     pub upcasting_cost_percent: f64,
 }
 
+/// The size of one function's coverage instrumentation and mappings, as
+/// reported by `-Zcoverage-stats`.
+pub struct CoverageFnStats {
+    pub function_name: String,
+
+    /// Number of nodes in the function's coverage graph.
+    pub bcbs: usize,
+
+    /// Number of physical counters incremented at runtime, which is 0 for
+    /// functions that were not codegenned.
+    pub counters: usize,
+
+    pub expressions: usize,
+
+    /// Number of branch regions, including MC/DC branch regions.
+    pub branch_regions: usize,
+
+    pub mcdc_decisions: usize,
+
+    /// Size in bytes of the function's encoded mapping data.
+    pub mapping_bytes: usize,
+}
+
 #[derive(Default)]
 pub struct CodeStats {
     type_sizes: Lock<FxHashSet<TypeSizeInfo>>,
     vtable_sizes: Lock<FxHashMap<DefId, VTableSizeInfo>>,
+    /// Keyed by the name of each function's coverage record, so that a record
+    /// emitted by several codegen units is only counted once, like the linker
+    /// only keeps one copy of it.
+    coverage_fns: Lock<FxHashMap<String, CoverageFnStats>>,
 }
 
 impl CodeStats {
@@ -267,4 +294,44 @@ pub fn print_vtable_sizes(&self, crate_name: Symbol) {
             );
         }
     }
+
+    pub fn record_coverage_fn(&self, record_name: &str, stats: CoverageFnStats) {
+        self.coverage_fns.lock().insert(record_name.to_owned(), stats);
+    }
+
+    pub fn print_coverage_stats(&self, crate_name: Symbol) {
+        // We will soon sort, so the initial order does not matter.
+        #[allow(rustc::potential_query_instability)]
+        let mut fns =
+            std::mem::take(&mut *self.coverage_fns.lock()).into_values().collect::<Vec<_>>();
+
+        // Largest counter count first, then by name, so that the output is deterministic.
+        fns.sort_by(|a, b| {
+            b.counters.cmp(&a.counters).then_with(|| a.function_name.cmp(&b.function_name))
+        });
+
+        let total = |f: fn(&CoverageFnStats) -> usize| fns.iter().map(f).sum::<usize>();
+        let prefix = "coverage-stats";
+
+        eprintln!("{prefix} COVERAGE STATS FOR `{crate_name}`");
+        eprintln!("{} {:<23}{:>10}", prefix, "Item", "Count");
+        eprintln!("{prefix} ----------------------------------------------------------------");
+        for (label, count) in [
+            ("Functions", fns.len()),
+            ("BCBs", total(|f| f.bcbs)),
+            ("Physical counters", total(|f| f.counters)),
+            ("Expressions", total(|f| f.expressions)),
+            ("Branch regions", total(|f| f.branch_regions)),
+            ("MC/DC decisions", total(|f| f.mcdc_decisions)),
+            ("Mapping bytes", total(|f| f.mapping_bytes)),
+        ] {
+            eprintln!("{prefix} {label:<23}{count:>10}");
+        }
+        eprintln!("{prefix} ----------------------------------------------------------------");
+        eprintln!("{} {:<23}{:>10}", prefix, "Largest functions", "Counters");
+        for CoverageFnStats { function_name, counters, .. } in fns.iter().take(10) {
+            eprintln!("{prefix} {counters:>33}  {function_name}");
+        }
+        eprintln!("{prefix}");
+    }
 }
@@ -165,11 +165,248 @@ pub struct CoverageOptions {
     /// regression tests for #133606, because we don't have an easy way to
     /// reproduce it from actual source code.
     pub discard_all_spans_in_codegen: bool,
+
+    /// `-Zcoverage-options=sysroot-local-paths`: For standard library source
+    /// files whose paths were remapped to `/rustc/$hash`, embed the path of
+    /// the corresponding file in the local `rust-src` component instead, so
+    /// that `llvm-cov` can find those files without `--path-equivalence`.
+    pub sysroot_local_paths: bool,
+
+    /// `-Zcoverage-options=stable-ids`: Derive each function's coverage hash
+    /// from its def-path and the structure of its coverage graph, instead of
+    /// from its HIR. Functions whose control flow is unchanged will then keep
+    /// the same hash and counter layout across rebuilds, so that profiles from
+    /// before and after reloading a rebuilt dylib can be merged.
+    pub stable_ids: bool,
+
+    /// `-Zcoverage-options=thread-local-counters`: Increment per-thread shadow
+    /// copies of each function's counters instead of the shared counters, and
+    /// emit a layout table that lets the profiler runtime merge those shadows
+    /// back into the shared counters when a thread exits or profiles are
+    /// flushed.
+    pub thread_local_counters: bool,
+
+    /// `-Zcoverage-options=first-hit-timestamps`: Alongside each function's
+    /// counters, keep a table recording when each counter was first
+    /// incremented, in an auxiliary section that can be dumped with the profile.
+    pub first_hit_timestamps: bool,
+
+    /// `-Zcoverage-options=counter-metadata`: Emit a side table describing
+    /// static properties (loop depth, branch fan-out) of the site of each
+    /// physical counter, for use by tools such as corpus minimizers.
+    pub counter_metadata: bool,
+
+    /// `-Zcoverage-options=post-inline`: Instrument each function after the
+    /// MIR inliner has run, instead of before borrowck, so that counters
+    /// reflect the code that actually executes. Code inlined from other
+    /// functions is attributed to its call site.
+    pub post_inline: bool,
+
+    /// `-Zcoverage-options=split-at-panics`: End a coverage block after each
+    /// likely panic site (e.g. `unwrap()` or a bounds check), so that code
+    /// after a panic isn't counted as having run.
+    pub split_at_panics: bool,
+
+    /// `-Zcoverage-options=instrument-shims`: Also instrument compiler-generated
+    /// shims. Currently this covers drop glue for local types, which is
+    /// attributed to the type's `impl Drop` block, or to its definition.
+    pub instrument_shims: bool,
+
+    /// `-Zcoverage-options=const-eval`: Count the coverage counters of local
+    /// functions that run during const evaluation, and write those counts to a
+    /// text profile that can be merged with runtime profiles.
+    pub const_eval: bool,
+
+    /// `-Zcoverage-options=counter-regions`: Emit a side table mapping each
+    /// physical counter to the source regions that it counts, so that fuzzers
+    /// can weight inputs by the source code they reach.
+    pub counter_regions: bool,
+
+    /// `-Zcoverage-options=test-harness`: Instrument the `main` function that
+    /// `--test` synthesizes, attributing its regions to a synthetic
+    /// `<test harness>` file instead of to any user file.
+    pub test_harness: bool,
+
+    /// `-Zcoverage-options=subexpr-calls` or `subexpr-all`: Experimentally end
+    /// a coverage block after each sub-expression of the given granularity, so
+    /// that e.g. each call in `a.min(b).max(c)` gets its own counter.
+    pub subexpr: Option<SubexprGranularity>,
+
+    /// `-Zcoverage-options=paths`: Experimentally count each acyclic path
+    /// through a function's coverage graph, using Ball-Larus path numbering,
+    /// and emit a table describing the spans along each path.
+    pub paths: bool,
+
+    /// `-Zcoverage-options=call-graph`: Record which functions each
+    /// instrumented function calls, counting statically known callees in a
+    /// side table and value-profiling the targets of indirect calls.
+    pub call_graph: bool,
+
+    /// `-Zcoverage-options=assertions`: For each panicking guard (a built-in
+    /// check, an `assert!`, or a call to a `#[track_caller]` function such as
+    /// `unwrap`), count how often it was evaluated and how often it failed.
+    pub assertions: bool,
+
+    /// `-Zcoverage-options=no-abort-flush`: Don't register a hook that lets
+    /// the standard library write the profile before aborting the process,
+    /// so that only processes that exit normally write a profile.
+    pub no_abort_flush: bool,
+
+    /// `-Zcoverage-options=overflow-checks`: Increment counters with
+    /// saturating arithmetic, and set a per-function flag when a counter
+    /// saturates, so that reports can mark its counts as suspect.
+    pub overflow_checks: bool,
+
+    /// `-Zcoverage-options=post-dominators`: Compute post-dominators of each
+    /// function's coverage graph, so that a node that always runs exactly as
+    /// many times as a node that dominates it can share that node's counter.
+    pub post_dominators: bool,
+
+    /// `-Zcoverage-options=unwind-paths`: Keep the edges that unwind into
+    /// cleanup blocks in the coverage graph, so that code which only runs
+    /// while unwinding from a panic gets counters of its own.
+    pub unwind_paths: bool,
+
+    /// `-Zcoverage-options=coroutine-drops`: Keep the edge from each `yield`
+    /// to the code that runs if the coroutine is dropped while suspended
+    /// there, so that code gets counters of its own.
+    pub coroutine_drops: bool,
+
+    /// `-Zcoverage-options=static-unreachable`: Give no counters to code that
+    /// is only reachable through a branch on a constant that can never be
+    /// taken, and report that code as never having run.
+    pub static_unreachable: bool,
+
+    /// `-Zcoverage-options=spanning-tree`: Only give physical counters to the
+    /// edges off a maximal spanning tree of the coverage graph, and derive
+    /// every other count from them.
+    pub spanning_tree: bool,
+
+    /// `-Zcoverage-options=boolean-counters`: Make each physical counter a
+    /// single byte that only records whether it was reached, instead of a
+    /// 64-bit count.
+    pub boolean_counters: bool,
+
+    /// `-Zcoverage-options=profile-guided`: Use the counts recorded in the
+    /// coverage profile passed with `-Cprofile-use` to keep physical counters
+    /// off the hottest edges.
+    pub profile_guided: bool,
+
+    /// `-Zcoverage-options=switch-arms`: Count how often each arm of a dense
+    /// multi-way `SwitchInt` is taken, with a single indexed increment at the
+    /// switch.
+    pub switch_arms: bool,
+
+    /// `-Zcoverage-options=non-atomic`: Increment counters with plain loads
+    /// and stores, instead of atomic read-modify-write operations.
+    pub non_atomic: bool,
+
+    /// `-Zcoverage-options=saturating`: Increment counters with saturating
+    /// arithmetic, so that they stick at their largest value instead of
+    /// wrapping around to zero.
+    pub saturating: bool,
+
+    /// `-Zcoverage-options=expansions`: Map code in the expansions of macros
+    /// defined in the current crate to the macro definitions, with expansion
+    /// regions at the call sites, instead of only to the call sites.
+    pub expansions: bool,
+
+    /// `-Zcoverage-options=macro-definitions`: Also map code in the expansions
+    /// of macros defined in the current crate directly to the lines of their
+    /// definitions, so that the definitions show how often each arm ran.
+    pub macro_definitions: bool,
+
+    /// `-Zcoverage-options=gap-regions`: Emit gap regions after `return`,
+    /// `break`, `continue` and `become`, like clang does for C and C++.
+    pub gap_regions: bool,
+
+    /// `-Zcoverage-options=skipped-regions`: Emit skipped regions for nested
+    /// items and closures that are excluded by `#[coverage(off)]`.
+    pub skipped_regions: bool,
+
+    /// `-Zcoverage-options=line-only`: Widen every region to whole lines before
+    /// encoding it, for consumers that only use per-line data.
+    pub line_only: bool,
+
+    /// `-Zcoverage-options=recover-spans`: Give statements and tail expressions
+    /// that lowering left without any MIR span the count of a dominating node.
+    pub recover_spans: bool,
+
+    /// `-Zcoverage-options=folded-branches-omit` or `folded-branches-mark`:
+    /// How to report branches whose condition is a literal constant, and which
+    /// therefore can only ever go one way.
+    pub folded_branches: Option<FoldedBranches>,
+
+    /// `-Zcoverage-options=collapse-external-macros`: Give each invocation of a
+    /// bang-macro from another crate a single region, instead of separate
+    /// regions for its arguments and the code that it expands to.
+    pub collapse_external_macros: bool,
+
+    /// `-Zcoverage-options=raw-spans`: Skip the merging, sorting and carving
+    /// out of holes that normally refines the spans extracted from MIR, and
+    /// emit one region per extracted span instead.
+    ///
+    /// For internal debugging only.
+    pub raw_spans: bool,
+
+    /// `-Zcoverage-options=instrument-derives`: Also instrument the functions
+    /// generated by `#[derive(..)]`, mapping their regions to the definition
+    /// of the type that they were derived for.
+    pub instrument_derives: bool,
+
+    /// `-Zcoverage-options=char-columns`: Count the columns of mapping regions
+    /// in Unicode scalar values rather than bytes.
+    pub char_columns: bool,
+
+    /// `-Zcoverage-options=per-instance`: Name the coverage record of each
+    /// instance of a trait's default method after the impl that it was
+    /// instantiated for, so that each impl's coverage can be told apart.
+    pub per_instance: bool,
+
+    /// `-Zcoverage-options=shared-file-table`: Give every codegen unit the
+    /// same crate-wide table of file names, so that the linker can keep a
+    /// single copy of it.
+    pub shared_file_table: bool,
+
+    /// `-Zcoverage-options=loop-depth-order`: When choosing which nodes of the
+    /// coverage graph get physical counters, visit nodes in fewer loops first,
+    /// so that nodes in inner loops are more likely to get counter expressions.
+    pub loop_depth_order: bool,
+
+    /// `-Zcoverage-options=shared-counters`: Let every node of the coverage
+    /// graph that is known to run as often as another node share that node's
+    /// counter.
+    pub shared_counters: bool,
+}
+
+/// Which sub-expressions get their own counter under the experimental
+/// sub-expression coverage mode.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum SubexprGranularity {
+    /// Every function or method call that returns to its caller, including
+    /// calls to overloaded operators.
+    Calls,
+    /// Calls, and also built-in operations that can panic (e.g. overflow and
+    /// bounds checks) and drops that can run user code.
+    All,
+}
+
+/// How branches whose condition is known at compile time are reported.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum FoldedBranches {
+    /// Emit no branch region for them.
+    Omit,
+    /// Emit a branch region whose outcomes both have the constant count zero,
+    /// which `llvm-cov` reports as folded rather than as not taken.
+    Mark,
 }
 
 /// Controls whether branch coverage or MC/DC coverage is enabled.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
 pub enum CoverageLevel {
+    /// Only instrument the entry of each function, with a single region for
+    /// the whole function.
+    Function,
     /// Instrument for coverage at the MIR block level.
     #[default]
     Block,
@@ -196,6 +433,26 @@ pub enum CoverageLevel {
     Mcdc,
 }
 
+/// Individual flag values controlled by `-Zsanitizer-coverage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SanitizerCoverageOptions {
+    /// `-Zsanitizer-coverage=inline-8bit-counters`, increment an inline 8-bit
+    /// counter on each control-flow edge
+    pub inline_8bit_counters: bool,
+    /// `-Zsanitizer-coverage=stack-depth`, track the lowest stack address seen
+    /// in `__sancov_lowest_stack`
+    pub stack_depth: bool,
+    /// `-Zsanitizer-coverage=control-flow`, emit a table of each function's
+    /// control-flow graph, including the targets of indirect calls
+    pub control_flow: bool,
+}
+
+impl SanitizerCoverageOptions {
+    pub fn is_empty(&self) -> bool {
+        !(self.inline_8bit_counters || self.stack_depth || self.control_flow)
+    }
+}
+
 /// Settings for `-Z instrument-xray` flag.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct InstrumentXRay {
@@ -842,6 +1099,7 @@ pub enum PrintKind {
     LinkArgs,
     SplitDebuginfo,
     DeploymentTarget,
+    CoverageFormat,
 }
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -1974,6 +2232,7 @@ fn collect_print_requests(
         ("cfg", PrintKind::Cfg),
         ("check-cfg", PrintKind::CheckCfg),
         ("code-models", PrintKind::CodeModels),
+        ("coverage-format", PrintKind::CoverageFormat),
         ("crate-name", PrintKind::CrateName),
         ("deployment-target", PrintKind::DeploymentTarget),
         ("file-names", PrintKind::FileNames),
@@ -2033,6 +2292,16 @@ fn collect_print_requests(
                     );
                 }
             }
+            Some((_, PrintKind::CoverageFormat)) => {
+                if unstable_opts.unstable_options {
+                    PrintKind::CoverageFormat
+                } else {
+                    early_dcx.early_fatal(
+                        "the `-Z unstable-options` flag must also be passed to \
+                         enable the coverage-format print option",
+                    );
+                }
+            }
             Some(&(_, print_kind)) => print_kind,
             None => {
                 let prints =
@@ -2911,7 +3180,8 @@ pub(crate) mod dep_tracking {
         InliningThreshold, InstrumentCoverage, InstrumentXRay, LinkerPluginLto, LocationDetail,
         LtoCli, MirStripDebugInfo, NextSolverConfig, OomStrategy, OptLevel, OutFileName,
         OutputType, OutputTypes, PatchableFunctionEntry, Polonius, RemapPathScopeComponents,
-        ResolveDocLinks, SourceFileHashAlgorithm, SplitDwarfKind, SwitchWithOptPath,
+        ResolveDocLinks, SanitizerCoverageOptions, SourceFileHashAlgorithm, SplitDwarfKind,
+        SwitchWithOptPath,
         SymbolManglingVersion, WasiExecModel,
     };
     use crate::lint;
@@ -2970,6 +3240,7 @@ fn hash(
         TlsModel,
         InstrumentCoverage,
         CoverageOptions,
+        SanitizerCoverageOptions,
         InstrumentXRay,
         CrateType,
         MergeFunctions,
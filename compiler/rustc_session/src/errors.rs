@@ -88,6 +88,25 @@ pub(crate) struct ProfileUseFileDoesNotExist<'a> {
     pub(crate) path: &'a std::path::Path,
 }
 
+#[derive(Diagnostic)]
+#[diag(session_profile_use_coverage_requires_profile_use)]
+pub(crate) struct ProfileUseCoverageRequiresProfileUse;
+
+#[derive(Diagnostic)]
+#[diag(session_profile_use_coverage_with_instrument_coverage)]
+pub(crate) struct ProfileUseCoverageWithInstrumentCoverage;
+
+#[derive(Diagnostic)]
+#[diag(session_coverage_options_incompatible)]
+pub(crate) struct CoverageOptionsIncompatible {
+    pub(crate) first: &'static str,
+    pub(crate) second: &'static str,
+}
+
+#[derive(Diagnostic)]
+#[diag(session_coverage_profile_guided_requires_profile_use)]
+pub(crate) struct CoverageProfileGuidedRequiresProfileUse;
+
 #[derive(Diagnostic)]
 #[diag(session_profile_sample_use_file_does_not_exist)]
 pub(crate) struct ProfileSampleUseFileDoesNotExist<'a> {
@@ -116,6 +135,12 @@ pub(crate) struct SanitizersNotSupported {
     pub(crate) us: String,
 }
 
+#[derive(Diagnostic)]
+#[diag(session_sanitizer_coverage_not_supported)]
+pub(crate) struct SanitizerCoverageNotSupported {
+    pub(crate) option: &'static str,
+}
+
 #[derive(Diagnostic)]
 #[diag(session_cannot_mix_and_match_sanitizers)]
 pub(crate) struct CannotMixAndMatchSanitizers {
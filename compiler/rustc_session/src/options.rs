@@ -426,8 +426,20 @@ mod desc {
     pub(crate) const parse_linker_flavor: &str = ::rustc_target::spec::LinkerFlavorCli::one_of();
     pub(crate) const parse_dump_mono_stats: &str = "`markdown` (default) or `json`";
     pub(crate) const parse_instrument_coverage: &str = parse_bool;
-    pub(crate) const parse_coverage_options: &str =
-        "`block` | `branch` | `condition` | `mcdc` | `no-mir-spans`";
+    pub(crate) const parse_coverage_options: &str = "`function` | `block` | `branch` | \
+        `condition` | `mcdc` | `no-mir-spans` | `sysroot-local-paths` | `stable-ids` | \
+        `thread-local-counters` | `first-hit-timestamps` | `counter-metadata` | `post-inline` | `split-at-panics` | \
+        `instrument-shims` | `const-eval` | `counter-regions` | `test-harness` | \
+        `subexpr-calls` | `subexpr-all` | `paths` | `call-graph` | `assertions` | \
+        `no-abort-flush` | `overflow-checks` | `post-dominators` | `unwind-paths` | \
+        `coroutine-drops` | `static-unreachable` | `spanning-tree` | `boolean-counters` | \
+        `profile-guided` | `switch-arms` | `non-atomic` | `saturating` | `expansions` | \
+        `macro-definitions` | `gap-regions` | `skipped-regions` | `line-only` | \
+        `recover-spans` | `folded-branches-omit` | `folded-branches-mark` | \
+        `collapse-external-macros` | `raw-spans` | `instrument-derives` | `char-columns` | \
+        `per-instance` | `shared-file-table` | `loop-depth-order` | `shared-counters`";
+    pub(crate) const parse_sanitizer_coverage: &str =
+        "a comma-separated list of `inline-8bit-counters` | `stack-depth` | `control-flow`";
     pub(crate) const parse_instrument_xray: &str = "either a boolean (`yes`, `no`, `on`, `off`, etc), or a comma separated list of settings: `always` or `never` (mutually exclusive), `ignore-loops`, `instruction-threshold=N`, `skip-entry`, `skip-exit`";
     pub(crate) const parse_unpretty: &str = "`string` or `string=string`";
     pub(crate) const parse_treat_err_as_bug: &str = "either no value or a non-negative number";
@@ -1056,17 +1068,79 @@ pub(crate) fn parse_instrument_coverage(
         true
     }
 
+    pub(crate) fn parse_sanitizer_coverage(
+        slot: &mut SanitizerCoverageOptions,
+        v: Option<&str>,
+    ) -> bool {
+        let Some(v) = v else { return false };
+
+        for option in v.split(',') {
+            match option {
+                "inline-8bit-counters" => slot.inline_8bit_counters = true,
+                "stack-depth" => slot.stack_depth = true,
+                "control-flow" => slot.control_flow = true,
+                _ => return false,
+            }
+        }
+        true
+    }
+
     pub(crate) fn parse_coverage_options(slot: &mut CoverageOptions, v: Option<&str>) -> bool {
         let Some(v) = v else { return true };
 
         for option in v.split(',') {
             match option {
+                "function" => slot.level = CoverageLevel::Function,
                 "block" => slot.level = CoverageLevel::Block,
                 "branch" => slot.level = CoverageLevel::Branch,
                 "condition" => slot.level = CoverageLevel::Condition,
                 "mcdc" => slot.level = CoverageLevel::Mcdc,
                 "no-mir-spans" => slot.no_mir_spans = true,
                 "discard-all-spans-in-codegen" => slot.discard_all_spans_in_codegen = true,
+                "sysroot-local-paths" => slot.sysroot_local_paths = true,
+                "stable-ids" => slot.stable_ids = true,
+                "thread-local-counters" => slot.thread_local_counters = true,
+                "first-hit-timestamps" => slot.first_hit_timestamps = true,
+                "counter-metadata" => slot.counter_metadata = true,
+                "post-inline" => slot.post_inline = true,
+                "split-at-panics" => slot.split_at_panics = true,
+                "instrument-shims" => slot.instrument_shims = true,
+                "const-eval" => slot.const_eval = true,
+                "counter-regions" => slot.counter_regions = true,
+                "test-harness" => slot.test_harness = true,
+                "subexpr-calls" => slot.subexpr = Some(SubexprGranularity::Calls),
+                "subexpr-all" => slot.subexpr = Some(SubexprGranularity::All),
+                "paths" => slot.paths = true,
+                "call-graph" => slot.call_graph = true,
+                "assertions" => slot.assertions = true,
+                "no-abort-flush" => slot.no_abort_flush = true,
+                "overflow-checks" => slot.overflow_checks = true,
+                "post-dominators" => slot.post_dominators = true,
+                "unwind-paths" => slot.unwind_paths = true,
+                "coroutine-drops" => slot.coroutine_drops = true,
+                "static-unreachable" => slot.static_unreachable = true,
+                "spanning-tree" => slot.spanning_tree = true,
+                "boolean-counters" => slot.boolean_counters = true,
+                "profile-guided" => slot.profile_guided = true,
+                "switch-arms" => slot.switch_arms = true,
+                "non-atomic" => slot.non_atomic = true,
+                "saturating" => slot.saturating = true,
+                "expansions" => slot.expansions = true,
+                "macro-definitions" => slot.macro_definitions = true,
+                "gap-regions" => slot.gap_regions = true,
+                "skipped-regions" => slot.skipped_regions = true,
+                "line-only" => slot.line_only = true,
+                "recover-spans" => slot.recover_spans = true,
+                "folded-branches-omit" => slot.folded_branches = Some(FoldedBranches::Omit),
+                "folded-branches-mark" => slot.folded_branches = Some(FoldedBranches::Mark),
+                "collapse-external-macros" => slot.collapse_external_macros = true,
+                "raw-spans" => slot.raw_spans = true,
+                "instrument-derives" => slot.instrument_derives = true,
+                "char-columns" => slot.char_columns = true,
+                "per-instance" => slot.per_instance = true,
+                "shared-file-table" => slot.shared_file_table = true,
+                "loop-depth-order" => slot.loop_depth_order = true,
+                "shared-counters" => slot.shared_counters = true,
                 _ => return false,
             }
         }
@@ -1754,8 +1828,20 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "the backend to use"),
     combine_cgu: bool = (false, parse_bool, [TRACKED],
         "combine CGUs into a single one"),
+    coverage_baseline: Option<PathBuf> = (None, parse_opt_pathbuf, [TRACKED],
+        "give an entry counter only to functions whose coverage hash is listed in this file, \
+        and fully instrument all other functions"),
+    coverage_max_expression_terms: Option<usize> = (None, parse_opt_number, [TRACKED],
+        "give a coverage node its own counter if its counter expression would otherwise \
+        combine more than this many counters (default: no limit)"),
+    coverage_mcdc_max_test_vectors: Option<usize> = (None, parse_opt_number, [TRACKED],
+        "only instrument MC/DC decisions with at most this many test vectors, and give larger \
+        decisions branch coverage instead (default: no limit)"),
     coverage_options: CoverageOptions = (CoverageOptions::default(), parse_coverage_options, [TRACKED],
         "control details of coverage instrumentation"),
+    coverage_stats: bool = (false, parse_bool, [UNTRACKED],
+        "print statistics about the coverage instrumentation and mappings of the crate \
+        (default: no)"),
     crate_attr: Vec<String> = (Vec::new(), parse_string_push, [TRACKED],
         "inject the given attribute in the crate"),
     cross_crate_inline_threshold: InliningThreshold = (InliningThreshold::Sometimes(100), parse_inlining_threshold, [TRACKED],
@@ -1775,8 +1861,14 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         themselves (default: no)"),
     direct_access_external_data: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "Direct or use GOT indirect to reference external data symbols"),
+    doctest_line_table: Option<PathBuf> = (None, parse_opt_pathbuf, [TRACKED],
+        "read a table that maps the lines of merged doctests back to their original source, \
+        so that coverage regions can point at the doctests (used by rustdoc)"),
     dual_proc_macros: bool = (false, parse_bool, [TRACKED],
         "load proc macros for both target and host, but only link to the target (default: no)"),
+    dump_coverage_graph: bool = (false, parse_bool, [UNTRACKED],
+        "write the coverage graph of each instrumented function as a graphviz `.dot` file \
+        into the MIR dump directory (default: no)"),
     dump_dep_graph: bool = (false, parse_bool, [UNTRACKED],
         "dump the dependency graph to $RUST_DEP_GRAPH (default: /tmp/dep_graph.gv) \
         (default: no)"),
@@ -1876,6 +1968,9 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "enable LLVM inlining (default: yes)"),
     inline_mir: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "enable MIR inlining (default: no)"),
+    inline_mir_coverage_penalty: Option<usize> = (None, parse_opt_number, [TRACKED],
+        "extra inlining cost for each coverage counter in the callee, when \
+        instrumenting for coverage (default: 10)"),
     inline_mir_forwarder_threshold: Option<usize> = (None, parse_opt_number, [TRACKED],
         "inlining threshold when the caller is a simple forwarding function (default: 30)"),
     inline_mir_hint_threshold: Option<usize> = (None, parse_opt_number, [TRACKED],
@@ -1887,6 +1982,9 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "a default MIR inlining threshold (default: 50)"),
     input_stats: bool = (false, parse_bool, [UNTRACKED],
         "print some statistics about AST and HIR (default: no)"),
+    instrument_function_entry_exit: bool = (false, parse_bool, [TRACKED],
+        "call `__cyg_profile_func_enter` and `__cyg_profile_func_exit` on entry to and \
+        exit from each function (default: no)"),
     instrument_mcount: bool = (false, parse_bool, [TRACKED],
         "insert function instrument code for mcount-based tracing (default: no)"),
     instrument_xray: Option<InstrumentXRay> = (None, parse_instrument_xray, [TRACKED],
@@ -2046,6 +2144,9 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "profile size of closures"),
     profile_sample_use: Option<PathBuf> = (None, parse_opt_pathbuf, [TRACKED],
         "use the given `.prof` file for sampled profile-guided optimization (also known as AutoFDO)"),
+    profile_use_coverage: bool = (false, parse_bool, [TRACKED],
+        "treat the `-C profile-use` profile as having been collected from a \
+        `-C instrument-coverage` build, and derive branch weights from its counters (default: no)"),
     profiler_runtime: String = (String::from("profiler_builtins"), parse_string, [TRACKED],
         "name of the profiler runtime crate to automatically inject (default: `profiler_builtins`)"),
     query_dep_graph: bool = (false, parse_bool, [UNTRACKED],
@@ -2077,6 +2178,12 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "enable generalizing pointer types (default: no)"),
     sanitizer_cfi_normalize_integers: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "enable normalizing integer types (default: no)"),
+    sanitizer_coverage: SanitizerCoverageOptions = (SanitizerCoverageOptions::default(), parse_sanitizer_coverage, [TRACKED],
+        "instrument code with the given SanitizerCoverage features, for fuzzer feedback; \
+        can be combined with `-C instrument-coverage`"),
+    sanitizer_coverage_inline_8bit_counters: bool = (false, parse_bool, [TRACKED],
+        "instrument edges with SanitizerCoverage inline 8-bit counters, for fuzzer feedback; \
+        same as `-Z sanitizer-coverage=inline-8bit-counters` (default: no)"),
     sanitizer_dataflow_abilist: Vec<String> = (Vec::new(), parse_comma_list, [TRACKED],
         "additional ABI list files that control how shadow parameters are passed (comma separated)"),
     sanitizer_memory_track_origins: usize = (0, parse_sanitizer_memory_track_origins, [TRACKED],
@@ -2230,6 +2337,9 @@ pub(crate) fn parse_align(slot: &mut Option<Align>, v: Option<&str>) -> bool {
         "use legacy .ctors section for initializers rather than .init_array"),
     use_sync_unwind: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "Generate sync unwind tables instead of async unwind tables (default: no)"),
+    validate_coverage_graph: bool = (false, parse_bool, [UNTRACKED],
+        "check the structure of the coverage graph of each instrumented function, \
+        and report a compiler bug if it is broken (default: no)"),
     validate_mir: bool = (false, parse_bool, [UNTRACKED],
         "validate MIR after each transformation"),
     verbose_asm: bool = (false, parse_bool, [TRACKED],
@@ -37,9 +37,9 @@
 use crate::code_stats::CodeStats;
 pub use crate::code_stats::{DataTypeKind, FieldInfo, FieldKind, SizeKind, VariantInfo};
 use crate::config::{
-    self, CoverageLevel, CrateType, DebugInfo, ErrorOutputType, FunctionReturn, Input,
-    InstrumentCoverage, OptLevel, OutFileName, OutputType, RemapPathScopeComponents,
-    SwitchWithOptPath,
+    self, CoverageLevel, CrateType, DebugInfo, ErrorOutputType, FoldedBranches, FunctionReturn,
+    Input, InstrumentCoverage, OptLevel, OutFileName, OutputType, RemapPathScopeComponents,
+    SanitizerCoverageOptions, SubexprGranularity, SwitchWithOptPath,
 };
 use crate::filesearch::FileSearch;
 use crate::parse::{ParseSess, add_feature_diagnostics};
@@ -332,18 +332,37 @@ pub fn instrument_coverage(&self) -> bool {
         self.opts.cg.instrument_coverage() != InstrumentCoverage::No
     }
 
+    /// True if `-Zprofile-use-coverage` was passed, so the `-Cprofile-use`
+    /// profile holds counters from a `-Cinstrument-coverage` build.
+    pub fn profile_use_coverage(&self) -> bool {
+        self.opts.unstable_opts.profile_use_coverage
+    }
+
+    /// True if MIR building and the `InstrumentCoverage` MIR pass should
+    /// compute coverage counters. This is the case when instrumenting for
+    /// coverage, and also under `-Zprofile-use-coverage`, which needs the same
+    /// counter layout as the build that produced the profile.
+    pub fn coverage_analysis(&self) -> bool {
+        self.instrument_coverage() || self.profile_use_coverage()
+    }
+
+    pub fn instrument_coverage_function_only(&self) -> bool {
+        self.coverage_analysis()
+            && self.opts.unstable_opts.coverage_options.level == CoverageLevel::Function
+    }
+
     pub fn instrument_coverage_branch(&self) -> bool {
-        self.instrument_coverage()
+        self.coverage_analysis()
             && self.opts.unstable_opts.coverage_options.level >= CoverageLevel::Branch
     }
 
     pub fn instrument_coverage_condition(&self) -> bool {
-        self.instrument_coverage()
+        self.coverage_analysis()
             && self.opts.unstable_opts.coverage_options.level >= CoverageLevel::Condition
     }
 
     pub fn instrument_coverage_mcdc(&self) -> bool {
-        self.instrument_coverage()
+        self.coverage_analysis()
             && self.opts.unstable_opts.coverage_options.level >= CoverageLevel::Mcdc
     }
 
@@ -357,6 +376,236 @@ pub fn coverage_discard_all_spans_in_codegen(&self) -> bool {
         self.opts.unstable_opts.coverage_options.discard_all_spans_in_codegen
     }
 
+    /// True if `-Zcoverage-options=sysroot-local-paths` was passed.
+    pub fn coverage_sysroot_local_paths(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.sysroot_local_paths
+    }
+
+    /// True if `-Zcoverage-options=stable-ids` was passed, or if a baseline
+    /// was passed with `-Zcoverage-baseline`, which is matched against the
+    /// same structural hashes.
+    pub fn coverage_stable_ids(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.stable_ids
+            || self.opts.unstable_opts.coverage_baseline.is_some()
+    }
+
+    /// True if `-Zcoverage-options=thread-local-counters` was passed.
+    pub fn coverage_thread_local_counters(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.thread_local_counters
+    }
+
+    /// True if `-Zcoverage-options=first-hit-timestamps` was passed.
+    pub fn coverage_first_hit_timestamps(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.first_hit_timestamps
+    }
+
+    /// True if `-Zcoverage-options=counter-metadata` was passed.
+    pub fn coverage_counter_metadata(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.counter_metadata
+    }
+
+    /// True if `-Zcoverage-options=post-inline` was passed.
+    pub fn coverage_post_inline(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.post_inline
+    }
+
+    /// True if `-Zcoverage-options=split-at-panics` was passed.
+    pub fn coverage_split_at_panics(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.split_at_panics
+    }
+
+    /// True if `-Zcoverage-options=instrument-shims` was passed.
+    pub fn coverage_instrument_shims(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.instrument_shims
+    }
+
+    /// True if `-Zcoverage-options=const-eval` was passed.
+    pub fn coverage_const_eval(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.const_eval
+    }
+
+    /// True if `-Zcoverage-options=counter-regions` was passed.
+    pub fn coverage_counter_regions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.counter_regions
+    }
+
+    /// True if `-Zcoverage-options=test-harness` was passed.
+    pub fn coverage_test_harness(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.test_harness
+    }
+
+    /// The granularity given by `-Zcoverage-options=subexpr-calls` or
+    /// `subexpr-all`, if either was passed.
+    pub fn coverage_subexpr(&self) -> Option<SubexprGranularity> {
+        self.opts.unstable_opts.coverage_options.subexpr
+    }
+
+    /// True if `-Zcoverage-options=paths` was passed.
+    pub fn coverage_paths(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.paths
+    }
+
+    /// True if `-Zcoverage-options=call-graph` was passed.
+    pub fn coverage_call_graph(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.call_graph
+    }
+
+    /// True if `-Zcoverage-options=assertions` was passed.
+    pub fn coverage_assertions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.assertions
+    }
+
+    /// True if `-Zcoverage-options=no-abort-flush` was passed.
+    pub fn coverage_no_abort_flush(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.no_abort_flush
+    }
+
+    /// True if `-Zcoverage-options=overflow-checks` was passed.
+    pub fn coverage_overflow_checks(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.overflow_checks
+    }
+
+    /// True if `-Zcoverage-options=post-dominators` was passed.
+    pub fn coverage_post_dominators(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.post_dominators
+    }
+
+    /// True if `-Zcoverage-options=unwind-paths` was passed.
+    pub fn coverage_unwind_paths(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.unwind_paths
+    }
+
+    /// True if `-Zcoverage-options=coroutine-drops` was passed.
+    pub fn coverage_coroutine_drops(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.coroutine_drops
+    }
+
+    /// True if `-Zcoverage-options=static-unreachable` was passed.
+    pub fn coverage_static_unreachable(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.static_unreachable
+    }
+
+    /// True if `-Zcoverage-options=spanning-tree` was passed.
+    pub fn coverage_spanning_tree(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.spanning_tree
+    }
+
+    /// True if `-Zcoverage-options=boolean-counters` was passed.
+    pub fn coverage_boolean_counters(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.boolean_counters
+    }
+
+    /// True if `-Zcoverage-options=profile-guided` was passed.
+    pub fn coverage_profile_guided(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.profile_guided
+    }
+
+    /// True if `-Zcoverage-options=switch-arms` was passed.
+    pub fn coverage_switch_arms(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.switch_arms
+    }
+
+    /// True if `-Zcoverage-options=non-atomic` was passed.
+    pub fn coverage_non_atomic(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.non_atomic
+    }
+
+    /// True if `-Zcoverage-options=saturating` was passed.
+    pub fn coverage_saturating(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.saturating
+    }
+
+    /// True if `-Zcoverage-options=expansions` was passed.
+    pub fn coverage_expansions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.expansions
+    }
+
+    /// True if `-Zcoverage-options=macro-definitions` was passed.
+    pub fn coverage_macro_definitions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.macro_definitions
+    }
+
+    /// True if `-Zcoverage-options=gap-regions` was passed.
+    pub fn coverage_gap_regions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.gap_regions
+    }
+
+    /// True if `-Zcoverage-options=skipped-regions` was passed.
+    pub fn coverage_skipped_regions(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.skipped_regions
+    }
+
+    /// True if `-Zcoverage-options=line-only` was passed.
+    pub fn coverage_line_only(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.line_only
+    }
+
+    /// True if `-Zcoverage-options=recover-spans` was passed.
+    pub fn coverage_recover_spans(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.recover_spans
+    }
+
+    /// The treatment given by `-Zcoverage-options=folded-branches-omit` or
+    /// `folded-branches-mark`, if either was passed.
+    pub fn coverage_folded_branches(&self) -> Option<FoldedBranches> {
+        self.opts.unstable_opts.coverage_options.folded_branches
+    }
+
+    /// True if `-Zcoverage-options=collapse-external-macros` was passed.
+    pub fn coverage_collapse_external_macros(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.collapse_external_macros
+    }
+
+    /// True if `-Zcoverage-options=raw-spans` was passed.
+    pub fn coverage_raw_spans(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.raw_spans
+    }
+
+    /// True if `-Zcoverage-options=instrument-derives` was passed.
+    pub fn coverage_instrument_derives(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.instrument_derives
+    }
+
+    /// True if `-Zcoverage-options=char-columns` was passed.
+    pub fn coverage_char_columns(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.char_columns
+    }
+
+    /// True if `-Zcoverage-options=per-instance` was passed.
+    pub fn coverage_per_instance(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.per_instance
+    }
+
+    /// True if `-Zcoverage-options=shared-file-table` was passed.
+    pub fn coverage_shared_file_table(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.shared_file_table
+    }
+
+    /// True if `-Zcoverage-options=loop-depth-order` was passed.
+    pub fn coverage_loop_depth_order(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.loop_depth_order
+    }
+
+    /// True if `-Zcoverage-options=shared-counters` was passed.
+    pub fn coverage_shared_counters(&self) -> bool {
+        self.opts.unstable_opts.coverage_options.shared_counters
+    }
+
+    /// True if counters are incremented with saturating arithmetic, which is
+    /// the case under both `-Zcoverage-options=saturating` and
+    /// `-Zcoverage-options=overflow-checks`.
+    pub fn coverage_saturating_counters(&self) -> bool {
+        self.coverage_saturating() || self.coverage_overflow_checks()
+    }
+
+    /// The SanitizerCoverage features enabled by `-Zsanitizer-coverage`, or by
+    /// the older `-Zsanitizer-coverage-inline-8bit-counters` flag.
+    pub fn sanitizer_coverage(&self) -> SanitizerCoverageOptions {
+        let mut options = self.opts.unstable_opts.sanitizer_coverage;
+        options.inline_8bit_counters |= self.opts.unstable_opts.sanitizer_coverage_inline_8bit_counters;
+        options
+    }
+
     pub fn is_sanitizer_cfi_enabled(&self) -> bool {
         self.opts.unstable_opts.sanitizer.contains(SanitizerSet::CFI)
     }
@@ -1124,6 +1373,48 @@ fn validate_commandline_args_with_session_available(sess: &Session) {
         }
     }
 
+    // A coverage profile is read by rustc itself rather than by LLVM, and only
+    // makes sense for a build that isn't itself instrumented.
+    if sess.profile_use_coverage() {
+        if sess.opts.cg.profile_use.is_none() {
+            sess.dcx().emit_err(errors::ProfileUseCoverageRequiresProfileUse);
+        }
+        if sess.instrument_coverage() {
+            sess.dcx().emit_err(errors::ProfileUseCoverageWithInstrumentCoverage);
+        }
+    }
+
+    // Profile-guided counter placement reads the coverage profile of an
+    // earlier instrumented build. Stable IDs would make the layout that the
+    // profile was recorded with, and its hash, depend on the final layout.
+    if sess.coverage_profile_guided() {
+        if sess.opts.cg.profile_use.is_none() {
+            sess.dcx().emit_err(errors::CoverageProfileGuidedRequiresProfileUse);
+        }
+        if sess.coverage_stable_ids() {
+            let (first, second) = ("profile-guided", "stable-ids");
+            sess.dcx().emit_err(errors::CoverageOptionsIncompatible { first, second });
+        }
+    }
+
+    // Boolean counters are single bytes that LLVM's intrinsics can only set,
+    // so they can't be used by options that count into 64-bit counters.
+    if sess.coverage_boolean_counters() {
+        let incompatible = [
+            ("thread-local-counters", sess.coverage_thread_local_counters()),
+            ("overflow-checks", sess.coverage_overflow_checks()),
+            ("saturating", sess.coverage_saturating()),
+            ("call-graph", sess.coverage_call_graph()),
+            ("const-eval", sess.coverage_const_eval()),
+        ];
+        for (second, enabled) in incompatible {
+            if enabled {
+                let first = "boolean-counters";
+                sess.dcx().emit_err(errors::CoverageOptionsIncompatible { first, second });
+            }
+        }
+    }
+
     // Unwind tables cannot be disabled if the target requires them.
     if let Some(include_uwtables) = sess.opts.cg.force_unwind_tables {
         if sess.target.requires_uwtable && !include_uwtables {
@@ -1168,6 +1459,17 @@ fn validate_commandline_args_with_session_available(sess: &Session) {
         sess.dcx().emit_err(errors::CannotEnableCrtStaticLinux);
     }
 
+    // `-Zsanitizer-coverage=stack-depth` records the lowest stack address in a
+    // thread-local variable, and `control-flow` emits its table into a
+    // dedicated section, which needs an object format that LLVM supports.
+    let sanitizer_coverage = sess.sanitizer_coverage();
+    if sanitizer_coverage.stack_depth && !sess.target.has_thread_local {
+        sess.dcx().emit_err(errors::SanitizerCoverageNotSupported { option: "stack-depth" });
+    }
+    if sanitizer_coverage.control_flow && (sess.target.is_like_wasm || sess.target.is_like_aix) {
+        sess.dcx().emit_err(errors::SanitizerCoverageNotSupported { option: "control-flow" });
+    }
+
     // LLVM CFI requires LTO.
     if sess.is_sanitizer_cfi_enabled()
         && !(sess.lto() == config::Lto::Fat || sess.opts.cg.linker_plugin_lto.enabled())
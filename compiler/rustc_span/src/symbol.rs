@@ -1709,6 +1709,7 @@
         rustc_const_stable_indirect,
         rustc_const_unstable,
         rustc_conversion_suggestion,
+        rustc_coverage_exclude,
         rustc_deallocator,
         rustc_def_path,
         rustc_default_body_unstable,
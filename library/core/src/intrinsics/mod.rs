@@ -63,6 +63,8 @@
     issue = "none"
 )]
 #![allow(missing_docs)]
+// Intrinsic fallback bodies and their helpers are not useful in coverage reports.
+#![cfg_attr(not(bootstrap), rustc_coverage_exclude)]
 
 use crate::marker::{DiscriminantKind, Tuple};
 use crate::mem::SizedTypeProperties;
@@ -10,6 +10,7 @@
 #![allow(unused_features)]
 #![feature(asm_experimental_arch)]
 #![feature(core_intrinsics)]
+#![feature(linkage)]
 #![feature(panic_runtime)]
 #![feature(std_internals)]
 #![feature(staged_api)]
@@ -40,6 +41,11 @@ pub unsafe fn __rust_start_panic(_payload: &mut dyn PanicPayload) -> u32 {
     #[cfg(target_os = "zkvm")]
     zkvm::zkvm_set_abort_message(_payload);
 
+    // Coverage-instrumented programs don't get to write their profile at
+    // exit if they abort, so give them a chance to write it now.
+    #[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "espidf")))]
+    flush_coverage_profile();
+
     abort();
 
     cfg_if::cfg_if! {
@@ -103,6 +109,21 @@ unsafe fn abort() -> ! {
     }
 }
 
+/// Calls the hook that coverage-instrumented crates define to write the
+/// profile, unless `-Zcoverage-options=no-abort-flush` left it out. This is
+/// the same as in std's `abort_internal`.
+#[cfg(all(unix, not(target_vendor = "apple"), not(target_os = "espidf")))]
+fn flush_coverage_profile() {
+    extern "C" {
+        #[linkage = "extern_weak"]
+        static __rustc_coverage_flush_on_abort: Option<unsafe extern "C" fn()>;
+    }
+
+    if let Some(flush) = unsafe { __rustc_coverage_flush_on_abort } {
+        unsafe { flush() };
+    }
+}
+
 // This... is a bit of an oddity. The tl;dr; is that this is required to link
 // correctly, the longer explanation is below.
 //
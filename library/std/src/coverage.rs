@@ -0,0 +1,74 @@
+//! Control over the coverage profile of a program built with
+//! `-Cinstrument-coverage`.
+//!
+//! The profiler runtime that coverage instrumentation links in counts how often
+//! each region of code runs, and writes those counts to a `.profraw` file when
+//! the process exits. The functions in this module let harnesses, such as the
+//! test harness, take several profiles in the same process: for example, one
+//! for each test that it runs.
+//!
+//! The counters are shared by all threads of the process, so a profile taken
+//! while several threads are running counts the code that ran on any of them.
+//!
+//! Coverage instrumentation can currently only be detected on Unix targets,
+//! other than Apple targets and ESP-IDF. Elsewhere, programs are treated as
+//! uninstrumented.
+
+use crate::io;
+use crate::path::Path;
+use crate::sys::coverage as sys;
+
+/// Returns whether the program was built with coverage instrumentation, so
+/// that the other functions in this module have an effect.
+#[unstable(feature = "coverage_profile", issue = "none")]
+#[must_use]
+pub fn is_instrumented() -> bool {
+    sys::is_instrumented()
+}
+
+/// Resets every coverage counter of the program to zero, so that the next
+/// profile only counts the code that runs from now on.
+///
+/// Under `-Zcoverage-options=thread-local-counters`, only the calling thread's
+/// shadow counters are reset; other running threads keep their counts.
+///
+/// Does nothing if the program isn't instrumented.
+#[unstable(feature = "coverage_profile", issue = "none")]
+pub fn reset_counters() {
+    sys::reset_counters()
+}
+
+/// Sets the path that the profile is written to by [`write_profile`], and when
+/// the process exits.
+///
+/// The path may contain the same patterns as the `LLVM_PROFILE_FILE`
+/// environment variable, such as `%p` for the process ID, which are expanded
+/// when the profile is written.
+///
+/// # Errors
+///
+/// Returns an error of kind [`io::ErrorKind::Unsupported`] if the program isn't
+/// instrumented.
+#[unstable(feature = "coverage_profile", issue = "none")]
+pub fn set_profile_path<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    sys::set_profile_path(path.as_ref())
+}
+
+/// Writes the current counts to the profile, without resetting them.
+///
+/// Under `-Zcoverage-options=thread-local-counters`, the calling thread's
+/// counts are merged into the shared counts first. Other threads' counts are
+/// merged when they exit.
+///
+/// If the profile was already written, for example by an earlier call, it is
+/// overwritten, unless its path includes the `%m` pattern, in which case the
+/// counts are merged into it.
+///
+/// # Errors
+///
+/// Returns an error of kind [`io::ErrorKind::Unsupported`] if the program isn't
+/// instrumented, or another error if the profile couldn't be written.
+#[unstable(feature = "coverage_profile", issue = "none")]
+pub fn write_profile() -> io::Result<()> {
+    sys::write_profile()
+}
@@ -583,6 +583,8 @@
 pub mod ascii;
 pub mod backtrace;
 pub mod collections;
+#[unstable(feature = "coverage_profile", issue = "none")]
+pub mod coverage;
 pub mod env;
 pub mod error;
 pub mod ffi;
@@ -120,6 +120,8 @@ unsafe fn init(argc: isize, argv: *const *const u8, sigpipe: u8) {
         //        correct name there.
         rtabort!("code running before main must not use thread::current");
     }
+
+    sys::coverage::init();
 }
 
 /// Clean up the thread-local runtime state. This *should* be run after all other
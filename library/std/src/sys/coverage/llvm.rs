@@ -0,0 +1,205 @@
+//! Access to the LLVM profiler runtime that `-Cinstrument-coverage` links into
+//! the program.
+//!
+//! The runtime's functions are referenced weakly, so that they are null if
+//! nothing linked the runtime in, i.e. if the program isn't instrumented.
+//!
+//! With `-Zcoverage-options=thread-local-counters`, each thread counts into
+//! thread-local shadow counters instead of the runtime's shared counters, and
+//! rustc describes them in `__rustc_covtls` records. The runtime knows nothing
+//! about them, so the shadow counters are folded into the shared counters here:
+//! when a thread spawned by `std` exits, and before the main thread's profile
+//! is written at exit.
+
+use crate::ffi::{c_char, c_int};
+use crate::path::Path;
+use crate::sys::common::small_c_string::run_path_with_cstr;
+use crate::{io, ptr, slice};
+
+extern "C" {
+    #[linkage = "extern_weak"]
+    static __llvm_profile_begin_data: Option<unsafe extern "C" fn() -> *const ProfileData>;
+    #[linkage = "extern_weak"]
+    static __llvm_profile_end_data: Option<unsafe extern "C" fn() -> *const ProfileData>;
+    #[linkage = "extern_weak"]
+    static __llvm_profile_get_num_data:
+        Option<unsafe extern "C" fn(*const ProfileData, *const ProfileData) -> c_int>;
+    #[linkage = "extern_weak"]
+    static __llvm_profile_reset_counters: Option<unsafe extern "C" fn()>;
+    #[linkage = "extern_weak"]
+    static __llvm_profile_set_filename: Option<unsafe extern "C" fn(*const c_char)>;
+    #[linkage = "extern_weak"]
+    static __llvm_profile_write_file: Option<unsafe extern "C" fn() -> c_int>;
+
+    // Defined by the linker if any object file has a `__rustc_covtls` section.
+    #[linkage = "extern_weak"]
+    static __start___rustc_covtls: *const CovtlsRecord;
+    #[linkage = "extern_weak"]
+    static __stop___rustc_covtls: *const CovtlsRecord;
+}
+
+/// The start of a record in `__llvm_prf_data`, which is all that's needed to
+/// find a function's shared counters. The rest of the record differs between
+/// LLVM versions, so the records are stepped over by their measured size.
+#[repr(C)]
+struct ProfileData {
+    name_ref: u64,
+    func_hash: u64,
+    /// The offset of the function's counters from the start of this record.
+    counter_offset: isize,
+}
+
+/// A record in `__rustc_covtls`, emitted by rustc for each function that
+/// counts into thread-local shadow counters.
+#[repr(C)]
+struct CovtlsRecord {
+    name_ref: u64,
+    func_hash: u64,
+    /// Returns the calling thread's shadow counters for the function.
+    get_counters: unsafe extern "C" fn() -> *mut u64,
+    num_counters: u32,
+}
+
+const NOT_INSTRUMENTED: io::Error = io::const_error!(
+    io::ErrorKind::Unsupported,
+    "the program was not built with coverage instrumentation",
+);
+
+pub fn is_instrumented() -> bool {
+    unsafe { __llvm_profile_write_file }.is_some()
+}
+
+pub fn reset_counters() {
+    // Fold the calling thread's counts in first, so that they're reset too.
+    merge_thread_counters();
+    if let Some(reset_counters) = unsafe { __llvm_profile_reset_counters } {
+        unsafe { reset_counters() };
+    }
+}
+
+pub fn set_profile_path(path: &Path) -> io::Result<()> {
+    let Some(set_filename) = (unsafe { __llvm_profile_set_filename }) else {
+        return Err(NOT_INSTRUMENTED);
+    };
+    // The runtime copies the name, so it doesn't need to outlive the call.
+    run_path_with_cstr(path, &|path| {
+        unsafe { set_filename(path.as_ptr()) };
+        Ok(())
+    })
+}
+
+pub fn write_profile() -> io::Result<()> {
+    let Some(write_file) = (unsafe { __llvm_profile_write_file }) else {
+        return Err(NOT_INSTRUMENTED);
+    };
+    merge_thread_counters();
+    // The runtime reports the reason for a failure on stderr, but doesn't
+    // return it.
+    if unsafe { write_file() } == 0 {
+        Ok(())
+    } else {
+        Err(io::const_error!(io::ErrorKind::Other, "failed to write the coverage profile"))
+    }
+}
+
+/// Arranges for the main thread's shadow counters to be merged at exit.
+pub fn init() {
+    extern "C" fn merge_at_exit() {
+        merge_thread_counters();
+    }
+
+    if covtls_records().is_empty() {
+        return;
+    }
+    // The runtime registered its own handler, which writes the profile, from a
+    // static constructor, so this one runs before it.
+    unsafe { libc::atexit(merge_at_exit) };
+}
+
+/// Arranges for the calling thread's shadow counters to be merged when the
+/// thread exits.
+pub fn init_thread() {
+    struct MergeOnExit;
+
+    impl Drop for MergeOnExit {
+        fn drop(&mut self) {
+            merge_thread_counters();
+        }
+    }
+
+    thread_local! {
+        static MERGE_ON_EXIT: MergeOnExit = const { MergeOnExit };
+    }
+
+    if !covtls_records().is_empty() {
+        MERGE_ON_EXIT.with(|_| {});
+    }
+}
+
+fn covtls_records() -> &'static [CovtlsRecord] {
+    let (start, stop) = unsafe { (__start___rustc_covtls, __stop___rustc_covtls) };
+    if start.is_null() || stop.is_null() {
+        return &[];
+    }
+    let len = (stop.addr() - start.addr()) / size_of::<CovtlsRecord>();
+    unsafe { slice::from_raw_parts(start, len) }
+}
+
+/// Adds the calling thread's shadow counters to the shared counters, and
+/// zeroes them so that they aren't merged twice.
+#[cfg(target_has_atomic = "64")]
+fn merge_thread_counters() {
+    use crate::sync::atomic::AtomicU64;
+    use crate::sync::atomic::Ordering::Relaxed;
+
+    let records = covtls_records();
+    if records.is_empty() {
+        return;
+    }
+    let (Some(begin_data), Some(end_data), Some(get_num_data)) = (unsafe {
+        (__llvm_profile_begin_data, __llvm_profile_end_data, __llvm_profile_get_num_data)
+    }) else {
+        return;
+    };
+
+    let (begin, end) = unsafe { (begin_data(), end_data()) };
+    let num_data = unsafe { get_num_data(begin, end) } as usize;
+    if num_data == 0 {
+        return;
+    }
+    let stride = (end.addr() - begin.addr()) / num_data;
+    let mut shared: Vec<((u64, u64), *mut u64)> = (0..num_data)
+        .map(|i| {
+            let data = unsafe { begin.byte_add(i * stride) };
+            let data_ref = unsafe { &*data };
+            let counters = data.cast::<u8>().wrapping_offset(data_ref.counter_offset);
+            ((data_ref.name_ref, data_ref.func_hash), counters.cast::<u64>().cast_mut())
+        })
+        .collect();
+    shared.sort_unstable_by_key(|&(key, _)| key);
+
+    for record in records {
+        let Ok(i) =
+            shared.binary_search_by_key(&(record.name_ref, record.func_hash), |&(key, _)| key)
+        else {
+            continue;
+        };
+        let counters = shared[i].1;
+        let shadow = unsafe { (record.get_counters)() };
+        for index in 0..record.num_counters as usize {
+            let count = unsafe { ptr::replace(shadow.add(index), 0) };
+            if count == 0 {
+                continue;
+            }
+            // Other threads may be incrementing or merging into the same
+            // counter. The shadow counters saturate, so the merge does too.
+            let counter = unsafe { AtomicU64::from_ptr(counters.add(index)) };
+            let _ = counter.fetch_update(Relaxed, Relaxed, |c| Some(c.saturating_add(count)));
+        }
+    }
+}
+
+/// Without 64-bit atomics the shared counters can't be updated safely while
+/// other threads run, so the shadow counters are left unmerged.
+#[cfg(not(target_has_atomic = "64"))]
+fn merge_thread_counters() {}
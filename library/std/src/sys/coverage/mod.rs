@@ -0,0 +1,13 @@
+cfg_if::cfg_if! {
+    if #[cfg(all(unix, not(any(target_vendor = "apple", target_os = "espidf"))))] {
+        mod llvm;
+        pub use llvm::{
+            init, init_thread, is_instrumented, reset_counters, set_profile_path, write_profile,
+        };
+    } else {
+        mod unsupported;
+        pub use unsupported::{
+            init, init_thread, is_instrumented, reset_counters, set_profile_path, write_profile,
+        };
+    }
+}
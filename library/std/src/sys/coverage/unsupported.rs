@@ -0,0 +1,25 @@
+use crate::io;
+use crate::path::Path;
+
+const NOT_INSTRUMENTED: io::Error = io::const_error!(
+    io::ErrorKind::Unsupported,
+    "coverage instrumentation is not supported on this platform",
+);
+
+pub fn init() {}
+
+pub fn init_thread() {}
+
+pub fn is_instrumented() -> bool {
+    false
+}
+
+pub fn reset_counters() {}
+
+pub fn set_profile_path(_path: &Path) -> io::Result<()> {
+    Err(NOT_INSTRUMENTED)
+}
+
+pub fn write_profile() -> io::Result<()> {
+    Err(NOT_INSTRUMENTED)
+}
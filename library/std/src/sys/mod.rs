@@ -11,6 +11,7 @@
 pub mod anonymous_pipe;
 pub mod backtrace;
 pub mod cmath;
+pub mod coverage;
 pub mod exit_guard;
 pub mod os_str;
 pub mod path;
@@ -370,7 +370,19 @@ pub fn cvt_nz(error: libc::c_int) -> crate::io::Result<()> {
 // multithreaded C program.  It is much less severe for Rust, because Rust
 // stdlib doesn't use libc stdio buffering.  In a typical Rust program, which
 // does not use C stdio, even a buggy libc::abort() is, in fact, safe.
+//
+// Before aborting, this writes the profile of coverage-instrumented programs,
+// whose profiler runtime would otherwise only write it at exit. Instrumented
+// crates define a hook for this, unless they were built with
+// `-Zcoverage-options=no-abort-flush`.
 pub fn abort_internal() -> ! {
+    #[cfg(not(any(target_vendor = "apple", target_os = "espidf")))]
+    {
+        weak::weak!(fn __rustc_coverage_flush_on_abort() -> ());
+        if let Some(flush) = __rustc_coverage_flush_on_abort.get() {
+            unsafe { flush() };
+        }
+    }
     unsafe { libc::abort() }
 }
 
@@ -558,6 +558,8 @@ fn drop(&mut self) {
                 imp::Thread::set_name(name);
             }
 
+            crate::sys::coverage::init_thread();
+
             let f = f.into_inner();
             let try_result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
                 crate::sys::backtrace::__rust_begin_short_backtrace(|| hooks.run());
@@ -142,6 +142,15 @@ fn optgroups() -> getopts::Options {
             `CRITICAL_TIME` here means the limit that should not be exceeded by test.
             ",
         )
+        .optflag(
+            "",
+            "coverage-per-test",
+            "Write a separate coverage profile for each test, in the
+            directory of `LLVM_PROFILE_FILE`. Tests are run one at a time,
+            overriding --test-threads.
+
+            Requires the test binary to be built with `-Cinstrument-coverage`.",
+        )
         .optflag("", "shuffle", "Run tests in random order")
         .optopt(
             "",
@@ -261,6 +270,7 @@ fn parse_opts_impl(matches: getopts::Matches) -> OptRes {
     let time_options = get_time_options(&matches, allow_unstable)?;
     let shuffle = get_shuffle(&matches, allow_unstable)?;
     let shuffle_seed = get_shuffle_seed(&matches, allow_unstable)?;
+    let coverage_per_test = get_coverage_per_test(&matches, allow_unstable)?;
 
     let include_ignored = matches.opt_present("include-ignored");
     let quiet = matches.opt_present("quiet");
@@ -279,7 +289,9 @@ fn parse_opts_impl(matches: getopts::Matches) -> OptRes {
     let color = get_color_config(&matches)?;
     let format = get_format(&matches, quiet, allow_unstable)?;
 
-    let options = Options::new().display_output(matches.opt_present("show-output"));
+    let options = Options::new()
+        .display_output(matches.opt_present("show-output"))
+        .coverage_per_test(coverage_per_test);
 
     let test_opts = TestOpts {
         list,
@@ -375,6 +387,17 @@ fn get_shuffle_seed(matches: &getopts::Matches, allow_unstable: bool) -> OptPart
     Ok(shuffle_seed)
 }
 
+fn get_coverage_per_test(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<bool> {
+    let coverage_per_test = unstable_optflag!(matches, allow_unstable, "coverage-per-test");
+    if coverage_per_test && !std::coverage::is_instrumented() {
+        return Err("the \"coverage-per-test\" flag requires a test binary that was built \
+                    with `-Cinstrument-coverage`"
+            .to_string());
+    }
+
+    Ok(coverage_per_test)
+}
+
 fn get_test_threads(matches: &getopts::Matches) -> OptPartRes<Option<usize>> {
     let test_threads = match matches.opt_str("test-threads") {
         Some(n_str) => match n_str.parse::<usize>() {
@@ -0,0 +1,65 @@
+//! Per-test coverage profiles, for `--coverage-per-test`.
+//!
+//! The coverage counters are shared by the whole process, so tests are run one
+//! at a time, with the counters reset before each test and written to the
+//! test's own profile after it. Tests that run in a subprocess (under
+//! `panic=abort`) write their profile when the subprocess exits instead.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::{coverage, env, io};
+
+use crate::types::TestName;
+
+/// The profile name that rustc's instrumentation uses by default, if
+/// `LLVM_PROFILE_FILE` isn't set.
+const DEFAULT_PROFILE_FILE: &str = "default_%m_%p.profraw";
+
+/// Returns the path of the profile for the given test, in the same directory
+/// as the profile that would be written without `--coverage-per-test`.
+///
+/// Characters that can't appear in file names are replaced, so the file name
+/// also includes a hash of the full test name, to keep tests whose names only
+/// differ in those characters (like `a::b` and `a__b`) apart.
+fn test_profile_path(name: &TestName) -> PathBuf {
+    let name = name.as_slice();
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let hash = hasher.finish();
+    let dir = env::var_os("LLVM_PROFILE_FILE")
+        .and_then(|path| PathBuf::from(path).parent().map(PathBuf::from))
+        .unwrap_or_default();
+    // The process ID keeps tests with the same name in different test
+    // binaries apart.
+    dir.join(format!("{sanitized}-{hash:016x}-%p.profraw"))
+}
+
+/// Resets the counters before running a test in this process.
+pub(crate) fn start_test() {
+    coverage::reset_counters();
+}
+
+/// Writes the profile of a test that just ran in this process, and resets
+/// the counters, so that they don't count towards the next test, or the
+/// profile written at exit.
+pub(crate) fn finish_test(name: &TestName) -> io::Result<()> {
+    coverage::set_profile_path(test_profile_path(name))?;
+    let written = coverage::write_profile();
+    let default_path = env::var_os("LLVM_PROFILE_FILE")
+        .map_or_else(|| PathBuf::from(DEFAULT_PROFILE_FILE), PathBuf::from);
+    coverage::set_profile_path(default_path)?;
+    coverage::reset_counters();
+    written
+}
+
+/// Prepares a subprocess that runs a single test, so that the profile that
+/// it writes when it exits is the test's own.
+pub(crate) fn start_test_in_subprocess(name: &TestName) -> io::Result<()> {
+    coverage::set_profile_path(test_profile_path(name))?;
+    coverage::reset_counters();
+    Ok(())
+}
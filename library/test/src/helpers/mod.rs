@@ -2,5 +2,6 @@
 //! but used in `libtest`.
 
 pub mod concurrency;
+pub mod coverage;
 pub mod metrics;
 pub mod shuffle;
@@ -18,6 +18,7 @@
 #![doc(test(attr(deny(warnings))))]
 #![doc(rust_logo)]
 #![feature(rustdoc_internals)]
+#![feature(coverage_profile)]
 #![feature(file_buffered)]
 #![feature(internal_output_capture)]
 #![feature(staged_api)]
@@ -92,6 +93,7 @@ pub mod test {
 
 const SECONDARY_TEST_INVOKER_VAR: &str = "__RUST_TEST_INVOKE";
 const SECONDARY_TEST_BENCH_BENCHMARKS_VAR: &str = "__RUST_TEST_BENCH_BENCHMARKS";
+const SECONDARY_TEST_COVERAGE_PER_TEST_VAR: &str = "__RUST_TEST_COVERAGE_PER_TEST";
 
 // The default console test runner. It accepts the command line
 // arguments and a vector of test_descs.
@@ -105,7 +107,8 @@ pub fn test_main(args: &[String], tests: Vec<TestDescAndFn>, options: Option<Opt
         None => return,
     };
     if let Some(options) = options {
-        opts.options = options;
+        // `--coverage-per-test` is only set on the command line.
+        opts.options = options.coverage_per_test(opts.options.coverage_per_test);
     }
     if opts.list {
         if let Err(e) = console::list_tests_console(&opts, tests) {
@@ -198,6 +201,12 @@ pub fn test_main_static_abort(tests: &[&TestDescAndFn]) {
             .find(|test| test.desc.name.as_slice() == name)
             .unwrap_or_else(|| panic!("couldn't find a test with the provided name '{name}'"));
         let TestDescAndFn { desc, testfn } = test;
+        if env::var(SECONDARY_TEST_COVERAGE_PER_TEST_VAR).is_ok() {
+            env::remove_var(SECONDARY_TEST_COVERAGE_PER_TEST_VAR);
+            if let Err(e) = helpers::coverage::start_test_in_subprocess(&desc.name) {
+                panic!("failed to set up the coverage profile of the test: {e}");
+            }
+        }
         match testfn.into_runnable() {
             Runnable::Test(runnable_test) => {
                 if runnable_test.is_dynamic() {
@@ -335,7 +344,18 @@ struct TimeoutEntry {
     let event = TestEvent::TeFiltered(filtered.total_len(), shuffle_seed);
     notify_about_test_event(event)?;
 
-    let concurrency = opts.test_threads.unwrap_or_else(get_concurrency);
+    let concurrency = if opts.options.coverage_per_test {
+        // The coverage counters are shared by all tests in this process.
+        if opts.test_threads.is_some_and(|test_threads| test_threads > 1) {
+            eprintln!(
+                "warning: ignoring --test-threads, because \
+                 --coverage-per-test runs tests one at a time"
+            );
+        }
+        1
+    } else {
+        opts.test_threads.unwrap_or_else(get_concurrency)
+    };
 
     let mut remaining = filtered.tests;
     if let Some(shuffle_seed) = shuffle_seed {
@@ -386,10 +406,18 @@ fn calc_timeout(timeout_queue: &VecDeque<TimeoutEntry>) -> Option<Duration> {
             let (id, test) = remaining.pop_front().unwrap();
             let event = TestEvent::TeWait(test.desc.clone());
             notify_about_test_event(event)?;
+            let per_test_coverage =
+                opts.options.coverage_per_test && matches!(run_strategy, RunStrategy::InProcess);
+            if per_test_coverage {
+                helpers::coverage::start_test();
+            }
             let join_handle = run_test(opts, !opts.run_tests, id, test, run_strategy, tx.clone());
             // Wait for the test to complete.
             let mut completed_test = rx.recv().unwrap();
             RunningTest { join_handle }.join(&mut completed_test);
+            if per_test_coverage {
+                helpers::coverage::finish_test(&completed_test.desc.name)?;
+            }
 
             let fail_fast = match completed_test.result {
                 TrIgnored | TrOk | TrBench(_) => false,
@@ -571,6 +599,7 @@ pub fn run_test(
             let nocapture = opts.nocapture;
             let time_options = opts.time_options;
             let bench_benchmarks = opts.bench_benchmarks;
+            let coverage_per_test = opts.options.coverage_per_test;
 
             let runtest = move || match strategy {
                 RunStrategy::InProcess => run_test_in_process(
@@ -590,6 +619,7 @@ pub fn run_test(
                     monitor_ch,
                     time_options,
                     bench_benchmarks,
+                    coverage_per_test,
                 ),
             };
 
@@ -690,6 +720,7 @@ fn spawn_test_subprocess(
     monitor_ch: Sender<CompletedTest>,
     time_opts: Option<time::TestTimeOptions>,
     bench_benchmarks: bool,
+    coverage_per_test: bool,
 ) {
     let (result, test_output, exec_time) = (|| {
         let args = env::args().collect::<Vec<_>>();
@@ -700,6 +731,9 @@ fn spawn_test_subprocess(
         if bench_benchmarks {
             command.env(SECONDARY_TEST_BENCH_BENCHMARKS_VAR, "1");
         }
+        if coverage_per_test {
+            command.env(SECONDARY_TEST_COVERAGE_PER_TEST_VAR, "1");
+        }
         if nocapture {
             command.stdout(process::Stdio::inherit());
             command.stderr(process::Stdio::inherit());
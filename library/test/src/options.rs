@@ -65,11 +65,12 @@ pub enum RunStrategy {
 pub struct Options {
     pub display_output: bool,
     pub panic_abort: bool,
+    pub coverage_per_test: bool,
 }
 
 impl Options {
     pub fn new() -> Options {
-        Options { display_output: false, panic_abort: false }
+        Options { display_output: false, panic_abort: false, coverage_per_test: false }
     }
 
     pub fn display_output(mut self, display_output: bool) -> Options {
@@ -81,4 +82,9 @@ pub fn panic_abort(mut self, panic_abort: bool) -> Options {
         self.panic_abort = panic_abort;
         self
     }
+
+    pub fn coverage_per_test(mut self, coverage_per_test: bool) -> Options {
+        self.coverage_per_test = coverage_per_test;
+        self
+    }
 }
@@ -471,6 +471,18 @@ fn parse_show_output_flag() {
     assert!(opts.options.display_output);
 }
 
+#[test]
+fn parse_coverage_per_test_flag_requires_instrumentation() {
+    let args = vec![
+        "progname".to_string(),
+        "-Zunstable-options".to_string(),
+        "--coverage-per-test".to_string(),
+    ];
+    // libtest's own tests aren't built with coverage instrumentation.
+    let result = parse_opts(&args).unwrap();
+    assert!(result.unwrap_err().contains("-Cinstrument-coverage"));
+}
+
 #[test]
 fn parse_include_ignored_flag() {
     let args = vec!["progname".to_string(), "filter".to_string(), "--include-ignored".to_string()];
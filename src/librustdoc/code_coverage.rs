@@ -0,0 +1,129 @@
+//! This module loads the source-based code coverage of a crate, as exported by
+//! `llvm-cov export`, so that function docs can show whether the function was
+//! executed (e.g. by the crate's tests).
+//!
+//! Rustdoc doesn't read `.profdata` files directly, because their format is
+//! private to LLVM. Instead, it reads the JSON that `llvm-cov export` produces
+//! from the merged profile and the instrumented binary:
+//!
+//! ```text
+//! llvm-cov export --format=text --instr-profile=merged.profdata <binary> > coverage.json
+//! rustdoc -Zunstable-options --with-coverage=coverage.json src/lib.rs
+//! ```
+
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use rustc_data_structures::fx::FxIndexMap;
+use rustc_errors::DiagCtxtHandle;
+use serde::Deserialize;
+
+/// `llvm-cov` region kind of ordinary code regions. The other kinds (expansion,
+/// skipped, gap and branch regions) don't count towards the summary.
+const CODE_REGION_KIND: u64 = 0;
+
+/// Start line, start column, end line and end column of a region.
+type RegionPos = (u64, u64, u64, u64);
+
+/// The code regions of each source file in the export, with the highest
+/// execution count seen for each region across all of the instantiations that
+/// contain it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CodeCoverage {
+    files: FxIndexMap<PathBuf, FxIndexMap<RegionPos, u64>>,
+}
+
+/// Number of code regions within an item, and how many of them were executed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CoverageSummary {
+    pub(crate) covered: usize,
+    pub(crate) total: usize,
+}
+
+#[derive(Deserialize)]
+struct Export {
+    data: Vec<ExportData>,
+}
+
+#[derive(Deserialize)]
+struct ExportData {
+    functions: Vec<ExportFunction>,
+}
+
+#[derive(Deserialize)]
+struct ExportFunction {
+    filenames: Vec<PathBuf>,
+    /// Each region is `[line_start, col_start, line_end, col_end, count, file_id,
+    /// expanded_file_id, kind]`.
+    regions: Vec<Vec<u64>>,
+}
+
+impl CodeCoverage {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Summarizes the code regions of `path` that start within `lines`, or
+    /// returns `None` if the export has no code regions there.
+    pub(crate) fn summary(
+        &self,
+        path: &Path,
+        lines: RangeInclusive<u64>,
+    ) -> Option<CoverageSummary> {
+        let mut summary = CoverageSummary { covered: 0, total: 0 };
+        // The export records paths as they were passed to the compiler, so they
+        // may be absolute or relative to a different directory than ours.
+        let files =
+            self.files.iter().filter(|(file, _)| file.ends_with(path) || path.ends_with(file));
+        for (_, regions) in files {
+            for (&(start_line, ..), &count) in regions {
+                if lines.contains(&start_line) {
+                    summary.total += 1;
+                    if count > 0 {
+                        summary.covered += 1;
+                    }
+                }
+            }
+        }
+        (summary.total > 0).then_some(summary)
+    }
+
+    fn add_function(&mut self, function: ExportFunction) {
+        for region in function.regions {
+            let &[start_line, start_col, end_line, end_col, count, file_id, _, kind, ..] =
+                region.as_slice()
+            else {
+                continue;
+            };
+            if kind != CODE_REGION_KIND {
+                continue;
+            }
+            let Some(file) = function.filenames.get(file_id as usize) else { continue };
+            let max_count = self
+                .files
+                .entry(file.clone())
+                .or_default()
+                .entry((start_line, start_col, end_line, end_col))
+                .or_default();
+            *max_count = (*max_count).max(count);
+        }
+    }
+}
+
+pub(crate) fn load_code_coverage(path: Option<String>, dcx: DiagCtxtHandle<'_>) -> CodeCoverage {
+    let mut coverage = CodeCoverage::default();
+    let Some(path) = path else { return coverage };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => dcx.fatal(format!("failed to load coverage from {path}: {e}")),
+    };
+    let export: Export = match serde_json::from_slice(&bytes) {
+        Ok(export) => export,
+        Err(e) => dcx.fatal(format!("{path} is not a valid `llvm-cov export` file: {e}")),
+    };
+    for function in export.data.into_iter().flat_map(|data| data.functions) {
+        coverage.add_function(function);
+    }
+    coverage
+}
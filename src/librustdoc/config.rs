@@ -19,6 +19,7 @@
 use rustc_span::edition::Edition;
 use rustc_target::spec::TargetTuple;
 
+use crate::code_coverage::CodeCoverage;
 use crate::core::new_dcx;
 use crate::externalfiles::ExternalHtml;
 use crate::html::markdown::IdMap;
@@ -290,6 +291,8 @@ pub(crate) struct RenderOptions {
     pub(crate) generate_link_to_definition: bool,
     /// Set of function-call locations to include as examples
     pub(crate) call_locations: AllCallLocations,
+    /// Code coverage to show on function documentation
+    pub(crate) code_coverage: CodeCoverage,
     /// If `true`, Context::init will not emit shared files.
     pub(crate) no_emit_shared: bool,
     /// If `true`, HTML source code pages won't be generated.
@@ -777,6 +780,8 @@ fn println_condition(condition: Condition) {
         let scrape_examples_options = ScrapeExamplesOptions::new(matches, dcx);
         let with_examples = matches.opt_strs("with-examples");
         let call_locations = crate::scrape_examples::load_call_locations(with_examples, dcx);
+        let with_coverage = matches.opt_str("with-coverage");
+        let code_coverage = crate::code_coverage::load_code_coverage(with_coverage, dcx);
         let doctest_compilation_args = matches.opt_strs("doctest-compilation-args");
 
         let unstable_features =
@@ -851,6 +856,7 @@ fn println_condition(condition: Condition) {
             emit,
             generate_link_to_definition,
             call_locations,
+            code_coverage,
             no_emit_shared: false,
             html_no_source,
             output_to_stdout,
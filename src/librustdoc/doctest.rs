@@ -20,7 +20,7 @@
 use rustc_hir::CRATE_HIR_ID;
 use rustc_hir::def_id::LOCAL_CRATE;
 use rustc_interface::interface;
-use rustc_session::config::{self, CrateType, ErrorOutputType, Input};
+use rustc_session::config::{self, CrateType, ErrorOutputType, Input, InstrumentCoverage};
 use rustc_session::lint;
 use rustc_span::FileName;
 use rustc_span::edition::Edition;
@@ -482,6 +482,9 @@ fn wrapped_rustc_command(rustc_wrappers: &[PathBuf], rustc_binary: &Path) -> Com
 pub(crate) struct RunnableDocTest {
     full_test_code: String,
     full_test_line_offset: usize,
+    /// For merged doctests, the lines that each doctest's code occupies in
+    /// `full_test_code`, in the format read by `-Zdoctest-line-table`.
+    merged_line_table: String,
     test_opts: IndividualTestOptions,
     global_opts: GlobalTestOptions,
     langstr: LangString,
@@ -495,6 +498,10 @@ impl RunnableDocTest {
     fn path_for_merged_doctest(&self) -> PathBuf {
         self.test_opts.outdir.path().join(format!("doctest_{}.rs", self.edition))
     }
+
+    fn path_for_merged_line_table(&self) -> PathBuf {
+        self.test_opts.outdir.path().join(format!("doctest_{}.lines", self.edition))
+    }
 }
 
 /// Execute a `RunnableDoctest`.
@@ -591,6 +598,16 @@ fn run_test(
             return Err(TestFailure::CompileError);
         }
         compiler.arg(input_file);
+        // With coverage enabled, let rustc map the regions of each doctest
+        // back to its source, as it does for doctests compiled on their own.
+        if rustdoc_options.codegen_options.instrument_coverage() != InstrumentCoverage::No
+            && rustdoc_options.unstable_features.is_nightly_build()
+        {
+            let line_table_file = doctest.path_for_merged_line_table();
+            if std::fs::write(&line_table_file, &doctest.merged_line_table).is_ok() {
+                compiler.arg("-Z").arg(format!("doctest-line-table={}", line_table_file.display()));
+            }
+        }
         if !rustdoc_options.nocapture {
             // If `nocapture` is disabled, then we don't display rustc's output when compiling
             // the merged doctests.
@@ -971,6 +988,7 @@ fn doctest_run_fn(
     let runnable_test = RunnableDocTest {
         full_test_code,
         full_test_line_offset,
+        merged_line_table: String::new(),
         test_opts,
         global_opts,
         langstr: scraped_test.langstr.clone(),
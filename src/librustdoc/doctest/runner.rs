@@ -1,4 +1,5 @@
 use std::fmt::Write;
+use std::path::PathBuf;
 
 use rustc_data_structures::fx::FxIndexSet;
 use rustc_span::edition::Edition;
@@ -14,16 +15,30 @@ pub(crate) struct DocTestRunner {
     crate_attrs: FxIndexSet<String>,
     ids: String,
     output: String,
+    /// Where the code of each doctest ended up in `output`.
+    merged_lines: Vec<MergedDoctestLines>,
     supports_color: bool,
     nb_tests: usize,
 }
 
+/// The lines of `output` that hold the code of one doctest, so that rustc can
+/// map them back to the doctest's source with `-Zdoctest-line-table`.
+struct MergedDoctestLines {
+    /// The 0-based index of the first line in `output`.
+    first_line: usize,
+    num_lines: usize,
+    /// The 1-based line number that the first line has in the doctest's source.
+    original_line: usize,
+    path: PathBuf,
+}
+
 impl DocTestRunner {
     pub(crate) fn new() -> Self {
         Self {
             crate_attrs: FxIndexSet::default(),
             ids: String::new(),
             output: String::new(),
+            merged_lines: Vec::new(),
             supports_color: true,
             nb_tests: 0,
         }
@@ -55,7 +70,8 @@ pub(crate) fn add_test(
                 scraped_test,
                 ignore,
                 self.nb_tests,
-                &mut self.output
+                &mut self.output,
+                &mut self.merged_lines,
             ),
         ));
         self.supports_color &= doctest.supports_color;
@@ -98,6 +114,16 @@ pub(crate) fn run_merged_tests(
 
         code.push_str("extern crate test;\n");
 
+        // The merged doctests come right after this header.
+        let header_lines = code.matches('\n').count();
+        let merged_line_table = self.merged_lines.iter().fold(String::new(), |mut x, lines| {
+            let first_line = header_lines + lines.first_line + 1;
+            let line_offset = lines.original_line as isize - first_line as isize;
+            writeln!(x, "{first_line} {} {line_offset} {}", lines.num_lines, lines.path.display())
+                .unwrap();
+            x
+        });
+
         let test_args = test_args.iter().fold(String::new(), |mut x, arg| {
             write!(x, "{arg:?}.to_string(),").unwrap();
             x
@@ -168,6 +194,7 @@ fn main() -> std::process::ExitCode {{
         let runnable_test = RunnableDocTest {
             full_test_code: code,
             full_test_line_offset: 0,
+            merged_line_table,
             test_opts: test_options,
             global_opts: opts.clone(),
             langstr: LangString::default(),
@@ -189,6 +216,7 @@ fn generate_mergeable_doctest(
     ignore: bool,
     id: usize,
     output: &mut String,
+    merged_lines: &mut Vec<MergedDoctestLines>,
 ) -> String {
     let test_id = format!("__doctest_{id}");
 
@@ -198,9 +226,29 @@ fn generate_mergeable_doctest(
     } else {
         writeln!(output, "mod {test_id} {{\n{}{}", doctest.crates, doctest.maybe_crate_attrs)
             .unwrap();
+        // The attributes and crates that were split off the doctest came
+        // before the rest of its code, just after the opening code fence.
+        let original_line = scraped_test.line
+            + 1
+            + [&doctest.crate_attrs, &doctest.maybe_crate_attrs, &doctest.crates]
+                .into_iter()
+                .map(|part| part.lines().count())
+                .sum::<usize>();
+        let first_line = output.matches('\n').count();
+        let num_lines = doctest.everything_else.lines().count();
+        let path = scraped_test.path();
         if doctest.has_main_fn {
+            merged_lines.push(MergedDoctestLines { first_line, num_lines, original_line, path });
             output.push_str(&doctest.everything_else);
         } else {
+            // Include the lines of the `main` wrapper, which take the place of
+            // the code fences around the doctest.
+            merged_lines.push(MergedDoctestLines {
+                first_line,
+                num_lines: num_lines + 2,
+                original_line: original_line - 1,
+                path,
+            });
             let returns_result = if doctest.everything_else.trim_end().ends_with("(())") {
                 "-> Result<(), impl core::fmt::Debug>"
             } else {
@@ -19,6 +19,7 @@
 use crate::clean::types::ExternalLocation;
 use crate::clean::utils::has_doc_flag;
 use crate::clean::{self, ExternalCrate};
+use crate::code_coverage::CodeCoverage;
 use crate::config::{ModuleSorting, RenderOptions, ShouldMerge};
 use crate::docfs::{DocFS, PathError};
 use crate::error::Error;
@@ -141,6 +142,8 @@ pub(crate) struct SharedContext<'tcx> {
     /// The [`Cache`] used during rendering.
     pub(crate) cache: Cache,
     pub(crate) call_locations: AllCallLocations,
+    /// Code coverage loaded with `--with-coverage`.
+    pub(crate) code_coverage: CodeCoverage,
     /// Controls whether we read / write to cci files in the doc root. Defaults read=true,
     /// write=true
     should_merge: ShouldMerge,
@@ -480,6 +483,7 @@ fn init(
             show_type_layout,
             generate_link_to_definition,
             call_locations,
+            code_coverage,
             no_emit_shared,
             html_no_source,
             ..
@@ -567,6 +571,7 @@ fn init(
             span_correspondence_map: matches,
             cache,
             call_locations,
+            code_coverage,
             should_merge: options.should_merge,
         };
 
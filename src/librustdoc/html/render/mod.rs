@@ -63,6 +63,7 @@
 pub(crate) use self::span_map::{LinkFromSrc, collect_spans_and_sources};
 pub(crate) use self::write_shared::*;
 use crate::clean::{self, ItemId, RenderedLink};
+use crate::code_coverage::CoverageSummary;
 use crate::error::Error;
 use crate::formats::Impl;
 use crate::formats::cache::Cache;
@@ -684,10 +685,17 @@ enum ShortItemInfo {
     Portability {
         message: String,
     },
+    /// How many of the function's coverage regions were executed, according to
+    /// `--with-coverage`.
+    Coverage {
+        tested: bool,
+        covered: usize,
+        total: usize,
+    },
 }
 
-/// Render the stability, deprecation and portability information that is displayed at the top of
-/// the item's documentation.
+/// Render the stability, deprecation, portability and code coverage information that is displayed
+/// at the top of the item's documentation.
 fn short_item_info(
     item: &clean::Item,
     cx: &Context<'_>,
@@ -744,9 +752,29 @@ fn short_item_info(
         extra_info.push(ShortItemInfo::Portability { message });
     }
 
+    if let Some(CoverageSummary { covered, total }) = code_coverage(item, cx) {
+        extra_info.push(ShortItemInfo::Coverage { tested: covered > 0, covered, total });
+    }
+
     extra_info
 }
 
+/// Looks up the coverage of a local function's body in the `--with-coverage` export.
+fn code_coverage(item: &clean::Item, cx: &Context<'_>) -> Option<CoverageSummary> {
+    if cx.shared.code_coverage.is_empty() || !item.item_id.is_local() {
+        return None;
+    }
+    if !matches!(item.kind, clean::FunctionItem(..) | clean::MethodItem(..)) {
+        return None;
+    }
+    let sess = cx.sess();
+    let span = item.span(cx.tcx())?;
+    let FileName::Real(ref filename) = span.filename(sess) else { return None };
+    let path = filename.local_path()?;
+    let lines = span.lo(sess).line as u64..=span.hi(sess).line as u64;
+    cx.shared.code_coverage.summary(path, lines)
+}
+
 // Render the list of items inside one of the sections "Trait Implementations",
 // "Auto Trait Implementations," "Blanket Trait Implementations" (on struct/enum pages).
 pub(crate) fn render_impls(
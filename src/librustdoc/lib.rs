@@ -104,6 +104,7 @@ macro_rules! map {
 }
 
 mod clean;
+mod code_coverage;
 mod config;
 mod core;
 mod docfs;
@@ -615,6 +616,15 @@ fn opts() -> Vec<RustcOptGroup> {
             "",
             "path to function call information (for displaying examples in the documentation)",
         ),
+        opt(
+            Unstable,
+            Opt,
+            "",
+            "with-coverage",
+            "path to the `llvm-cov export` JSON of the crate's code coverage (for displaying \
+                whether functions were executed in the documentation)",
+            "PATH",
+        ),
         opt(
             Unstable,
             Opt,
@@ -0,0 +1,53 @@
+//! Support for `-Zmiri-coverage`: writing out the coverage counters that the
+//! interpreted program incremented.
+//!
+//! Miri doesn't link the program, so it can't use the profiler runtime to
+//! write a `.profraw` file. Instead, the counters are written in the text
+//! format accepted by `llvm-profdata merge`, using the same function names and
+//! hashes as the coverage map of a native build with the same flags.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{fs, process};
+
+use crate::*;
+
+pub(crate) fn write_coverage_profile<'tcx>(ecx: &MiriInterpCx<'tcx>, out: &str) {
+    let Some(coverage_counters) = &ecx.machine.coverage_counters else { return };
+    let tcx = *ecx.tcx;
+
+    let mut records = coverage_counters
+        .iter()
+        .filter_map(|(&instance, counters)| {
+            let fn_cov_info = tcx.instance_mir(instance.def).function_coverage_info.as_deref()?;
+            // A native build only has the counters that survived MIR
+            // optimizations, and `llvm-profdata` rejects records whose number
+            // of counters disagree.
+            let num_counters = tcx.coverage_ids_info(instance).num_counters_after_mir_opts();
+            let counters = &counters[..(num_counters as usize).min(counters.len())];
+            Some((tcx.symbol_name(instance).name, fn_cov_info.function_source_hash, counters))
+        })
+        .collect::<Vec<_>>();
+    records.sort_by_key(|&(name, ..)| name);
+
+    let mut profile = String::new();
+    for (name, hash, counters) in records {
+        writeln!(profile, "{name}").unwrap();
+        writeln!(profile, "# Func Hash:\n{hash}").unwrap();
+        writeln!(profile, "# Num Counters:\n{}", counters.len()).unwrap();
+        writeln!(profile, "# Counter Values:").unwrap();
+        for count in counters {
+            writeln!(profile, "{count}").unwrap();
+        }
+        writeln!(profile).unwrap();
+    }
+
+    // Use the same naming scheme as for `-Zmiri-measureme`.
+    let crate_name =
+        tcx.sess.opts.crate_name.clone().unwrap_or_else(|| "unknown-crate".to_string());
+    let pid = process::id();
+    let path = Path::new(out).join(format!("{crate_name}-{pid:07}.proftext"));
+    if let Err(err) = fs::create_dir_all(out).and_then(|()| fs::write(&path, profile)) {
+        tcx.dcx().warn(format!("failed to write coverage profile to `{}`: {err}", path.display()));
+    }
+}
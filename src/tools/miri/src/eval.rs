@@ -15,6 +15,7 @@
 use rustc_session::config::EntryFnType;
 
 use crate::concurrency::thread::TlsAllocAction;
+use crate::coverage::write_coverage_profile;
 use crate::diagnostics::report_leaks;
 use crate::shims::tls;
 use crate::*;
@@ -129,6 +130,10 @@ pub struct MiriConfig {
     /// If `Some`, enable the `measureme` profiler, writing results to a file
     /// with the specified prefix.
     pub measureme_out: Option<String>,
+    /// If `Some`, count the coverage counters of functions that were built with
+    /// `-Cinstrument-coverage`, and write them to a text profile in the
+    /// specified directory.
+    pub coverage_out: Option<String>,
     /// Which style to use for printing backtraces.
     pub backtrace_style: BacktraceStyle,
     /// Which provenance to use for int2ptr casts
@@ -181,6 +186,7 @@ fn default() -> MiriConfig {
             track_outdated_loads: false,
             cmpxchg_weak_failure_rate: 0.8, // 80%
             measureme_out: None,
+            coverage_out: None,
             backtrace_style: BacktraceStyle::Short,
             provenance_mode: ProvenanceMode::Default,
             mute_stdout_stderr: false,
@@ -437,8 +443,9 @@ pub fn eval_entry<'tcx>(
     entry_type: EntryFnType,
     config: MiriConfig,
 ) -> Option<i32> {
-    // Copy setting before we move `config`.
+    // Copy settings before we move `config`.
     let ignore_leaks = config.ignore_leaks;
+    let coverage_out = config.coverage_out.clone();
 
     let mut ecx = match create_ecx(tcx, entry_id, entry_type, &config).report_err() {
         Ok(v) => v,
@@ -469,6 +476,12 @@ pub fn eval_entry<'tcx>(
         EnvVars::cleanup(&mut ecx).expect("error during env var cleanup");
     }
 
+    // Write out the coverage counters, even if the program failed, so that
+    // the code leading up to the failure is still reported as covered.
+    if let Some(out) = &coverage_out {
+        write_coverage_profile(&ecx, out);
+    }
+
     // Process the result.
     let (return_code, leak_check) = report_error(&ecx, err)?;
     if leak_check && !ignore_leaks {
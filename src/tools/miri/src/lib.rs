@@ -78,6 +78,7 @@
 mod borrow_tracker;
 mod clock;
 mod concurrency;
+mod coverage;
 mod diagnostics;
 mod eval;
 mod helpers;
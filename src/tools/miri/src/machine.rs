@@ -511,6 +511,10 @@ pub struct MiriMachine<'tcx> {
     /// used with `measureme`.
     string_cache: FxHashMap<String, measureme::StringId>,
 
+    /// With `-Zmiri-coverage`, the number of times each coverage counter of
+    /// each instrumented function was incremented.
+    pub(crate) coverage_counters: Option<FxHashMap<Instance<'tcx>, Vec<u64>>>,
+
     /// Cache of `Instance` exported under the given `Symbol` name.
     /// `None` means no `Instance` exported under the given name is found.
     pub(crate) exported_symbols_cache: FxHashMap<Symbol, Option<Instance<'tcx>>>,
@@ -695,6 +699,7 @@ pub(crate) fn new(config: &MiriConfig, layout_cx: LayoutCx<'tcx>) -> Self {
             static_roots: Vec::new(),
             profiler,
             string_cache: Default::default(),
+            coverage_counters: config.coverage_out.is_some().then(FxHashMap::default),
             exported_symbols_cache: FxHashMap::default(),
             backtrace_style: config.backtrace_style,
             local_crates,
@@ -842,6 +847,7 @@ fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
             static_roots: _,
             profiler: _,
             string_cache: _,
+            coverage_counters: _,
             exported_symbols_cache: _,
             backtrace_style: _,
             local_crates: _,
@@ -1545,6 +1551,25 @@ fn before_terminator(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
         interp_ok(())
     }
 
+    fn coverage_counter_increment(
+        ecx: &mut InterpCx<'tcx, Self>,
+        instance: Instance<'tcx>,
+        id: mir::coverage::CounterId,
+    ) -> InterpResult<'tcx> {
+        let tcx = *ecx.tcx;
+        let Some(coverage_counters) = &mut ecx.machine.coverage_counters else {
+            return interp_ok(());
+        };
+        let counters = coverage_counters.entry(instance).or_insert_with(|| {
+            let fn_cov_info = tcx.instance_mir(instance.def).function_coverage_info.as_deref();
+            vec![0; fn_cov_info.map_or(0, |info| info.num_counters)]
+        });
+        if let Some(counter) = counters.get_mut(id.as_usize()) {
+            *counter += 1;
+        }
+        interp_ok(())
+    }
+
     #[inline(always)]
     fn after_stack_push(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
         if ecx.frame().extra.is_user_relevant {
@@ -0,0 +1,20 @@
+// Test that an instrumented executable defines the hook that the standard
+// library calls to write the profile before aborting, unless
+// `-Zcoverage-options=no-abort-flush` was passed.
+
+//@ only-elf
+//@ needs-profiler-runtime
+//@ revisions: flush no-flush
+//@ compile-flags: -Cinstrument-coverage -Copt-level=0
+//@ [no-flush] compile-flags: -Zcoverage-options=no-abort-flush
+
+// flush: @llvm.used = {{.*}}ptr @__rustc_coverage_flush_on_abort
+// no-flush-NOT: __rustc_coverage_flush_on_abort
+
+// flush-LABEL: define linkonce_odr hidden void @__rustc_coverage_flush_on_abort()
+// flush-SAME: comdat
+// flush-NEXT: start:
+// flush-NEXT: call i32 @__llvm_profile_dump()
+// flush-NEXT: ret void
+
+fn main() {}
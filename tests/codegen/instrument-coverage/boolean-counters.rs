@@ -0,0 +1,17 @@
+// Test that `-Zcoverage-options=boolean-counters` sets single-byte counters
+// with `llvm.instrprof.cover`, and marks the profile as using them.
+
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=boolean-counters
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__llvm_profile_raw_version = weak hidden constant i64
+
+// CHECK-LABEL: define{{.*}}straight_line
+// CHECK: call void @llvm.instrprof.cover(
+// CHECK-NOT: @llvm.instrprof.increment
+#[no_mangle]
+pub fn straight_line() -> u32 {
+    1
+}
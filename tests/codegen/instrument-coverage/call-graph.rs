@@ -0,0 +1,35 @@
+// Test that `-Zcoverage-options=call-graph` counts calls to statically known
+// callees in a call edge record, and value-profiles indirect call targets.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=call-graph
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__covcall_{{[0-9A-F]+}}_{{[0-9A-F]+}} = linkonce_odr hidden global { i64, i64, i64 }
+// CHECK-SAME: section "__rustc_covcall"
+// CHECK-SAME: align 8
+
+#[inline(never)]
+#[no_mangle]
+pub fn callee() -> u32 {
+    1
+}
+
+// CHECK-LABEL: define{{.*}}direct_caller
+// CHECK: load i64
+// CHECK-NEXT: add i64 {{.*}}, 1
+// CHECK-NEXT: store i64
+// CHECK: call{{.*}}callee
+#[no_mangle]
+pub fn direct_caller() -> u32 {
+    callee()
+}
+
+// CHECK-LABEL: define{{.*}}indirect_caller
+// CHECK: call void @llvm.instrprof.value.profile(ptr @__profn_{{.*}}, i64 {{-?[0-9]+}}, i64 %{{.*}}, i32 0, i32 0)
+#[no_mangle]
+pub fn indirect_caller(f: fn() -> u32) -> u32 {
+    f()
+}
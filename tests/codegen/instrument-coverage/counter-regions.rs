@@ -0,0 +1,16 @@
+// Test that `-Zcoverage-options=counter-regions` emits a record linking each
+// physical counter of a used function to its source regions.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=counter-regions
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__covregion_{{[0-9A-F]+}} = linkonce_odr hidden constant { i64, i64, i64, i64, [{{[0-9]+}} x { i32, i32, i32, i32, i32, i32 }] }
+// CHECK-SAME: section "__rustc_covregion"
+// CHECK-SAME: align 8
+
+pub fn branchy(x: bool) -> u32 {
+    if x { 1 } else { 2 }
+}
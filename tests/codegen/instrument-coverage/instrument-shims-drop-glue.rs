@@ -0,0 +1,26 @@
+// Test that `-Zcoverage-options=instrument-shims` adds a coverage counter to
+// the drop glue of local types, including types that don't implement `Drop`
+// themselves.
+
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=instrument-shims
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0 -Csymbol-mangling-version=legacy
+
+#![crate_type = "lib"]
+
+pub struct HasDrop(u8);
+
+impl Drop for HasDrop {
+    fn drop(&mut self) {}
+}
+
+pub struct Fields {
+    _a: HasDrop,
+    _b: HasDrop,
+}
+
+// CHECK-LABEL: define{{.*}}4core3ptr{{[0-9]+}}drop_in_place$LT${{.*}}Fields$GT$
+// CHECK: call void @llvm.instrprof.increment(
+
+pub fn drop_fields(fields: Fields) {
+    drop(fields);
+}
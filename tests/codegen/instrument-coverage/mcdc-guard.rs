@@ -0,0 +1,22 @@
+// Test that the copies of an or-pattern's match guard, one for each of its
+// alternatives, share the test vector bitmap of a single MC/DC decision.
+
+//@ only-elf
+//@ min-llvm-version: 19
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=mcdc
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// The guard's decision has 5 test vectors, so one copy of its bitmap fits in
+// a byte, but two copies would not.
+// CHECK: @__profbm_guard = {{.*}}global [1 x i8]
+
+// CHECK-LABEL: define{{.*}}@guard(
+#[no_mangle]
+pub fn guard(x: Result<u32, u32>, a: bool, b: bool, c: bool, d: bool) -> u32 {
+    match x {
+        Ok(v) | Err(v) if a && b && c && d => v,
+        _ => 0,
+    }
+}
@@ -0,0 +1,27 @@
+// Test that an MC/DC decision that was MIR-inlined into another function gets
+// condition bitmaps of its own in that function, and updates the callee's
+// test vector bitmap.
+
+//@ only-elf
+//@ min-llvm-version: 19
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=mcdc -Zinline-mir
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__profbm_callee = {{.*}}global
+
+// CHECK-LABEL: define{{.*}}@caller(
+// CHECK: %mcdc.addr.0 = alloca
+// CHECK: store i32 0, ptr %mcdc.addr.0
+// CHECK: @__profbm_callee
+#[no_mangle]
+pub fn caller(a: bool, b: bool) -> u32 {
+    callee(a, b)
+}
+
+#[no_mangle]
+#[inline(always)]
+pub fn callee(a: bool, b: bool) -> u32 {
+    if a && b { 1 } else { 0 }
+}
@@ -0,0 +1,26 @@
+// Test that a decision in the scrutinee of a `let` in a let-chain is a nested
+// MC/DC decision with a condition bitmap of its own, instead of having its
+// conditions mixed up with those of the let-chain.
+
+//@ only-elf
+//@ min-llvm-version: 19
+//@ edition: 2021
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=mcdc
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+#![feature(let_chains)]
+
+// CHECK-LABEL: define{{.*}}@let_chain(
+// CHECK-DAG: %mcdc.addr.0 = alloca
+// CHECK-DAG: %mcdc.addr.1 = alloca
+#[no_mangle]
+pub fn let_chain(a: bool, b: bool, c: bool) -> u32 {
+    if let Some(x) = (a && b).then_some(1)
+        && c
+    {
+        x
+    } else {
+        0
+    }
+}
@@ -0,0 +1,19 @@
+// Test that `-Zcoverage-options=non-atomic` increments counters with plain
+// loads and stores, instead of atomic read-modify-write operations.
+
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=non-atomic
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: define{{.*}}straight_line
+// CHECK-NOT: atomicrmw
+// CHECK: [[COUNT:%.*]] = load i64, ptr @__profc_straight_line
+// CHECK-NEXT: [[NEW_COUNT:%.*]] = add i64 [[COUNT]], 1
+// CHECK-NEXT: store i64 [[NEW_COUNT]], ptr @__profc_straight_line
+// CHECK-NOT: atomicrmw
+// CHECK: ret
+#[no_mangle]
+pub fn straight_line() -> u32 {
+    1
+}
@@ -0,0 +1,28 @@
+// Test that `-Zcoverage-options=overflow-checks` increments the shared
+// `__llvm_prf_cnts` counters in place with saturating arithmetic, and sets the
+// overflow flag in a separate record instead of wrapping.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=overflow-checks
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__covovf_{{[0-9A-F]+}} = linkonce_odr hidden global { i64, i64, i64 }
+// CHECK-SAME: section "__rustc_covovf"
+// CHECK-SAME: align 8
+
+// CHECK-LABEL: define{{.*}}straight_line
+// CHECK: [[COUNT:%.*]] = load i64, ptr @__profc_straight_line, align 8
+// CHECK-NEXT: [[SATURATED:%.*]] = icmp eq i64 [[COUNT]], -1
+// CHECK-NEXT: [[INCREMENTED:%.*]] = add i64 [[COUNT]], 1
+// CHECK-NEXT: [[NEW:%.*]] = select i1 [[SATURATED]], i64 [[COUNT]], i64 [[INCREMENTED]]
+// CHECK-NEXT: store i64 [[NEW]], ptr @__profc_straight_line, align 8
+// CHECK: covovf.overflow:
+// CHECK: store i64 1, ptr getelementptr inbounds {{.*}}@__covovf_
+#[no_mangle]
+pub fn straight_line() -> u32 {
+    1
+}
+
+// CHECK-NOT: __rustc_coverage_counter_address
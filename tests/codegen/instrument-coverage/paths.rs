@@ -0,0 +1,25 @@
+// Test that `-Zcoverage-options=paths` numbers the acyclic paths of a used
+// function, counts them through a path register, and emits a record
+// describing the regions along each path.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=paths
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: @__covpathcnt_{{[0-9A-F]+}} = linkonce_odr hidden global { i64, i64, i64, [2 x i64] }
+// CHECK-SAME: section "__rustc_covpathcnt"
+// CHECK-SAME: align 8
+
+// CHECK: @__covpath_{{[0-9A-F]+}} = linkonce_odr hidden constant { i64, i64, i64, i64, i64, [{{[0-9]+}} x { i32, i32, i32, i32, i32, i32 }] }
+// CHECK-SAME: section "__rustc_covpath"
+// CHECK-SAME: align 8
+
+// CHECK-LABEL: define{{.*}}branchy
+// CHECK: %covpath.reg = alloca
+// CHECK: store i32 0, ptr %covpath.reg
+#[no_mangle]
+pub fn branchy(x: bool) -> u32 {
+    if x { 1 } else { 2 }
+}
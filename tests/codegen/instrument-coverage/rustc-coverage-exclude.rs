@@ -0,0 +1,37 @@
+// Test that items marked with `#[rustc_coverage_exclude]`, and everything
+// nested in them, get neither counters nor a function coverage record, even
+// if they are marked with `#[coverage(on)]`.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+#![feature(coverage_attribute, rustc_attrs)]
+
+// Only `kept` has a function coverage record.
+// CHECK-COUNT-1: @__covrec_{{[A-F0-9]+}}u =
+// CHECK-NOT: @__covrec_
+
+// CHECK-NOT: @__profc_{{.*}}skipped
+// CHECK: @__profc_{{.*}}kept
+// CHECK-NOT: @__profc_{{.*}}skipped
+
+#[no_mangle]
+pub fn kept(x: bool) -> u32 {
+    if x { 1 } else { 0 }
+}
+
+#[no_mangle]
+#[rustc_coverage_exclude]
+pub fn skipped_fn(x: bool) -> u32 {
+    if x { 1 } else { 0 }
+}
+
+#[rustc_coverage_exclude]
+pub mod skipped_mod {
+    #[no_mangle]
+    #[coverage(on)]
+    pub fn skipped_nested(x: bool) -> u32 {
+        if x { 1 } else { 0 }
+    }
+}
@@ -0,0 +1,21 @@
+// Test that `-Zsanitizer-coverage=stack-depth,control-flow` is passed through
+// to LLVM's SanitizerCoverage pass, alongside inline 8-bit counters.
+
+//@ only-linux
+//@ compile-flags: -Copt-level=0
+//@ compile-flags: -Zsanitizer-coverage=inline-8bit-counters,stack-depth,control-flow
+
+// CHECK-DAG: section "__sancov_cntrs"
+// CHECK-DAG: @__sancov_lowest_stack = external thread_local
+// CHECK-DAG: section "__sancov_cfs"
+
+#![crate_type = "lib"]
+
+#[inline(never)]
+fn some_function(x: bool) -> u32 {
+    if x { 1 } else { 2 }
+}
+
+pub fn some_other_function() -> u32 {
+    some_function(true)
+}
@@ -0,0 +1,21 @@
+// Test that `-Zsanitizer-coverage-inline-8bit-counters` can be combined with
+// `-Cinstrument-coverage`, and that both sets of counters are emitted.
+
+//@ only-linux
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+//@ compile-flags: -Cinstrument-coverage -Zsanitizer-coverage-inline-8bit-counters
+
+// CHECK-DAG: section "__llvm_prf_cnts"
+// CHECK-DAG: section "__sancov_cntrs"
+// CHECK-DAG: @__llvm_coverage_mapping
+
+#![crate_type = "lib"]
+
+#[inline(never)]
+fn some_function(x: bool) -> u32 {
+    if x { 1 } else { 2 }
+}
+
+pub fn some_other_function() -> u32 {
+    some_function(true)
+}
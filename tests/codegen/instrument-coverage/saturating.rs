@@ -0,0 +1,26 @@
+// Test that `-Zcoverage-options=saturating` increments the shared
+// `__llvm_prf_cnts` counters in place with saturating arithmetic, without
+// emitting an overflow record.
+
+//@ only-elf
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=saturating
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK-NOT: @__covovf_
+
+// CHECK-LABEL: define{{.*}}straight_line
+// CHECK: [[COUNT:%.*]] = load i64, ptr @__profc_straight_line, align 8
+// CHECK-NEXT: [[SATURATED:%.*]] = icmp eq i64 [[COUNT]], -1
+// CHECK-NEXT: [[INCREMENTED:%.*]] = add i64 [[COUNT]], 1
+// CHECK-NEXT: [[NEW:%.*]] = select i1 [[SATURATED]], i64 [[COUNT]], i64 [[INCREMENTED]]
+// CHECK-NEXT: store i64 [[NEW]], ptr @__profc_straight_line, align 8
+// CHECK-NOT: covovf.overflow
+// CHECK: ret
+#[no_mangle]
+pub fn straight_line() -> u32 {
+    1
+}
+
+// CHECK-NOT: __rustc_coverage_counter_address
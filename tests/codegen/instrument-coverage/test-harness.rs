@@ -0,0 +1,11 @@
+// Test that `-Zcoverage-options=test-harness` instruments the `main` function
+// generated by `--test`, which is otherwise marked `#[coverage(off)]`.
+
+//@ compile-flags: --test -Cinstrument-coverage -Zcoverage-options=test-harness
+//@ compile-flags: -Zno-profiler-runtime -Copt-level=0 -Csymbol-mangling-version=legacy
+
+// CHECK-LABEL: define{{.*}}4main17h{{[0-9a-f]+}}E
+// CHECK: call void @llvm.instrprof.increment(
+
+#[test]
+fn passes() {}
@@ -0,0 +1,9 @@
+// Test that `-Z instrument-function-entry-exit` requests the
+// `__cyg_profile_func_{enter,exit}` hooks from LLVM.
+//
+//@ compile-flags: -Z instrument-function-entry-exit -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: attributes #{{.*}} "instrument-function-entry-inlined"="__cyg_profile_func_enter" "instrument-function-exit-inlined"="__cyg_profile_func_exit"
+pub fn foo() {}
@@ -0,0 +1,27 @@
+#![feature(coverage_attribute)]
+//@ edition: 2021
+//@ only-x86_64
+
+// Check that the template lines of an `asm!` block are counted along with the
+// code around them, instead of being left as holes in the line coverage.
+
+use std::arch::asm;
+
+fn add_one(x: u64) -> u64 {
+    let y: u64;
+    unsafe {
+        asm!(
+            "mov {y}, {x}",
+            "add {y}, 1",
+            x = in(reg) x,
+            y = out(reg) y,
+        );
+    }
+    y
+}
+
+#[coverage(off)]
+fn main() {
+    assert_eq!(add_one(1), 2);
+    assert_eq!(add_one(2), 3);
+}
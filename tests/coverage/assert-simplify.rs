@@ -0,0 +1,18 @@
+#![feature(coverage_attribute)]
+//@ edition: 2021
+//@ compile-flags: -Zcoverage-options=assertions
+//@ llvm-cov-flags: --show-branches=count
+
+// The assertion passed as often as it was evaluated minus as often as it
+// failed, which is `c0 - (c0 - c1)`. That expression is folded into `c1`, the
+// counter of the code after the assertion, instead of being added to the map.
+
+fn check(ok: bool) {
+    assert!(ok, "not ok");
+}
+
+#[coverage(off)]
+fn main() {
+    check(true);
+    check(true);
+}
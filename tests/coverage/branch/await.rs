@@ -0,0 +1,47 @@
+#![feature(coverage_attribute)]
+#![coverage(off)]
+//@ edition: 2021
+//@ compile-flags: -Zcoverage-options=branch
+//@ llvm-cov-flags: --show-branches=count
+
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::task::{Context, Poll, Waker};
+
+// A future that is pending the first time it is polled, and ready after that.
+struct PendingOnce {
+    polled: bool,
+}
+
+impl Future for PendingOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+        if self.polled {
+            return Poll::Ready(());
+        }
+        self.polled = true;
+        Poll::Pending
+    }
+}
+
+#[coverage(on)]
+#[rustfmt::skip]
+async fn await_pending_once() {
+    PendingOnce { polled: false }
+        .await
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(val) = future.as_mut().poll(&mut context) {
+            break val;
+        }
+    }
+}
+
+fn main() {
+    block_on(await_pending_once());
+}
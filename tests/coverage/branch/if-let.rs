@@ -32,6 +32,30 @@ fn if_let_chain(a: Option<&str>, b: Option<&str>) {
     say("done");
 }
 
+fn if_let_chain_irrefutable(a: Option<&str>, b: Option<&str>) {
+    if let Some(x) = a
+        && let z = x
+        && let Some(y) = b
+    {
+        say(z);
+        say(y);
+    } else {
+        say("not both");
+    }
+    say("done");
+}
+
+fn if_let_chain_and_in_scrutinee(a: Option<&str>, b: bool, c: bool) {
+    if let Some(x) = a
+        && let true = (b && c)
+    {
+        say(x);
+    } else {
+        say("not both");
+    }
+    say("done");
+}
+
 #[coverage(off)]
 fn say(message: &str) {
     core::hint::black_box(message);
@@ -53,6 +77,15 @@ fn main() {
         if_let_chain(None, Some("b"));
     }
     if_let_chain(None, None);
-}
 
-// FIXME(#124118) Actually instrument if-let and let-chains for branch coverage.
+    for _ in 0..2 {
+        if_let_chain_irrefutable(Some("a"), Some("b"));
+    }
+    if_let_chain_irrefutable(Some("a"), None);
+    if_let_chain_irrefutable(None, Some("b"));
+
+    if_let_chain_and_in_scrutinee(Some("a"), true, true);
+    if_let_chain_and_in_scrutinee(Some("a"), true, false);
+    if_let_chain_and_in_scrutinee(Some("a"), false, true);
+    if_let_chain_and_in_scrutinee(None, true, true);
+}
@@ -0,0 +1,29 @@
+#![feature(coverage_attribute)]
+//@ edition: 2021
+//@ compile-flags: -Zcoverage-options=branch
+//@ llvm-cov-flags: --show-branches=count
+
+macro_rules! no_merge {
+    () => {
+        for _ in 0..1 {}
+    };
+}
+
+fn try_result(n: u32) -> Result<u32, u32> {
+    no_merge!();
+
+    check(n)?;
+    Ok(n)
+}
+
+#[coverage(off)]
+fn check(n: u32) -> Result<u32, u32> {
+    if n % 2 == 0 { Ok(n) } else { Err(n) }
+}
+
+#[coverage(off)]
+fn main() {
+    for n in [0, 1, 2] {
+        let _ = try_result(n);
+    }
+}
@@ -0,0 +1,33 @@
+#![feature(coverage_attribute)]
+//@ edition: 2021
+//@ compile-flags: -Zcoverage-options=shared-counters,post-dominators
+
+// The code after the `if` joins up again, so it always runs exactly as often
+// as the code before the `if`, and its region shares that code's counter.
+
+macro_rules! no_merge {
+    () => {
+        for _ in 0..1 {}
+    };
+}
+
+fn join_after_if(a: bool) {
+    no_merge!();
+
+    if a {
+        say("then")
+    }
+    say("after");
+}
+
+#[coverage(off)]
+fn say(message: &str) {
+    core::hint::black_box(message);
+}
+
+#[coverage(off)]
+fn main() {
+    for a in [true, false, false] {
+        join_after_if(a);
+    }
+}
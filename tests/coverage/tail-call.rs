@@ -0,0 +1,19 @@
+#![feature(coverage_attribute, explicit_tail_calls)]
+#![expect(incomplete_features)]
+//@ edition: 2021
+
+// Check that a `become` expression gets a coverage region of its own, so that
+// it shows whether the tail call was actually made.
+
+fn ping(n: u32) -> u32 {
+    if n == 0 { 0 } else { become pong(n - 1) }
+}
+
+fn pong(n: u32) -> u32 {
+    become ping(n)
+}
+
+#[coverage(off)]
+fn main() {
+    assert_eq!(ping(3), 0);
+}
@@ -0,0 +1,35 @@
+// Checks that an instrumented program that ends with `std::process::abort()`
+// still writes its profile, because the standard library calls the hook that
+// instrumented crates define before it aborts, and that the profile is lost
+// again with `-Zcoverage-options=no-abort-flush`, which leaves the hook out.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+//@ only-unix
+//@ ignore-apple
+
+use std::path::Path;
+
+use run_make_support::{bin_name, cmd, cwd, llvm_profdata, rustc};
+
+/// Builds `main.rs` with the given extra arguments, runs it until it aborts,
+/// and returns whether it wrote `<name>.profraw`.
+fn writes_profile(name: &str, args: &[&str]) -> bool {
+    rustc().input("main.rs").output(bin_name(name)).arg("-Cinstrument-coverage").args(args).run();
+
+    let output = cmd(cwd().join(bin_name(name)))
+        .env("LLVM_PROFILE_FILE", format!("{name}.profraw"))
+        .run_fail();
+    // The process was killed by `SIGABRT`, rather than exiting normally.
+    assert_eq!(output.status().code(), None);
+
+    Path::new(&format!("{name}.profraw")).exists()
+}
+
+fn main() {
+    assert!(writes_profile("flush", &[]));
+    // The profile that was written before aborting is complete.
+    llvm_profdata().merge().input("flush.profraw").output("flush.profdata").run();
+
+    assert!(!writes_profile("no_flush", &["-Zcoverage-options=no-abort-flush"]));
+}
@@ -0,0 +1,18 @@
+fn unchanged(x: u32) -> u32 {
+    if x % 2 == 0 { x / 2 } else { 3 * x + 1 }
+}
+
+fn changed(x: u32) -> u32 {
+    #[cfg(changed)]
+    if x > 100 {
+        return 0;
+    }
+    if x % 3 == 0 { x / 3 } else { x + 1 }
+}
+
+fn main() {
+    for x in 0..10 {
+        unchanged(x);
+        changed(x);
+    }
+}
@@ -0,0 +1,58 @@
+// Checks that `-Zcoverage-baseline` only gives an entry counter to functions
+// that are structurally unchanged since the baseline was recorded, and fully
+// instruments everything else. The baseline is made from the hashes that
+// `llvm-profdata` reports for a `-Zcoverage-options=stable-ids` build.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+
+use run_make_support::{assert_contains, bin_name, cmd, cwd, llvm_profdata, rfs, rustc};
+
+/// Builds and runs `main.rs` with the given extra arguments, and returns the
+/// contents of the resulting profile as printed by `llvm-profdata show`.
+fn profile(name: &str, args: &[&str]) -> String {
+    rustc().input("main.rs").output(bin_name(name)).arg("-Cinstrument-coverage").args(args).run();
+
+    cmd(cwd().join(bin_name(name))).env("LLVM_PROFILE_FILE", format!("{name}.profraw")).run();
+    llvm_profdata()
+        .merge()
+        .input(format!("{name}.profraw"))
+        .output(format!("{name}.profdata"))
+        .run();
+    llvm_profdata()
+        .arg("show")
+        .arg("--all-functions")
+        .input(format!("{name}.profdata"))
+        .run()
+        .stdout_utf8()
+}
+
+/// Returns the `Counters: N` value that `llvm-profdata show` printed for the
+/// function whose mangled name contains `name`.
+fn num_counters(profile: &str, name: &str) -> u32 {
+    let mut lines = profile.lines().skip_while(|line| !line.contains(name));
+    lines
+        .find_map(|line| line.trim().strip_prefix("Counters: "))
+        .unwrap_or_else(|| panic!("no record for `{name}` in profile:\n{profile}"))
+        .parse()
+        .unwrap()
+}
+
+fn main() {
+    let baseline = profile("baseline", &["-Zcoverage-options=stable-ids"]);
+    assert!(num_counters(&baseline, "9unchanged") > 1);
+
+    let hashes = baseline
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Hash: "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    rfs::write("baseline.txt", format!("# from a stable-ids build\n{hashes}\n"));
+
+    let differential =
+        profile("differential", &["--cfg=changed", "-Zcoverage-baseline=baseline.txt"]);
+    // Every function is still reported, so reports cover the whole crate.
+    assert_contains(&differential, "9unchanged");
+    assert_eq!(num_counters(&differential, "9unchanged"), 1);
+    assert!(num_counters(&differential, "7changed") > 1);
+}
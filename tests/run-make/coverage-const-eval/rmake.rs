@@ -0,0 +1,34 @@
+// Checks that `-Zcoverage-options=const-eval` writes the coverage counters of
+// functions that ran during const evaluation to a text profile, and that this
+// profile can be merged with the profile written by the instrumented program.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+
+use run_make_support::{
+    assert_contains, cwd, has_extension, llvm_profdata, rfs, run, rustc, shallow_find_files,
+};
+
+fn main() {
+    rustc()
+        .input("main.rs")
+        .arg("-Cinstrument-coverage")
+        .arg("-Zcoverage-options=const-eval")
+        .run();
+
+    // `collatz_steps` only runs at compile time, so it only appears in the
+    // const-eval profile.
+    let const_eval_profile = rfs::read_to_string("main.const-eval.proftext");
+    assert_contains(&const_eval_profile, "collatz_steps");
+    assert_contains(&const_eval_profile, "# Counter Values:");
+
+    run("main");
+
+    let profraw_files = shallow_find_files(cwd(), |path| has_extension(path, "profraw"));
+    let mut merge = llvm_profdata();
+    merge.merge().output("merged.profdata").input("main.const-eval.proftext");
+    for profraw in profraw_files {
+        merge.input(profraw);
+    }
+    merge.run();
+}
@@ -0,0 +1,27 @@
+#[cold]
+#[inline(never)]
+fn report(message: &str, value: u32) {
+    eprintln!("{message}: {value}");
+}
+
+#[inline(never)]
+fn clamped_sum(values: &[u32]) -> u32 {
+    let mut total = 0u32;
+    for &value in values {
+        if value > 1000 {
+            // This branch ends in a call to a `#[cold]` function, so hot/cold
+            // splitting moves it out of the function's hot code.
+            report("value too large", value);
+            total = total.wrapping_add(1000);
+            continue;
+        }
+        total = total.wrapping_add(value);
+    }
+    total
+}
+
+fn main() {
+    let values = (0..100).chain([5000, 6000]).collect::<Vec<u32>>();
+    let total = clamped_sum(std::hint::black_box(&values));
+    assert_eq!(total, 4950 + 2000);
+}
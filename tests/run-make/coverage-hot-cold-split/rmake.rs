@@ -0,0 +1,59 @@
+// Checks that coverage counters still count correctly when LLVM moves cold
+// code out of line, with hot/cold splitting (which outlines cold blocks into
+// separate `.cold` functions) or machine function splitting (which moves
+// cold blocks into a separate section). Counter increments are lowered
+// before either kind of splitting runs, so the split-out code must keep
+// updating the counters of the function it came from, and the resulting
+// profile must be identical to the one from an unsplit build.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+//@ only-x86_64
+//@ only-linux
+
+use run_make_support::{
+    assert_contains, assert_equals, bin_name, cmd, cwd, llvm_nm, llvm_profdata, rustc,
+};
+
+/// Builds and runs `main.rs` with the given extra LLVM arguments, and returns
+/// the contents of the resulting profile as printed by `llvm-profdata show`.
+fn profile_with_llvm_args(name: &str, llvm_args: &[&str]) -> String {
+    let mut rustc = rustc();
+    rustc.input("main.rs").output(bin_name(name)).arg("-Cinstrument-coverage").opt_level("2");
+    for llvm_arg in llvm_args {
+        rustc.arg(format!("-Cllvm-args={llvm_arg}"));
+    }
+    rustc.run();
+
+    cmd(cwd().join(bin_name(name))).env("LLVM_PROFILE_FILE", format!("{name}.profraw")).run();
+    llvm_profdata()
+        .merge()
+        .input(format!("{name}.profraw"))
+        .output(format!("{name}.profdata"))
+        .run();
+    llvm_profdata()
+        .arg("show")
+        .arg("--all-functions")
+        .arg("--counts")
+        .input(format!("{name}.profdata"))
+        .run()
+        .stdout_utf8()
+}
+
+fn main() {
+    let unsplit = profile_with_llvm_args("unsplit", &[]);
+    assert_contains(&unsplit, "clamped_sum");
+
+    let hot_cold =
+        profile_with_llvm_args("hot_cold", &["-hot-cold-split=true", "-hotcoldsplit-threshold=-1"]);
+    // Make sure that hot/cold splitting really did move code out of line.
+    let symbols = llvm_nm().input(bin_name("hot_cold")).run().stdout_utf8();
+    assert_contains(&symbols, ".cold.");
+    assert_equals(&hot_cold, &unsplit);
+
+    let machine_split = profile_with_llvm_args(
+        "machine_split",
+        &["-enable-split-machine-functions", "-mfs-split-ehcode"],
+    );
+    assert_equals(&machine_split, &unsplit);
+}
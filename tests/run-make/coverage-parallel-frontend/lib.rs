@@ -0,0 +1,65 @@
+// A crate with enough instrumented functions, generics, closures and unused
+// code that parallel query execution has a chance to reorder things.
+
+pub const fn triangle(n: u32) -> u32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < n {
+        total += i;
+        i += 1;
+    }
+    total
+}
+
+pub const TRIANGLE: u32 = triangle(10);
+
+pub fn classify(x: i32) -> &'static str {
+    if x < 0 {
+        "negative"
+    } else if x == 0 {
+        "zero"
+    } else {
+        "positive"
+    }
+}
+
+pub fn sum_matching<T: Copy + Into<i64>>(items: &[T], pred: impl Fn(i64) -> bool) -> i64 {
+    items.iter().map(|&item| item.into()).filter(|&item| pred(item)).sum()
+}
+
+pub fn uses_generics() -> i64 {
+    sum_matching(&[1u8, 2, 3], |x| x % 2 == 1) + sum_matching(&[-4i32, 5], |x| x > 0)
+}
+
+pub trait Shape {
+    fn area(&self) -> f64;
+}
+
+pub struct Square(pub f64);
+pub struct Circle(pub f64);
+
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.0 * self.0
+    }
+}
+
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        if self.0 <= 0.0 { 0.0 } else { std::f64::consts::PI * self.0 * self.0 }
+    }
+}
+
+pub fn total_area(shapes: &[&dyn Shape]) -> f64 {
+    shapes.iter().map(|shape| shape.area()).sum()
+}
+
+#[allow(dead_code)]
+fn never_called(flag: bool) -> u32 {
+    if flag { TRIANGLE } else { 0 }
+}
+
+#[allow(dead_code)]
+fn never_called_generic<T: Default>() -> T {
+    T::default()
+}
@@ -0,0 +1,61 @@
+// Checks that coverage instrumentation doesn't depend on how the parallel
+// frontend (`-Zthreads`) schedules queries: repeated builds with many threads
+// must produce the same coverage mappings, counters and const-eval profile as
+// a single-threaded build.
+
+use run_make_support::{rfs, rustc};
+
+fn build(name: &str, threads: u32, codegen_units: usize) {
+    rustc()
+        .input("lib.rs")
+        .crate_type("lib")
+        .arg("-Cinstrument-coverage")
+        .arg("-Zno-profiler-runtime")
+        .arg("-Zcoverage-options=branch,const-eval")
+        .arg(format!("-Zthreads={threads}"))
+        .codegen_units(codegen_units)
+        .emit("llvm-ir")
+        .output(format!("{name}.ll"))
+        .run();
+}
+
+fn assert_same_output(expected: &str, actual: &str) {
+    for extension in ["ll", "const-eval.proftext"] {
+        let expected_path = format!("{expected}.{extension}");
+        let actual_path = format!("{actual}.{extension}");
+        assert!(
+            rfs::read(&expected_path) == rfs::read(&actual_path),
+            "`{actual_path}` differs from `{expected_path}`"
+        );
+    }
+}
+
+fn main() {
+    build("serial", 1, 1);
+    for run in 0..4 {
+        let name = format!("parallel{run}");
+        build(&name, 8, 1);
+        assert_same_output("serial", &name);
+    }
+
+    // With several codegen units, each unit builds its own share of the
+    // unused-function records, so also compare the final library between
+    // builds that used several threads.
+    let build_multi_cgu = || {
+        rustc()
+            .input("lib.rs")
+            .crate_type("lib")
+            .arg("-Cinstrument-coverage")
+            .arg("-Zno-profiler-runtime")
+            .arg("-Zthreads=8")
+            .codegen_units(16)
+            .run();
+    };
+    build_multi_cgu();
+    rfs::rename("liblib.rlib", "first.rlib");
+    build_multi_cgu();
+    assert!(
+        rfs::read("first.rlib") == rfs::read("liblib.rlib"),
+        "builds with 16 codegen units differ"
+    );
+}
@@ -0,0 +1,19 @@
+#[no_mangle]
+#[inline(never)]
+pub fn branchy(c: char) -> u32 {
+    if c == 'a' {
+        // This branch is taken 3 times
+        1
+    } else {
+        // This branch is taken once
+        2
+    }
+}
+
+fn main() {
+    let mut total = 0;
+    for c in "aaab".chars() {
+        total += branchy(std::hint::black_box(c));
+    }
+    assert_eq!(total, 5);
+}
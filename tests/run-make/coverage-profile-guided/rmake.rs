@@ -0,0 +1,37 @@
+// Checks that a profile collected from a `-Cinstrument-coverage` build can be
+// used to place the counters of a later instrumented build, with
+// `-Zcoverage-options=profile-guided`, and that the later build still runs
+// and produces a usable profile of its own.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+
+use run_make_support::{cwd, has_extension, llvm_profdata, rfs, run, rustc, shallow_find_files};
+
+fn merge_profiles(output: &str) {
+    let profraw_files = shallow_find_files(cwd(), |path| has_extension(path, "profraw"));
+    let mut merge = llvm_profdata();
+    merge.merge().output(output);
+    for profraw in &profraw_files {
+        merge.input(profraw);
+    }
+    merge.run();
+    for profraw in profraw_files {
+        rfs::remove_file(profraw);
+    }
+}
+
+fn main() {
+    rustc().input("main.rs").arg("-Cinstrument-coverage").run();
+    run("main");
+    merge_profiles("first.profdata");
+
+    rustc()
+        .input("main.rs")
+        .arg("-Cinstrument-coverage")
+        .profile_use("first.profdata")
+        .arg("-Zcoverage-options=profile-guided")
+        .run();
+    run("main");
+    merge_profiles("second.profdata");
+}
@@ -0,0 +1,7 @@
+fn main() {
+    let mut total = 0u64;
+    for i in 0..10 {
+        total += i;
+    }
+    assert_eq!(total, 45);
+}
@@ -0,0 +1,46 @@
+// Checks that the counts of an instrumented program reach the profile when
+// counters saturate under `-Zcoverage-options=saturating` or `overflow-checks`,
+// because the shared `__llvm_prf_cnts` counters are incremented in place,
+// rather than a copy that nothing writes out.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+
+use run_make_support::external_deps::llvm::llvm_bin_dir;
+use run_make_support::{bin_name, cmd, cwd, llvm_profdata, rustc};
+
+/// Builds and runs `main.rs` with the given coverage options, and returns the
+/// count that `llvm-cov show` reports for the line of the loop body.
+fn loop_body_count(name: &str, coverage_options: &str) -> u64 {
+    rustc()
+        .input("main.rs")
+        .output(bin_name(name))
+        .arg("-Cinstrument-coverage")
+        .arg(format!("-Zcoverage-options={coverage_options}"))
+        .run();
+
+    cmd(cwd().join(bin_name(name))).env("LLVM_PROFILE_FILE", format!("{name}.profraw")).run();
+    llvm_profdata()
+        .merge()
+        .input(format!("{name}.profraw"))
+        .output(format!("{name}.profdata"))
+        .run();
+    let report = cmd(llvm_bin_dir().join("llvm-cov"))
+        .arg("show")
+        .arg(format!("--instr-profile={name}.profdata"))
+        .arg(bin_name(name))
+        .run()
+        .stdout_utf8();
+
+    // Lines look like `    4|     10|        total += i;`.
+    let line = report
+        .lines()
+        .find(|line| line.ends_with("total += i;"))
+        .unwrap_or_else(|| panic!("no loop body in report:\n{report}"));
+    line.split('|').nth(1).unwrap().trim().parse().unwrap()
+}
+
+fn main() {
+    assert_eq!(loop_body_count("saturating", "saturating"), 10);
+    assert_eq!(loop_body_count("overflow_checks", "overflow-checks"), 10);
+}
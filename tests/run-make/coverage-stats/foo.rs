@@ -0,0 +1,12 @@
+pub fn count_down(mut n: u32) -> u32 {
+    let mut steps = 0;
+    while n > 0 {
+        n -= 1;
+        steps += 1;
+    }
+    steps
+}
+
+pub fn is_even(n: u32) -> bool {
+    if n % 2 == 0 { true } else { false }
+}
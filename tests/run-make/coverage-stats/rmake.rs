@@ -0,0 +1,20 @@
+// Checks that `-Zcoverage-stats` prints the crate's coverage totals, and lists
+// the instrumented functions with the most counters.
+
+use run_make_support::rustc;
+
+fn main() {
+    rustc()
+        .crate_type("lib")
+        .input("foo.rs")
+        .arg("-Cinstrument-coverage")
+        .arg("-Zno-profiler-runtime")
+        .arg("-Zcoverage-stats")
+        .run()
+        .assert_stderr_contains("coverage-stats COVERAGE STATS FOR `foo`")
+        .assert_stderr_contains("coverage-stats Functions                       2")
+        .assert_stderr_contains("coverage-stats Physical counters")
+        .assert_stderr_contains("coverage-stats Mapping bytes")
+        .assert_stderr_contains("count_down")
+        .assert_stderr_contains("is_even");
+}
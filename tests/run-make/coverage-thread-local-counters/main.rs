@@ -0,0 +1,17 @@
+use std::thread;
+
+fn work(n: u64) -> u64 {
+    let mut total = 0;
+    for i in 0..n {
+        total += i;
+    }
+    total
+}
+
+fn main() {
+    let threads: Vec<_> = (0..2).map(|_| thread::spawn(|| work(10))).collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 45);
+    }
+    assert_eq!(work(5), 10);
+}
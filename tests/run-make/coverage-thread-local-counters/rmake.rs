@@ -0,0 +1,44 @@
+// Checks that under `-Zcoverage-options=thread-local-counters`, the counts of
+// spawned threads and of the main thread reach the profile, because std merges
+// each thread's shadow counters into the shared counters when the thread exits
+// and before the profile is written at exit.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+//@ only-unix
+//@ ignore-apple
+
+use run_make_support::external_deps::llvm::llvm_bin_dir;
+use run_make_support::{bin_name, cmd, cwd, llvm_profdata, rustc};
+
+/// Returns the count that `llvm-cov show` reports for the line ending with
+/// `code`.
+fn line_count(report: &str, code: &str) -> u64 {
+    // Lines look like `    4|     25|        total += i;`.
+    let line = report
+        .lines()
+        .find(|line| line.ends_with(code))
+        .unwrap_or_else(|| panic!("no `{code}` in report:\n{report}"));
+    line.split('|').nth(1).unwrap().trim().parse().unwrap()
+}
+
+fn main() {
+    rustc()
+        .input("main.rs")
+        .arg("-Cinstrument-coverage")
+        .arg("-Zcoverage-options=thread-local-counters")
+        .run();
+
+    cmd(cwd().join(bin_name("main"))).env("LLVM_PROFILE_FILE", "main.profraw").run();
+    llvm_profdata().merge().input("main.profraw").output("main.profdata").run();
+    let report = cmd(llvm_bin_dir().join("llvm-cov"))
+        .arg("show")
+        .arg("--instr-profile=main.profdata")
+        .arg(bin_name("main"))
+        .run()
+        .stdout_utf8();
+
+    // Two spawned threads call `work(10)`, and the main thread calls `work(5)`.
+    assert_eq!(line_count(&report, "fn work(n: u64) -> u64 {"), 3);
+    assert_eq!(line_count(&report, "total += i;"), 25);
+}
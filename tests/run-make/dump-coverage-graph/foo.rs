@@ -0,0 +1,8 @@
+pub fn count_down(mut n: u32) -> u32 {
+    let mut steps = 0;
+    while n > 0 {
+        n -= 1;
+        steps += 1;
+    }
+    steps
+}
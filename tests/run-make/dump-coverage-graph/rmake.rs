@@ -0,0 +1,21 @@
+// Checks that `-Zdump-coverage-graph` writes each instrumented function's
+// coverage graph as a graphviz file, with loop headers and back-edges marked.
+
+use run_make_support::{assert_contains, rfs, rustc};
+
+fn main() {
+    rustc()
+        .crate_type("lib")
+        .input("foo.rs")
+        .arg("-Cinstrument-coverage")
+        .arg("-Zno-profiler-runtime")
+        .arg("-Zdump-coverage-graph")
+        .arg("-Zdump-mir-dir=dump")
+        .run();
+
+    let dot = rfs::read_to_string("dump/foo.count_down.-------.coverage_graph.-----.dot");
+    assert_contains(&dot, "digraph ");
+    assert_contains(&dot, r#"bcb0 [label="bcb0\nbb0"#);
+    assert_contains(&dot, r#"\nloop header\nrank 1"];"#);
+    assert_contains(&dot, r#" [style="dashed"];"#);
+}
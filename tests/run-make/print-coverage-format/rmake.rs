@@ -0,0 +1,24 @@
+//! This checks that `--print=coverage-format` reports the coverage format
+//! versions as `key=value` lines, with the tool versions matching rustc's
+//! LLVM major version.
+
+use run_make_support::rustc;
+
+fn main() {
+    let output = rustc().arg("-Zunstable-options").print("coverage-format").run().stdout_utf8();
+
+    let value = |key: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .unwrap_or_else(|| panic!("missing `{key}` in output:\n{output}"))
+    };
+
+    for key in ["profraw-version", "profdata-version", "coverage-mapping-version"] {
+        value(key).parse::<u64>().unwrap_or_else(|_| panic!("`{key}` should be a number"));
+    }
+
+    let llvm_major = value("llvm-version").split('.').next().unwrap();
+    assert_eq!(value("llvm-profdata-version"), llvm_major);
+    assert_eq!(value("llvm-cov-version"), llvm_major);
+}
@@ -0,0 +1,33 @@
+// Checks that a profile collected from a `-Cinstrument-coverage` build can be
+// used as PGO input with `-Zprofile-use-coverage`, by building the same
+// program with coverage instrumentation, running it, and then rebuilding it
+// with the merged profile. The branch counts recorded by coverage should show
+// up as branch weights in the second build's LLVM IR.
+
+//@ needs-profiler-runtime
+//@ ignore-cross-compile
+
+use run_make_support::{
+    cwd, has_extension, llvm_filecheck, llvm_profdata, rfs, run, rustc, shallow_find_files,
+};
+
+fn main() {
+    rustc().input("main.rs").arg("-Cinstrument-coverage").run();
+    run("main");
+
+    let profraw_files = shallow_find_files(cwd(), |path| has_extension(path, "profraw"));
+    let mut merge = llvm_profdata();
+    merge.merge().output("merged.profdata");
+    for profraw in profraw_files {
+        merge.input(profraw);
+    }
+    merge.run();
+
+    rustc()
+        .input("main.rs")
+        .profile_use("merged.profdata")
+        .arg("-Zprofile-use-coverage")
+        .emit("llvm-ir")
+        .run();
+    llvm_filecheck().patterns("filecheck-patterns.txt").stdin_buf(rfs::read("main.ll")).run();
+}
@@ -0,0 +1,20 @@
+//@ has foo/fn.tested.html '//*[@class="stab coverage"]' \
+//      'Tested: 2 of 3 coverage regions were executed.'
+pub fn tested(x: bool) -> u32 {
+    if x { 1 } else { 2 }
+}
+
+//@ has foo/fn.untested.html '//*[@class="stab coverage"]' \
+//      'Untested: none of its 1 coverage regions were executed.'
+pub fn untested() {}
+
+//@ !has foo/fn.not_in_export.html '//*[@class="stab coverage"]'
+pub fn not_in_export() {}
+
+pub struct Foo;
+
+impl Foo {
+    //@ has foo/struct.Foo.html '//*[@class="stab coverage"]' \
+    //      'Tested: 1 of 1 coverage regions were executed.'
+    pub fn method(&self) {}
+}
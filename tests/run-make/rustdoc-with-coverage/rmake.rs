@@ -0,0 +1,19 @@
+// Test that `--with-coverage` shows the code coverage from an `llvm-cov export`
+// file on the documentation of the functions it covers, even when the export
+// was made from a checkout at a different path.
+
+use std::path::Path;
+
+use run_make_support::{htmldocck, rustdoc};
+
+fn main() {
+    let out_dir = Path::new("rustdoc");
+    rustdoc()
+        .out_dir(out_dir)
+        .input("foo.rs")
+        .arg("-Zunstable-options")
+        .arg("--with-coverage")
+        .arg("coverage.json")
+        .run();
+    htmldocck().arg(out_dir).arg("foo.rs").run();
+}
@@ -0,0 +1,20 @@
+//! The coverage attribute can be applied directly to closure and async-block
+//! expressions, without needing `#![feature(stmt_expr_attributes)]`.
+
+//@ check-pass
+//@ edition: 2021
+//@ compile-flags: -Cinstrument-coverage -Zno-profiler-runtime
+
+#![feature(coverage_attribute)]
+
+fn main() {
+    let closure = #[coverage(off)] |x: u32| x + 1;
+    let _ = closure(1);
+
+    std::panic::set_hook(Box::new(#[coverage(off)] |info| {
+        eprintln!("{info}");
+    }));
+
+    let _fut = #[coverage(off)] async {};
+    let _fut = #[coverage(off)] async move { closure(2) };
+}
@@ -0,0 +1,3 @@
+//@ compile-flags: --print=coverage-format
+
+fn main() {}
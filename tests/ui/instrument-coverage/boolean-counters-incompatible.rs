@@ -0,0 +1,8 @@
+// Check that `-Zcoverage-options=boolean-counters` is rejected together with
+// options that need 64-bit counters.
+
+//@ check-fail
+//@ compile-flags: -Cinstrument-coverage -Zno-profiler-runtime
+//@ compile-flags: -Zcoverage-options=boolean-counters,overflow-checks
+
+fn main() {}
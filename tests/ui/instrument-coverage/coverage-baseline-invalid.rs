@@ -0,0 +1,7 @@
+// Check that malformed lines in a `-Zcoverage-baseline` file are reported.
+
+//@ build-fail
+//@ compile-flags: -Cinstrument-coverage -Zno-profiler-runtime
+//@ compile-flags: -Zcoverage-baseline={{src-base}}/instrument-coverage/auxiliary/bad-baseline.txt
+
+fn main() {}
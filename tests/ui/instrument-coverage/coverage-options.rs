@@ -1,6 +1,19 @@
-//@ revisions: block branch condition mcdc bad
+//@ revisions: function block branch condition mcdc sysroot-local-paths stable-ids
+//@ revisions: thread-local-counters first-hit-timestamps counter-metadata post-inline
+//@ revisions: split-at-panics instrument-shims
+//@ revisions: const-eval counter-regions test-harness subexpr-calls subexpr-all paths
+//@ revisions: call-graph assertions no-abort-flush overflow-checks post-dominators
+//@ revisions: unwind-paths coroutine-drops static-unreachable spanning-tree boolean-counters
+//@ revisions: profile-guided switch-arms non-atomic saturating expansions
+//@ revisions: macro-definitions gap-regions skipped-regions line-only recover-spans
+//@ revisions: folded-branches-omit folded-branches-mark collapse-external-macros
+//@ revisions: raw-spans instrument-derives char-columns per-instance shared-file-table
+//@ revisions: loop-depth-order shared-counters bad
 //@ compile-flags -Cinstrument-coverage -Zno-profiler-runtime
 
+//@ [function] check-pass
+//@ [function] compile-flags: -Zcoverage-options=function
+
 //@ [block] check-pass
 //@ [block] compile-flags: -Zcoverage-options=block
 
@@ -13,6 +26,139 @@
 //@ [mcdc] check-pass
 //@ [mcdc] compile-flags: -Zcoverage-options=mcdc
 
+//@ [sysroot-local-paths] check-pass
+//@ [sysroot-local-paths] compile-flags: -Zcoverage-options=sysroot-local-paths
+
+//@ [stable-ids] check-pass
+//@ [stable-ids] compile-flags: -Zcoverage-options=stable-ids
+
+//@ [thread-local-counters] check-pass
+//@ [thread-local-counters] compile-flags: -Zcoverage-options=thread-local-counters
+
+//@ [first-hit-timestamps] check-pass
+//@ [first-hit-timestamps] compile-flags: -Zcoverage-options=first-hit-timestamps
+
+//@ [counter-metadata] check-pass
+//@ [counter-metadata] compile-flags: -Zcoverage-options=counter-metadata
+
+//@ [post-inline] check-pass
+//@ [post-inline] compile-flags: -Zcoverage-options=post-inline
+
+//@ [split-at-panics] check-pass
+//@ [split-at-panics] compile-flags: -Zcoverage-options=split-at-panics
+
+//@ [instrument-shims] check-pass
+//@ [instrument-shims] compile-flags: -Zcoverage-options=instrument-shims
+
+//@ [const-eval] check-pass
+//@ [const-eval] compile-flags: -Zcoverage-options=const-eval
+
+//@ [counter-regions] check-pass
+//@ [counter-regions] compile-flags: -Zcoverage-options=counter-regions
+
+//@ [test-harness] check-pass
+//@ [test-harness] compile-flags: -Zcoverage-options=test-harness
+
+//@ [subexpr-calls] check-pass
+//@ [subexpr-calls] compile-flags: -Zcoverage-options=subexpr-calls
+
+//@ [subexpr-all] check-pass
+//@ [subexpr-all] compile-flags: -Zcoverage-options=subexpr-all
+
+//@ [paths] check-pass
+//@ [paths] compile-flags: -Zcoverage-options=paths
+
+//@ [call-graph] check-pass
+//@ [call-graph] compile-flags: -Zcoverage-options=call-graph
+
+//@ [assertions] check-pass
+//@ [assertions] compile-flags: -Zcoverage-options=assertions
+
+//@ [no-abort-flush] check-pass
+//@ [no-abort-flush] compile-flags: -Zcoverage-options=no-abort-flush
+
+//@ [overflow-checks] check-pass
+//@ [overflow-checks] compile-flags: -Zcoverage-options=overflow-checks
+
+//@ [post-dominators] check-pass
+//@ [post-dominators] compile-flags: -Zcoverage-options=post-dominators
+
+//@ [unwind-paths] check-pass
+//@ [unwind-paths] compile-flags: -Zcoverage-options=unwind-paths
+
+//@ [coroutine-drops] check-pass
+//@ [coroutine-drops] compile-flags: -Zcoverage-options=coroutine-drops
+
+//@ [static-unreachable] check-pass
+//@ [static-unreachable] compile-flags: -Zcoverage-options=static-unreachable
+
+//@ [spanning-tree] check-pass
+//@ [spanning-tree] compile-flags: -Zcoverage-options=spanning-tree
+
+//@ [boolean-counters] check-pass
+//@ [boolean-counters] compile-flags: -Zcoverage-options=boolean-counters
+
+//@ [switch-arms] check-pass
+//@ [switch-arms] compile-flags: -Zcoverage-options=switch-arms
+
+//@ [non-atomic] check-pass
+//@ [non-atomic] compile-flags: -Zcoverage-options=non-atomic
+
+//@ [saturating] check-pass
+//@ [saturating] compile-flags: -Zcoverage-options=saturating
+
+//@ [expansions] check-pass
+//@ [expansions] compile-flags: -Zcoverage-options=expansions
+
+//@ [macro-definitions] check-pass
+//@ [macro-definitions] compile-flags: -Zcoverage-options=macro-definitions
+
+//@ [gap-regions] check-pass
+//@ [gap-regions] compile-flags: -Zcoverage-options=gap-regions
+
+//@ [skipped-regions] check-pass
+//@ [skipped-regions] compile-flags: -Zcoverage-options=skipped-regions
+
+//@ [line-only] check-pass
+//@ [line-only] compile-flags: -Zcoverage-options=line-only
+
+//@ [recover-spans] check-pass
+//@ [recover-spans] compile-flags: -Zcoverage-options=recover-spans
+
+//@ [folded-branches-omit] check-pass
+//@ [folded-branches-omit] compile-flags: -Zcoverage-options=branch,folded-branches-omit
+
+//@ [folded-branches-mark] check-pass
+//@ [folded-branches-mark] compile-flags: -Zcoverage-options=branch,folded-branches-mark
+
+//@ [collapse-external-macros] check-pass
+//@ [collapse-external-macros] compile-flags: -Zcoverage-options=collapse-external-macros
+
+//@ [raw-spans] check-pass
+//@ [raw-spans] compile-flags: -Zcoverage-options=raw-spans
+
+//@ [instrument-derives] check-pass
+//@ [instrument-derives] compile-flags: -Zcoverage-options=instrument-derives
+
+//@ [char-columns] check-pass
+//@ [char-columns] compile-flags: -Zcoverage-options=char-columns
+
+//@ [per-instance] check-pass
+//@ [per-instance] compile-flags: -Zcoverage-options=per-instance
+
+//@ [shared-file-table] check-pass
+//@ [shared-file-table] compile-flags: -Zcoverage-options=shared-file-table
+
+//@ [loop-depth-order] check-pass
+//@ [loop-depth-order] compile-flags: -Zcoverage-options=loop-depth-order
+
+//@ [shared-counters] check-pass
+//@ [shared-counters] compile-flags: -Zcoverage-options=shared-counters
+
+// Without `-Cprofile-use`, there is no profile to be guided by.
+//@ [profile-guided] check-fail
+//@ [profile-guided] compile-flags: -Zcoverage-options=profile-guided
+
 //@ [bad] check-fail
 //@ [bad] compile-flags: -Zcoverage-options=bad
 
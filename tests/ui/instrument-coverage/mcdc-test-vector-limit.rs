@@ -0,0 +1,23 @@
+//@ edition: 2021
+//@ min-llvm-version: 19
+//@ check-pass
+//@ compile-flags: -Cinstrument-coverage -Zcoverage-options=mcdc -Zno-profiler-runtime
+//@ compile-flags: -Zcoverage-mcdc-max-test-vectors=4
+
+// Check that MC/DC instrumentation warns about a decision with more test
+// vectors than `-Zcoverage-mcdc-max-test-vectors` allows, and falls back to
+// only instrumenting that decision for branch coverage.
+
+fn main() {
+    // 3 conditions joined by `&&` have 4 test vectors, so no diagnostic.
+    let [a, b, c, d] = <[bool; 4]>::default();
+    if a && b && c {
+        core::hint::black_box("hello");
+    }
+
+    // 4 conditions have 5 test vectors, which is over the limit.
+    if a && b && c && d {
+        //~^ WARN number of test vectors in decision (5) exceeds limit (4)
+        core::hint::black_box("world");
+    }
+}
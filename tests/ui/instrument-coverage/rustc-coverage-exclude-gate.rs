@@ -0,0 +1,6 @@
+// Test that the internal `#[rustc_coverage_exclude]` attribute is gated by
+// the `rustc_attrs` feature.
+
+#[rustc_coverage_exclude]
+//~^ ERROR #[rustc_coverage_exclude] unconditionally excludes an item and its contents from coverage instrumentation
+fn main() {}
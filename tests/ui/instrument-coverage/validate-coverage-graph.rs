@@ -0,0 +1,45 @@
+// Test that the coverage graphs of functions with various kinds of control
+// flow pass `-Zvalidate-coverage-graph`.
+
+//@ check-pass
+//@ revisions: default unwind-paths
+//@ compile-flags: -Cinstrument-coverage -Zno-profiler-runtime -Zvalidate-coverage-graph
+//@ [unwind-paths] compile-flags: -Zcoverage-options=unwind-paths
+
+fn branches(x: u32) -> u32 {
+    match x {
+        0 => 1,
+        1 | 2 => x * 2,
+        _ if x % 2 == 0 => x / 2,
+        _ => x + 1,
+    }
+}
+
+fn loops(v: &[u32]) -> u32 {
+    let mut total = 0;
+    'outer: for &a in v {
+        let mut b = a;
+        while b > 0 {
+            if b == 7 {
+                continue 'outer;
+            }
+            b -= 1;
+            total += 1;
+        }
+        if total > 100 {
+            break;
+        }
+    }
+    total
+}
+
+fn calls(v: Vec<String>) -> Option<usize> {
+    let first = v.first()?;
+    Some(first.len() + v.len())
+}
+
+fn main() {
+    let _ = branches(3);
+    let _ = loops(&[1, 2, 3]);
+    let _ = calls(vec![String::new()]);
+}